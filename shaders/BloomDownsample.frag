@@ -0,0 +1,93 @@
+#version 450
+
+layout (set = 0, binding = 0) uniform sampler2D samplerSource;
+
+layout (push_constant) uniform Push {
+    vec2 sourceTexelSize;
+    float threshold;
+    float knee;
+    uint prefilter;
+} push;
+
+layout (location = 0) in vec2 inUV;
+
+layout (location = 0) out vec4 outColor;
+
+vec3 Sample(vec2 offset);
+float KarisWeight(vec3 color);
+vec3 Threshold(vec3 color);
+
+// 13 tap downsample from Jimenez, "Next Generation Post Processing in Call of Duty: Advanced Warfare"
+void main()
+{
+    vec3 a = Sample(vec2(-2.0, 2.0));
+    vec3 b = Sample(vec2(0.0, 2.0));
+    vec3 c = Sample(vec2(2.0, 2.0));
+    vec3 d = Sample(vec2(-2.0, 0.0));
+    vec3 e = Sample(vec2(0.0, 0.0));
+    vec3 f = Sample(vec2(2.0, 0.0));
+    vec3 g = Sample(vec2(-2.0, -2.0));
+    vec3 h = Sample(vec2(0.0, -2.0));
+    vec3 i = Sample(vec2(2.0, -2.0));
+    vec3 j = Sample(vec2(-1.0, 1.0));
+    vec3 k = Sample(vec2(1.0, 1.0));
+    vec3 l = Sample(vec2(-1.0, -1.0));
+    vec3 m = Sample(vec2(1.0, -1.0));
+
+    vec3 color;
+
+    if (push.prefilter != 0) {
+        // Karis average of the 5 boxes keeps single bright pixels from flickering
+        vec3 boxes[5] = vec3[5](
+            (j + k + l + m) * 0.25,
+            (a + b + d + e) * 0.25,
+            (b + c + e + f) * 0.25,
+            (d + e + g + h) * 0.25,
+            (e + f + h + i) * 0.25
+        );
+        float boxWeights[5] = float[5](0.5, 0.125, 0.125, 0.125, 0.125);
+
+        color = vec3(0.0);
+        float totalWeight = 0.0;
+        for (int box = 0; box < 5; box++) {
+            float weight = boxWeights[box] * KarisWeight(boxes[box]);
+            color += boxes[box] * weight;
+            totalWeight += weight;
+        }
+        color /= totalWeight;
+
+        color = Threshold(color);
+    } else {
+        color = e * 0.125;
+        color += (a + c + g + i) * 0.03125;
+        color += (b + d + f + h) * 0.0625;
+        color += (j + k + l + m) * 0.125;
+    }
+
+    outColor = vec4(color, 1.0);
+}
+
+vec3 Sample(vec2 offset)
+{
+    return max(texture(samplerSource, inUV + offset * push.sourceTexelSize).rgb, vec3(0.0));
+}
+
+float KarisWeight(vec3 color)
+{
+    float luma = dot(color, vec3(0.2126, 0.7152, 0.0722));
+    return 1.0 / (1.0 + luma);
+}
+
+// Quadratic soft knee around the threshold, a threshold of 0 keeps everything
+vec3 Threshold(vec3 color)
+{
+    float brightness = max(color.r, max(color.g, color.b));
+    float softness = push.threshold * push.knee;
+
+    float soft = clamp(brightness - push.threshold + softness, 0.0, 2.0 * softness);
+    soft = soft * soft / (4.0 * softness + 1e-5);
+
+    float contribution = max(soft, brightness - push.threshold) / max(brightness, 1e-5);
+
+    return color * contribution;
+}
@@ -0,0 +1,37 @@
+#version 450
+
+layout (set = 0, binding = 0) uniform sampler2D samplerSource;
+
+layout (push_constant) uniform Push {
+    vec2 sourceTexelSize;
+    float threshold;
+    float knee;
+    uint prefilter;
+} push;
+
+layout (location = 0) in vec2 inUV;
+
+layout (location = 0) out vec4 outColor;
+
+vec3 Sample(vec2 offset)
+{
+    return texture(samplerSource, inUV + offset * push.sourceTexelSize).rgb;
+}
+
+// 3x3 tent filter, additively blended into the larger mip
+void main()
+{
+    vec3 color = Sample(vec2(0.0, 0.0)) * 4.0;
+
+    color += (Sample(vec2(0.0, 1.0))
+        + Sample(vec2(-1.0, 0.0))
+        + Sample(vec2(1.0, 0.0))
+        + Sample(vec2(0.0, -1.0))) * 2.0;
+
+    color += Sample(vec2(-1.0, 1.0))
+        + Sample(vec2(1.0, 1.0))
+        + Sample(vec2(-1.0, -1.0))
+        + Sample(vec2(1.0, -1.0));
+
+    outColor = vec4(color / 16.0, 1.0);
+}
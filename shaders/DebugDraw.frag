@@ -0,0 +1,11 @@
+#version 450
+
+// Linear HDR, exposed and tonemapped with the scene
+layout (location = 0) in vec4 inColor;
+
+layout (location = 0) out vec4 outColor;
+
+void main()
+{
+    outColor = inColor;
+}
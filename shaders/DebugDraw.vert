@@ -0,0 +1,17 @@
+#version 450
+
+layout (push_constant) uniform Push {
+    mat4 viewProjection;
+} push;
+
+// World space
+layout (location = 0) in vec3 inPosition;
+layout (location = 1) in vec4 inColor;
+
+layout (location = 0) out vec4 outColor;
+
+void main()
+{
+    outColor = inColor;
+    gl_Position = push.viewProjection * vec4(inPosition, 1.0);
+}
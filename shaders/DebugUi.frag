@@ -0,0 +1,72 @@
+#version 450
+
+// Matches DisplayEncoding::get_shader_index
+#define DISPLAY_LINEAR 0u
+#define DISPLAY_SRGB 1u
+#define DISPLAY_PQ 2u
+#define DISPLAY_SCRGB 3u
+
+// Same as shaders/Tonemapping.frag, so the UI is as bright as the scene's white
+const float PAPER_WHITE_NITS = 200.0;
+
+layout (set = 0, binding = 0) uniform sampler2D samplerTexture;
+
+layout (push_constant) uniform Push {
+    vec2 screenSize;
+    uint displayEncoding;
+} push;
+
+layout (location = 0) in vec2 inUV;
+// Premultiplied sRGB
+layout (location = 1) in vec4 inColor;
+
+layout (location = 0) out vec4 outColor;
+
+vec3 SrgbToLinear(vec3 color)
+{
+    vec3 low = color / 12.92;
+    vec3 high = pow((color + 0.055) / 1.055, vec3(2.4));
+
+    return mix(high, low, lessThanEqual(color, vec3(0.04045)));
+}
+
+const mat3 BT709_TO_BT2020 = mat3(
+    0.627404, 0.069097, 0.016391,
+    0.329283, 0.919541, 0.088013,
+    0.043313, 0.011362, 0.895595
+);
+
+vec3 LinearToPq(vec3 color)
+{
+    const float m1 = 0.1593017578125;
+    const float m2 = 78.84375;
+    const float c1 = 0.8359375;
+    const float c2 = 18.8515625;
+    const float c3 = 18.6875;
+
+    vec3 y = pow(max(color, vec3(0.0)), vec3(m1));
+
+    return pow((c1 + c2 * y) / (1.0 + c3 * y), vec3(m2));
+}
+
+void main()
+{
+    // egui blends in gamma space, textures are stored as UNORM so they aren't decoded either
+    vec4 color = inColor * texture(samplerTexture, inUV);
+
+    switch (push.displayEncoding) {
+        // *_SRGB swapchain formats encode on write, blending is then done in linear space
+        case DISPLAY_LINEAR:
+            color.rgb = SrgbToLinear(color.rgb);
+            break;
+        case DISPLAY_PQ:
+            color.rgb = LinearToPq(
+                BT709_TO_BT2020 * SrgbToLinear(color.rgb) * (PAPER_WHITE_NITS / 10000.0));
+            break;
+        case DISPLAY_SCRGB:
+            color.rgb = SrgbToLinear(color.rgb) * (PAPER_WHITE_NITS / 80.0);
+            break;
+    }
+
+    outColor = color;
+}
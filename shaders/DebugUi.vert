@@ -0,0 +1,21 @@
+#version 450
+
+layout (push_constant) uniform Push {
+    // In points, egui vertices are positioned in points rather than pixels
+    vec2 screenSize;
+    uint displayEncoding;
+} push;
+
+layout (location = 0) in vec2 inPosition;
+layout (location = 1) in vec2 inUV;
+layout (location = 2) in vec4 inColor;
+
+layout (location = 0) out vec2 outUV;
+layout (location = 1) out vec4 outColor;
+
+void main()
+{
+    outUV = inUV;
+    outColor = inColor;
+    gl_Position = vec4(inPosition / push.screenSize * 2.0 - 1.0, 0.0, 1.0);
+}
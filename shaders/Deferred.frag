@@ -1,14 +1,27 @@
 #version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "GBuffer.glsl"
 
 layout (location = 0) in vec3 inNormal;
-layout (location = 1) in vec3 inPosition;
+layout (location = 1) in vec4 inCurrentClip;
+layout (location = 2) in vec4 inPreviousClip;
+layout (location = 3) flat in vec4 inBaseColor;
 
-layout (location = 0) out vec4 outColor;
-layout (location = 1) out vec4 outNormal;
-layout (location = 2) out vec4 outPosition;
+layout (location = GBUFFER_ALBEDO) out vec4 outAlbedo;
+layout (location = GBUFFER_NORMAL) out vec2 outNormal;
+layout (location = GBUFFER_MATERIAL) out vec2 outMaterial;
+layout (location = GBUFFER_VELOCITY) out vec2 outVelocity;
 
+// Opaque only, alpha tested objects use DeferredMasked.frag so this pipeline keeps early depth
+// testing
 void main() {
-    outColor = vec4(1.0, 0.0, 0.0, 1.0);
-    outNormal = vec4(inNormal, 0.1); // metallic
-    outPosition = vec4(inPosition, 0.3); // roughness
-}
\ No newline at end of file
+    outAlbedo = vec4(inBaseColor.rgb, 1.0);
+    outNormal = PackNormal(normalize(inNormal));
+    outMaterial = vec2(0.1, 0.3); // metallic, roughness
+
+    // NDC to UV, current minus previous
+    vec2 current = inCurrentClip.xy / inCurrentClip.w;
+    vec2 previous = inPreviousClip.xy / inPreviousClip.w;
+    outVelocity = PackVelocity((current - previous) * 0.5);
+}
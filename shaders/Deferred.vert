@@ -1,7 +1,13 @@
 #version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "ObjectData.glsl"
+
+layout (std430, set = 0, binding = 0) readonly buffer Objects {
+    ObjectData objects[];
+};
 
 layout (push_constant) uniform Push {
-    mat4 model;
     mat4 view;
     mat4 projection;
 } push;
@@ -10,14 +16,26 @@ layout (location = 0) in vec3 inPosition;
 layout (location = 1) in vec3 inNormal;
 
 layout (location = 0) out vec3 outNormal;
-layout (location = 1) out vec3 outPosition;
+layout (location = 1) out vec4 outCurrentClip;
+layout (location = 2) out vec4 outPreviousClip;
+layout (location = 3) flat out vec4 outBaseColor;
+layout (location = 4) flat out float outAlphaCutoff;
+layout (location = 5) out vec3 outObjectNormal;
 
 void main() {
-    mat3 normalMatrix = transpose(inverse(mat3(push.model)));
+    mat4 model = objects[gl_InstanceIndex].model;
+
+    mat3 normalMatrix = transpose(inverse(mat3(model)));
     outNormal = normalMatrix * inNormal;
 
-    vec4 position = push.model * vec4(inPosition, 1.0);
-    outPosition = position.xyz;
+    vec4 position = model * vec4(inPosition, 1.0);
+
+    outCurrentClip = objects[gl_InstanceIndex].modelViewProjection * vec4(inPosition, 1.0);
+    outPreviousClip = objects[gl_InstanceIndex].previousModelViewProjection * vec4(inPosition, 1.0);
+
+    outBaseColor = objects[gl_InstanceIndex].baseColor;
+    outAlphaCutoff = objects[gl_InstanceIndex].alphaCutoff;
+    outObjectNormal = inNormal;
 
     gl_Position = push.projection * push.view * position;
 }
\ No newline at end of file
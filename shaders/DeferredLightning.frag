@@ -1,149 +1,114 @@
 #version 450
+#extension GL_GOOGLE_include_directive : require
 
-layout (set = 0, binding = 0) uniform sampler2D samplerColor;
+layout (set = 0, binding = 0) uniform sampler2D samplerAlbedo;
 layout (set = 0, binding = 1) uniform sampler2D samplerNormal;
-layout (set = 0, binding = 2) uniform sampler2D samplerPosition;
-layout (set = 0, binding = 3) uniform sampler2D samplerShadowMap;
+layout (set = 0, binding = 2) uniform sampler2D samplerMaterial;
+layout (set = 0, binding = 3) uniform sampler2D samplerDepth;
+layout (set = 0, binding = 4) uniform sampler2D samplerShadowMap;
+layout (set = 0, binding = 5) uniform sampler2D samplerAmbientOcclusion;
+layout (set = 0, binding = 6) uniform samplerCube samplerIrradiance;
+layout (set = 0, binding = 7) uniform samplerCube samplerPrefiltered;
+layout (set = 0, binding = 8) uniform sampler2D samplerBrdfLut;
 
 layout (push_constant) uniform Push {
     mat4 lightSpace;
-    vec3 view;
+    mat4 inverseViewProjection;
+    vec3 cameraPosition;
+    float environmentIntensity;
+    float farDepth;
+    uint debugView;
 } push;
 
+// Matches render_settings::DebugView
+#define DEBUG_VIEW_NONE 0u
+#define DEBUG_VIEW_ALBEDO 1u
+#define DEBUG_VIEW_NORMALS 2u
+#define DEBUG_VIEW_WORLD_POSITION 3u
+#define DEBUG_VIEW_METALLIC 4u
+#define DEBUG_VIEW_ROUGHNESS 5u
+#define DEBUG_VIEW_DEPTH 6u
+#define DEBUG_VIEW_SHADOW_FACTOR 7u
+#define DEBUG_VIEW_SHADOW_MAP 8u
+#define DEBUG_VIEW_CASCADE_INDEX 9u
+
 layout (location = 0) in vec2 inPos;
 
 layout (location = 0) out vec4 outColor;
 
-const float PI = 3.14159265359;
-
-vec3 Specular(float N, float G, vec3 F, float NoV, float NoL);
+#include "GBuffer.glsl"
+#include "Lighting.glsl"
 
-float NormalDistribution(vec3 N, vec3 VhL, float roughness);
-float GeometricShadowing(vec3 N, vec3 V, vec3 L, float roughness);
-float SchlickGGX(vec3 N, vec3 R, float roughness);
-vec3 Fresnel(vec3 F0, vec3 V, vec3 VhL);
-
-float Shadow(vec4 worldPosition);
+vec3 DebugView(vec3 albedo, vec3 normal, vec3 position, float metallic, float roughness);
 
 void main()
 {
-    vec4 sampledColor = texture(samplerColor, inPos);
-    vec4 sampledNormal = texture(samplerNormal, inPos);
-    vec4 sampledPosition = texture(samplerPosition, inPos);
-    vec4 sampledShadowMap = texture(samplerShadowMap, inPos);
-
-    vec3 normal = normalize(sampledNormal.xyz);
+    // Covers the whole screen, also where nothing was drawn
+    if (push.debugView == DEBUG_VIEW_SHADOW_MAP) {
+        outColor = vec4(vec3(texture(samplerShadowMap, inPos).r), 1.0);
+        return;
+    }
 
-    float metallic = sampledNormal.w;
-    float roughness = sampledPosition.w;
+    float depth = FetchDepth(samplerDepth, inPos);
 
-    vec3 light = normalize(vec3(0.0, 0.0, -1.0));
+    // Nothing was drawn here
+    if (depth == push.farDepth) {
+        outColor = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
 
-    float NoV = dot(normal, push.view);
-    float NoL = dot(normal, light);
+    vec3 albedo = texture(samplerAlbedo, inPos).rgb;
+    vec3 normal = UnpackNormal(texture(samplerNormal, inPos).xy);
+    vec2 material = texture(samplerMaterial, inPos).xy;
+    vec3 position = ReconstructPosition(inPos, depth, push.inverseViewProjection);
 
-    vec3 VhL = normalize(push.view + light);
+    vec3 view = normalize(push.cameraPosition - position);
 
-    vec3 F0 = mix(vec3(0.04), sampledColor.xyz, metallic);
+    float metallic = material.x;
+    float roughness = material.y;
 
-    float normalDistribution = NormalDistribution(normal, VhL, roughness);
-    float geometricShadowing = GeometricShadowing(normal, push.view, light, roughness);
-    vec3 fresnel = Fresnel(F0, push.view, VhL);
+    if (push.debugView != DEBUG_VIEW_NONE) {
+        outColor = vec4(DebugView(albedo, normal, position, metallic, roughness), 1.0);
+        return;
+    }
 
-    vec3 specular = Specular(normalDistribution, geometricShadowing, fresnel, NoV, NoL);
+    float ambientOcclusion = texture(samplerAmbientOcclusion, inPos).r;
 
-    vec3 kD = mix(vec3(1.0) - fresnel, vec3(0.0), metallic);
-
-    vec3 diffuse = sampledColor.xyz / PI;
-
-    float shadow = Shadow(sampledPosition);
-    vec3 finalColor = (kD * diffuse + specular) * NoL * (1.0 - shadow);
-
-    finalColor = finalColor / (finalColor + vec3(1.0));
-    finalColor = pow(finalColor, vec3(1.0/2.2));
+    vec3 finalColor = Shade(albedo, normal, view, position, metallic, roughness,
+                            ambientOcclusion, push.environmentIntensity);
 
     outColor = vec4(finalColor, 1.0);
-
-    //outColor = vec4(shadow, shadow, shadow, 1.0);
-    //outColor = sampledShadowMap;
 }
 
-vec3 Specular(float N, float G, vec3 F, float NoV, float NoL)
+vec3 DebugView(vec3 albedo, vec3 normal, vec3 position, float metallic, float roughness)
 {
-    vec3 numerator = N * G * F;
-    float denominator = 4.0 * NoV * NoL;
-
-    return numerator / denominator;
+    switch (push.debugView) {
+        case DEBUG_VIEW_ALBEDO:
+            return albedo;
+        case DEBUG_VIEW_NORMALS:
+            return normal * 0.5 + 0.5;
+        case DEBUG_VIEW_WORLD_POSITION:
+            // Repeats every unit, shows both the orientation and the scale
+            return fract(position);
+        case DEBUG_VIEW_METALLIC:
+            return vec3(metallic);
+        case DEBUG_VIEW_ROUGHNESS:
+            return vec3(roughness);
+        case DEBUG_VIEW_DEPTH: {
+            // Distance to the camera compressed into [0, 1), the same in every projection mode
+            float distance = length(position - push.cameraPosition);
+            return vec3(distance / (distance + 1.0));
+        }
+        case DEBUG_VIEW_SHADOW_FACTOR:
+            return vec3(1.0 - Shadow(vec4(position, 1.0)));
+        case DEBUG_VIEW_CASCADE_INDEX: {
+            // There is a single cascade, this marks the area covered by the shadow map
+            vec3 lightSpacePosition = (push.lightSpace * vec4(position, 1.0)).xyz;
+            bool covered = all(lessThanEqual(abs(lightSpacePosition.xy), vec2(1.0)))
+                && lightSpacePosition.z >= 0.0 && lightSpacePosition.z <= 1.0;
+            return covered ? vec3(1.0, 0.0, 0.0) : vec3(0.0);
+        }
+    }
+
+    return vec3(0.0);
 }
-
-// GGX (Trowbridge-Reitz)
-// N = Normal
-// VhL = Half (View, Light)
-float NormalDistribution(vec3 N, vec3 VhL, float roughness)
-{
-    float a = roughness * roughness;
-    float a2 = a * a;
-    float NoH = max(dot(N, VhL), 0.0);
-    float NoH2 = NoH * NoH;
-
-    float denominator = a2 - 1.0;
-    denominator = NoH2 * denominator + 1.0;
-    denominator = denominator * denominator;
-    denominator = PI * denominator;
-
-    return a2 / denominator;
-}
-
-
-// Smith
-// L = Light
-// V = View
-// VhL = Half (View, Light)
-float GeometricShadowing(vec3 N, vec3 V, vec3 L, float roughness)
-{
-    float g1 = SchlickGGX(N, V, roughness);
-    float g2 = SchlickGGX(N, L, roughness);
-
-    return g1 * g2;
-}
-
-// N = Normal
-// R = View/Light
-float SchlickGGX(vec3 N, vec3 R, float roughness)
-{
-    float remappedRoughness = roughness + 1.0;
-    float k = (remappedRoughness * remappedRoughness) / 8.0;
-
-    float NoR = max(dot(N, R), 0.0);
-
-    float denominator = NoR * (1.0 - k) + k;
-
-    return NoR / denominator;
-}
-
-// Schlick
-// F0 = Normal Incidence
-// V = View
-// VhL = Half (View, Light)
-vec3 Fresnel(vec3 F0, vec3 V, vec3 VhL)
-{
-    float VoH = dot(V, VhL);
-    return F0 + (1.0 - F0) * pow(1.0 - VoH, 5.0);
-}
-
-float Shadow(vec4 worldPosition) {
-    //return 0.0;
-    // roughness is stored in worldPosition.w
-    vec4 lightSpacePosition2 = push.lightSpace * vec4(worldPosition.rgb, 1.0);
-    vec3 lightSpacePosition = lightSpacePosition2.rgb/* / lightSpacePosition2.w*/;
-    lightSpacePosition = lightSpacePosition * 0.5 + 0.5;
-    float closestDepth = texture(samplerShadowMap, lightSpacePosition.xy).r;
-    float currentDepth = lightSpacePosition2.z;
-
-    // TODO: dot bias
-    float bias = 0.005;
-    float shadow = currentDepth - bias > closestDepth ? 1.0 : 0.0;
-
-    //return closestDepth;
-    return shadow;
-}
\ No newline at end of file
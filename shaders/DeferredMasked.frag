@@ -0,0 +1,47 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "GBuffer.glsl"
+
+layout (set = 1, binding = 0) uniform sampler2D baseColorTexture;
+
+layout (location = 0) in vec3 inNormal;
+layout (location = 1) in vec4 inCurrentClip;
+layout (location = 2) in vec4 inPreviousClip;
+layout (location = 3) flat in vec4 inBaseColor;
+layout (location = 4) flat in float inAlphaCutoff;
+layout (location = 5) in vec3 inObjectNormal;
+
+layout (location = GBUFFER_ALBEDO) out vec4 outAlbedo;
+layout (location = GBUFFER_NORMAL) out vec2 outNormal;
+layout (location = GBUFFER_MATERIAL) out vec2 outMaterial;
+layout (location = GBUFFER_VELOCITY) out vec2 outVelocity;
+
+const float PI = 3.14159265359;
+
+// The meshes have no texture coordinates, the texture is wrapped around the object space normal
+vec2 SphericalUv(vec3 direction)
+{
+    direction = normalize(direction);
+    float u = atan(direction.z, direction.x) / (2.0 * PI) + 0.5;
+    float v = acos(clamp(direction.y, -1.0, 1.0)) / PI;
+    return vec2(u, v);
+}
+
+// Alpha tested variant of Deferred.frag, kept in its own pipeline since discard disables early
+// depth testing
+void main() {
+    vec4 baseColor = inBaseColor * texture(baseColorTexture, SphericalUv(inObjectNormal));
+    if (baseColor.a < inAlphaCutoff) {
+        discard;
+    }
+
+    outAlbedo = vec4(baseColor.rgb, 1.0);
+    outNormal = PackNormal(normalize(inNormal));
+    outMaterial = vec2(0.1, 0.3); // metallic, roughness
+
+    // NDC to UV, current minus previous
+    vec2 current = inCurrentClip.xy / inCurrentClip.w;
+    vec2 previous = inPreviousClip.xy / inPreviousClip.w;
+    outVelocity = PackVelocity((current - previous) * 0.5);
+}
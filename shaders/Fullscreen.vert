@@ -0,0 +1,11 @@
+#version 450
+
+layout (location = 0) out vec2 outUV;
+
+// Single triangle covering the screen, draw with 3 vertices
+// UVs go up to 2 outside of the screen so they are [0, 1] inside
+void main()
+{
+    outUV = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+    gl_Position = vec4(outUV * 2.0 - 1.0, 0.0, 1.0);
+}
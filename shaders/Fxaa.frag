@@ -0,0 +1,147 @@
+#version 450
+
+layout (set = 0, binding = 0) uniform sampler2D samplerColor;
+
+layout (push_constant) uniform Push {
+    vec2 texelSize;
+    uint linearInput;
+} push;
+
+layout (location = 0) in vec2 inUV;
+
+layout (location = 0) out vec4 outColor;
+
+// FXAA 3.11 quality preset 12
+const float SUBPIXEL_QUALITY = 0.75;
+const float EDGE_THRESHOLD = 0.166;
+const float EDGE_THRESHOLD_MIN = 0.0833;
+const int SEARCH_STEPS = 5;
+const float SEARCH_STEP_SIZES[SEARCH_STEPS] = float[](1.0, 1.5, 2.0, 4.0, 12.0);
+
+float Luma(vec3 color)
+{
+    float luma = dot(color, vec3(0.299, 0.587, 0.114));
+
+    // Edges are found in perceptual space, sRGB images are decoded to linear when sampled
+    return push.linearInput != 0u ? sqrt(luma) : luma;
+}
+
+float SampleLuma(vec2 uv)
+{
+    return Luma(textureLod(samplerColor, uv, 0.0).rgb);
+}
+
+float SampleLuma(vec2 uv, ivec2 offset)
+{
+    return Luma(textureLodOffset(samplerColor, uv, 0.0, offset).rgb);
+}
+
+void main()
+{
+    vec4 color = textureLod(samplerColor, inUV, 0.0);
+
+    float lumaM = Luma(color.rgb);
+    float lumaN = SampleLuma(inUV, ivec2(0, -1));
+    float lumaS = SampleLuma(inUV, ivec2(0, 1));
+    float lumaW = SampleLuma(inUV, ivec2(-1, 0));
+    float lumaE = SampleLuma(inUV, ivec2(1, 0));
+
+    float rangeMax = max(lumaM, max(max(lumaN, lumaS), max(lumaW, lumaE)));
+    float rangeMin = min(lumaM, min(min(lumaN, lumaS), min(lumaW, lumaE)));
+    float range = rangeMax - rangeMin;
+
+    // Not an edge, or too dark to notice
+    if (range < max(EDGE_THRESHOLD_MIN, rangeMax * EDGE_THRESHOLD)) {
+        outColor = color;
+        return;
+    }
+
+    float lumaNW = SampleLuma(inUV, ivec2(-1, -1));
+    float lumaNE = SampleLuma(inUV, ivec2(1, -1));
+    float lumaSW = SampleLuma(inUV, ivec2(-1, 1));
+    float lumaSE = SampleLuma(inUV, ivec2(1, 1));
+
+    float lumaNS = lumaN + lumaS;
+    float lumaWE = lumaW + lumaE;
+    float lumaNWSW = lumaNW + lumaSW;
+    float lumaNESE = lumaNE + lumaSE;
+    float lumaNWNE = lumaNW + lumaNE;
+    float lumaSWSE = lumaSW + lumaSE;
+
+    // Single pixel features get blended with their neighborhood
+    float subpixel = clamp(abs((2.0 * (lumaNS + lumaWE) + lumaNWSW + lumaNESE) / 12.0 - lumaM) / range, 0.0, 1.0);
+    subpixel = (-2.0 * subpixel + 3.0) * subpixel * subpixel;
+    subpixel = subpixel * subpixel * SUBPIXEL_QUALITY;
+
+    float edgeHorizontal = abs(lumaNWSW - 2.0 * lumaW) + 2.0 * abs(lumaNS - 2.0 * lumaM) + abs(lumaNESE - 2.0 * lumaE);
+    float edgeVertical = abs(lumaNWNE - 2.0 * lumaN) + 2.0 * abs(lumaWE - 2.0 * lumaM) + abs(lumaSWSE - 2.0 * lumaS);
+    bool horizontal = edgeHorizontal >= edgeVertical;
+
+    // Step across the edge, towards the neighbor with the steepest gradient
+    float stepLength = horizontal ? push.texelSize.y : push.texelSize.x;
+    float luma1 = horizontal ? lumaN : lumaW;
+    float luma2 = horizontal ? lumaS : lumaE;
+    float gradient1 = abs(luma1 - lumaM);
+    float gradient2 = abs(luma2 - lumaM);
+    float gradientScaled = 0.25 * max(gradient1, gradient2);
+
+    float lumaLocalAverage;
+    if (gradient1 >= gradient2) {
+        stepLength = -stepLength;
+        lumaLocalAverage = 0.5 * (luma1 + lumaM);
+    } else {
+        lumaLocalAverage = 0.5 * (luma2 + lumaM);
+    }
+
+    vec2 edgeUV = inUV;
+    vec2 searchOffset;
+    if (horizontal) {
+        edgeUV.y += 0.5 * stepLength;
+        searchOffset = vec2(push.texelSize.x, 0.0);
+    } else {
+        edgeUV.x += 0.5 * stepLength;
+        searchOffset = vec2(0.0, push.texelSize.y);
+    }
+
+    // Walk along the edge in both directions until the luma no longer matches it
+    vec2 uv1 = edgeUV - searchOffset * SEARCH_STEP_SIZES[0];
+    vec2 uv2 = edgeUV + searchOffset * SEARCH_STEP_SIZES[0];
+    float lumaEnd1 = SampleLuma(uv1) - lumaLocalAverage;
+    float lumaEnd2 = SampleLuma(uv2) - lumaLocalAverage;
+    bool reached1 = abs(lumaEnd1) >= gradientScaled;
+    bool reached2 = abs(lumaEnd2) >= gradientScaled;
+
+    for (int i = 1; i < SEARCH_STEPS && !(reached1 && reached2); i++) {
+        if (!reached1) {
+            uv1 -= searchOffset * SEARCH_STEP_SIZES[i];
+            lumaEnd1 = SampleLuma(uv1) - lumaLocalAverage;
+            reached1 = abs(lumaEnd1) >= gradientScaled;
+        }
+
+        if (!reached2) {
+            uv2 += searchOffset * SEARCH_STEP_SIZES[i];
+            lumaEnd2 = SampleLuma(uv2) - lumaLocalAverage;
+            reached2 = abs(lumaEnd2) >= gradientScaled;
+        }
+    }
+
+    float distance1 = horizontal ? inUV.x - uv1.x : inUV.y - uv1.y;
+    float distance2 = horizontal ? uv2.x - inUV.x : uv2.y - inUV.y;
+    bool closerToEnd1 = distance1 < distance2;
+    float pixelOffset = 0.5 - min(distance1, distance2) / (distance1 + distance2);
+
+    // Only blend when the closest end varies in the opposite direction of the center
+    bool centerSmaller = lumaM < lumaLocalAverage;
+    bool correctVariation = ((closerToEnd1 ? lumaEnd1 : lumaEnd2) < 0.0) != centerSmaller;
+
+    float finalOffset = max(correctVariation ? pixelOffset : 0.0, subpixel);
+
+    vec2 finalUV = inUV;
+    if (horizontal) {
+        finalUV.y += finalOffset * stepLength;
+    } else {
+        finalUV.x += finalOffset * stepLength;
+    }
+
+    outColor = textureLod(samplerColor, finalUV, 0.0);
+}
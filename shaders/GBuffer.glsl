@@ -0,0 +1,68 @@
+// Packing of the G-buffer targets, shared by the deferred pass and every pass sampling it.
+// Locations and formats come from GBufferLayout.glsl
+
+#include "GBufferLayout.glsl"
+
+vec2 OctahedronWrap(vec2 v)
+{
+    return (1.0 - abs(v.yx)) * vec2(v.x >= 0.0 ? 1.0 : -1.0, v.y >= 0.0 ? 1.0 : -1.0);
+}
+
+// Unit vector projected onto an octahedron and unfolded into [-1, 1]^2
+vec2 PackNormal(vec3 normal)
+{
+    normal /= abs(normal.x) + abs(normal.y) + abs(normal.z);
+    vec2 packed = normal.z >= 0.0 ? normal.xy : OctahedronWrap(normal.xy);
+
+#if GBUFFER_NORMAL_SIGNED
+    return packed;
+#else
+    return packed * 0.5 + 0.5;
+#endif
+}
+
+vec3 UnpackNormal(vec2 packed)
+{
+#if !GBUFFER_NORMAL_SIGNED
+    packed = packed * 2.0 - 1.0;
+#endif
+
+    vec3 normal = vec3(packed, 1.0 - abs(packed.x) - abs(packed.y));
+    float t = clamp(-normal.z, 0.0, 1.0);
+    normal.x += normal.x >= 0.0 ? -t : t;
+    normal.y += normal.y >= 0.0 ? -t : t;
+
+    return normalize(normal);
+}
+
+vec2 PackVelocity(vec2 velocity)
+{
+#if GBUFFER_VELOCITY_SIGNED
+    return velocity;
+#else
+    return velocity * 0.5 + 0.5;
+#endif
+}
+
+vec2 UnpackVelocity(vec2 packed)
+{
+#if GBUFFER_VELOCITY_SIGNED
+    return packed;
+#else
+    return packed * 2.0 - 1.0;
+#endif
+}
+
+// Depth formats aren't guaranteed to support linear filtering
+float FetchDepth(sampler2D samplerDepth, vec2 uv)
+{
+    ivec2 size = textureSize(samplerDepth, 0);
+    return texelFetch(samplerDepth, clamp(ivec2(uv * vec2(size)), ivec2(0), size - 1), 0).r;
+}
+
+// World position from the depth buffer, with the jittered view projection it was rendered with
+vec3 ReconstructPosition(vec2 uv, float depth, mat4 inverseViewProjection)
+{
+    vec4 position = inverseViewProjection * vec4(uv * 2.0 - 1.0, depth, 1.0);
+    return position.xyz / position.w;
+}
@@ -0,0 +1,19 @@
+// Mirrors src/gbuffer_layout.rs, checked by the test there
+
+// R8G8B8A8Unorm
+#define GBUFFER_ALBEDO 0
+#define GBUFFER_ALBEDO_SIGNED 0
+
+// R16G16Snorm
+#define GBUFFER_NORMAL 1
+#define GBUFFER_NORMAL_SIGNED 1
+
+// R8G8Unorm
+#define GBUFFER_MATERIAL 2
+#define GBUFFER_MATERIAL_SIGNED 0
+
+// R16G16Sfloat
+#define GBUFFER_VELOCITY 3
+#define GBUFFER_VELOCITY_SIGNED 1
+
+#define GBUFFER_TARGET_COUNT 4
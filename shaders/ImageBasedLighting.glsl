@@ -0,0 +1,65 @@
+// Shared by the image based lighting precomputation shaders
+
+const float PI = 3.14159265359;
+
+// Direction through the center of a cubemap texel, z is the face in the Vulkan order +X, -X, +Y, -Y, +Z, -Z
+vec3 CubeDirection(ivec3 texel, int size)
+{
+    vec2 uv = (vec2(texel.xy) + 0.5) / float(size) * 2.0 - 1.0;
+
+    switch (texel.z) {
+        case 0: return normalize(vec3(1.0, -uv.y, -uv.x));
+        case 1: return normalize(vec3(-1.0, -uv.y, uv.x));
+        case 2: return normalize(vec3(uv.x, 1.0, uv.y));
+        case 3: return normalize(vec3(uv.x, -1.0, -uv.y));
+        case 4: return normalize(vec3(uv.x, -uv.y, 1.0));
+        default: return normalize(vec3(-uv.x, -uv.y, -1.0));
+    }
+}
+
+vec2 Hammersley(uint index, uint count)
+{
+    float radicalInverse = float(bitfieldReverse(index)) * 2.3283064365386963e-10;
+
+    return vec2(float(index) / float(count), radicalInverse);
+}
+
+// Builds a basis around N and transforms the tangent space `direction` into it
+vec3 TangentToWorld(vec3 direction, vec3 N)
+{
+    vec3 up = abs(N.z) < 0.999 ? vec3(0.0, 0.0, 1.0) : vec3(1.0, 0.0, 0.0);
+    vec3 tangent = normalize(cross(up, N));
+    vec3 bitangent = cross(N, tangent);
+
+    return normalize(tangent * direction.x + bitangent * direction.y + N * direction.z);
+}
+
+// GGX distributed half vector around N, same roughness remapping as the lightning shader
+vec3 ImportanceSampleGGX(vec2 Xi, vec3 N, float roughness)
+{
+    float a = roughness * roughness;
+
+    float phi = 2.0 * PI * Xi.x;
+    float cosTheta = sqrt((1.0 - Xi.y) / (1.0 + (a * a - 1.0) * Xi.y));
+    float sinTheta = sqrt(1.0 - cosTheta * cosTheta);
+
+    return TangentToWorld(vec3(cos(phi) * sinTheta, sin(phi) * sinTheta, cosTheta), N);
+}
+
+float DistributionGGX(float NoH, float roughness)
+{
+    float a = roughness * roughness;
+    float a2 = a * a;
+    float denominator = NoH * NoH * (a2 - 1.0) + 1.0;
+
+    return a2 / (PI * denominator * denominator);
+}
+
+// Mip of a cubemap with `size` texels per side whose texel covers the solid angle of one sample
+float SampleLod(float pdf, uint sampleCount, int size)
+{
+    float texelSolidAngle = 4.0 * PI / (6.0 * float(size * size));
+    float sampleSolidAngle = 1.0 / (float(sampleCount) * pdf + 1e-4);
+
+    return max(0.5 * log2(sampleSolidAngle / texelSolidAngle) + 1.0, 0.0);
+}
@@ -0,0 +1,153 @@
+// Shared by the deferred lightning and the forward transparency shaders. The including shader
+// declares samplerShadowMap, samplerIrradiance, samplerPrefiltered, samplerBrdfLut and a push
+// block with lightSpace
+
+const float PI = 3.14159265359;
+
+vec3 Specular(float N, float G, vec3 F, float NoV, float NoL);
+
+float NormalDistribution(vec3 N, vec3 VhL, float roughness);
+float GeometricShadowing(vec3 N, vec3 V, vec3 L, float roughness);
+float SchlickGGX(vec3 N, vec3 R, float roughness);
+vec3 Fresnel(vec3 F0, vec3 V, vec3 VhL);
+vec3 FresnelRoughness(vec3 F0, float NoV, float roughness);
+
+vec3 ImageBasedLighting(vec3 albedo, vec3 F0, vec3 N, vec3 V, float metallic, float roughness);
+
+float Shadow(vec4 worldPosition);
+
+// Shadowed sun light plus the environment, which is scaled by the ambient occlusion
+vec3 Shade(vec3 albedo, vec3 normal, vec3 view, vec3 worldPosition, float metallic, float roughness,
+           float ambientOcclusion, float environmentIntensity)
+{
+    vec3 light = normalize(vec3(0.0, 0.0, -1.0));
+
+    float NoV = dot(normal, view);
+    float NoL = dot(normal, light);
+
+    vec3 VhL = normalize(view + light);
+
+    vec3 F0 = mix(vec3(0.04), albedo, metallic);
+
+    float normalDistribution = NormalDistribution(normal, VhL, roughness);
+    float geometricShadowing = GeometricShadowing(normal, view, light, roughness);
+    vec3 fresnel = Fresnel(F0, view, VhL);
+
+    vec3 specular = Specular(normalDistribution, geometricShadowing, fresnel, NoV, NoL);
+
+    vec3 kD = mix(vec3(1.0) - fresnel, vec3(0.0), metallic);
+
+    vec3 diffuse = albedo / PI;
+
+    float shadow = Shadow(vec4(worldPosition, 1.0));
+    vec3 color = (kD * diffuse + specular) * NoL * (1.0 - shadow);
+
+    vec3 ambient = ImageBasedLighting(albedo, F0, normal, view, metallic, roughness);
+    color += environmentIntensity * ambient * ambientOcclusion;
+
+    return color;
+}
+
+vec3 Specular(float N, float G, vec3 F, float NoV, float NoL)
+{
+    vec3 numerator = N * G * F;
+    float denominator = 4.0 * NoV * NoL;
+
+    return numerator / denominator;
+}
+
+// GGX (Trowbridge-Reitz)
+// N = Normal
+// VhL = Half (View, Light)
+float NormalDistribution(vec3 N, vec3 VhL, float roughness)
+{
+    float a = roughness * roughness;
+    float a2 = a * a;
+    float NoH = max(dot(N, VhL), 0.0);
+    float NoH2 = NoH * NoH;
+
+    float denominator = a2 - 1.0;
+    denominator = NoH2 * denominator + 1.0;
+    denominator = denominator * denominator;
+    denominator = PI * denominator;
+
+    return a2 / denominator;
+}
+
+
+// Smith
+// L = Light
+// V = View
+// VhL = Half (View, Light)
+float GeometricShadowing(vec3 N, vec3 V, vec3 L, float roughness)
+{
+    float g1 = SchlickGGX(N, V, roughness);
+    float g2 = SchlickGGX(N, L, roughness);
+
+    return g1 * g2;
+}
+
+// N = Normal
+// R = View/Light
+float SchlickGGX(vec3 N, vec3 R, float roughness)
+{
+    float remappedRoughness = roughness + 1.0;
+    float k = (remappedRoughness * remappedRoughness) / 8.0;
+
+    float NoR = max(dot(N, R), 0.0);
+
+    float denominator = NoR * (1.0 - k) + k;
+
+    return NoR / denominator;
+}
+
+// Schlick
+// F0 = Normal Incidence
+// V = View
+// VhL = Half (View, Light)
+vec3 Fresnel(vec3 F0, vec3 V, vec3 VhL)
+{
+    float VoH = dot(V, VhL);
+    return F0 + (1.0 - F0) * pow(1.0 - VoH, 5.0);
+}
+
+// Schlick with the reflectance at grazing angles limited by the roughness
+vec3 FresnelRoughness(vec3 F0, float NoV, float roughness)
+{
+    return F0 + (max(vec3(1.0 - roughness), F0) - F0) * pow(1.0 - NoV, 5.0);
+}
+
+// Split sum: irradiance for diffuse, prefiltered environment scaled by the BRDF LUT for specular
+vec3 ImageBasedLighting(vec3 albedo, vec3 F0, vec3 N, vec3 V, float metallic, float roughness)
+{
+    float NoV = max(dot(N, V), 0.0);
+    vec3 R = reflect(-V, N);
+
+    vec3 F = FresnelRoughness(F0, NoV, roughness);
+    vec3 kD = (vec3(1.0) - F) * (1.0 - metallic);
+
+    vec3 irradiance = texture(samplerIrradiance, N).rgb;
+    vec3 diffuse = kD * irradiance * albedo;
+
+    float maxLod = float(textureQueryLevels(samplerPrefiltered) - 1);
+    vec3 prefiltered = textureLod(samplerPrefiltered, R, roughness * maxLod).rgb;
+    vec2 brdf = texture(samplerBrdfLut, vec2(NoV, roughness)).rg;
+    vec3 specular = prefiltered * (F0 * brdf.x + brdf.y);
+
+    return diffuse + specular;
+}
+
+float Shadow(vec4 worldPosition) {
+    // roughness is stored in worldPosition.w
+    vec4 lightSpacePosition2 = push.lightSpace * vec4(worldPosition.rgb, 1.0);
+    vec3 lightSpacePosition = lightSpacePosition2.rgb/* / lightSpacePosition2.w*/;
+    lightSpacePosition = lightSpacePosition * 0.5 + 0.5;
+    float closestDepth = texture(samplerShadowMap, lightSpacePosition.xy).r;
+    float currentDepth = lightSpacePosition2.z;
+
+    // TODO: dot bias
+    float bias = 0.005;
+    float shadow = currentDepth - bias > closestDepth ? 1.0 : 0.0;
+
+    return shadow;
+}
\ No newline at end of file
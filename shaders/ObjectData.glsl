@@ -0,0 +1,21 @@
+// Matches ObjectData in src/draw_data.rs
+
+const uint OBJECT_FLAG_CAST_SHADOWS = 1;
+const uint OBJECT_FLAG_TRANSPARENT = 2;
+const uint OBJECT_FLAG_ALPHA_TESTED = 4;
+
+struct ObjectData {
+    mat4 model;
+    // Unjittered, for motion vectors
+    mat4 modelViewProjection;
+    mat4 previousModelViewProjection;
+    vec4 boundingSphere; // World space center and radius
+    uint firstIndex;
+    uint indexCount;
+    int vertexOffset;
+    uint flags;
+    // Linear albedo and opacity
+    vec4 baseColor;
+    // Alpha tested materials discard below it, 0 for everything else
+    float alphaCutoff;
+};
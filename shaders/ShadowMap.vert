@@ -1,7 +1,13 @@
 #version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "ObjectData.glsl"
+
+layout (std430, set = 0, binding = 0) readonly buffer Objects {
+    ObjectData objects[];
+};
 
 layout (push_constant) uniform Push {
-    mat4 model;
     mat4 view;
     mat4 projection;
 } push;
@@ -10,5 +16,7 @@ layout (location = 0) in vec3 inPosition;
 
 void main()
 {
-    gl_Position = (push.projection * push.view) * push.model * vec4(inPosition, 1.0);
+    mat4 model = objects[gl_InstanceIndex].model;
+
+    gl_Position = (push.projection * push.view) * model * vec4(inPosition, 1.0);
 }
\ No newline at end of file
@@ -0,0 +1,31 @@
+#version 450
+
+layout (set = 0, binding = 0) uniform samplerCube samplerEnvironment;
+
+layout (push_constant) uniform Push {
+    mat4 inverseViewProjection;
+    float farDepth;
+    float intensity;
+} push;
+
+layout (location = 0) in vec2 inNdc;
+
+layout (location = 0) out vec4 outColor;
+
+vec3 Unproject(float depth)
+{
+    vec4 position = push.inverseViewProjection * vec4(inNdc, depth, 1.0);
+    return position.xyz / position.w;
+}
+
+void main()
+{
+    // Ray from the near plane through a point halfway in depth, the far plane can be at
+    // infinity with reverse-Z. Orthographic rays are parallel, so the sky is a single direction
+    float nearDepth = 1.0 - push.farDepth;
+    vec3 direction = normalize(Unproject(0.5) - Unproject(nearDepth));
+
+    vec3 radiance = textureLod(samplerEnvironment, direction, 0.0).rgb;
+
+    outColor = vec4(push.intensity * radiance, 1.0);
+}
@@ -0,0 +1,17 @@
+#version 450
+
+layout (push_constant) uniform Push {
+    mat4 inverseViewProjection;
+    float farDepth;
+    float intensity;
+} push;
+
+layout (location = 0) out vec2 outNdc;
+
+// Fullscreen triangle like Fullscreen.vert, placed on the far plane
+void main()
+{
+    vec2 uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+    outNdc = uv * 2.0 - 1.0;
+    gl_Position = vec4(outNdc, push.farDepth, 1.0);
+}
@@ -0,0 +1,117 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "GBuffer.glsl"
+
+layout (set = 0, binding = 0) uniform sampler2D samplerNormal;
+layout (set = 0, binding = 1) uniform sampler2D samplerDepth;
+layout (set = 0, binding = 2) uniform sampler2D samplerNoise;
+
+layout (push_constant) uniform Push {
+    mat4 viewProjection;
+    mat4 inverseViewProjection;
+    vec3 cameraPosition;
+    float radius;
+    vec3 cameraForward;
+    float bias;
+    float power;
+    float farDepth;
+} push;
+
+layout (location = 0) in vec2 inUV;
+
+layout (location = 0) out float outOcclusion;
+
+const float PI = 3.14159265359;
+const int KERNEL_SIZE = 16;
+// Matches ssao_renderpass_consts::NOISE_SIZE
+const int NOISE_SIZE = 4;
+
+vec3 KernelSample(int index);
+float ViewDepth(vec3 position);
+
+void main()
+{
+    float sampledDepth = FetchDepth(samplerDepth, inUV);
+
+    // Nothing was drawn here
+    if (sampledDepth == push.farDepth) {
+        outOcclusion = 1.0;
+        return;
+    }
+
+    vec3 normal = UnpackNormal(texture(samplerNormal, inUV).xy);
+    vec3 position = ReconstructPosition(inUV, sampledDepth, push.inverseViewProjection);
+    float depth = ViewDepth(position);
+
+    // Random rotation around the normal, repeats every NOISE_SIZE pixels
+    vec3 random = vec3(texelFetch(samplerNoise, ivec2(gl_FragCoord.xy) % NOISE_SIZE, 0).xy, 0.0);
+    vec3 tangent = random - normal * dot(random, normal);
+    if (dot(tangent, tangent) < 1e-4) {
+        tangent = abs(normal.x) < 0.9 ? vec3(1.0, 0.0, 0.0) : vec3(0.0, 1.0, 0.0);
+        tangent -= normal * dot(tangent, normal);
+    }
+    tangent = normalize(tangent);
+    vec3 bitangent = cross(normal, tangent);
+    mat3 tbn = mat3(tangent, bitangent, normal);
+
+    float occlusion = 0.0;
+
+    for (int i = 0; i < KERNEL_SIZE; i++) {
+        vec3 samplePosition = position + tbn * KernelSample(i) * push.radius;
+
+        vec4 clip = push.viewProjection * vec4(samplePosition, 1.0);
+        vec2 sampleUV = clip.xy / clip.w * 0.5 + 0.5;
+
+        if (any(lessThan(sampleUV, vec2(0.0))) || any(greaterThan(sampleUV, vec2(1.0)))) {
+            continue;
+        }
+
+        float sceneSampledDepth = FetchDepth(samplerDepth, sampleUV);
+        if (sceneSampledDepth == push.farDepth) {
+            continue;
+        }
+
+        float sceneDepth =
+            ViewDepth(ReconstructPosition(sampleUV, sceneSampledDepth, push.inverseViewProjection));
+        float sampleDepth = ViewDepth(samplePosition);
+
+        // Fades out occluders far outside of the radius
+        float rangeCheck = smoothstep(0.0, 1.0, push.radius / max(abs(depth - sceneDepth), 1e-4));
+        occlusion += (sceneDepth <= sampleDepth - push.bias ? 1.0 : 0.0) * rangeCheck;
+    }
+
+    occlusion = 1.0 - occlusion / float(KERNEL_SIZE);
+
+    outOcclusion = pow(occlusion, push.power);
+}
+
+float RadicalInverse(uint bits)
+{
+    return float(bitfieldReverse(bits)) * 2.3283064365386963e-10;
+}
+
+// Hammersley points on a cosine weighted hemisphere around +Z,
+// pulled towards the origin so close occluders get more samples
+vec3 KernelSample(int index)
+{
+    float u = (float(index) + 0.5) / float(KERNEL_SIZE);
+    float v = RadicalInverse(uint(index));
+
+    float phi = 2.0 * PI * v;
+    float cosTheta = sqrt(1.0 - u);
+    float sinTheta = sqrt(u);
+
+    vec3 direction = vec3(cos(phi) * sinTheta, sin(phi) * sinTheta, cosTheta);
+
+    float scale = float(index) / float(KERNEL_SIZE);
+    scale = mix(0.1, 1.0, scale * scale);
+
+    return direction * scale;
+}
+
+// Distance along the camera forward, the same for every projection mode
+float ViewDepth(vec3 position)
+{
+    return dot(position - push.cameraPosition, push.cameraForward);
+}
@@ -0,0 +1,66 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "GBuffer.glsl"
+
+layout (set = 0, binding = 0) uniform sampler2D samplerOcclusion;
+layout (set = 0, binding = 1) uniform sampler2D samplerDepth;
+
+layout (push_constant) uniform Push {
+    mat4 inverseViewProjection;
+    vec3 cameraPosition;
+    float sharpness;
+    vec3 cameraForward;
+    float farDepth;
+} push;
+
+layout (location = 0) in vec2 inUV;
+
+layout (location = 0) out float outOcclusion;
+
+// Covers one tile of the SSAO noise, which removes its pattern
+const int BLUR_SIZE = 4;
+
+float ViewDepth(vec2 uv, float sampledDepth)
+{
+    vec3 position = ReconstructPosition(uv, sampledDepth, push.inverseViewProjection);
+    return dot(position - push.cameraPosition, push.cameraForward);
+}
+
+// Bilateral box blur, samples at a different depth than the center get less weight
+void main()
+{
+    vec2 texelSize = 1.0 / vec2(textureSize(samplerOcclusion, 0));
+    float sampledDepth = FetchDepth(samplerDepth, inUV);
+
+    // Nothing was drawn here, the far plane may not reconstruct to a finite position
+    if (sampledDepth == push.farDepth) {
+        outOcclusion = 1.0;
+        return;
+    }
+
+    float depth = ViewDepth(inUV, sampledDepth);
+
+    float occlusion = 0.0;
+    float totalWeight = 0.0;
+
+    for (int x = -BLUR_SIZE / 2; x < BLUR_SIZE / 2; x++) {
+        for (int y = -BLUR_SIZE / 2; y < BLUR_SIZE / 2; y++) {
+            vec2 sampleUV = inUV + (vec2(x, y) + 0.5) * texelSize;
+
+            float sampleSampledDepth = FetchDepth(samplerDepth, sampleUV);
+            if (sampleSampledDepth == push.farDepth) {
+                continue;
+            }
+
+            float sampleDepth = ViewDepth(sampleUV, sampleSampledDepth);
+            float difference = abs(sampleDepth - depth) / max(abs(depth), 1e-4);
+            float weight = exp(-difference * push.sharpness);
+
+            occlusion += texture(samplerOcclusion, sampleUV).r * weight;
+            totalWeight += weight;
+        }
+    }
+
+    outOcclusion = occlusion / max(totalWeight, 1e-4);
+}
@@ -0,0 +1,79 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "GBuffer.glsl"
+
+layout (set = 0, binding = 0) uniform sampler2D samplerHdr;
+layout (set = 0, binding = 1) uniform sampler2D samplerVelocity;
+layout (set = 0, binding = 2) uniform sampler2D samplerDepth;
+layout (set = 0, binding = 3) uniform sampler2D samplerHistory;
+
+layout (push_constant) uniform Push {
+    mat4 reprojection;
+    float farDepth;
+    float blendFactor;
+    uint resetHistory;
+} push;
+
+layout (location = 0) in vec2 inUV;
+
+layout (location = 0) out vec4 outColor;
+
+float Luminance(vec3 color)
+{
+    return dot(color, vec3(0.2126, 0.7152, 0.0722));
+}
+
+// Nothing was drawn into the G-buffer here, the sky only moves with the camera
+vec2 BackgroundVelocity(vec2 uv)
+{
+    vec2 ndc = uv * 2.0 - 1.0;
+    vec4 previous = push.reprojection * vec4(ndc, push.farDepth, 1.0);
+
+    return (ndc - previous.xy / previous.w) * 0.5;
+}
+
+void main()
+{
+    vec3 current = texture(samplerHdr, inUV).rgb;
+
+    if (push.resetHistory != 0u) {
+        outColor = vec4(current, 1.0);
+        return;
+    }
+
+    vec2 velocity = FetchDepth(samplerDepth, inUV) == push.farDepth
+        ? BackgroundVelocity(inUV)
+        : UnpackVelocity(texture(samplerVelocity, inUV).xy);
+
+    vec2 previousUV = inUV - velocity;
+
+    // Disoccluded from outside the screen
+    if (any(lessThan(previousUV, vec2(0.0))) || any(greaterThan(previousUV, vec2(1.0)))) {
+        outColor = vec4(current, 1.0);
+        return;
+    }
+
+    // History outside the range of the current neighborhood is stale, clamping it removes ghosting
+    vec2 texelSize = 1.0 / vec2(textureSize(samplerHdr, 0));
+    vec3 minimum = current;
+    vec3 maximum = current;
+
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            vec3 neighbor = texture(samplerHdr, inUV + vec2(x, y) * texelSize).rgb;
+            minimum = min(minimum, neighbor);
+            maximum = max(maximum, neighbor);
+        }
+    }
+
+    vec3 history = clamp(texture(samplerHistory, previousUV).rgb, minimum, maximum);
+
+    // Weighting by inverse luminance keeps bright subpixel highlights from flickering
+    float currentWeight = push.blendFactor / (1.0 + Luminance(current));
+    float historyWeight = (1.0 - push.blendFactor) / (1.0 + Luminance(history));
+
+    vec3 color = (current * currentWeight + history * historyWeight) / (currentWeight + historyWeight);
+
+    outColor = vec4(color, 1.0);
+}
@@ -0,0 +1,176 @@
+#version 450
+
+#define TONEMAPPING_REINHARD 0u
+#define TONEMAPPING_ACES 1u
+#define TONEMAPPING_AGX 2u
+// Debug views, no case in the switch below
+#define TONEMAPPING_NONE 3u
+
+// Matches DisplayEncoding::get_shader_index
+#define DISPLAY_LINEAR 0u
+#define DISPLAY_SRGB 1u
+#define DISPLAY_PQ 2u
+#define DISPLAY_SCRGB 3u
+
+// HDR outputs show the tonemapped image at this brightness, like an SDR display would
+const float PAPER_WHITE_NITS = 200.0;
+
+layout (set = 0, binding = 0) uniform sampler2D samplerHdr;
+layout (set = 0, binding = 1) uniform sampler2D samplerBloom;
+
+layout (push_constant) uniform Push {
+    float exposure;
+    uint tonemappingOperator;
+    uint displayEncoding;
+    float bloomIntensity;
+} push;
+
+layout (location = 0) in vec2 inUV;
+
+layout (location = 0) out vec4 outColor;
+
+vec3 Reinhard(vec3 color);
+vec3 Aces(vec3 color);
+vec3 AgX(vec3 color);
+vec3 EncodeDisplay(vec3 color, uint displayEncoding);
+
+void main()
+{
+    vec3 color = max(texture(samplerHdr, inUV).rgb, vec3(0.0));
+
+    // Energy preserving, bloom replaces part of the scene instead of adding light
+    if (push.bloomIntensity > 0.0) {
+        color = mix(color, texture(samplerBloom, inUV).rgb, push.bloomIntensity);
+    }
+
+    color *= push.exposure;
+
+    switch (push.tonemappingOperator) {
+        case TONEMAPPING_REINHARD:
+            color = Reinhard(color);
+            break;
+        case TONEMAPPING_ACES:
+            color = Aces(color);
+            break;
+        case TONEMAPPING_AGX:
+            color = AgX(color);
+            break;
+    }
+
+    outColor = vec4(EncodeDisplay(color, push.displayEncoding), 1.0);
+}
+
+vec3 Reinhard(vec3 color)
+{
+    return color / (color + vec3(1.0));
+}
+
+// Stephen Hill's fit of the ACES RRT + ODT, including the sRGB <-> ACEScg conversions
+const mat3 ACES_INPUT = mat3(
+    0.59719, 0.07600, 0.02840,
+    0.35458, 0.90834, 0.13383,
+    0.04823, 0.01566, 0.83777
+);
+
+const mat3 ACES_OUTPUT = mat3(
+    1.60475, -0.10208, -0.00327,
+    -0.53108, 1.10813, -0.07276,
+    -0.07367, -0.00605, 1.07602
+);
+
+vec3 Aces(vec3 color)
+{
+    color = ACES_INPUT * color;
+
+    vec3 a = color * (color + 0.0245786) - 0.000090537;
+    vec3 b = color * (0.983729 * color + 0.4329510) + 0.238081;
+    color = a / b;
+
+    return clamp(ACES_OUTPUT * color, 0.0, 1.0);
+}
+
+// Minimal AgX by Benjamin Wrensch, sigmoid approximated with a polynomial
+const mat3 AGX_INSET = mat3(
+    0.842479062253094, 0.0423282422610123, 0.0423756549057051,
+    0.0784335999999992, 0.878468636469772, 0.0784336,
+    0.0792237451477643, 0.0791661274605434, 0.879142973793104
+);
+
+const mat3 AGX_OUTSET = mat3(
+    1.19687900512017, -0.0528968517574562, -0.0529716355144438,
+    -0.0980208811401368, 1.15190312990417, -0.0980434501171241,
+    -0.0990297440797205, -0.0989611768448433, 1.15107367264116
+);
+
+const float AGX_MIN_EV = -12.47393;
+const float AGX_MAX_EV = 4.026069;
+
+vec3 AgXContrast(vec3 x)
+{
+    vec3 x2 = x * x;
+    vec3 x4 = x2 * x2;
+
+    return 15.5 * x4 * x2
+        - 40.14 * x4 * x
+        + 31.96 * x4
+        - 6.868 * x2 * x
+        + 0.4298 * x2
+        + 0.1191 * x
+        - 0.00232;
+}
+
+vec3 AgX(vec3 color)
+{
+    color = AGX_INSET * color;
+    color = clamp(log2(max(color, vec3(1e-10))), AGX_MIN_EV, AGX_MAX_EV);
+    color = (color - AGX_MIN_EV) / (AGX_MAX_EV - AGX_MIN_EV);
+    color = AgXContrast(color);
+    color = AGX_OUTSET * color;
+
+    // The curve outputs display encoded values, back to linear so every operator
+    // goes through the same encoding
+    return pow(clamp(color, 0.0, 1.0), vec3(2.2));
+}
+
+vec3 LinearToSrgb(vec3 color)
+{
+    vec3 low = color * 12.92;
+    vec3 high = 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055;
+
+    return mix(high, low, lessThanEqual(color, vec3(0.0031308)));
+}
+
+const mat3 BT709_TO_BT2020 = mat3(
+    0.627404, 0.069097, 0.016391,
+    0.329283, 0.919541, 0.088013,
+    0.043313, 0.011362, 0.895595
+);
+
+// SMPTE ST 2084, `color` in units of 10000 nits
+vec3 LinearToPq(vec3 color)
+{
+    const float m1 = 0.1593017578125;
+    const float m2 = 78.84375;
+    const float c1 = 0.8359375;
+    const float c2 = 18.8515625;
+    const float c3 = 18.6875;
+
+    vec3 y = pow(max(color, vec3(0.0)), vec3(m1));
+
+    return pow((c1 + c2 * y) / (1.0 + c3 * y), vec3(m2));
+}
+
+vec3 EncodeDisplay(vec3 color, uint displayEncoding)
+{
+    switch (displayEncoding) {
+        case DISPLAY_SRGB:
+            return LinearToSrgb(color);
+        case DISPLAY_PQ:
+            return LinearToPq(BT709_TO_BT2020 * color * (PAPER_WHITE_NITS / 10000.0));
+        case DISPLAY_SCRGB:
+            return color * (PAPER_WHITE_NITS / 80.0);
+    }
+
+    // *_SRGB swapchain formats encode on write
+    return color;
+}
@@ -0,0 +1,45 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+layout (set = 1, binding = 0) uniform sampler2D samplerShadowMap;
+layout (set = 1, binding = 1) uniform samplerCube samplerIrradiance;
+layout (set = 1, binding = 2) uniform samplerCube samplerPrefiltered;
+layout (set = 1, binding = 3) uniform sampler2D samplerBrdfLut;
+
+layout (push_constant) uniform Push {
+    mat4 viewProjection;
+    mat4 lightSpace;
+    vec3 cameraPosition;
+    float environmentIntensity;
+} push;
+
+layout (location = 0) in vec3 inNormal;
+layout (location = 1) in vec3 inPosition;
+layout (location = 2) flat in vec4 inBaseColor;
+
+layout (location = 0) out vec4 outColor;
+
+#include "Lighting.glsl"
+
+void main()
+{
+    vec3 normal = normalize(inNormal);
+    vec3 view = normalize(push.cameraPosition - inPosition);
+
+    // Both sides are drawn, the inside faces are lit as seen from the camera
+    if (dot(normal, view) < 0.0) {
+        normal = -normal;
+    }
+
+    // Same material parameters as Deferred.frag
+    float metallic = 0.1;
+    float roughness = 0.3;
+
+    // Not part of the G-buffer, so SSAO doesn't cover it
+    float ambientOcclusion = 1.0;
+
+    vec3 color = Shade(inBaseColor.rgb, normal, view, inPosition, metallic, roughness,
+                       ambientOcclusion, push.environmentIntensity);
+
+    outColor = vec4(color, inBaseColor.a);
+}
@@ -0,0 +1,36 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "ObjectData.glsl"
+
+layout (std430, set = 0, binding = 0) readonly buffer Objects {
+    ObjectData objects[];
+};
+
+layout (push_constant) uniform Push {
+    mat4 viewProjection;
+    mat4 lightSpace;
+    vec3 cameraPosition;
+    float environmentIntensity;
+} push;
+
+layout (location = 0) in vec3 inPosition;
+layout (location = 1) in vec3 inNormal;
+
+layout (location = 0) out vec3 outNormal;
+layout (location = 1) out vec3 outPosition;
+layout (location = 2) flat out vec4 outBaseColor;
+
+void main() {
+    mat4 model = objects[gl_InstanceIndex].model;
+
+    mat3 normalMatrix = transpose(inverse(mat3(model)));
+    outNormal = normalMatrix * inNormal;
+
+    vec4 position = model * vec4(inPosition, 1.0);
+    outPosition = position.xyz;
+
+    outBaseColor = objects[gl_InstanceIndex].baseColor;
+
+    gl_Position = push.viewProjection * position;
+}
@@ -4,9 +4,31 @@ glslc composite.vert -o composite.vert.spv
 glslc composite.frag -o composite.frag.spv
 glslc Deferred.vert -o Deferred.vert.spv
 glslc Deferred.frag -o Deferred.frag.spv
+glslc DeferredMasked.frag -o DeferredMasked.frag.spv
 glslc DeferredLightning.vert -o DeferredLightning.vert.spv
 glslc DeferredLightning.frag -o DeferredLightning.frag.spv
+glslc Fullscreen.vert -o Fullscreen.vert.spv
+glslc Skybox.vert -o Skybox.vert.spv
+glslc Skybox.frag -o Skybox.frag.spv
+glslc Transparent.vert -o Transparent.vert.spv
+glslc Transparent.frag -o Transparent.frag.spv
+glslc DebugDraw.vert -o DebugDraw.vert.spv
+glslc DebugDraw.frag -o DebugDraw.frag.spv
+glslc TemporalAntiAliasing.frag -o TemporalAntiAliasing.frag.spv
+glslc Tonemapping.frag -o Tonemapping.frag.spv
+glslc Fxaa.frag -o Fxaa.frag.spv
+glslc DebugUi.vert -o DebugUi.vert.spv
+glslc DebugUi.frag -o DebugUi.frag.spv
+glslc BloomDownsample.frag -o BloomDownsample.frag.spv
+glslc BloomUpsample.frag -o BloomUpsample.frag.spv
+glslc Ssao.frag -o Ssao.frag.spv
+glslc SsaoBlur.frag -o SsaoBlur.frag.spv
 glslc ShadowMap.vert -o ShadowMap.vert.spv
 glslc ShadowMap.frag -o ShadowMap.frag.spv
+glslc Culling.comp -o Culling.comp.spv
+glslc EquirectangularToCube.comp -o EquirectangularToCube.comp.spv
+glslc Irradiance.comp -o Irradiance.comp.spv
+glslc Prefilter.comp -o Prefilter.comp.spv
+glslc BrdfLut.comp -o BrdfLut.comp.spv
 glslc unlit.vert -o unlit.vert.spv
 glslc unlit.frag -o unlit.frag.spv
@@ -0,0 +1,334 @@
+use ash::{vk, Device};
+use gpu_allocator::vulkan::Allocator;
+use nalgebra::Vector2;
+
+use crate::{
+    command_buffer_helpers::{self, ImageTransition},
+    deferred_lightning_render_pass::{
+        lightning_renderpass_consts, DeferredLightningRenderPassOutput,
+    },
+    image::{Image, ImageCreateInfo},
+    pipeline_manager::{BloomMaterial, PipelineManager},
+    push_constants_data::BloomPushConstantsData,
+    render_pass_attachment_output::RenderPassAttachmentOutput,
+    render_settings::{AntiAliasing, RenderSettings},
+    renderer_error::Result,
+    taa_render_pass::TaaRenderPassOutput,
+};
+
+pub mod bloom_renderpass_consts {
+    // Mip 0 is half the render resolution
+    pub const MIP_COUNT: u32 = 6;
+}
+
+#[derive(Clone)]
+pub struct BloomRenderPassOutput {
+    // Mip 0 only
+    pub bloom: RenderPassAttachmentOutput,
+}
+
+// Downsamples the anti-aliased HDR color into a mip chain with a 13 tap filter,
+// then accumulates it back up to mip 0 with a 3x3 tent filter
+pub struct BloomRenderPass {
+    device: Device,
+    render_area: vk::Rect2D,
+    bloom_image: Image,
+    material: BloomMaterial,
+}
+
+impl BloomRenderPass {
+    pub fn new(
+        device: &Device,
+        allocator: &mut Allocator,
+        pipeline_manager: &mut PipelineManager,
+        render_area: &vk::Rect2D,
+        lightning_render_pass_output: &DeferredLightningRenderPassOutput,
+        taa_render_pass_output: &TaaRenderPassOutput,
+    ) -> Result<Self> {
+        let bloom_image = Self::create_bloom_image(device, allocator, render_area)?;
+
+        // The input with TAA disabled and enabled, then each mip is sampled by the next
+        // downsample and the previous upsample
+        let mut source_image_views = vec![
+            lightning_render_pass_output.color.image_view,
+            taa_render_pass_output.color.image_view,
+        ];
+        source_image_views
+            .extend((0..bloom_image.mip_levels).map(|mip| bloom_image.get_mip_image_view(mip)));
+
+        Ok(Self {
+            device: device.clone(),
+            render_area: *render_area,
+            material: pipeline_manager.create_bloom_material(&source_image_views)?,
+            bloom_image,
+        })
+    }
+
+    pub fn destroy(&mut self, allocator: &mut Allocator) {
+        self.bloom_image.destroy(&self.device, allocator);
+    }
+
+    pub fn render(&self, command_buffer: vk::CommandBuffer, settings: &RenderSettings) {
+        if !settings.bloom {
+            // Not sampled while disabled, but the descriptor still has to be in the right layout
+            command_buffer_helpers::single_mip_pipeline_barrier(
+                &self.device,
+                command_buffer,
+                &ImageTransition {
+                    src_stage_mask: vk::PipelineStageFlags::TOP_OF_PIPE,
+                    dst_stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER,
+                    src_access_mask: vk::AccessFlags::NONE,
+                    dst_access_mask: vk::AccessFlags::SHADER_READ,
+                    old_layout: vk::ImageLayout::UNDEFINED,
+                    new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                },
+                self.bloom_image.image,
+                vk::ImageAspectFlags::COLOR,
+                0,
+            );
+
+            return;
+        }
+
+        unsafe {
+            self.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.material.downsample_pipeline,
+            );
+        }
+
+        for mip in 0..self.bloom_image.mip_levels {
+            let source_extent = if mip == 0 {
+                self.render_area.extent
+            } else {
+                self.get_mip_extent(mip - 1)
+            };
+
+            let push_data = BloomPushConstantsData::new(
+                &Self::get_texel_size(source_extent),
+                settings.bloom_threshold,
+                settings.bloom_knee,
+                mip == 0,
+            );
+
+            command_buffer_helpers::single_mip_pipeline_barrier(
+                &self.device,
+                command_buffer,
+                &ImageTransition {
+                    src_stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER,
+                    dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                    src_access_mask: vk::AccessFlags::NONE,
+                    dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                    old_layout: vk::ImageLayout::UNDEFINED,
+                    new_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                },
+                self.bloom_image.image,
+                vk::ImageAspectFlags::COLOR,
+                mip,
+            );
+
+            self.draw_mip(
+                command_buffer,
+                mip,
+                vk::AttachmentLoadOp::DONT_CARE,
+                if mip == 0 {
+                    self.get_input_set(settings)
+                } else {
+                    self.get_mip_set(mip - 1)
+                },
+                &push_data,
+            );
+        }
+
+        unsafe {
+            self.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.material.upsample_pipeline,
+            );
+        }
+
+        // Blended on top of the downsampled contents of the target mip
+        for mip in (1..self.bloom_image.mip_levels).rev() {
+            let target_mip = mip - 1;
+
+            let push_data = BloomPushConstantsData::new(
+                &Self::get_texel_size(self.get_mip_extent(mip)),
+                settings.bloom_threshold,
+                settings.bloom_knee,
+                false,
+            );
+
+            command_buffer_helpers::single_mip_pipeline_barrier(
+                &self.device,
+                command_buffer,
+                &ImageTransition {
+                    src_stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER,
+                    dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                    src_access_mask: vk::AccessFlags::SHADER_READ,
+                    dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_READ
+                        | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                    old_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    new_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                },
+                self.bloom_image.image,
+                vk::ImageAspectFlags::COLOR,
+                target_mip,
+            );
+
+            self.draw_mip(
+                command_buffer,
+                target_mip,
+                vk::AttachmentLoadOp::LOAD,
+                self.get_mip_set(mip),
+                &push_data,
+            );
+        }
+    }
+
+    fn get_input_set(&self, settings: &RenderSettings) -> vk::DescriptorSet {
+        if settings.anti_aliasing == AntiAliasing::Taa {
+            self.material.source_sets[1]
+        } else {
+            self.material.source_sets[0]
+        }
+    }
+
+    fn get_mip_set(&self, mip: u32) -> vk::DescriptorSet {
+        self.material.source_sets[mip as usize + 2]
+    }
+
+    // Leaves the mip readable by the next draw
+    fn draw_mip(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        mip: u32,
+        load_op: vk::AttachmentLoadOp,
+        source_set: vk::DescriptorSet,
+        push_data: &BloomPushConstantsData,
+    ) {
+        let extent = self.get_mip_extent(mip);
+        let render_area = vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent,
+        };
+
+        let viewport = vk::Viewport::default()
+            .x(0.0)
+            .y(0.0)
+            .width(extent.width as f32)
+            .height(extent.height as f32)
+            .min_depth(0.0)
+            .max_depth(1.0);
+
+        let color_attachments = [vk::RenderingAttachmentInfo::default()
+            .image_view(self.bloom_image.get_mip_image_view(mip))
+            .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .resolve_mode(vk::ResolveModeFlags::NONE)
+            .resolve_image_view(vk::ImageView::null())
+            .resolve_image_layout(vk::ImageLayout::UNDEFINED)
+            .load_op(load_op)
+            .store_op(vk::AttachmentStoreOp::STORE)];
+
+        let rendering_info = vk::RenderingInfo::default()
+            .render_area(render_area)
+            .layer_count(1)
+            .view_mask(0)
+            .color_attachments(&color_attachments);
+
+        unsafe {
+            self.device
+                .cmd_begin_rendering(command_buffer, &rendering_info);
+
+            self.device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+            self.device
+                .cmd_set_scissor(command_buffer, 0, &[render_area]);
+
+            self.device.cmd_push_constants(
+                command_buffer,
+                self.material.layout,
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                push_data.get(),
+            );
+
+            self.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.material.layout,
+                0,
+                &[source_set],
+                &[],
+            );
+
+            self.device.cmd_draw(command_buffer, 3, 1, 0, 0);
+
+            self.device.cmd_end_rendering(command_buffer);
+        }
+
+        command_buffer_helpers::single_mip_pipeline_barrier(
+            &self.device,
+            command_buffer,
+            &ImageTransition {
+                src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                dst_stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER,
+                src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                dst_access_mask: vk::AccessFlags::SHADER_READ,
+                old_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            },
+            self.bloom_image.image,
+            vk::ImageAspectFlags::COLOR,
+            mip,
+        );
+    }
+
+    fn get_mip_extent(&self, mip: u32) -> vk::Extent2D {
+        Image::get_mip_extent(Self::get_bloom_extent(&self.render_area), mip)
+    }
+
+    fn get_bloom_extent(render_area: &vk::Rect2D) -> vk::Extent2D {
+        Image::get_mip_extent(render_area.extent, 1)
+    }
+
+    fn get_texel_size(extent: vk::Extent2D) -> Vector2<f32> {
+        Vector2::new(1.0 / extent.width as f32, 1.0 / extent.height as f32)
+    }
+
+    fn create_bloom_image(
+        device: &Device,
+        allocator: &mut Allocator,
+        render_area: &vk::Rect2D,
+    ) -> Result<Image> {
+        let extent = Self::get_bloom_extent(render_area);
+        // Stop before the smallest side goes below a texel
+        let max_mip_count = 32 - extent.width.min(extent.height).leading_zeros();
+
+        let create_info = ImageCreateInfo {
+            extent: vk::Extent3D::default()
+                .width(extent.width)
+                .height(extent.height)
+                .depth(1),
+            image_type: vk::ImageType::TYPE_2D,
+            format: lightning_renderpass_consts::HDR_COLOR,
+            usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            view_type: vk::ImageViewType::TYPE_2D,
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_levels: bloom_renderpass_consts::MIP_COUNT.min(max_mip_count),
+            array_layers: 1,
+        };
+
+        Image::new(device, allocator, &create_info, "bloom")
+    }
+
+    pub fn get_output(&self) -> BloomRenderPassOutput {
+        BloomRenderPassOutput {
+            bloom: RenderPassAttachmentOutput {
+                image: self.bloom_image.image,
+                image_view: self.bloom_image.get_mip_image_view(0),
+                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            },
+        }
+    }
+}
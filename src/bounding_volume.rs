@@ -0,0 +1,163 @@
+use nalgebra::{Matrix4, Point3, Vector3};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+}
+
+impl Aabb {
+    pub fn from_positions(positions: &[Vector3<f32>]) -> Self {
+        let mut min = Vector3::repeat(f32::MAX);
+        let mut max = Vector3::repeat(f32::MIN);
+
+        for position in positions {
+            min = min.inf(position);
+            max = max.sup(position);
+        }
+
+        Self { min, max }
+    }
+
+    pub fn get_center(&self) -> Vector3<f32> {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn get_extents(&self) -> Vector3<f32> {
+        (self.max - self.min) * 0.5
+    }
+
+    // Arvo, "Transforming Axis-Aligned Bounding Boxes"
+    pub fn transform(&self, matrix: &Matrix4<f32>) -> Self {
+        let center = matrix.transform_point(&Point3::from(self.get_center()));
+        let extents = matrix.fixed_view::<3, 3>(0, 0).abs() * self.get_extents();
+
+        Self {
+            min: center.coords - extents,
+            max: center.coords + extents,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct BoundingSphere {
+    pub center: Vector3<f32>,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    // Centered on the AABB, not minimal, but good enough for culling
+    pub fn from_positions(positions: &[Vector3<f32>]) -> Self {
+        let center = Aabb::from_positions(positions).get_center();
+        let radius = positions
+            .iter()
+            .map(|position| (position - center).norm())
+            .fold(0.0, f32::max);
+
+        Self { center, radius }
+    }
+
+    pub fn transform(&self, matrix: &Matrix4<f32>) -> Self {
+        let center = matrix.transform_point(&Point3::from(self.center));
+        let linear = matrix.fixed_view::<3, 3>(0, 0);
+        let max_scale = linear
+            .column_iter()
+            .map(|column| column.norm())
+            .fold(0.0, f32::max);
+
+        Self {
+            center: center.coords,
+            radius: self.radius * max_scale,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+
+    use super::*;
+    use crate::frustum::{DepthRange, Frustum};
+
+    // The identity projection clips to x and y in [-1, 1] and z in [0, 1]
+    fn unit_frustum() -> Frustum {
+        Frustum::from_matrix(&Matrix4::identity(), DepthRange::ZeroToOne)
+    }
+
+    fn aabb(center: Vector3<f32>, extents: f32) -> Aabb {
+        Aabb {
+            min: center.add_scalar(-extents),
+            max: center.add_scalar(extents),
+        }
+    }
+
+    fn sphere(center: Vector3<f32>, radius: f32) -> BoundingSphere {
+        BoundingSphere { center, radius }
+    }
+
+    #[test]
+    fn from_positions() {
+        let positions = [
+            Vector3::new(-1.0, 0.0, 2.0),
+            Vector3::new(3.0, -2.0, 0.0),
+            Vector3::new(1.0, 2.0, 1.0),
+        ];
+
+        let aabb = Aabb::from_positions(&positions);
+        assert_eq!(aabb.min, Vector3::new(-1.0, -2.0, 0.0));
+        assert_eq!(aabb.max, Vector3::new(3.0, 2.0, 2.0));
+
+        let sphere = BoundingSphere::from_positions(&positions);
+        assert_eq!(sphere.center, aabb.get_center());
+        for position in &positions {
+            assert!((position - sphere.center).norm() <= sphere.radius);
+        }
+    }
+
+    #[test]
+    fn aabb_transform_covers_the_rotated_box() {
+        let rotation = Matrix4::from_euler_angles(0.0, std::f32::consts::FRAC_PI_4, 0.0)
+            .append_translation(&Vector3::new(5.0, 0.0, 0.0));
+        let transformed = aabb(Vector3::zeros(), 1.0).transform(&rotation);
+
+        let diagonal = std::f32::consts::SQRT_2;
+        assert!((transformed.get_center() - Vector3::new(5.0, 0.0, 0.0)).norm() < 1e-5);
+        assert!((transformed.get_extents() - Vector3::new(diagonal, 1.0, diagonal)).norm() < 1e-5);
+    }
+
+    #[test]
+    fn sphere_transform_uses_the_largest_scale() {
+        let scale = Matrix4::new_nonuniform_scaling(&Vector3::new(1.0, 3.0, 2.0))
+            .append_translation(&Vector3::new(0.0, 1.0, 0.0));
+        let transformed = sphere(Vector3::new(1.0, 0.0, 0.0), 0.5).transform(&scale);
+
+        assert_eq!(transformed.center, Vector3::new(1.0, 1.0, 0.0));
+        assert_eq!(transformed.radius, 1.5);
+    }
+
+    #[test]
+    fn aabb_against_frustum() {
+        let frustum = unit_frustum();
+
+        assert!(frustum.intersects_aabb(&aabb(Vector3::new(0.0, 0.0, 0.5), 0.2)));
+        // Straddling the right and far planes
+        assert!(frustum.intersects_aabb(&aabb(Vector3::new(1.1, 0.0, 1.1), 0.2)));
+        // Larger than the frustum
+        assert!(frustum.intersects_aabb(&aabb(Vector3::new(0.0, 0.0, 0.5), 10.0)));
+
+        assert!(!frustum.intersects_aabb(&aabb(Vector3::new(1.5, 0.0, 0.5), 0.4)));
+        assert!(!frustum.intersects_aabb(&aabb(Vector3::new(0.0, -1.5, 0.5), 0.4)));
+        assert!(!frustum.intersects_aabb(&aabb(Vector3::new(0.0, 0.0, -0.5), 0.4)));
+    }
+
+    #[test]
+    fn sphere_against_frustum() {
+        let frustum = unit_frustum();
+
+        assert!(frustum.intersects_sphere(&sphere(Vector3::new(0.0, 0.0, 0.5), 0.1)));
+        assert!(frustum.intersects_sphere(&sphere(Vector3::new(1.5, 0.0, 0.5), 0.6)));
+
+        assert!(!frustum.intersects_sphere(&sphere(Vector3::new(1.5, 0.0, 0.5), 0.4)));
+        assert!(!frustum.intersects_sphere(&sphere(Vector3::new(0.0, 0.0, 1.5), 0.4)));
+    }
+}
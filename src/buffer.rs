@@ -3,6 +3,8 @@ use gpu_allocator::vulkan::{Allocation, AllocationCreateDesc, AllocationScheme,
 use gpu_allocator::MemoryLocation;
 use std::intrinsics::copy_nonoverlapping;
 
+use crate::{debug_markers, renderer_error::Result};
+
 pub struct Buffer {
     pub buffer_size: vk::DeviceSize,
     pub buffer: vk::Buffer,
@@ -18,7 +20,7 @@ impl Buffer {
         data: &[T],
         name: &str,
         usage: vk::BufferUsageFlags,
-    ) -> Self {
+    ) -> Result<Self> {
         unsafe {
             let buffer_size = std::mem::size_of_val(data) as vk::DeviceSize;
 
@@ -26,29 +28,24 @@ impl Buffer {
                 .size(buffer_size)
                 .usage(usage | vk::BufferUsageFlags::TRANSFER_SRC);
 
-            let staging_buffer = device
-                .create_buffer(&staging_buffer_create_info, None)
-                .unwrap();
+            let staging_buffer = device.create_buffer(&staging_buffer_create_info, None)?;
+            debug_markers::set_object_name(staging_buffer, &format!("{}_staging", name));
 
             let requirements = device.get_buffer_memory_requirements(staging_buffer);
 
-            let staging_buffer_allocation = allocator
-                .allocate(&AllocationCreateDesc {
-                    name: &format!("{}_staging", name),
-                    requirements,
-                    location: MemoryLocation::CpuToGpu,
-                    linear: true,
-                    allocation_scheme: AllocationScheme::GpuAllocatorManaged,
-                })
-                .unwrap();
-
-            device
-                .bind_buffer_memory(
-                    staging_buffer,
-                    staging_buffer_allocation.memory(),
-                    staging_buffer_allocation.offset(),
-                )
-                .unwrap();
+            let staging_buffer_allocation = allocator.allocate(&AllocationCreateDesc {
+                name: &format!("{}_staging", name),
+                requirements,
+                location: MemoryLocation::CpuToGpu,
+                linear: true,
+                allocation_scheme: AllocationScheme::GpuAllocatorManaged,
+            })?;
+
+            device.bind_buffer_memory(
+                staging_buffer,
+                staging_buffer_allocation.memory(),
+                staging_buffer_allocation.offset(),
+            )?;
 
             copy_nonoverlapping(
                 data.as_ptr(),
@@ -64,63 +61,110 @@ impl Buffer {
                 .size(buffer_size)
                 .usage(usage | vk::BufferUsageFlags::TRANSFER_DST);
 
-            let buffer = device.create_buffer(&buffer_create_info, None).unwrap();
+            let buffer = device.create_buffer(&buffer_create_info, None)?;
+            debug_markers::set_object_name(buffer, name);
 
             let requirements = device.get_buffer_memory_requirements(buffer);
 
-            let allocation = allocator
-                .allocate(&AllocationCreateDesc {
-                    name,
-                    requirements,
-                    location: MemoryLocation::GpuOnly,
-                    linear: true, // Buffers are always linear
-                    allocation_scheme: AllocationScheme::GpuAllocatorManaged,
-                })
-                .unwrap();
+            let allocation = allocator.allocate(&AllocationCreateDesc {
+                name,
+                requirements,
+                location: MemoryLocation::GpuOnly,
+                linear: true, // Buffers are always linear
+                allocation_scheme: AllocationScheme::GpuAllocatorManaged,
+            })?;
 
-            device
-                .bind_buffer_memory(buffer, allocation.memory(), allocation.offset())
-                .unwrap();
+            device.bind_buffer_memory(buffer, allocation.memory(), allocation.offset())?;
 
             let allocate_info = vk::CommandBufferAllocateInfo::default()
                 .command_pool(command_pool)
                 .level(vk::CommandBufferLevel::PRIMARY)
                 .command_buffer_count(1);
 
-            let command_buffers = device.allocate_command_buffers(&allocate_info).unwrap();
+            let command_buffers = device.allocate_command_buffers(&allocate_info)?;
             let cmd = command_buffers[0];
+            debug_markers::set_object_name(cmd, &format!("{}_upload", name));
 
             let begin_info = vk::CommandBufferBeginInfo::default()
                 .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
 
-            device.begin_command_buffer(cmd, &begin_info).unwrap();
+            device.begin_command_buffer(cmd, &begin_info)?;
             device.cmd_copy_buffer(
                 cmd,
                 staging_buffer,
                 buffer,
                 &[vk::BufferCopy::default().size(buffer_size)],
             );
-            device.end_command_buffer(cmd).unwrap();
+            device.end_command_buffer(cmd)?;
 
-            device
-                .queue_submit(
-                    queue,
-                    &[vk::SubmitInfo::default().command_buffers(&command_buffers)],
-                    vk::Fence::null(),
-                )
-                .unwrap();
-            device.queue_wait_idle(queue).unwrap();
+            device.queue_submit(
+                queue,
+                &[vk::SubmitInfo::default().command_buffers(&command_buffers)],
+                vk::Fence::null(),
+            )?;
+            device.queue_wait_idle(queue)?;
 
             device.free_command_buffers(command_pool, &command_buffers);
 
-            allocator.free(staging_buffer_allocation).unwrap();
+            allocator.free(staging_buffer_allocation)?;
             device.destroy_buffer(staging_buffer, None);
 
-            Self {
+            Ok(Self {
+                buffer_size,
+                buffer,
+                allocation: Some(allocation),
+            })
+        }
+    }
+
+    // Uninitialized, CpuToGpu buffers stay persistently mapped for `write`
+    pub fn new_empty(
+        device: &Device,
+        allocator: &mut Allocator,
+        buffer_size: vk::DeviceSize,
+        name: &str,
+        usage: vk::BufferUsageFlags,
+        location: MemoryLocation,
+    ) -> Result<Self> {
+        unsafe {
+            let buffer_create_info = vk::BufferCreateInfo::default()
+                .size(buffer_size)
+                .usage(usage);
+
+            let buffer = device.create_buffer(&buffer_create_info, None)?;
+            debug_markers::set_object_name(buffer, name);
+
+            let requirements = device.get_buffer_memory_requirements(buffer);
+
+            let allocation = allocator.allocate(&AllocationCreateDesc {
+                name,
+                requirements,
+                location,
+                linear: true, // Buffers are always linear
+                allocation_scheme: AllocationScheme::GpuAllocatorManaged,
+            })?;
+
+            device.bind_buffer_memory(buffer, allocation.memory(), allocation.offset())?;
+
+            Ok(Self {
                 buffer_size,
                 buffer,
                 allocation: Some(allocation),
-            }
+            })
+        }
+    }
+
+    pub fn write<T>(&mut self, data: &[T]) {
+        assert!(std::mem::size_of_val(data) as vk::DeviceSize <= self.buffer_size);
+
+        let mapped_ptr = self
+            .allocation
+            .as_ref()
+            .and_then(|allocation| allocation.mapped_ptr())
+            .expect("Buffer is not host visible");
+
+        unsafe {
+            copy_nonoverlapping(data.as_ptr(), mapped_ptr.cast().as_ptr(), data.len());
         }
     }
 }
@@ -3,7 +3,10 @@ use std::collections::HashMap;
 use ash::{vk, Device};
 use gpu_allocator::vulkan::Allocator;
 
-use crate::buffer::{Buffer, VulkanResource};
+use crate::{
+    buffer::{Buffer, VulkanResource},
+    renderer_error::Result,
+};
 
 pub struct BufferManager {
     device: Device,
@@ -34,9 +37,9 @@ impl BufferManager {
         queue: vk::Queue,
         data: &[T],
         usage: vk::BufferUsageFlags,
-    ) {
+    ) -> Result<()> {
         if self.buffers.contains_key(name) {
-            return;
+            return Ok(());
         }
 
         self.buffers.insert(
@@ -49,8 +52,10 @@ impl BufferManager {
                 data,
                 name,
                 usage,
-            ),
+            )?,
         );
+
+        Ok(())
     }
 
     pub fn get_buffer(&self, name: &str) -> &Buffer {
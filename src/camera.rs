@@ -1,4 +1,6 @@
-use nalgebra::{Matrix4, Vector3};
+use nalgebra::{Isometry3, Matrix4, Point3, Vector2, Vector3};
+
+use crate::frustum::{DepthRange, Frustum};
 
 // Vulkan
 // x right
@@ -15,9 +17,28 @@ use nalgebra::{Matrix4, Vector3};
 // y up
 // z forward
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProjectionMode {
+    Perspective,
+    // Near maps to 1.0 and infinity to 0.0, for depth precision
+    ReverseZInfinite,
+    // Height of the view volume in world units
+    Orthographic { height: f32 },
+}
+
 pub struct Camera {
-    transform: Matrix4<f32>,
+    // Camera to world, looking down -Z like look_at_rh. Rigid, so the view is always invertible
+    transform: Isometry3<f32>,
+    width: f32,
+    height: f32,
+    fov: f32,
+    near: f32,
+    far: f32,
+    projection_mode: ProjectionMode,
+    // In NDC units
+    jitter: Vector2<f32>,
     projection: Matrix4<f32>,
+    unjittered_projection: Matrix4<f32>,
 }
 
 const TO_VULKAN_COORDINATE_SYSTEM: Matrix4<f32> = Matrix4::new(
@@ -35,40 +56,132 @@ impl Camera {
         near: f32,
         far: f32,
     ) -> Self {
-        let mut transform = Matrix4::identity();
-        transform.append_translation_mut(&Vector3::new(x, y, z));
+        let transform = Isometry3::translation(x, y, z);
 
-        Self {
+        let mut camera = Self {
             transform,
-            projection: Self::calculate_projection(width, height, fov, near, far),
-        }
+            width,
+            height,
+            fov,
+            near,
+            far,
+            projection_mode: ProjectionMode::Perspective,
+            jitter: Vector2::zeros(),
+            projection: Matrix4::identity(),
+            unjittered_projection: Matrix4::identity(),
+        };
+        camera.update_projection();
+
+        camera
+    }
+
+    fn update_projection(&mut self) {
+        let projection = Self::calculate_projection(
+            self.projection_mode,
+            self.width / self.height,
+            self.fov,
+            self.near,
+            self.far,
+        );
+
+        // Offsets the whole image in NDC, works for both perspective and orthographic
+        let jitter = Matrix4::new_translation(&Vector3::new(self.jitter.x, self.jitter.y, 0.0));
+
+        self.projection = jitter * projection;
+        self.unjittered_projection = projection;
     }
 
+    // All modes output Vulkan depth in [0, 1], the view already flips y
     fn calculate_projection(
-        width: f32,
-        height: f32,
+        projection_mode: ProjectionMode,
+        aspect: f32,
         fov: f32,
         near: f32,
         far: f32,
     ) -> Matrix4<f32> {
-        let perspective = nalgebra::Matrix4::new_perspective(width / height, fov, near, far);
+        let focal_length = 1.0 / (fov / 2.0).tan();
+        let mut projection = Matrix4::zeros();
+
+        match projection_mode {
+            ProjectionMode::Perspective => {
+                projection[(0, 0)] = focal_length / aspect;
+                projection[(1, 1)] = focal_length;
+                projection[(2, 2)] = far / (near - far);
+                projection[(2, 3)] = near * far / (near - far);
+                projection[(3, 2)] = -1.0;
+            }
+            ProjectionMode::ReverseZInfinite => {
+                projection[(0, 0)] = focal_length / aspect;
+                projection[(1, 1)] = focal_length;
+                projection[(2, 3)] = near;
+                projection[(3, 2)] = -1.0;
+            }
+            ProjectionMode::Orthographic { height } => {
+                projection[(0, 0)] = 2.0 / (height * aspect);
+                projection[(1, 1)] = 2.0 / height;
+                projection[(2, 2)] = 1.0 / (near - far);
+                projection[(2, 3)] = near / (near - far);
+                projection[(3, 3)] = 1.0;
+            }
+        }
 
-        //TO_VULKAN_COORDINATE_SYSTEM * perspective
-        perspective
+        projection
     }
 
     pub fn get_projection(&self) -> &Matrix4<f32> {
         &self.projection
     }
 
+    // Motion vectors are computed without the jitter so they only contain real motion
+    pub fn get_unjittered_projection(&self) -> &Matrix4<f32> {
+        &self.unjittered_projection
+    }
+
+    pub fn get_projection_mode(&self) -> ProjectionMode {
+        self.projection_mode
+    }
+
+    pub fn set_projection_mode(&mut self, projection_mode: ProjectionMode) {
+        self.projection_mode = projection_mode;
+        self.update_projection();
+    }
+
+    // Field of view in radians
+    pub fn set_perspective(&mut self, fov: f32, near: f32, far: f32) {
+        self.fov = fov;
+        self.near = near;
+        self.far = far;
+        self.update_projection();
+    }
+
+    pub fn is_reverse_z(&self) -> bool {
+        self.projection_mode == ProjectionMode::ReverseZInfinite
+    }
+
+    // Sub-pixel offset in pixels, for TAA
+    pub fn set_jitter(&mut self, jitter: Vector2<f32>) {
+        self.jitter = Vector2::new(2.0 * jitter.x / self.width, 2.0 * jitter.y / self.height);
+        self.update_projection();
+    }
+
+    pub fn get_position(&self) -> Point3<f32> {
+        Point3::from(self.transform.translation.vector)
+    }
+
+    // The camera looks down its local -Z
+    pub fn get_forward(&self) -> Vector3<f32> {
+        -(self.transform.rotation * Vector3::z())
+    }
+
+    pub fn look_at(&mut self, eye: &Point3<f32>, target: &Point3<f32>) {
+        self.transform = Isometry3::look_at_rh(eye, target, &Vector3::y()).inverse();
+    }
+
     pub fn get_view(&self) -> Matrix4<f32> {
-        //self.transform.try_inverse().unwrap()
-
-        TO_VULKAN_COORDINATE_SYSTEM
-            * nalgebra::Matrix4::look_at_rh(
-                &nalgebra::Point3::new(0.0, 0.0, -5.0),
-                &nalgebra::Point3::new(0.0, 0.0, 0.0),
-                &Vector3::new(0.0, 1.0, 0.0),
-            )
+        TO_VULKAN_COORDINATE_SYSTEM * self.transform.inverse().to_homogeneous()
+    }
+
+    pub fn get_frustum(&self) -> Frustum {
+        Frustum::from_matrix(&(self.projection * self.get_view()), DepthRange::ZeroToOne)
     }
 }
@@ -0,0 +1,273 @@
+use std::collections::HashSet;
+
+use nalgebra::{Point3, Vector2, Vector3};
+use winit::event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent};
+use winit::keyboard::{KeyCode, PhysicalKey};
+
+use crate::camera::Camera;
+
+const MAX_PITCH: f32 = 1.55;
+// Default zoom limit of OrbitController
+const MIN_ORBIT_DISTANCE: f32 = 0.5;
+
+// Tracks the raw winit input state shared by all controllers
+#[derive(Default)]
+pub struct InputState {
+    pressed_keys: HashSet<KeyCode>,
+    pressed_buttons: HashSet<MouseButton>,
+    cursor_position: Option<Vector2<f32>>,
+    cursor_delta: Vector2<f32>,
+    scroll_delta: f32,
+}
+
+impl InputState {
+    pub fn handle_window_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::KeyboardInput { event, .. } => {
+                if let PhysicalKey::Code(key_code) = event.physical_key {
+                    match event.state {
+                        ElementState::Pressed => self.pressed_keys.insert(key_code),
+                        ElementState::Released => self.pressed_keys.remove(&key_code),
+                    };
+                }
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                match state {
+                    ElementState::Pressed => self.pressed_buttons.insert(*button),
+                    ElementState::Released => self.pressed_buttons.remove(button),
+                };
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                let position = Vector2::new(position.x as f32, position.y as f32);
+                if let Some(last_position) = self.cursor_position {
+                    self.cursor_delta += position - last_position;
+                }
+                self.cursor_position = Some(position);
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.cursor_position = None;
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.scroll_delta += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 100.0,
+                };
+            }
+            WindowEvent::Focused(false) => {
+                self.pressed_keys.clear();
+                self.pressed_buttons.clear();
+            }
+            _ => (),
+        }
+    }
+
+    pub fn is_key_pressed(&self, key_code: KeyCode) -> bool {
+        self.pressed_keys.contains(&key_code)
+    }
+
+    pub fn is_button_pressed(&self, button: MouseButton) -> bool {
+        self.pressed_buttons.contains(&button)
+    }
+
+    // Consumed once per frame
+    fn take_cursor_delta(&mut self) -> Vector2<f32> {
+        std::mem::take(&mut self.cursor_delta)
+    }
+
+    fn take_scroll_delta(&mut self) -> f32 {
+        std::mem::take(&mut self.scroll_delta)
+    }
+
+    fn get_axis(&self, positive: KeyCode, negative: KeyCode) -> f32 {
+        let mut axis = 0.0;
+        if self.is_key_pressed(positive) {
+            axis += 1.0;
+        }
+        if self.is_key_pressed(negative) {
+            axis -= 1.0;
+        }
+        axis
+    }
+}
+
+pub trait CameraController {
+    fn update(&mut self, camera: &mut Camera, input: &mut InputState, delta_time: f32);
+}
+
+fn get_forward(yaw: f32, pitch: f32) -> Vector3<f32> {
+    Vector3::new(
+        yaw.sin() * pitch.cos(),
+        pitch.sin(),
+        yaw.cos() * pitch.cos(),
+    )
+}
+
+// WASD + right mouse drag to look around, Q/E down/up, Shift to go faster
+pub struct FreeFlyController {
+    position: Vector3<f32>,
+    yaw: f32,
+    pitch: f32,
+    pub speed: f32,
+    pub boost: f32,
+    pub sensitivity: f32,
+}
+
+impl FreeFlyController {
+    pub fn new(position: Vector3<f32>, target: Vector3<f32>) -> Self {
+        let direction = (target - position).normalize();
+
+        Self {
+            position,
+            yaw: direction.x.atan2(direction.z),
+            pitch: direction.y.asin(),
+            speed: 5.0,
+            boost: 4.0,
+            sensitivity: 0.003,
+        }
+    }
+}
+
+impl CameraController for FreeFlyController {
+    fn update(&mut self, camera: &mut Camera, input: &mut InputState, delta_time: f32) {
+        let cursor_delta = input.take_cursor_delta();
+        input.take_scroll_delta();
+
+        if input.is_button_pressed(MouseButton::Right) {
+            self.yaw += cursor_delta.x * self.sensitivity;
+            self.pitch =
+                (self.pitch - cursor_delta.y * self.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+        }
+
+        let forward = get_forward(self.yaw, self.pitch);
+        // Engine space is left handed once the view flips x, see camera.rs
+        let right = Vector3::y().cross(&forward).normalize();
+
+        let mut speed = self.speed;
+        if input.is_key_pressed(KeyCode::ShiftLeft) {
+            speed *= self.boost;
+        }
+
+        let movement = forward * input.get_axis(KeyCode::KeyW, KeyCode::KeyS)
+            + right * input.get_axis(KeyCode::KeyD, KeyCode::KeyA)
+            + Vector3::y() * input.get_axis(KeyCode::KeyE, KeyCode::KeyQ);
+
+        if movement.norm_squared() > 0.0 {
+            self.position += movement.normalize() * speed * delta_time;
+        }
+
+        camera.look_at(
+            &Point3::from(self.position),
+            &Point3::from(self.position + forward),
+        );
+    }
+}
+
+// Left mouse drag to rotate around the target, wheel to zoom, WASD to move the target
+pub struct OrbitController {
+    target: Vector3<f32>,
+    distance: f32,
+    yaw: f32,
+    pitch: f32,
+    pub speed: f32,
+    pub sensitivity: f32,
+    pub zoom_speed: f32,
+    pub min_distance: f32,
+}
+
+impl OrbitController {
+    pub fn new(position: Vector3<f32>, target: Vector3<f32>) -> Self {
+        let offset = position - target;
+        // A position on the target has no direction, look down +Z like a yaw and pitch of zero
+        let direction = (-offset)
+            .try_normalize(f32::EPSILON)
+            .unwrap_or_else(Vector3::z);
+
+        Self {
+            target,
+            distance: offset.norm().max(MIN_ORBIT_DISTANCE),
+            yaw: direction.x.atan2(direction.z),
+            // Straight up or down would leave update without a horizontal forward
+            pitch: direction.y.asin().clamp(-MAX_PITCH, MAX_PITCH),
+            speed: 5.0,
+            sensitivity: 0.005,
+            zoom_speed: 0.1,
+            min_distance: MIN_ORBIT_DISTANCE,
+        }
+    }
+}
+
+impl CameraController for OrbitController {
+    fn update(&mut self, camera: &mut Camera, input: &mut InputState, delta_time: f32) {
+        let cursor_delta = input.take_cursor_delta();
+        let scroll_delta = input.take_scroll_delta();
+
+        if input.is_button_pressed(MouseButton::Left) {
+            self.yaw -= cursor_delta.x * self.sensitivity;
+            self.pitch =
+                (self.pitch + cursor_delta.y * self.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+        }
+
+        // Exponential zoom feels the same regardless of distance
+        self.distance =
+            (self.distance * (1.0 - scroll_delta * self.zoom_speed)).max(self.min_distance);
+
+        let forward = get_forward(self.yaw, self.pitch);
+        let flat_forward = Vector3::new(forward.x, 0.0, forward.z).normalize();
+        let right = Vector3::y().cross(&flat_forward);
+
+        let movement = flat_forward * input.get_axis(KeyCode::KeyW, KeyCode::KeyS)
+            + right * input.get_axis(KeyCode::KeyD, KeyCode::KeyA)
+            + Vector3::y() * input.get_axis(KeyCode::KeyE, KeyCode::KeyQ);
+
+        if movement.norm_squared() > 0.0 {
+            self.target += movement.normalize() * self.speed * delta_time;
+        }
+
+        camera.look_at(
+            &Point3::from(self.target - forward * self.distance),
+            &Point3::from(self.target),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::relative_eq;
+
+    use super::*;
+
+    fn get_position(controller: &OrbitController) -> Vector3<f32> {
+        controller.target - get_forward(controller.yaw, controller.pitch) * controller.distance
+    }
+
+    #[test]
+    fn orbit_keeps_the_initial_position() {
+        let position = Vector3::new(3.0, 2.0, -4.0);
+        let controller = OrbitController::new(position, Vector3::new(0.0, 1.0, 0.0));
+
+        assert!(relative_eq!(
+            get_position(&controller),
+            position,
+            epsilon = 0.0001
+        ));
+    }
+
+    #[test]
+    fn orbit_around_its_own_position_is_finite() {
+        let position = Vector3::new(1.0, 2.0, 3.0);
+        let controller = OrbitController::new(position, position);
+
+        assert!(controller.yaw.is_finite());
+        assert!(controller.pitch.is_finite());
+        assert_eq!(controller.distance, MIN_ORBIT_DISTANCE);
+        assert!(get_position(&controller).iter().all(|x| x.is_finite()));
+    }
+
+    #[test]
+    fn orbit_from_straight_above_is_clamped() {
+        let controller = OrbitController::new(Vector3::new(0.0, 5.0, 0.0), Vector3::zeros());
+
+        assert_eq!(controller.pitch, -MAX_PITCH);
+        assert_eq!(controller.distance, 5.0);
+    }
+}
@@ -1,7 +1,20 @@
 use ash::{vk, Device};
 
+use crate::{debug_markers, renderer_error::Result};
+
 //pub struct CommandBufferHelpers;
 
+// Synchronization and layout change of an image barrier
+#[derive(Clone, Copy)]
+pub struct ImageTransition {
+    pub src_stage_mask: vk::PipelineStageFlags,
+    pub dst_stage_mask: vk::PipelineStageFlags,
+    pub src_access_mask: vk::AccessFlags,
+    pub dst_access_mask: vk::AccessFlags,
+    pub old_layout: vk::ImageLayout,
+    pub new_layout: vk::ImageLayout,
+}
+
 pub fn single_image_pipeline_barrier(
     device: &Device,
     command_buffer: vk::CommandBuffer,
@@ -13,37 +26,133 @@ pub fn single_image_pipeline_barrier(
     new_layout: vk::ImageLayout,
     image: vk::Image,
     aspect_mask: vk::ImageAspectFlags,
+) {
+    let transition = ImageTransition {
+        src_stage_mask,
+        dst_stage_mask,
+        src_access_mask,
+        dst_access_mask,
+        old_layout,
+        new_layout,
+    };
+
+    single_mip_pipeline_barrier(device, command_buffer, &transition, image, aspect_mask, 0);
+}
+
+pub fn single_mip_pipeline_barrier(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    transition: &ImageTransition,
+    image: vk::Image,
+    aspect_mask: vk::ImageAspectFlags,
+    mip_level: u32,
 ) {
     let subresource_range = vk::ImageSubresourceRange::default()
         .aspect_mask(aspect_mask)
-        .base_mip_level(0)
+        .base_mip_level(mip_level)
         .level_count(1)
         .base_array_layer(0)
         .layer_count(1);
 
+    image_range_pipeline_barrier(device, command_buffer, transition, image, subresource_range);
+}
+
+// For barriers over several mips or array layers, like a whole cubemap
+pub fn image_range_pipeline_barrier(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    transition: &ImageTransition,
+    image: vk::Image,
+    subresource_range: vk::ImageSubresourceRange,
+) {
     let image_memory_barriers = [vk::ImageMemoryBarrier::default()
-        .src_access_mask(src_access_mask)
-        .dst_access_mask(dst_access_mask)
-        .old_layout(old_layout)
-        .new_layout(new_layout)
+        .src_access_mask(transition.src_access_mask)
+        .dst_access_mask(transition.dst_access_mask)
+        .old_layout(transition.old_layout)
+        .new_layout(transition.new_layout)
         .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
         .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
         .image(image)
         .subresource_range(subresource_range)];
 
+    unsafe {
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            transition.src_stage_mask,
+            transition.dst_stage_mask,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &image_memory_barriers,
+        )
+    };
+}
+
+pub fn memory_pipeline_barrier(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    src_stage_mask: vk::PipelineStageFlags,
+    dst_stage_mask: vk::PipelineStageFlags,
+    src_access_mask: vk::AccessFlags,
+    dst_access_mask: vk::AccessFlags,
+) {
+    let memory_barriers = [vk::MemoryBarrier::default()
+        .src_access_mask(src_access_mask)
+        .dst_access_mask(dst_access_mask)];
+
     unsafe {
         device.cmd_pipeline_barrier(
             command_buffer,
             src_stage_mask,
             dst_stage_mask,
             vk::DependencyFlags::empty(),
+            &memory_barriers,
             &[],
             &[],
-            &image_memory_barriers,
         )
     };
 }
 
+// Records with `record` into a temporary command buffer and waits for it to finish,
+// for one off uploads and precomputation during initialization
+pub fn immediate_submit(
+    device: &Device,
+    queue: vk::Queue,
+    command_pool: vk::CommandPool,
+    record: impl FnOnce(vk::CommandBuffer),
+) -> Result<()> {
+    let allocate_info = vk::CommandBufferAllocateInfo::default()
+        .command_pool(command_pool)
+        .level(vk::CommandBufferLevel::PRIMARY)
+        .command_buffer_count(1);
+
+    let command_buffers = unsafe { device.allocate_command_buffers(&allocate_info)? };
+    let command_buffer = command_buffers[0];
+    debug_markers::set_object_name(command_buffer, "immediate_submit");
+
+    let begin_info =
+        vk::CommandBufferBeginInfo::default().flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+    unsafe { device.begin_command_buffer(command_buffer, &begin_info)? };
+
+    record(command_buffer);
+
+    unsafe {
+        device.end_command_buffer(command_buffer)?;
+
+        device.queue_submit(
+            queue,
+            &[vk::SubmitInfo::default().command_buffers(&command_buffers)],
+            vk::Fence::null(),
+        )?;
+        device.queue_wait_idle(queue)?;
+
+        device.free_command_buffers(command_pool, &command_buffers);
+    }
+
+    Ok(())
+}
+
 // impl CommandBufferHelpers {
 //     pub fn single_image_pipeline_barrier(
 //         device: &Device,
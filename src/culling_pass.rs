@@ -0,0 +1,319 @@
+use ash::{vk, Device};
+use gpu_allocator::{vulkan::Allocator, MemoryLocation};
+
+use crate::{
+    buffer::{Buffer, VulkanResource},
+    command_buffer_helpers,
+    draw_data::{object_flags, DrawData, ObjectData},
+    frustum::Frustum,
+    pipeline_manager::{CullingMaterial, PipelineManager},
+    push_constants_data::CullingPushConstantsData,
+    renderer_error::Result,
+};
+
+pub mod culling_pass_consts {
+    pub const MAX_OBJECTS: u32 = 16384;
+    pub const WORKGROUP_SIZE: u32 = 64;
+}
+
+// Indirect draws written by the culling shader for a single view
+#[derive(Clone)]
+pub struct IndirectDraws {
+    pub draw_commands: vk::Buffer,
+    pub draw_count: vk::Buffer,
+    pub max_draw_count: u32,
+}
+
+impl IndirectDraws {
+    pub const STRIDE: u32 = std::mem::size_of::<vk::DrawIndexedIndirectCommand>() as u32;
+}
+
+struct CullingView {
+    draw_commands: Buffer,
+    draw_count: Buffer,
+    set: vk::DescriptorSet,
+}
+
+impl CullingView {
+    fn new(
+        device: &Device,
+        allocator: &mut Allocator,
+        pipeline_manager: &mut PipelineManager,
+        objects: vk::Buffer,
+        name: &str,
+    ) -> Result<Self> {
+        let draw_commands = Buffer::new_empty(
+            device,
+            allocator,
+            (culling_pass_consts::MAX_OBJECTS * IndirectDraws::STRIDE) as vk::DeviceSize,
+            &format!("{}_draw_commands", name),
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::INDIRECT_BUFFER,
+            MemoryLocation::GpuOnly,
+        )?;
+
+        let draw_count = Buffer::new_empty(
+            device,
+            allocator,
+            std::mem::size_of::<u32>() as vk::DeviceSize,
+            &format!("{}_draw_count", name),
+            vk::BufferUsageFlags::STORAGE_BUFFER
+                | vk::BufferUsageFlags::INDIRECT_BUFFER
+                | vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuOnly,
+        )?;
+
+        let set = pipeline_manager.create_culling_set(
+            objects,
+            draw_commands.buffer,
+            draw_count.buffer,
+            name,
+        )?;
+
+        Ok(Self {
+            draw_commands,
+            draw_count,
+            set,
+        })
+    }
+
+    fn destroy(&mut self, device: &Device, allocator: &mut Allocator) {
+        self.draw_commands.release(device, allocator);
+        self.draw_count.release(device, allocator);
+    }
+
+    fn get_output(&self) -> IndirectDraws {
+        IndirectDraws {
+            draw_commands: self.draw_commands.buffer,
+            draw_count: self.draw_count.buffer,
+            max_draw_count: culling_pass_consts::MAX_OBJECTS,
+        }
+    }
+}
+
+// Uploads per object data and culls it on the GPU for the camera and the shadow map
+pub struct CullingPass {
+    device: Device,
+    objects: Buffer,
+    objects_set: vk::DescriptorSet,
+    camera_view: CullingView,
+    // Alpha tested objects seen by the camera, drawn with their own pipeline
+    camera_masked_view: CullingView,
+    shadow_view: CullingView,
+    material: CullingMaterial,
+}
+
+impl CullingPass {
+    pub fn new(
+        device: &Device,
+        allocator: &mut Allocator,
+        pipeline_manager: &mut PipelineManager,
+    ) -> Result<Self> {
+        let objects = Buffer::new_empty(
+            device,
+            allocator,
+            (culling_pass_consts::MAX_OBJECTS as usize * std::mem::size_of::<ObjectData>())
+                as vk::DeviceSize,
+            "objects",
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            MemoryLocation::CpuToGpu,
+        )?;
+
+        let objects_set = pipeline_manager.create_objects_set(objects.buffer)?;
+        let camera_view = CullingView::new(
+            device,
+            allocator,
+            pipeline_manager,
+            objects.buffer,
+            "camera",
+        )?;
+        let camera_masked_view = CullingView::new(
+            device,
+            allocator,
+            pipeline_manager,
+            objects.buffer,
+            "camera_masked",
+        )?;
+        let shadow_view = CullingView::new(
+            device,
+            allocator,
+            pipeline_manager,
+            objects.buffer,
+            "shadow",
+        )?;
+
+        Ok(Self {
+            device: device.clone(),
+            objects,
+            objects_set,
+            camera_view,
+            camera_masked_view,
+            shadow_view,
+            material: pipeline_manager.culling_material.clone(),
+        })
+    }
+
+    pub fn destroy(&mut self, allocator: &mut Allocator) {
+        self.objects.release(&self.device, allocator);
+        self.camera_view.destroy(&self.device, allocator);
+        self.camera_masked_view.destroy(&self.device, allocator);
+        self.shadow_view.destroy(&self.device, allocator);
+    }
+
+    // Object data is written even when not GPU driven, since the vertex shaders read it
+    pub fn update_objects(&mut self, draw_data: &DrawData) {
+        let mut object_data = draw_data.get_object_data();
+
+        if object_data.len() > culling_pass_consts::MAX_OBJECTS as usize {
+            log::warn!(
+                "Too many objects: {}, only the first {} are drawn",
+                object_data.len(),
+                culling_pass_consts::MAX_OBJECTS
+            );
+            object_data.truncate(culling_pass_consts::MAX_OBJECTS as usize);
+        }
+
+        self.objects.write(&object_data);
+    }
+
+    pub fn render(&self, command_buffer: vk::CommandBuffer, draw_data: &DrawData) {
+        let object_count =
+            (draw_data.draw_calls.len() as u32).min(culling_pass_consts::MAX_OBJECTS);
+
+        unsafe {
+            self.device.cmd_fill_buffer(
+                command_buffer,
+                self.camera_view.draw_count.buffer,
+                0,
+                vk::WHOLE_SIZE,
+                0,
+            );
+            self.device.cmd_fill_buffer(
+                command_buffer,
+                self.camera_masked_view.draw_count.buffer,
+                0,
+                vk::WHOLE_SIZE,
+                0,
+            );
+            self.device.cmd_fill_buffer(
+                command_buffer,
+                self.shadow_view.draw_count.buffer,
+                0,
+                vk::WHOLE_SIZE,
+                0,
+            );
+        }
+
+        command_buffer_helpers::memory_pipeline_barrier(
+            &self.device,
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+        );
+
+        unsafe {
+            self.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.material.pipeline,
+            );
+        }
+
+        self.dispatch(
+            command_buffer,
+            &self.camera_view,
+            &draw_data.camera_frustum,
+            object_count,
+            0,
+            // Drawn sorted by TransparentRenderPass and by the masked view
+            object_flags::TRANSPARENT | object_flags::ALPHA_TESTED,
+        );
+
+        self.dispatch(
+            command_buffer,
+            &self.camera_masked_view,
+            &draw_data.camera_frustum,
+            object_count,
+            object_flags::ALPHA_TESTED,
+            object_flags::TRANSPARENT,
+        );
+
+        self.dispatch(
+            command_buffer,
+            &self.shadow_view,
+            &draw_data.directional_light.get_frustum(),
+            object_count,
+            object_flags::CAST_SHADOWS,
+            0,
+        );
+
+        command_buffer_helpers::memory_pipeline_barrier(
+            &self.device,
+            command_buffer,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::DRAW_INDIRECT,
+            vk::AccessFlags::SHADER_WRITE,
+            vk::AccessFlags::INDIRECT_COMMAND_READ,
+        );
+    }
+
+    fn dispatch(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        view: &CullingView,
+        frustum: &Frustum,
+        object_count: u32,
+        required_flags: u32,
+        excluded_flags: u32,
+    ) {
+        let push_data = CullingPushConstantsData::new(
+            &frustum.get_planes(),
+            object_count,
+            required_flags,
+            excluded_flags,
+        );
+
+        unsafe {
+            self.device.cmd_push_constants(
+                command_buffer,
+                self.material.layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                push_data.get(),
+            );
+
+            self.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.material.layout,
+                0,
+                &[view.set],
+                &[],
+            );
+
+            self.device.cmd_dispatch(
+                command_buffer,
+                object_count.div_ceil(culling_pass_consts::WORKGROUP_SIZE),
+                1,
+                1,
+            );
+        }
+    }
+
+    pub fn get_objects_set(&self) -> vk::DescriptorSet {
+        self.objects_set
+    }
+
+    pub fn get_camera_output(&self) -> IndirectDraws {
+        self.camera_view.get_output()
+    }
+
+    pub fn get_camera_masked_output(&self) -> IndirectDraws {
+        self.camera_masked_view.get_output()
+    }
+
+    pub fn get_shadow_output(&self) -> IndirectDraws {
+        self.shadow_view.get_output()
+    }
+}
@@ -0,0 +1,196 @@
+use nalgebra::{Matrix4, Point3, Vector3, Vector4};
+
+use crate::{bounding_volume::Aabb, frustum::DepthRange};
+
+// Per circle, spheres are drawn as three circles
+const CIRCLE_SEGMENTS: usize = 32;
+// Length of the arrow head relative to the arrow
+const ARROW_HEAD_SIZE: f32 = 0.2;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct DebugLineVertex {
+    // World space
+    pub position: Vector3<f32>,
+    // Linear HDR, alpha blended
+    pub color: Vector4<f32>,
+}
+
+// Lines accumulated on the CPU during a frame and drawn by DebugDrawRenderPass after lightning.
+// Shapes are kept for a single frame, see `Renderer::get_debug_draw_mut`
+#[derive(Clone, Default)]
+pub struct DebugDraw {
+    // Line lists, two vertices per line
+    depth_tested: Vec<DebugLineVertex>,
+    overlay: Vec<DebugLineVertex>,
+    // Shapes drawn after `set_depth_test(false)` are visible through the scene
+    disable_depth_test: bool,
+}
+
+// Unit vectors perpendicular to `direction` and to each other
+fn get_perpendicular_axes(direction: &Vector3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+    let helper = if direction.x.abs() < 0.9 {
+        Vector3::x()
+    } else {
+        Vector3::y()
+    };
+
+    let u = direction.cross(&helper).normalize();
+    let v = direction.normalize().cross(&u);
+
+    (u, v)
+}
+
+impl DebugDraw {
+    pub fn set_depth_test(&mut self, depth_test: bool) {
+        self.disable_depth_test = !depth_test;
+    }
+
+    pub fn get_depth_tested_vertices(&self) -> &[DebugLineVertex] {
+        &self.depth_tested
+    }
+
+    pub fn get_overlay_vertices(&self) -> &[DebugLineVertex] {
+        &self.overlay
+    }
+
+    pub fn line(&mut self, from: &Vector3<f32>, to: &Vector3<f32>, color: &Vector4<f32>) {
+        let vertices = if self.disable_depth_test {
+            &mut self.overlay
+        } else {
+            &mut self.depth_tested
+        };
+
+        vertices.push(DebugLineVertex {
+            position: *from,
+            color: *color,
+        });
+        vertices.push(DebugLineVertex {
+            position: *to,
+            color: *color,
+        });
+    }
+
+    // Corners are indexed by their bits, x is bit 0, y bit 1 and z bit 2
+    fn box_edges(&mut self, corners: &[Vector3<f32>; 8], color: &Vector4<f32>) {
+        for corner in 0..8 {
+            for bit in [1, 2, 4] {
+                if corner & bit == 0 {
+                    self.line(&corners[corner], &corners[corner | bit], color);
+                }
+            }
+        }
+    }
+
+    pub fn aabb(&mut self, aabb: &Aabb, color: &Vector4<f32>) {
+        let corners = std::array::from_fn(|corner| {
+            Vector3::new(
+                if corner & 1 == 0 {
+                    aabb.min.x
+                } else {
+                    aabb.max.x
+                },
+                if corner & 2 == 0 {
+                    aabb.min.y
+                } else {
+                    aabb.max.y
+                },
+                if corner & 4 == 0 {
+                    aabb.min.z
+                } else {
+                    aabb.max.z
+                },
+            )
+        });
+
+        self.box_edges(&corners, color);
+    }
+
+    // Unprojects the clip space corners. Projections with an infinite far plane can't be drawn
+    pub fn frustum(
+        &mut self,
+        view_projection: &Matrix4<f32>,
+        depth_range: DepthRange,
+        color: &Vector4<f32>,
+    ) {
+        let Some(inverse_view_projection) = view_projection.try_inverse() else {
+            return;
+        };
+
+        let near_depth = match depth_range {
+            DepthRange::NegativeOneToOne => -1.0,
+            DepthRange::ZeroToOne => 0.0,
+        };
+
+        let corners = std::array::from_fn(|corner| {
+            let ndc = Point3::new(
+                if corner & 1 == 0 { -1.0 } else { 1.0 },
+                if corner & 2 == 0 { -1.0 } else { 1.0 },
+                if corner & 4 == 0 { near_depth } else { 1.0 },
+            );
+
+            inverse_view_projection.transform_point(&ndc).coords
+        });
+
+        self.box_edges(&corners, color);
+    }
+
+    fn circle(
+        &mut self,
+        center: &Vector3<f32>,
+        u: &Vector3<f32>,
+        v: &Vector3<f32>,
+        radius: f32,
+        color: &Vector4<f32>,
+    ) {
+        let point = |segment: usize| {
+            let angle = segment as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+            center + (u * angle.cos() + v * angle.sin()) * radius
+        };
+
+        for segment in 0..CIRCLE_SEGMENTS {
+            self.line(&point(segment), &point(segment + 1), color);
+        }
+    }
+
+    // One circle per axis plane
+    pub fn sphere(&mut self, center: &Vector3<f32>, radius: f32, color: &Vector4<f32>) {
+        let (x, y, z) = (Vector3::x(), Vector3::y(), Vector3::z());
+
+        self.circle(center, &x, &y, radius, color);
+        self.circle(center, &x, &z, radius, color);
+        self.circle(center, &y, &z, radius, color);
+    }
+
+    pub fn arrow(&mut self, from: &Vector3<f32>, to: &Vector3<f32>, color: &Vector4<f32>) {
+        self.line(from, to, color);
+
+        let direction = to - from;
+        let length = direction.norm();
+        if length <= f32::EPSILON {
+            return;
+        }
+
+        let (u, v) = get_perpendicular_axes(&direction);
+        let head_length = length * ARROW_HEAD_SIZE;
+        let head_base = to - direction / length * head_length;
+
+        for side in [u, -u, v, -v] {
+            self.line(to, &(head_base + side * head_length * 0.5), color);
+        }
+    }
+
+    // X red, Y green and Z blue, `size` long in the space of `transform`
+    pub fn axes(&mut self, transform: &Matrix4<f32>, size: f32) {
+        let origin = transform.transform_point(&Point3::origin()).coords;
+
+        for (axis, color) in [
+            (Vector3::x(), Vector4::new(1.0, 0.0, 0.0, 1.0)),
+            (Vector3::y(), Vector4::new(0.0, 1.0, 0.0, 1.0)),
+            (Vector3::z(), Vector4::new(0.0, 0.0, 1.0, 1.0)),
+        ] {
+            let end = transform.transform_point(&Point3::from(axis * size)).coords;
+            self.arrow(&origin, &end, &color);
+        }
+    }
+}
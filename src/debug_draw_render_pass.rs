@@ -0,0 +1,226 @@
+use ash::{vk, Device};
+use gpu_allocator::{vulkan::Allocator, MemoryLocation};
+
+use crate::{
+    buffer::{Buffer, VulkanResource},
+    command_buffer_helpers,
+    debug_draw::DebugLineVertex,
+    deferred_lightning_render_pass::DeferredLightningRenderPassOutput,
+    deferred_render_pass::DeferredRenderPassOutput,
+    draw_data::DrawData,
+    pipeline_manager::{DebugDrawMaterial, PipelineManager},
+    push_constants_data::DebugDrawPushConstantsData,
+    render_pass_attachment_output::RenderPassAttachmentOutput,
+    renderer_error::Result,
+};
+
+pub mod debug_draw_renderpass_consts {
+    // Even, lines are never split
+    pub const MAX_VERTICES: usize = 1 << 16;
+}
+
+// Lines from DebugDraw over the lightning output, before TAA so they are anti-aliased with the
+// scene. Depth tested lines are drawn first, against the G-buffer depth without writing it
+pub struct DebugDrawRenderPass {
+    device: Device,
+    render_area: vk::Rect2D,
+    color: RenderPassAttachmentOutput,
+    depth: RenderPassAttachmentOutput,
+    material: DebugDrawMaterial,
+    vertex_buffer: Buffer,
+    // Written by update_vertices, overlay vertices follow the depth tested ones
+    depth_tested_count: u32,
+    overlay_count: u32,
+}
+
+impl DebugDrawRenderPass {
+    pub fn new(
+        device: &Device,
+        allocator: &mut Allocator,
+        pipeline_manager: &PipelineManager,
+        render_area: &vk::Rect2D,
+        deferred_render_pass_output: &DeferredRenderPassOutput,
+        lightning_render_pass_output: &DeferredLightningRenderPassOutput,
+    ) -> Result<Self> {
+        let vertex_buffer = Buffer::new_empty(
+            device,
+            allocator,
+            (debug_draw_renderpass_consts::MAX_VERTICES * std::mem::size_of::<DebugLineVertex>())
+                as vk::DeviceSize,
+            "debug_draw_vertices",
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            MemoryLocation::CpuToGpu,
+        )?;
+
+        Ok(Self {
+            device: device.clone(),
+            render_area: *render_area,
+            color: lightning_render_pass_output.color.clone(),
+            depth: deferred_render_pass_output.depth.clone(),
+            material: pipeline_manager.debug_draw_material.clone(),
+            vertex_buffer,
+            depth_tested_count: 0,
+            overlay_count: 0,
+        })
+    }
+
+    pub fn destroy(&mut self, allocator: &mut Allocator) {
+        self.vertex_buffer.release(&self.device, allocator);
+    }
+
+    pub fn has_draws(&self) -> bool {
+        self.depth_tested_count + self.overlay_count > 0
+    }
+
+    // Fills the vertex buffer, only once the previous submission using it has finished
+    pub fn update_vertices(&mut self, draw_data: &DrawData) {
+        let depth_tested = draw_data.debug_draw.get_depth_tested_vertices();
+        let overlay = draw_data.debug_draw.get_overlay_vertices();
+
+        let max_vertices = debug_draw_renderpass_consts::MAX_VERTICES;
+        if depth_tested.len() + overlay.len() > max_vertices {
+            log::warn!(
+                "Too many debug lines: {}, only the first {} are drawn",
+                (depth_tested.len() + overlay.len()) / 2,
+                max_vertices / 2
+            );
+        }
+
+        let depth_tested = &depth_tested[..depth_tested.len().min(max_vertices)];
+        let overlay = &overlay[..overlay.len().min(max_vertices - depth_tested.len())];
+
+        self.vertex_buffer.write(&[depth_tested, overlay].concat());
+
+        self.depth_tested_count = depth_tested.len() as u32;
+        self.overlay_count = overlay.len() as u32;
+    }
+
+    pub fn render(&self, command_buffer: vk::CommandBuffer, draw_data: &DrawData) {
+        if !self.has_draws() {
+            return;
+        }
+
+        self.begin_render_pass(command_buffer);
+
+        let depth_compare_op = if draw_data.reverse_z {
+            vk::CompareOp::GREATER_OR_EQUAL
+        } else {
+            vk::CompareOp::LESS_OR_EQUAL
+        };
+
+        // Jittered like the G-buffer depth the lines are tested against
+        let push_data = DebugDrawPushConstantsData::new(&(draw_data.projection * draw_data.view));
+
+        unsafe {
+            self.device.cmd_push_constants(
+                command_buffer,
+                self.material.layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                push_data.get(),
+            );
+
+            self.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.material.pipeline,
+            );
+
+            self.device.cmd_bind_vertex_buffers(
+                command_buffer,
+                0,
+                &[self.vertex_buffer.buffer],
+                &[0],
+            );
+
+            self.device
+                .cmd_set_depth_compare_op(command_buffer, depth_compare_op);
+            self.device
+                .cmd_set_depth_write_enable(command_buffer, false);
+
+            if self.depth_tested_count > 0 {
+                self.device.cmd_set_depth_test_enable(command_buffer, true);
+                self.device
+                    .cmd_draw(command_buffer, self.depth_tested_count, 1, 0, 0);
+            }
+
+            if self.overlay_count > 0 {
+                self.device.cmd_set_depth_test_enable(command_buffer, false);
+                self.device.cmd_draw(
+                    command_buffer,
+                    self.overlay_count,
+                    1,
+                    self.depth_tested_count,
+                    0,
+                );
+            }
+        };
+
+        self.end_render_pass(command_buffer);
+    }
+
+    fn begin_render_pass(&self, command_buffer: vk::CommandBuffer) {
+        let color_attachments = [vk::RenderingAttachmentInfo::default()
+            .image_view(self.color.image_view)
+            .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .resolve_mode(vk::ResolveModeFlags::NONE)
+            .resolve_image_view(vk::ImageView::null())
+            .resolve_image_layout(vk::ImageLayout::UNDEFINED)
+            .load_op(vk::AttachmentLoadOp::LOAD)
+            .store_op(vk::AttachmentStoreOp::STORE)];
+
+        let depth_attachment = vk::RenderingAttachmentInfo::default()
+            .image_view(self.depth.image_view)
+            .image_layout(self.depth.image_layout)
+            .resolve_mode(vk::ResolveModeFlags::NONE)
+            .resolve_image_view(vk::ImageView::null())
+            .resolve_image_layout(vk::ImageLayout::UNDEFINED)
+            .load_op(vk::AttachmentLoadOp::LOAD)
+            // Read only, made visible by the barrier at the end of DeferredRenderPass
+            .store_op(vk::AttachmentStoreOp::NONE);
+
+        // Left readable by the lightning, skybox or transparent pass
+        command_buffer_helpers::single_image_pipeline_barrier(
+            &self.device,
+            command_buffer,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            self.color.image_layout,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            self.color.image,
+            vk::ImageAspectFlags::COLOR,
+        );
+
+        let rendering_info = vk::RenderingInfo::default()
+            .render_area(self.render_area)
+            .layer_count(1)
+            .view_mask(0)
+            .color_attachments(&color_attachments)
+            .depth_attachment(&depth_attachment);
+
+        unsafe {
+            self.device
+                .cmd_begin_rendering(command_buffer, &rendering_info);
+        }
+    }
+
+    // Hands the lightning output back to the following passes in the layout it reports
+    fn end_render_pass(&self, command_buffer: vk::CommandBuffer) {
+        unsafe { self.device.cmd_end_rendering(command_buffer) };
+
+        command_buffer_helpers::single_image_pipeline_barrier(
+            &self.device,
+            command_buffer,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            vk::AccessFlags::SHADER_READ,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            self.color.image_layout,
+            self.color.image,
+            vk::ImageAspectFlags::COLOR,
+        );
+    }
+}
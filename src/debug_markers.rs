@@ -0,0 +1,53 @@
+use std::{ffi::CString, sync::RwLock};
+
+use ash::{ext::debug_utils, vk, Device, Instance};
+
+// Set by the renderer when the instance has debug utils, everything here is a no-op otherwise.
+// Global so resources can be named where they are created without passing the loader around
+static DEBUG_UTILS: RwLock<Option<debug_utils::Device>> = RwLock::new(None);
+
+pub fn init(instance: &Instance, device: &Device) {
+    *DEBUG_UTILS.write().unwrap() = Some(debug_utils::Device::new(instance, device));
+}
+
+// Before the device is destroyed
+pub fn shutdown() {
+    *DEBUG_UTILS.write().unwrap() = None;
+}
+
+// Shown by validation messages and graphics debuggers instead of the raw handle
+pub fn set_object_name<T: vk::Handle>(handle: T, name: &str) {
+    let Some(debug_utils) = &*DEBUG_UTILS.read().unwrap() else {
+        return;
+    };
+    let Ok(name) = CString::new(name) else {
+        return;
+    };
+
+    let name_info = vk::DebugUtilsObjectNameInfoEXT::default()
+        .object_handle(handle)
+        .object_name(&name);
+
+    if let Err(error) = unsafe { debug_utils.set_debug_utils_object_name(&name_info) } {
+        log::debug!("Failed to name {}: {}", name.to_string_lossy(), error);
+    }
+}
+
+// Labels nest, every begin needs an end in the same command buffer
+pub fn begin_label(command_buffer: vk::CommandBuffer, name: &str) {
+    let Some(debug_utils) = &*DEBUG_UTILS.read().unwrap() else {
+        return;
+    };
+    // Skipping the label on an interior nul would unbalance the end
+    let name = CString::new(name).unwrap_or_default();
+
+    let label = vk::DebugUtilsLabelEXT::default().label_name(&name);
+
+    unsafe { debug_utils.cmd_begin_debug_utils_label(command_buffer, &label) };
+}
+
+pub fn end_label(command_buffer: vk::CommandBuffer) {
+    if let Some(debug_utils) = &*DEBUG_UTILS.read().unwrap() {
+        unsafe { debug_utils.cmd_end_debug_utils_label(command_buffer) };
+    }
+}
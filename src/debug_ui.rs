@@ -0,0 +1,217 @@
+use std::fmt::Debug;
+
+use winit::{event::WindowEvent, window::Window};
+
+use crate::renderer::Renderer;
+
+// What a frame of the UI hands to the renderer, see `Renderer::update_debug_ui`
+pub struct DebugUiOutput {
+    pub primitives: Vec<egui::ClippedPrimitive>,
+    pub textures_delta: egui::TexturesDelta,
+    pub pixels_per_point: f32,
+}
+
+// egui context fed by the window events, drawn by DebugUiRenderPass
+pub struct DebugUi {
+    context: egui::Context,
+    state: egui_winit::State,
+    visible: bool,
+}
+
+impl DebugUi {
+    pub fn new(window: &Window) -> Self {
+        let context = egui::Context::default();
+        let state = egui_winit::State::new(
+            context.clone(),
+            egui::ViewportId::ROOT,
+            window,
+            Some(window.scale_factor() as f32),
+            None,
+            None,
+        );
+
+        Self {
+            context,
+            state,
+            visible: true,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    // Returns true when the UI consumed the event, it shouldn't move the camera then
+    pub fn on_window_event(&mut self, window: &Window, event: &WindowEvent) -> bool {
+        if !self.visible {
+            return false;
+        }
+
+        self.state.on_window_event(window, event).consumed
+    }
+
+    // Builds the UI with `build`, nothing is drawn while it's hidden
+    pub fn run(&mut self, window: &Window, build: impl FnMut(&egui::Context)) -> DebugUiOutput {
+        if !self.visible {
+            return DebugUiOutput {
+                primitives: Vec::new(),
+                textures_delta: egui::TexturesDelta::default(),
+                pixels_per_point: self.context.pixels_per_point(),
+            };
+        }
+
+        let raw_input = self.state.take_egui_input(window);
+        let output = self.context.run(raw_input, build);
+
+        self.state
+            .handle_platform_output(window, output.platform_output);
+
+        DebugUiOutput {
+            primitives: self
+                .context
+                .tessellate(output.shapes, output.pixels_per_point),
+            textures_delta: output.textures_delta,
+            pixels_per_point: output.pixels_per_point,
+        }
+    }
+}
+
+// Lists every value of a settings enum by cycling through `next`
+fn enum_combo_box<T: Copy + PartialEq + Debug>(
+    ui: &mut egui::Ui,
+    label: &str,
+    value: &mut T,
+    next: fn(T) -> T,
+) {
+    egui::ComboBox::from_label(label)
+        .selected_text(format!("{:?}", value))
+        .show_ui(ui, |ui| {
+            let first = *value;
+            let mut option = first;
+
+            loop {
+                ui.selectable_value(value, option, format!("{:?}", option));
+                option = next(option);
+
+                if option == first {
+                    break;
+                }
+            }
+        });
+}
+
+// Render settings and frame statistics
+pub fn draw_settings_window(context: &egui::Context, renderer: &mut Renderer) {
+    let frame_stats = renderer.get_frame_stats();
+    let gpu_timings = renderer.get_gpu_timings().clone();
+    let culling_stats = renderer.get_culling_stats();
+
+    egui::Window::new("sr-engine")
+        .default_width(280.0)
+        .show(context, |ui| {
+            ui.label(format!(
+                "{:.0} fps, {:.2} ms (p95 {:.2} ms, p99 {:.2} ms)",
+                frame_stats.fps,
+                frame_stats.frame_ms,
+                frame_stats.frame_ms_p95,
+                frame_stats.frame_ms_p99
+            ));
+            ui.label(format!(
+                "CPU {:.2} ms, GPU {:.2} ms",
+                frame_stats.cpu_ms, frame_stats.gpu_ms
+            ));
+            ui.label(format!(
+                "{}/{} objects visible, {} draws, {} triangles",
+                culling_stats.visible,
+                culling_stats.total,
+                frame_stats.draw_calls,
+                frame_stats.triangles
+            ));
+
+            egui::CollapsingHeader::new("GPU timings").show(ui, |ui| {
+                egui::Grid::new("gpu_timings").show(ui, |ui| {
+                    for pass in &gpu_timings.passes {
+                        ui.label(pass.name);
+                        ui.label(format!("{:.3} ms", pass.duration_ms));
+                        ui.end_row();
+                    }
+                });
+            });
+
+            let settings = renderer.get_settings_mut();
+
+            egui::CollapsingHeader::new("Settings")
+                .default_open(true)
+                .show(ui, |ui| {
+                    ui.checkbox(&mut settings.gpu_driven, "GPU driven");
+
+                    ui.add(
+                        egui::Slider::new(&mut settings.exposure, -8.0..=8.0)
+                            .text("Exposure")
+                            .suffix(" EV"),
+                    );
+                    enum_combo_box(
+                        ui,
+                        "Tonemapping",
+                        &mut settings.tonemapping_operator,
+                        |operator| operator.next(),
+                    );
+                    enum_combo_box(
+                        ui,
+                        "Anti-aliasing",
+                        &mut settings.anti_aliasing,
+                        |anti_aliasing| anti_aliasing.next(),
+                    );
+                    enum_combo_box(ui, "Debug view", &mut settings.debug_view, |debug_view| {
+                        debug_view.next()
+                    });
+                    ui.checkbox(&mut settings.debug_bounds, "Debug bounds");
+                    ui.add(
+                        egui::Slider::new(&mut settings.environment_intensity, 0.0..=4.0)
+                            .text("Environment intensity"),
+                    );
+
+                    ui.separator();
+                    ui.checkbox(&mut settings.bloom, "Bloom");
+                    ui.add_enabled_ui(settings.bloom, |ui| {
+                        ui.add(
+                            egui::Slider::new(&mut settings.bloom_intensity, 0.0..=0.5)
+                                .text("Intensity"),
+                        );
+                        ui.add(
+                            egui::Slider::new(&mut settings.bloom_threshold, 0.0..=4.0)
+                                .text("Threshold"),
+                        );
+                        ui.add(egui::Slider::new(&mut settings.bloom_knee, 0.0..=1.0).text("Knee"));
+                    });
+
+                    ui.separator();
+                    ui.checkbox(&mut settings.ssao, "SSAO");
+                    ui.add_enabled_ui(settings.ssao, |ui| {
+                        ui.add(
+                            egui::Slider::new(&mut settings.ssao_radius, 0.05..=2.0).text("Radius"),
+                        );
+                        ui.add(egui::Slider::new(&mut settings.ssao_bias, 0.0..=0.1).text("Bias"));
+                        ui.add(
+                            egui::Slider::new(&mut settings.ssao_power, 0.1..=4.0).text("Power"),
+                        );
+                    });
+
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        let mut limited = settings.frame_limit.is_some();
+                        let mut limit = settings.frame_limit.unwrap_or(60.0);
+
+                        ui.checkbox(&mut limited, "Frame limit");
+                        ui.add_enabled(
+                            limited,
+                            egui::DragValue::new(&mut limit)
+                                .range(1.0..=1000.0)
+                                .suffix(" fps"),
+                        );
+
+                        settings.frame_limit = limited.then_some(limit);
+                    });
+                });
+        });
+}
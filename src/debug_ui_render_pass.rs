@@ -0,0 +1,551 @@
+use std::collections::HashMap;
+
+use ash::{vk, Device};
+use egui::{epaint::Vertex, TextureId};
+use gpu_allocator::{vulkan::Allocator, MemoryLocation};
+use nalgebra::Vector2;
+
+use crate::{
+    buffer::{Buffer, VulkanResource},
+    command_buffer_helpers,
+    draw_data::DrawData,
+    image::{Image, ImageCreateInfo},
+    pipeline_manager::{DebugUiMaterial, PipelineManager},
+    push_constants_data::DebugUiPushConstantsData,
+    renderer_error::Result,
+    tonemapping_render_pass::DisplayEncoding,
+};
+
+pub mod debug_ui_renderpass_consts {
+    pub const MAX_VERTICES: usize = 1 << 16;
+    pub const MAX_INDICES: usize = 3 << 16;
+    // egui colors are sRGB, stored without decoding so the shader multiplies them as egui expects
+    pub const TEXTURE_FORMAT: ash::vk::Format = ash::vk::Format::R8G8B8A8_UNORM;
+}
+
+// egui mesh with its texture resolved, positions and the clip rect are in points
+#[derive(Clone)]
+pub struct DebugUiMesh {
+    pub clip_rect: egui::Rect,
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    pub set: vk::DescriptorSet,
+}
+
+#[derive(Clone, Default)]
+pub struct DebugUiDrawData {
+    pub meshes: Vec<DebugUiMesh>,
+    pub pixels_per_point: f32,
+}
+
+struct DebugUiTexture {
+    image: Image,
+    set: vk::DescriptorSet,
+}
+
+fn get_texture_name(id: TextureId) -> String {
+    match id {
+        TextureId::Managed(id) => format!("debug_ui_texture_{}", id),
+        TextureId::User(id) => format!("debug_ui_user_texture_{}", id),
+    }
+}
+
+// Textures egui allocates, mostly the font atlas. Shared by all frame workers
+#[derive(Default)]
+pub struct DebugUiTextures {
+    textures: HashMap<TextureId, DebugUiTexture>,
+    // The descriptor pool can't free single sets, sets of freed textures are reused instead
+    free_sets: Vec<vk::DescriptorSet>,
+    // egui frees textures after the frame that last drew them
+    pending_free: Vec<TextureId>,
+}
+
+impl DebugUiTextures {
+    pub fn destroy(&mut self, device: &Device, allocator: &mut Allocator) {
+        for texture in self.textures.values_mut() {
+            texture.image.destroy(device, allocator);
+        }
+    }
+
+    pub fn get_set(&self, id: TextureId) -> Option<vk::DescriptorSet> {
+        self.textures.get(&id).map(|texture| texture.set)
+    }
+
+    pub fn update(
+        &mut self,
+        device: &Device,
+        allocator: &mut Allocator,
+        pipeline_manager: &mut PipelineManager,
+        queue: vk::Queue,
+        command_pool: vk::CommandPool,
+        textures_delta: &egui::TexturesDelta,
+    ) -> Result<()> {
+        let replaces_texture = textures_delta
+            .set
+            .iter()
+            .any(|(id, delta)| delta.pos.is_none() && self.textures.contains_key(id));
+
+        // Frames in flight may still sample the textures that are destroyed. Both only happen when
+        // the font atlas grows or a texture is dropped, so waiting is fine
+        if replaces_texture || !self.pending_free.is_empty() {
+            unsafe { device.device_wait_idle()? };
+        }
+
+        for id in self.pending_free.drain(..) {
+            if let Some(mut texture) = self.textures.remove(&id) {
+                texture.image.destroy(device, allocator);
+                self.free_sets.push(texture.set);
+            }
+        }
+
+        for (id, delta) in &textures_delta.set {
+            let egui::ImageData::Color(image) = &delta.image;
+
+            match delta.pos {
+                None => {
+                    let new_image =
+                        Self::create_image(device, allocator, queue, command_pool, *id, image)?;
+                    self.set_texture(device, allocator, pipeline_manager, *id, new_image)?;
+                }
+                Some(pos) => {
+                    if let Some(texture) = self.textures.get(id) {
+                        Self::update_region(
+                            device,
+                            allocator,
+                            queue,
+                            command_pool,
+                            &texture.image,
+                            pos,
+                            image,
+                        )?;
+                    }
+                }
+            }
+        }
+
+        self.pending_free.extend_from_slice(&textures_delta.free);
+
+        Ok(())
+    }
+
+    // Texture options are ignored, everything is sampled linearly with clamping
+    fn create_image(
+        device: &Device,
+        allocator: &mut Allocator,
+        queue: vk::Queue,
+        command_pool: vk::CommandPool,
+        id: TextureId,
+        image: &egui::ColorImage,
+    ) -> Result<Image> {
+        let create_info = ImageCreateInfo {
+            extent: vk::Extent3D::default()
+                .width(image.width() as u32)
+                .height(image.height() as u32)
+                .depth(1),
+            image_type: vk::ImageType::TYPE_2D,
+            format: debug_ui_renderpass_consts::TEXTURE_FORMAT,
+            usage: vk::ImageUsageFlags::SAMPLED,
+            view_type: vk::ImageViewType::TYPE_2D,
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_levels: 1,
+            array_layers: 1,
+        };
+
+        Image::new_with_data(
+            device,
+            allocator,
+            queue,
+            command_pool,
+            &create_info,
+            &image.pixels,
+            &get_texture_name(id),
+        )
+    }
+
+    fn set_texture(
+        &mut self,
+        device: &Device,
+        allocator: &mut Allocator,
+        pipeline_manager: &mut PipelineManager,
+        id: TextureId,
+        new_image: Image,
+    ) -> Result<()> {
+        if let Some(texture) = self.textures.get_mut(&id) {
+            texture.image.destroy(device, allocator);
+            texture.image = new_image;
+            pipeline_manager.update_debug_ui_set(texture.set, texture.image.image_view);
+        } else {
+            let set = match self.free_sets.pop() {
+                Some(set) => {
+                    pipeline_manager.update_debug_ui_set(set, new_image.image_view);
+                    set
+                }
+                None => pipeline_manager
+                    .create_debug_ui_set(new_image.image_view, &get_texture_name(id))?,
+            };
+
+            self.textures.insert(
+                id,
+                DebugUiTexture {
+                    image: new_image,
+                    set,
+                },
+            );
+        }
+
+        Ok(())
+    }
+
+    // Glyphs added to the font atlas
+    fn update_region(
+        device: &Device,
+        allocator: &mut Allocator,
+        queue: vk::Queue,
+        command_pool: vk::CommandPool,
+        image: &Image,
+        pos: [usize; 2],
+        region: &egui::ColorImage,
+    ) -> Result<()> {
+        let mut staging_buffer = Buffer::new_empty(
+            device,
+            allocator,
+            std::mem::size_of_val(region.pixels.as_slice()) as vk::DeviceSize,
+            "debug_ui_texture_region_staging",
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            MemoryLocation::CpuToGpu,
+        )?;
+        staging_buffer.write(&region.pixels);
+
+        command_buffer_helpers::immediate_submit(device, queue, command_pool, |command_buffer| {
+            // Waits for frames in flight that sample the texture
+            command_buffer_helpers::single_image_pipeline_barrier(
+                device,
+                command_buffer,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::NONE,
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                image.image,
+                vk::ImageAspectFlags::COLOR,
+            );
+
+            let copy_region = vk::BufferImageCopy::default()
+                .buffer_offset(0)
+                .buffer_row_length(0)
+                .buffer_image_height(0)
+                .image_subresource(
+                    vk::ImageSubresourceLayers::default()
+                        .aspect_mask(vk::ImageAspectFlags::COLOR)
+                        .mip_level(0)
+                        .base_array_layer(0)
+                        .layer_count(1),
+                )
+                .image_offset(vk::Offset3D {
+                    x: pos[0] as i32,
+                    y: pos[1] as i32,
+                    z: 0,
+                })
+                .image_extent(vk::Extent3D {
+                    width: region.width() as u32,
+                    height: region.height() as u32,
+                    depth: 1,
+                });
+
+            unsafe {
+                device.cmd_copy_buffer_to_image(
+                    command_buffer,
+                    staging_buffer.buffer,
+                    image.image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &[copy_region],
+                )
+            };
+
+            command_buffer_helpers::single_image_pipeline_barrier(
+                device,
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::AccessFlags::SHADER_READ,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                image.image,
+                vk::ImageAspectFlags::COLOR,
+            );
+        })?;
+
+        staging_buffer.release(device, allocator);
+
+        Ok(())
+    }
+}
+
+struct DebugUiDraw {
+    scissor: vk::Rect2D,
+    set: vk::DescriptorSet,
+    first_index: u32,
+    index_count: u32,
+    vertex_offset: i32,
+}
+
+// egui overlay drawn into the swapchain image after tonemapping and FXAA
+pub struct DebugUiRenderPass {
+    device: Device,
+    render_area: vk::Rect2D,
+    material: DebugUiMaterial,
+    vertex_buffer: Buffer,
+    index_buffer: Buffer,
+    // Written by update_meshes
+    draws: Vec<DebugUiDraw>,
+    screen_size: Vector2<f32>,
+    display_encoding: DisplayEncoding,
+}
+
+impl DebugUiRenderPass {
+    pub fn new(
+        device: &Device,
+        allocator: &mut Allocator,
+        pipeline_manager: &PipelineManager,
+        render_area: &vk::Rect2D,
+    ) -> Result<Self> {
+        let vertex_buffer = Buffer::new_empty(
+            device,
+            allocator,
+            (debug_ui_renderpass_consts::MAX_VERTICES * std::mem::size_of::<Vertex>())
+                as vk::DeviceSize,
+            "debug_ui_vertices",
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            MemoryLocation::CpuToGpu,
+        )?;
+
+        let index_buffer = Buffer::new_empty(
+            device,
+            allocator,
+            (debug_ui_renderpass_consts::MAX_INDICES * std::mem::size_of::<u32>())
+                as vk::DeviceSize,
+            "debug_ui_indices",
+            vk::BufferUsageFlags::INDEX_BUFFER,
+            MemoryLocation::CpuToGpu,
+        )?;
+
+        Ok(Self {
+            device: device.clone(),
+            render_area: *render_area,
+            material: pipeline_manager.debug_ui_material.clone(),
+            vertex_buffer,
+            index_buffer,
+            draws: Vec::new(),
+            screen_size: Vector2::zeros(),
+            display_encoding: pipeline_manager.get_display_encoding(),
+        })
+    }
+
+    pub fn destroy(&mut self, allocator: &mut Allocator) {
+        self.vertex_buffer.release(&self.device, allocator);
+        self.index_buffer.release(&self.device, allocator);
+    }
+
+    pub fn has_draws(&self) -> bool {
+        !self.draws.is_empty()
+    }
+
+    // Fills the vertex and index buffers, only once the previous submission using them has finished
+    pub fn update_meshes(&mut self, draw_data: &DrawData) {
+        let debug_ui = &draw_data.debug_ui;
+        let extent = self.render_area.extent;
+
+        self.draws.clear();
+        self.screen_size = Vector2::new(extent.width as f32, extent.height as f32)
+            / debug_ui.pixels_per_point.max(f32::EPSILON);
+
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+
+        for mesh in &debug_ui.meshes {
+            if vertices.len() + mesh.vertices.len() > debug_ui_renderpass_consts::MAX_VERTICES
+                || indices.len() + mesh.indices.len() > debug_ui_renderpass_consts::MAX_INDICES
+            {
+                log::warn!("Debug UI exceeds its vertex or index buffer, the rest isn't drawn");
+                break;
+            }
+
+            let Some(scissor) = self.get_scissor(mesh.clip_rect, debug_ui.pixels_per_point) else {
+                continue;
+            };
+
+            self.draws.push(DebugUiDraw {
+                scissor,
+                set: mesh.set,
+                first_index: indices.len() as u32,
+                index_count: mesh.indices.len() as u32,
+                vertex_offset: vertices.len() as i32,
+            });
+
+            vertices.extend_from_slice(&mesh.vertices);
+            indices.extend_from_slice(&mesh.indices);
+        }
+
+        self.vertex_buffer.write(&vertices);
+        self.index_buffer.write(&indices);
+    }
+
+    // In pixels, clamped to the render area. None when nothing is visible
+    fn get_scissor(&self, clip_rect: egui::Rect, pixels_per_point: f32) -> Option<vk::Rect2D> {
+        let extent = self.render_area.extent;
+
+        let min_x = (clip_rect.min.x * pixels_per_point).round().max(0.0) as u32;
+        let min_y = (clip_rect.min.y * pixels_per_point).round().max(0.0) as u32;
+        let max_x =
+            ((clip_rect.max.x * pixels_per_point).round().max(0.0) as u32).min(extent.width);
+        let max_y =
+            ((clip_rect.max.y * pixels_per_point).round().max(0.0) as u32).min(extent.height);
+
+        if min_x >= max_x || min_y >= max_y {
+            return None;
+        }
+
+        Some(vk::Rect2D {
+            offset: vk::Offset2D {
+                x: min_x as i32,
+                y: min_y as i32,
+            },
+            extent: vk::Extent2D {
+                width: max_x - min_x,
+                height: max_y - min_y,
+            },
+        })
+    }
+
+    pub fn render(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        swapchain_image: vk::Image,
+        swapchain_image_view: vk::ImageView,
+    ) {
+        if self.draws.is_empty() {
+            return;
+        }
+
+        self.begin_render_pass(command_buffer, swapchain_image, swapchain_image_view);
+
+        let push_data = DebugUiPushConstantsData::new(
+            &self.screen_size,
+            self.display_encoding.get_shader_index(),
+        );
+
+        unsafe {
+            self.device.cmd_push_constants(
+                command_buffer,
+                self.material.layout,
+                vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                0,
+                push_data.get(),
+            );
+
+            self.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.material.pipeline,
+            );
+
+            self.device.cmd_bind_vertex_buffers(
+                command_buffer,
+                0,
+                &[self.vertex_buffer.buffer],
+                &[0],
+            );
+            self.device.cmd_bind_index_buffer(
+                command_buffer,
+                self.index_buffer.buffer,
+                0,
+                vk::IndexType::UINT32,
+            );
+
+            for draw in &self.draws {
+                self.device
+                    .cmd_set_scissor(command_buffer, 0, &[draw.scissor]);
+
+                self.device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.material.layout,
+                    0,
+                    &[draw.set],
+                    &[],
+                );
+
+                self.device.cmd_draw_indexed(
+                    command_buffer,
+                    draw.index_count,
+                    1,
+                    draw.first_index,
+                    draw.vertex_offset,
+                    0,
+                );
+            }
+        };
+
+        self.end_render_pass(command_buffer, swapchain_image);
+    }
+
+    // Loads the swapchain image the tonemapping or FXAA pass left for presenting
+    fn begin_render_pass(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        swapchain_image: vk::Image,
+        swapchain_image_view: vk::ImageView,
+    ) {
+        let swapchain_attachments = [vk::RenderingAttachmentInfo::default()
+            .image_view(swapchain_image_view)
+            .image_layout(vk::ImageLayout::ATTACHMENT_OPTIMAL)
+            .resolve_mode(vk::ResolveModeFlags::NONE)
+            .resolve_image_view(vk::ImageView::null())
+            .resolve_image_layout(vk::ImageLayout::UNDEFINED)
+            .load_op(vk::AttachmentLoadOp::LOAD)
+            .store_op(vk::AttachmentStoreOp::STORE)];
+
+        command_buffer_helpers::single_image_pipeline_barrier(
+            &self.device,
+            command_buffer,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            vk::ImageLayout::PRESENT_SRC_KHR,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            swapchain_image,
+            vk::ImageAspectFlags::COLOR,
+        );
+
+        let rendering_info = vk::RenderingInfo::default()
+            .render_area(self.render_area)
+            .layer_count(1)
+            .view_mask(0)
+            .color_attachments(&swapchain_attachments);
+
+        unsafe {
+            self.device
+                .cmd_begin_rendering(command_buffer, &rendering_info);
+        }
+    }
+
+    fn end_render_pass(&self, command_buffer: vk::CommandBuffer, swapchain_image: vk::Image) {
+        unsafe { self.device.cmd_end_rendering(command_buffer) };
+
+        command_buffer_helpers::single_image_pipeline_barrier(
+            &self.device,
+            command_buffer,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            vk::AccessFlags::NONE,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::PRESENT_SRC_KHR,
+            swapchain_image,
+            vk::ImageAspectFlags::COLOR,
+        );
+    }
+}
@@ -1,25 +1,51 @@
 use ash::{vk, Device};
+use gpu_allocator::vulkan::Allocator;
 
 use crate::{
     command_buffer_helpers,
     deferred_render_pass::DeferredRenderPassOutput,
-    image::Image,
+    draw_data::DrawData,
+    image::{Image, ImageCreateInfo},
+    image_based_lighting::ImageBasedLightingOutput,
     pipeline_manager::{DeferredLightningMaterial, PipelineManager},
     push_constants_data::LightningPushConstantsData,
+    render_pass_attachment_output::RenderPassAttachmentOutput,
+    renderer_error::Result,
     shadow_map_render_pass::ShadowMapRenderPassOutput,
+    ssao_render_pass::SsaoRenderPassOutput,
 };
 
-const SWAPCHAIN_CLEAR_VALUE: vk::ClearValue = vk::ClearValue {
+pub mod lightning_renderpass_consts {
+    use ash::vk;
+
+    // Linear scene radiance, tonemapped later by TonemappingRenderPass
+    pub const HDR_COLOR: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+}
+
+const CLEAR_VALUE: vk::ClearValue = vk::ClearValue {
     color: vk::ClearColorValue {
         float32: [0.0, 0.0, 0.0, 0.0],
     },
 };
 
+// Outputs of the earlier passes sampled by the lightning shader
+pub struct DeferredLightningRenderPassInput<'a> {
+    pub deferred: &'a DeferredRenderPassOutput,
+    pub shadow_map: &'a ShadowMapRenderPassOutput,
+    pub ssao: &'a SsaoRenderPassOutput,
+    pub image_based_lighting: &'a ImageBasedLightingOutput,
+}
+
+#[derive(Clone)]
+pub struct DeferredLightningRenderPassOutput {
+    pub color: RenderPassAttachmentOutput,
+}
+
 pub struct DeferredLightningRenderPass {
     device: Device,
     // TODO: As parameter to render?
     render_area: vk::Rect2D,
-    deferred_render_pass_output: DeferredRenderPassOutput,
+    color_image: Image,
     shadow_map_render_pass_output: ShadowMapRenderPassOutput,
     material: DeferredLightningMaterial,
 }
@@ -27,36 +53,44 @@ pub struct DeferredLightningRenderPass {
 impl DeferredLightningRenderPass {
     pub fn new(
         device: &Device,
+        allocator: &mut Allocator,
         pipeline_manager: &mut PipelineManager,
         render_area: &vk::Rect2D,
-        deferred_render_pass_output: &DeferredRenderPassOutput,
-        shadow_map_render_pass_output: &ShadowMapRenderPassOutput,
-    ) -> Self {
-        Self {
+        input: &DeferredLightningRenderPassInput,
+    ) -> Result<Self> {
+        Ok(Self {
             device: device.clone(),
             render_area: *render_area,
-            deferred_render_pass_output: deferred_render_pass_output.clone(),
-            shadow_map_render_pass_output: shadow_map_render_pass_output.clone(),
-            material: pipeline_manager.create_deferred_lightning_material(
-                deferred_render_pass_output.color.image_view,
-                deferred_render_pass_output.normal.image_view,
-                deferred_render_pass_output.position.image_view,
-                shadow_map_render_pass_output.depth.image_view,
-            ),
-        }
+            color_image: Self::create_color_image(device, allocator, render_area)?,
+            shadow_map_render_pass_output: input.shadow_map.clone(),
+            material: pipeline_manager.create_deferred_lightning_material(input)?,
+        })
     }
 
-    pub fn render(
-        &self,
-        command_buffer: vk::CommandBuffer,
-        swapchain_image: vk::Image,
-        swapchain_image_view: vk::ImageView,
-        light_space: &nalgebra::Matrix4<f32>,
-        view_direction: &nalgebra::Vector3<f32>,
-    ) {
-        self.begin_render_pass(command_buffer, swapchain_image, swapchain_image_view);
+    pub fn destroy(&mut self, allocator: &mut Allocator) {
+        self.color_image.destroy(&self.device, allocator);
+    }
 
-        let push_data = LightningPushConstantsData::new(light_space, view_direction);
+    pub fn render(&self, command_buffer: vk::CommandBuffer, draw_data: &DrawData) {
+        self.begin_render_pass(command_buffer);
+
+        // Jittered like the G-buffer depth the positions are reconstructed from
+        let inverse_view_projection = (draw_data.projection * draw_data.view)
+            .try_inverse()
+            .unwrap();
+
+        // Matches the depth clear value of DeferredRenderPass
+        let far_depth = if draw_data.reverse_z { 0.0 } else { 1.0 };
+
+        let push_data = LightningPushConstantsData::new(
+            &(draw_data.directional_light.get_projection()
+                * draw_data.directional_light.get_view()),
+            &inverse_view_projection,
+            &draw_data.camera_position,
+            draw_data.settings.environment_intensity,
+            far_depth,
+            draw_data.settings.debug_view.get_shader_index(),
+        );
 
         unsafe {
             self.device.cmd_push_constants(
@@ -85,74 +119,31 @@ impl DeferredLightningRenderPass {
             self.device.cmd_draw(command_buffer, 6, 1, 0, 0);
         };
 
-        self.end_render_pass(command_buffer, swapchain_image);
+        self.end_render_pass(command_buffer);
     }
 
-    fn begin_render_pass(
-        &self,
-        command_buffer: vk::CommandBuffer,
-        swapchain_image: vk::Image,
-        swapchain_image_view: vk::ImageView,
-    ) {
-        let swapchain_attachments = [vk::RenderingAttachmentInfo::default()
-            .image_view(swapchain_image_view)
-            .image_layout(vk::ImageLayout::ATTACHMENT_OPTIMAL)
+    fn begin_render_pass(&self, command_buffer: vk::CommandBuffer) {
+        let color_attachments = [vk::RenderingAttachmentInfo::default()
+            .image_view(self.color_image.image_view)
+            .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
             .resolve_mode(vk::ResolveModeFlags::NONE)
             .resolve_image_view(vk::ImageView::null())
             .resolve_image_layout(vk::ImageLayout::UNDEFINED)
             .load_op(vk::AttachmentLoadOp::CLEAR)
             .store_op(vk::AttachmentStoreOp::STORE)
-            .clear_value(SWAPCHAIN_CLEAR_VALUE)];
+            .clear_value(CLEAR_VALUE)];
 
+        // Sampled by the tonemapping pass of the previous frame
         command_buffer_helpers::single_image_pipeline_barrier(
             &self.device,
             command_buffer,
-            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
             vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
             vk::AccessFlags::NONE,
             vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
             vk::ImageLayout::UNDEFINED,
             vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-            swapchain_image,
-            vk::ImageAspectFlags::COLOR,
-        );
-
-        command_buffer_helpers::single_image_pipeline_barrier(
-            &self.device,
-            command_buffer,
-            vk::PipelineStageFlags::TOP_OF_PIPE,
-            vk::PipelineStageFlags::FRAGMENT_SHADER,
-            vk::AccessFlags::NONE,
-            vk::AccessFlags::SHADER_READ,
-            self.deferred_render_pass_output.color.image_layout,
-            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-            self.deferred_render_pass_output.color.image,
-            vk::ImageAspectFlags::COLOR,
-        );
-
-        command_buffer_helpers::single_image_pipeline_barrier(
-            &self.device,
-            command_buffer,
-            vk::PipelineStageFlags::TOP_OF_PIPE,
-            vk::PipelineStageFlags::FRAGMENT_SHADER,
-            vk::AccessFlags::NONE,
-            vk::AccessFlags::SHADER_READ,
-            self.deferred_render_pass_output.normal.image_layout,
-            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-            self.deferred_render_pass_output.normal.image,
-            vk::ImageAspectFlags::COLOR,
-        );
-
-        command_buffer_helpers::single_image_pipeline_barrier(
-            &self.device,
-            command_buffer,
-            vk::PipelineStageFlags::TOP_OF_PIPE,
-            vk::PipelineStageFlags::FRAGMENT_SHADER,
-            vk::AccessFlags::NONE,
-            vk::AccessFlags::SHADER_READ,
-            self.deferred_render_pass_output.position.image_layout,
-            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-            self.deferred_render_pass_output.position.image,
+            self.color_image.image,
             vk::ImageAspectFlags::COLOR,
         );
 
@@ -173,7 +164,7 @@ impl DeferredLightningRenderPass {
             .render_area(self.render_area)
             .layer_count(1)
             .view_mask(0)
-            .color_attachments(&swapchain_attachments);
+            .color_attachments(&color_attachments);
         //.depth_attachment(&depth_attachment);
 
         unsafe {
@@ -182,20 +173,53 @@ impl DeferredLightningRenderPass {
         }
     }
 
-    fn end_render_pass(&self, command_buffer: vk::CommandBuffer, swapchain_image: vk::Image) {
+    // Post processing passes only sample the output
+    fn end_render_pass(&self, command_buffer: vk::CommandBuffer) {
         unsafe { self.device.cmd_end_rendering(command_buffer) };
 
         command_buffer_helpers::single_image_pipeline_barrier(
             &self.device,
             command_buffer,
             vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
             vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
-            vk::AccessFlags::NONE,
+            vk::AccessFlags::SHADER_READ,
             vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-            vk::ImageLayout::PRESENT_SRC_KHR,
-            swapchain_image,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            self.color_image.image,
             vk::ImageAspectFlags::COLOR,
         );
     }
+
+    fn create_color_image(
+        device: &Device,
+        allocator: &mut Allocator,
+        render_area: &vk::Rect2D,
+    ) -> Result<Image> {
+        let create_info = ImageCreateInfo {
+            extent: vk::Extent3D::default()
+                .width(render_area.extent.width)
+                .height(render_area.extent.height)
+                .depth(1),
+            image_type: vk::ImageType::TYPE_2D,
+            format: lightning_renderpass_consts::HDR_COLOR,
+            usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            view_type: vk::ImageViewType::TYPE_2D,
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_levels: 1,
+            array_layers: 1,
+        };
+
+        Image::new(device, allocator, &create_info, "lightning_color")
+    }
+
+    pub fn get_output(&self) -> DeferredLightningRenderPassOutput {
+        DeferredLightningRenderPassOutput {
+            color: RenderPassAttachmentOutput {
+                image: self.color_image.image,
+                image_view: self.color_image.image_view,
+                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            },
+        }
+    }
 }
@@ -5,34 +5,46 @@ use gpu_allocator::vulkan::Allocator;
 
 use crate::{
     command_buffer_helpers,
+    culling_pass::IndirectDraws,
     draw_data::DrawData,
+    gbuffer_layout::{GBufferFormat, GBufferTarget, GBUFFER_LAYOUT},
     image::{Image, ImageCreateInfo},
+    pipeline_manager::{DeferredMaskedMaterial, DeferredMaterial, PipelineManager},
     push_constants_data::PushConstantsData,
+    render_pass_attachment_output::RenderPassAttachmentOutput,
+    renderer_error::Result,
     shadow_map_render_pass::deferred_renderpass_consts::{self},
 };
 
-#[derive(Clone)]
-pub struct RenderPassAttachmentOutput {
-    pub image: vk::Image,
-    pub image_view: vk::ImageView,
-    pub image_layout: vk::ImageLayout, // = vk::ImageLayout::eUndefined;
-}
-
 #[derive(Clone)]
 pub struct DeferredRenderPassOutput {
-    pub color: RenderPassAttachmentOutput,
+    pub albedo: RenderPassAttachmentOutput,
     pub normal: RenderPassAttachmentOutput,
-    pub position: RenderPassAttachmentOutput,
+    pub material: RenderPassAttachmentOutput,
+    pub velocity: RenderPassAttachmentOutput,
+    // Read only after the pass, sampled for position reconstruction and depth tested against
     pub depth: RenderPassAttachmentOutput,
 }
 
+impl GBufferFormat {
+    pub fn to_vk(self) -> vk::Format {
+        match self {
+            GBufferFormat::R8G8B8A8Unorm => vk::Format::R8G8B8A8_UNORM,
+            GBufferFormat::R16G16Snorm => vk::Format::R16G16_SNORM,
+            GBufferFormat::R8G8Unorm => vk::Format::R8G8_UNORM,
+            GBufferFormat::R16G16Sfloat => vk::Format::R16G16_SFLOAT,
+        }
+    }
+}
+
 pub struct DeferredRenderPass {
     device: Device,
     render_area: vk::Rect2D,
-    color_image: Image,
-    normal_image: Image,
-    position_image: Image,
+    // In GBUFFER_LAYOUT order
+    target_images: Vec<Image>,
     depth_image: Image,
+    material: DeferredMaterial,
+    masked_material: DeferredMaskedMaterial,
 }
 const CLEAR_VALUE: vk::ClearValue = vk::ClearValue {
     color: vk::ClearColorValue {
@@ -45,124 +57,203 @@ const DEPTH_CLEAR_VALUE: vk::ClearValue = vk::ClearValue {
         stencil: 0,
     },
 };
+const REVERSE_Z_DEPTH_CLEAR_VALUE: vk::ClearValue = vk::ClearValue {
+    depth_stencil: vk::ClearDepthStencilValue {
+        depth: 0.0,
+        stencil: 0,
+    },
+};
 
 impl DeferredRenderPass {
-    pub fn new(device: &Device, allocator: &mut Allocator, render_area: &vk::Rect2D) -> Self {
-        Self {
+    pub fn new(
+        device: &Device,
+        allocator: &mut Allocator,
+        pipeline_manager: &mut PipelineManager,
+        render_area: &vk::Rect2D,
+        masked_base_color: vk::ImageView,
+    ) -> Result<Self> {
+        Ok(Self {
             device: device.clone(),
             render_area: render_area.clone(),
-            color_image: Self::create_color_image(
-                device,
-                allocator,
-                render_area,
-                deferred_renderpass_consts::COLOR,
-            ),
-            normal_image: Self::create_color_image(
-                device,
-                allocator,
-                render_area,
-                deferred_renderpass_consts::NORMAL,
-            ),
-            position_image: Self::create_color_image(
-                device,
-                allocator,
-                render_area,
-                deferred_renderpass_consts::POSITION,
-            ),
-            depth_image: Self::create_depth_image(device, allocator, render_area),
-        }
+            target_images: GBUFFER_LAYOUT
+                .iter()
+                .map(|layout| {
+                    Self::create_color_image(
+                        device,
+                        allocator,
+                        render_area,
+                        layout.format.to_vk(),
+                        &format!("gbuffer_{:?}", layout.target).to_lowercase(),
+                    )
+                })
+                .collect::<Result<_>>()?,
+            depth_image: Self::create_depth_image(device, allocator, render_area)?,
+            material: pipeline_manager.deferred_material.clone(),
+            masked_material: pipeline_manager.create_deferred_masked_material(masked_base_color)?,
+        })
     }
 
     pub fn destroy(&mut self, allocator: &mut Allocator) {
-        self.color_image.destroy(&self.device, allocator);
-        self.normal_image.destroy(&self.device, allocator);
-        self.position_image.destroy(&self.device, allocator);
+        for image in &mut self.target_images {
+            image.destroy(&self.device, allocator);
+        }
         self.depth_image.destroy(&self.device, allocator);
     }
 
-    pub fn render(&self, command_buffer: vk::CommandBuffer, draw_data: &DrawData) {
-        self.begin_render_pass(command_buffer);
+    pub fn render(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        draw_data: &DrawData,
+        objects_set: vk::DescriptorSet,
+        indirect_draws: &IndirectDraws,
+        masked_indirect_draws: &IndirectDraws,
+    ) {
+        self.begin_render_pass(command_buffer, draw_data.reverse_z);
+
+        let depth_compare_op = if draw_data.reverse_z {
+            vk::CompareOp::GREATER_OR_EQUAL
+        } else {
+            vk::CompareOp::LESS_OR_EQUAL
+        };
 
-        // TODO: Why tf is deferredPipelineLayout a part of DrawData
+        let push_data = PushConstantsData::new(&draw_data.view, &draw_data.projection);
+        let buffers = [
+            draw_data.geometry_buffers.positions_buffer,
+            draw_data.geometry_buffers.attributes_buffer,
+        ];
+        let offsets = [0, 0];
 
-        for draw_call in &draw_data.draw_calls {
-            let push_data =
-                PushConstantsData::new(&draw_call.model, &draw_data.view, &draw_data.projection);
-            let buffers = [
-                draw_call.mesh.positions_buffer,
-                draw_call.mesh.attributes_buffer,
-            ];
-            let offsets = [0, 0];
+        unsafe {
+            self.device.cmd_push_constants(
+                command_buffer,
+                self.material.layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                push_data.get(),
+            );
+
+            self.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.material.layout,
+                0,
+                &[objects_set],
+                &[],
+            );
+
+            // Stays bound while the opaque pipeline is used, its layout has no set 1
+            self.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.masked_material.layout,
+                1,
+                &[self.masked_material.set],
+                &[],
+            );
 
-            unsafe {
-                self.device.cmd_push_constants(
-                    command_buffer,
-                    draw_data.deferred_pipeline_layout,
-                    vk::ShaderStageFlags::VERTEX,
-                    0,
-                    push_data.get(),
-                );
+            self.device
+                .cmd_bind_vertex_buffers(command_buffer, 0, &buffers, &offsets);
+
+            self.device.cmd_bind_index_buffer(
+                command_buffer,
+                draw_data.geometry_buffers.index_buffer,
+                0,
+                vk::IndexType::UINT32,
+            );
+        }
 
+        if draw_data.settings.gpu_driven {
+            unsafe {
                 self.device.cmd_bind_pipeline(
                     command_buffer,
                     vk::PipelineBindPoint::GRAPHICS,
-                    draw_call.pipeline,
+                    self.material.pipeline,
                 );
 
                 self.device
-                    .cmd_bind_vertex_buffers(command_buffer, 0, &buffers, &offsets);
+                    .cmd_set_depth_compare_op(command_buffer, depth_compare_op);
 
-                self.device.cmd_bind_index_buffer(
+                self.device.cmd_draw_indexed_indirect_count(
                     command_buffer,
-                    draw_call.mesh.index_buffer,
+                    indirect_draws.draw_commands,
+                    0,
+                    indirect_draws.draw_count,
                     0,
-                    vk::IndexType::UINT32,
+                    indirect_draws.max_draw_count,
+                    IndirectDraws::STRIDE,
                 );
 
-                self.device.cmd_draw_indexed(
+                // After the opaque draws, which keep early depth testing
+                self.device.cmd_bind_pipeline(
                     command_buffer,
-                    draw_call.mesh.index_count,
-                    1,
-                    0,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.masked_material.pipeline,
+                );
+
+                self.device.cmd_draw_indexed_indirect_count(
+                    command_buffer,
+                    masked_indirect_draws.draw_commands,
                     0,
+                    masked_indirect_draws.draw_count,
                     0,
+                    masked_indirect_draws.max_draw_count,
+                    IndirectDraws::STRIDE,
                 );
-            };
+            }
+        } else {
+            // First instance selects the object data in the vertex shader
+            for (object_index, draw_call) in draw_data
+                .get_visible_draw_calls()
+                .filter(|(_, draw_call)| !draw_call.is_transparent())
+            {
+                unsafe {
+                    self.device.cmd_bind_pipeline(
+                        command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        draw_call.pipeline,
+                    );
+
+                    self.device
+                        .cmd_set_depth_compare_op(command_buffer, depth_compare_op);
+
+                    self.device.cmd_draw_indexed(
+                        command_buffer,
+                        draw_call.mesh.index_count,
+                        1,
+                        draw_call.mesh.first_index,
+                        draw_call.mesh.vertex_offset,
+                        object_index,
+                    );
+                };
+            }
         }
 
         self.end_render_pass(command_buffer);
     }
 
-    fn begin_render_pass(&self, command_buffer: vk::CommandBuffer) {
-        let color_attachment = vk::RenderingAttachmentInfo::default()
-            .image_view(self.color_image.image_view)
-            .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
-            .resolve_mode(vk::ResolveModeFlags::NONE)
-            .resolve_image_view(vk::ImageView::null())
-            .resolve_image_layout(vk::ImageLayout::UNDEFINED)
-            .load_op(vk::AttachmentLoadOp::CLEAR)
-            .store_op(vk::AttachmentStoreOp::STORE)
-            .clear_value(CLEAR_VALUE);
-
-        let normal_attachment = vk::RenderingAttachmentInfo::default()
-            .image_view(self.normal_image.image_view)
-            .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
-            .resolve_mode(vk::ResolveModeFlags::NONE)
-            .resolve_image_view(vk::ImageView::null())
-            .resolve_image_layout(vk::ImageLayout::UNDEFINED)
-            .load_op(vk::AttachmentLoadOp::CLEAR)
-            .store_op(vk::AttachmentStoreOp::STORE)
-            .clear_value(CLEAR_VALUE);
+    fn begin_render_pass(&self, command_buffer: vk::CommandBuffer, reverse_z: bool) {
+        let depth_clear_value = if reverse_z {
+            REVERSE_Z_DEPTH_CLEAR_VALUE
+        } else {
+            DEPTH_CLEAR_VALUE
+        };
 
-        let position_attachment = vk::RenderingAttachmentInfo::default()
-            .image_view(self.position_image.image_view)
-            .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
-            .resolve_mode(vk::ResolveModeFlags::NONE)
-            .resolve_image_view(vk::ImageView::null())
-            .resolve_image_layout(vk::ImageLayout::UNDEFINED)
-            .load_op(vk::AttachmentLoadOp::CLEAR)
-            .store_op(vk::AttachmentStoreOp::STORE)
-            .clear_value(CLEAR_VALUE);
+        // Velocity is cleared to no motion, TAA reprojects the background with the camera instead
+        let attachments: Vec<vk::RenderingAttachmentInfo> = self
+            .target_images
+            .iter()
+            .map(|image| {
+                vk::RenderingAttachmentInfo::default()
+                    .image_view(image.image_view)
+                    .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                    .resolve_mode(vk::ResolveModeFlags::NONE)
+                    .resolve_image_view(vk::ImageView::null())
+                    .resolve_image_layout(vk::ImageLayout::UNDEFINED)
+                    .load_op(vk::AttachmentLoadOp::CLEAR)
+                    .store_op(vk::AttachmentStoreOp::STORE)
+                    .clear_value(CLEAR_VALUE)
+            })
+            .collect();
 
         let depth_attachment = vk::RenderingAttachmentInfo::default()
             .image_view(self.depth_image.image_view)
@@ -172,50 +263,24 @@ impl DeferredRenderPass {
             .resolve_image_layout(vk::ImageLayout::UNDEFINED)
             .load_op(vk::AttachmentLoadOp::CLEAR)
             .store_op(vk::AttachmentStoreOp::STORE)
-            .clear_value(DEPTH_CLEAR_VALUE);
-
-        let attachments = [color_attachment, normal_attachment, position_attachment];
+            .clear_value(depth_clear_value);
 
         // // TODO: Single vk::CmdPipelineBarrier
 
-        command_buffer_helpers::single_image_pipeline_barrier(
-            &self.device,
-            command_buffer,
-            vk::PipelineStageFlags::TOP_OF_PIPE,
-            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-            vk::AccessFlags::NONE,
-            vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
-            vk::ImageLayout::UNDEFINED,
-            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-            self.color_image.image,
-            vk::ImageAspectFlags::COLOR,
-        );
-
-        command_buffer_helpers::single_image_pipeline_barrier(
-            &self.device,
-            command_buffer,
-            vk::PipelineStageFlags::TOP_OF_PIPE,
-            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-            vk::AccessFlags::NONE,
-            vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
-            vk::ImageLayout::UNDEFINED,
-            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-            self.normal_image.image,
-            vk::ImageAspectFlags::COLOR,
-        );
-
-        command_buffer_helpers::single_image_pipeline_barrier(
-            &self.device,
-            command_buffer,
-            vk::PipelineStageFlags::TOP_OF_PIPE,
-            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-            vk::AccessFlags::NONE,
-            vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
-            vk::ImageLayout::UNDEFINED,
-            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-            self.position_image.image,
-            vk::ImageAspectFlags::COLOR,
-        );
+        for image in &self.target_images {
+            command_buffer_helpers::single_image_pipeline_barrier(
+                &self.device,
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::AccessFlags::NONE,
+                vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                image.image,
+                vk::ImageAspectFlags::COLOR,
+            );
+        }
 
         command_buffer_helpers::single_image_pipeline_barrier(
             &self.device,
@@ -243,8 +308,38 @@ impl DeferredRenderPass {
         }
     }
 
+    // Color targets are only sampled afterwards, by SSAO, lightning and TAA. Depth is also
+    // sampled, and still tested against by the skybox and transparent passes
     fn end_render_pass(&self, command_buffer: vk::CommandBuffer) {
         unsafe { self.device.cmd_end_rendering(command_buffer) };
+
+        for image in &self.target_images {
+            command_buffer_helpers::single_image_pipeline_barrier(
+                &self.device,
+                command_buffer,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                vk::AccessFlags::SHADER_READ,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                image.image,
+                vk::ImageAspectFlags::COLOR,
+            );
+        }
+
+        command_buffer_helpers::single_image_pipeline_barrier(
+            &self.device,
+            command_buffer,
+            vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+            vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            vk::AccessFlags::SHADER_READ | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ,
+            vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+            self.depth_image.image,
+            vk::ImageAspectFlags::DEPTH,
+        );
     }
 
     fn create_color_image(
@@ -252,7 +347,8 @@ impl DeferredRenderPass {
         allocator: &mut Allocator,
         render_area: &vk::Rect2D,
         format: vk::Format,
-    ) -> Image {
+        name: &str,
+    ) -> Result<Image> {
         let create_info = ImageCreateInfo {
             extent: vk::Extent3D::default()
                 .width(render_area.extent.width)
@@ -263,16 +359,61 @@ impl DeferredRenderPass {
             usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
             view_type: vk::ImageViewType::TYPE_2D,
             aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_levels: 1,
+            array_layers: 1,
+        };
+
+        Image::new(device, allocator, &create_info, name)
+    }
+
+    // White with a grid of round holes in its alpha, for the alpha tested objects
+    pub fn create_masked_base_color_image(
+        device: &Device,
+        allocator: &mut Allocator,
+        queue: vk::Queue,
+        command_pool: vk::CommandPool,
+    ) -> Result<Image> {
+        const SIZE: u32 = 64;
+        const CELL_SIZE: u32 = 8;
+        const HOLE_RADIUS: f32 = 2.5;
+
+        let texels: Vec<[u8; 4]> = (0..SIZE * SIZE)
+            .map(|index| {
+                let center = (CELL_SIZE as f32 - 1.0) * 0.5;
+                let x = (index % SIZE % CELL_SIZE) as f32 - center;
+                let y = (index / SIZE % CELL_SIZE) as f32 - center;
+                let alpha = if x.hypot(y) < HOLE_RADIUS { 0 } else { u8::MAX };
+                [u8::MAX, u8::MAX, u8::MAX, alpha]
+            })
+            .collect();
+
+        let create_info = ImageCreateInfo {
+            extent: vk::Extent3D::default().width(SIZE).height(SIZE).depth(1),
+            image_type: vk::ImageType::TYPE_2D,
+            format: vk::Format::R8G8B8A8_UNORM,
+            usage: vk::ImageUsageFlags::SAMPLED,
+            view_type: vk::ImageViewType::TYPE_2D,
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_levels: 1,
+            array_layers: 1,
         };
 
-        Image::new(device, allocator, &create_info)
+        Image::new_with_data(
+            device,
+            allocator,
+            queue,
+            command_pool,
+            &create_info,
+            &texels,
+            "masked_base_color",
+        )
     }
 
     fn create_depth_image(
         device: &Device,
         allocator: &mut Allocator,
         render_area: &vk::Rect2D,
-    ) -> Image {
+    ) -> Result<Image> {
         let create_info = ImageCreateInfo {
             extent: vk::Extent3D::default()
                 .width(render_area.extent.width)
@@ -280,44 +421,43 @@ impl DeferredRenderPass {
                 .depth(1),
             image_type: vk::ImageType::TYPE_2D,
             format: deferred_renderpass_consts::DEPTH,
-            usage: vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            usage: vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
             view_type: vk::ImageViewType::TYPE_2D,
             aspect_mask: vk::ImageAspectFlags::DEPTH,
+            mip_levels: 1,
+            array_layers: 1,
         };
 
-        Image::new(device, allocator, &create_info)
+        Image::new(device, allocator, &create_info, "gbuffer_depth")
     }
 
     pub fn get_output(&self) -> DeferredRenderPassOutput {
-        let color = RenderPassAttachmentOutput {
-            image: self.color_image.image,
-            image_view: self.color_image.image_view,
-            image_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-        };
-
-        let normal = RenderPassAttachmentOutput {
-            image: self.normal_image.image,
-            image_view: self.normal_image.image_view,
-            image_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-        };
-
-        let position = RenderPassAttachmentOutput {
-            image: self.position_image.image,
-            image_view: self.position_image.image_view,
-            image_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-        };
-
         let depth = RenderPassAttachmentOutput {
             image: self.depth_image.image,
             image_view: self.depth_image.image_view,
-            image_layout: vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+            image_layout: vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
         };
 
         DeferredRenderPassOutput {
-            color,
-            normal,
-            position,
+            albedo: self.get_target_output(GBufferTarget::Albedo),
+            normal: self.get_target_output(GBufferTarget::Normal),
+            material: self.get_target_output(GBufferTarget::Material),
+            velocity: self.get_target_output(GBufferTarget::Velocity),
             depth,
         }
     }
+
+    fn get_target_output(&self, target: GBufferTarget) -> RenderPassAttachmentOutput {
+        let index = GBUFFER_LAYOUT
+            .iter()
+            .position(|layout| layout.target == target)
+            .unwrap();
+        let image = &self.target_images[index];
+
+        RenderPassAttachmentOutput {
+            image: image.image,
+            image_view: image.image_view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        }
+    }
 }
@@ -0,0 +1,363 @@
+use ash::{khr::surface, khr::swapchain, vk, Instance};
+
+use crate::renderer_error::{RendererError, Result};
+
+// Forces a device by its index in the enumeration order or by a case insensitive part of its name
+pub const DEVICE_ENV_VAR: &str = "SR_ENGINE_DEVICE";
+
+pub struct SelectedDevice {
+    pub physical_device: vk::PhysicalDevice,
+    // Supports both graphics and presenting to the surface
+    pub queue_family_index: u32,
+}
+
+struct Candidate {
+    index: usize,
+    physical_device: vk::PhysicalDevice,
+    name: String,
+    device_type: vk::PhysicalDeviceType,
+    device_local_memory: vk::DeviceSize,
+    // None when the device can't run the renderer
+    queue_family_index: Option<u32>,
+    missing: Vec<&'static str>,
+}
+
+impl Candidate {
+    fn new(
+        instance: &Instance,
+        surface_loader: &surface::Instance,
+        surface: vk::SurfaceKHR,
+        index: usize,
+        physical_device: vk::PhysicalDevice,
+    ) -> Result<Self> {
+        let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+        let name = properties
+            .device_name_as_c_str()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let memory_properties =
+            unsafe { instance.get_physical_device_memory_properties(physical_device) };
+        let device_local_memory = memory_properties
+            .memory_heaps_as_slice()
+            .iter()
+            .filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+            .map(|heap| heap.size)
+            .sum();
+
+        let mut missing = Self::get_missing_features(instance, physical_device, &properties)?;
+
+        let queue_family_index =
+            Self::get_queue_family_index(instance, surface_loader, surface, physical_device)?;
+        if queue_family_index.is_none() {
+            missing.push("graphics queue with present support");
+        }
+
+        Ok(Self {
+            index,
+            physical_device,
+            name,
+            device_type: properties.device_type,
+            device_local_memory,
+            queue_family_index: queue_family_index.filter(|_| missing.is_empty()),
+            missing,
+        })
+    }
+
+    // Everything create_device enables
+    fn get_missing_features(
+        instance: &Instance,
+        physical_device: vk::PhysicalDevice,
+        properties: &vk::PhysicalDeviceProperties,
+    ) -> Result<Vec<&'static str>> {
+        let mut missing = Vec::new();
+
+        if properties.api_version < vk::API_VERSION_1_3 {
+            missing.push("Vulkan 1.3");
+        }
+
+        let mut vulkan_12_features = vk::PhysicalDeviceVulkan12Features::default();
+        let mut vulkan_13_features = vk::PhysicalDeviceVulkan13Features::default();
+        let mut features = vk::PhysicalDeviceFeatures2::default()
+            .push_next(&mut vulkan_12_features)
+            .push_next(&mut vulkan_13_features);
+
+        unsafe { instance.get_physical_device_features2(physical_device, &mut features) };
+        let core_features = features.features;
+
+        if core_features.draw_indirect_first_instance == vk::FALSE {
+            missing.push("draw indirect first instance");
+        }
+
+        if vulkan_12_features.buffer_device_address == vk::FALSE {
+            missing.push("buffer device address");
+        }
+        if vulkan_12_features.draw_indirect_count == vk::FALSE {
+            missing.push("draw indirect count");
+        }
+        if vulkan_13_features.dynamic_rendering == vk::FALSE {
+            missing.push("dynamic rendering");
+        }
+        if vulkan_13_features.synchronization2 == vk::FALSE {
+            missing.push("synchronization2");
+        }
+
+        let extensions =
+            unsafe { instance.enumerate_device_extension_properties(physical_device)? };
+        let has_swapchain = extensions
+            .iter()
+            .any(|extension| extension.extension_name_as_c_str() == Ok(swapchain::NAME));
+        if !has_swapchain {
+            missing.push("swapchain");
+        }
+
+        Ok(missing)
+    }
+
+    fn get_queue_family_index(
+        instance: &Instance,
+        surface_loader: &surface::Instance,
+        surface: vk::SurfaceKHR,
+        physical_device: vk::PhysicalDevice,
+    ) -> Result<Option<u32>> {
+        let queue_families =
+            unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
+
+        for (index, queue_family) in queue_families.iter().enumerate() {
+            let index = index as u32;
+            let supports_present = unsafe {
+                surface_loader.get_physical_device_surface_support(
+                    physical_device,
+                    index,
+                    surface,
+                )?
+            };
+
+            if queue_family.queue_flags.contains(vk::QueueFlags::GRAPHICS) && supports_present {
+                return Ok(Some(index));
+            }
+        }
+
+        Ok(None)
+    }
+
+    // Device type first, memory only decides between devices of the same type
+    fn get_score(&self) -> u64 {
+        let type_score = match self.device_type {
+            vk::PhysicalDeviceType::DISCRETE_GPU => 4,
+            vk::PhysicalDeviceType::INTEGRATED_GPU => 3,
+            vk::PhysicalDeviceType::VIRTUAL_GPU => 2,
+            vk::PhysicalDeviceType::CPU => 1,
+            _ => 0,
+        };
+
+        (type_score << 48) + self.device_local_memory / (1024 * 1024)
+    }
+
+    fn matches(&self, preference: &str) -> bool {
+        match preference.parse::<usize>() {
+            Ok(index) => self.index == index,
+            Err(_) => self
+                .name
+                .to_lowercase()
+                .contains(&preference.to_lowercase()),
+        }
+    }
+}
+
+fn select_candidate<'a>(
+    candidates: &'a [Candidate],
+    preference: Option<&str>,
+) -> Option<&'a Candidate> {
+    let suitable = || {
+        candidates
+            .iter()
+            .filter(|candidate| candidate.queue_family_index.is_some())
+    };
+
+    let preferred = preference.and_then(|preference| {
+        let preferred = suitable().find(|candidate| candidate.matches(preference));
+        if preferred.is_none() {
+            log::warn!(
+                "No suitable device matches {}={}, selecting by score",
+                DEVICE_ENV_VAR,
+                preference
+            );
+        }
+        preferred
+    });
+
+    preferred.or_else(|| suitable().max_by_key(|candidate| candidate.get_score()))
+}
+
+// `preference` overrides the scoring, it falls back to the best scoring device when it doesn't
+// match a suitable one
+pub fn select_physical_device(
+    instance: &Instance,
+    surface_loader: &surface::Instance,
+    surface: vk::SurfaceKHR,
+    preference: Option<&str>,
+) -> Result<SelectedDevice> {
+    let physical_devices = unsafe { instance.enumerate_physical_devices()? };
+
+    let candidates = physical_devices
+        .into_iter()
+        .enumerate()
+        .map(|(index, physical_device)| {
+            Candidate::new(instance, surface_loader, surface, index, physical_device)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    for candidate in &candidates {
+        if candidate.missing.is_empty() {
+            log::info!(
+                "Device {}: {} ({:?}, {} MiB), score {}",
+                candidate.index,
+                candidate.name,
+                candidate.device_type,
+                candidate.device_local_memory / (1024 * 1024),
+                candidate.get_score()
+            );
+        } else {
+            log::info!(
+                "Device {}: {} ({:?}), unsuitable, missing {}",
+                candidate.index,
+                candidate.name,
+                candidate.device_type,
+                candidate.missing.join(", ")
+            );
+        }
+    }
+
+    let selected =
+        select_candidate(&candidates, preference).ok_or(RendererError::NoSuitableDevice)?;
+    let forced = preference.is_some_and(|preference| selected.matches(preference));
+
+    log::info!(
+        "Selected device {}: {}{}",
+        selected.index,
+        selected.name,
+        if forced {
+            format!(" (forced by {})", DEVICE_ENV_VAR)
+        } else {
+            String::new()
+        }
+    );
+
+    Ok(SelectedDevice {
+        physical_device: selected.physical_device,
+        queue_family_index: selected.queue_family_index.unwrap(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIB: vk::DeviceSize = 1024 * 1024;
+
+    fn candidate(
+        index: usize,
+        name: &str,
+        device_type: vk::PhysicalDeviceType,
+        device_local_memory: vk::DeviceSize,
+    ) -> Candidate {
+        Candidate {
+            index,
+            physical_device: vk::PhysicalDevice::null(),
+            name: name.to_string(),
+            device_type,
+            device_local_memory,
+            queue_family_index: Some(0),
+            missing: Vec::new(),
+        }
+    }
+
+    fn unsuitable(mut candidate: Candidate) -> Candidate {
+        candidate.queue_family_index = None;
+        candidate.missing.push("dynamic rendering");
+        candidate
+    }
+
+    fn get_selected_index(candidates: &[Candidate], preference: Option<&str>) -> Option<usize> {
+        select_candidate(candidates, preference).map(|candidate| candidate.index)
+    }
+
+    #[test]
+    fn device_type_outranks_memory() {
+        let candidates = [
+            candidate(
+                0,
+                "iGPU",
+                vk::PhysicalDeviceType::INTEGRATED_GPU,
+                16384 * MIB,
+            ),
+            candidate(1, "dGPU", vk::PhysicalDeviceType::DISCRETE_GPU, 2048 * MIB),
+            candidate(2, "llvmpipe", vk::PhysicalDeviceType::CPU, 65536 * MIB),
+        ];
+
+        assert_eq!(get_selected_index(&candidates, None), Some(1));
+        assert!(candidates[1].get_score() > candidates[0].get_score());
+        assert!(candidates[0].get_score() > candidates[2].get_score());
+    }
+
+    #[test]
+    fn memory_breaks_ties() {
+        let candidates = [
+            candidate(0, "Small", vk::PhysicalDeviceType::DISCRETE_GPU, 4096 * MIB),
+            candidate(1, "Large", vk::PhysicalDeviceType::DISCRETE_GPU, 8192 * MIB),
+        ];
+
+        assert_eq!(get_selected_index(&candidates, None), Some(1));
+    }
+
+    #[test]
+    fn unsuitable_devices_are_skipped() {
+        let candidates = [
+            unsuitable(candidate(
+                0,
+                "dGPU",
+                vk::PhysicalDeviceType::DISCRETE_GPU,
+                8192 * MIB,
+            )),
+            candidate(
+                1,
+                "iGPU",
+                vk::PhysicalDeviceType::INTEGRATED_GPU,
+                1024 * MIB,
+            ),
+        ];
+
+        assert_eq!(get_selected_index(&candidates, None), Some(1));
+        // Forcing an unsuitable device falls back to the score
+        assert_eq!(get_selected_index(&candidates, Some("0")), Some(1));
+
+        assert_eq!(get_selected_index(&candidates[..1], None), None);
+        assert_eq!(get_selected_index(&[], None), None);
+    }
+
+    #[test]
+    fn preference_by_index_or_name() {
+        let candidates = [
+            candidate(
+                0,
+                "NVIDIA GeForce RTX 4070",
+                vk::PhysicalDeviceType::DISCRETE_GPU,
+                12288 * MIB,
+            ),
+            candidate(
+                1,
+                "Intel(R) UHD Graphics",
+                vk::PhysicalDeviceType::INTEGRATED_GPU,
+                1024 * MIB,
+            ),
+        ];
+
+        assert_eq!(get_selected_index(&candidates, Some("1")), Some(1));
+        assert_eq!(get_selected_index(&candidates, Some("intel")), Some(1));
+        assert_eq!(get_selected_index(&candidates, Some("RTX")), Some(0));
+        // Neither an index nor a name of any device
+        assert_eq!(get_selected_index(&candidates, Some("7")), Some(0));
+        assert_eq!(get_selected_index(&candidates, Some("radeon")), Some(0));
+    }
+}
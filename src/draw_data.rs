@@ -1,7 +1,12 @@
 use ash::vk;
-use nalgebra::Matrix4;
+use nalgebra::{Matrix4, Vector4};
 
+use crate::bounding_volume::{Aabb, BoundingSphere};
 use crate::camera::Camera;
+use crate::debug_draw::DebugDraw;
+use crate::debug_ui_render_pass::DebugUiDrawData;
+use crate::frustum::{DepthRange, Frustum};
+use crate::render_settings::RenderSettings;
 
 pub struct DirectionalLight {
     position: nalgebra::Vector3<f32>,
@@ -35,71 +40,370 @@ impl DirectionalLight {
     pub fn get_projection(&self) -> nalgebra::Matrix4<f32> {
         self.projection
     }
+
+    pub fn get_position(&self) -> nalgebra::Vector3<f32> {
+        self.position
+    }
+
+    pub fn get_target(&self) -> nalgebra::Vector3<f32> {
+        self.target
+    }
+
+    // Casters behind the light's near plane can still cast into the frustum
+    pub fn get_frustum(&self) -> Frustum {
+        Frustum::from_matrix(
+            &(self.get_projection() * self.get_view()),
+            DepthRange::NegativeOneToOne,
+        )
+        .without_near_plane()
+    }
 }
 
+// Shared by every mesh, see MeshManager
 #[derive(Clone)]
-pub struct MeshData {
-    pub index_count: u32,
+pub struct GeometryBuffers {
     pub index_buffer: vk::Buffer,
     pub positions_buffer: vk::Buffer,
     pub attributes_buffer: vk::Buffer,
 }
 
+// Range inside GeometryBuffers
+#[derive(Clone)]
+pub struct MeshData {
+    pub index_count: u32,
+    pub first_index: u32,
+    pub vertex_offset: i32,
+    // Object space
+    pub aabb: Aabb,
+    pub bounding_sphere: BoundingSphere,
+}
+
 impl MeshData {
     pub fn new(
         index_count: u32,
-        index_buffer: vk::Buffer,
-        positions_buffer: vk::Buffer,
-        attributes_buffer: vk::Buffer,
+        first_index: u32,
+        vertex_offset: i32,
+        aabb: Aabb,
+        bounding_sphere: BoundingSphere,
     ) -> Self {
         Self {
             index_count,
-            index_buffer,
-            positions_buffer,
-            attributes_buffer,
+            first_index,
+            vertex_offset,
+            aabb,
+            bounding_sphere,
         }
     }
 }
 
+pub mod object_flags {
+    pub const CAST_SHADOWS: u32 = 1;
+    // Drawn sorted by the forward TransparentRenderPass instead of into the G-buffer
+    pub const TRANSPARENT: u32 = 2;
+    // Drawn with the alpha tested G-buffer pipeline, so the opaque one never discards
+    pub const ALPHA_TESTED: u32 = 4;
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AlphaMode {
+    Opaque,
+    // Alpha tested by its own G-buffer pipeline, fragments whose base color alpha times the
+    // texture's is below the cutoff are discarded
+    Mask(f32),
+    // Alpha blended after lightning
+    Blend,
+}
+
+// Matches ObjectData in shaders/ObjectData.glsl (std430)
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ObjectData {
+    model: Matrix4<f32>,
+    // Unjittered, for motion vectors
+    model_view_projection: Matrix4<f32>,
+    previous_model_view_projection: Matrix4<f32>,
+    // World space center and radius
+    bounding_sphere: Vector4<f32>,
+    first_index: u32,
+    index_count: u32,
+    vertex_offset: i32,
+    flags: u32,
+    // Linear albedo and opacity
+    base_color: Vector4<f32>,
+    alpha_cutoff: f32,
+    _padding: [f32; 3],
+}
+
 pub struct DrawCall {
     pub mesh: MeshData,
     pub model: Matrix4<f32>,
+    // Model of the same object in the previous frame, the same as model when it didn't move
+    pub previous_model: Matrix4<f32>,
+    // Both unjittered, set by DrawData::add_draw_call
+    pub model_view_projection: Matrix4<f32>,
+    pub previous_model_view_projection: Matrix4<f32>,
     pub pipeline: vk::Pipeline, // TODO: Why tf part of DrawCall?
+    pub cast_shadows: bool,
+    // Linear albedo and opacity
+    pub base_color: Vector4<f32>,
+    pub alpha_mode: AlphaMode,
 }
 
 impl DrawCall {
     pub fn new(mesh: &MeshData, model: Matrix4<f32>, pipeline: vk::Pipeline) -> Self {
         Self {
             model,
+            previous_model: model,
+            model_view_projection: Matrix4::identity(),
+            previous_model_view_projection: Matrix4::identity(),
             mesh: mesh.clone(),
             pipeline,
+            cast_shadows: true,
+            base_color: Vector4::new(1.0, 0.0, 0.0, 1.0),
+            alpha_mode: AlphaMode::Opaque,
+        }
+    }
+
+    pub fn is_transparent(&self) -> bool {
+        self.alpha_mode == AlphaMode::Blend
+    }
+
+    pub fn is_alpha_tested(&self) -> bool {
+        matches!(self.alpha_mode, AlphaMode::Mask(_))
+    }
+
+    pub fn get_world_aabb(&self) -> Aabb {
+        self.mesh.aabb.transform(&self.model)
+    }
+
+    pub fn get_world_bounding_sphere(&self) -> BoundingSphere {
+        self.mesh.bounding_sphere.transform(&self.model)
+    }
+
+    pub fn is_inside(&self, frustum: &Frustum) -> bool {
+        frustum.intersects_sphere(&self.get_world_bounding_sphere())
+            && frustum.intersects_aabb(&self.get_world_aabb())
+    }
+
+    pub fn get_object_data(&self) -> ObjectData {
+        let bounding_sphere = self.get_world_bounding_sphere();
+
+        let mut flags = 0;
+        if self.cast_shadows {
+            flags |= object_flags::CAST_SHADOWS;
+        }
+        if self.is_transparent() {
+            flags |= object_flags::TRANSPARENT;
+        }
+        if self.is_alpha_tested() {
+            flags |= object_flags::ALPHA_TESTED;
+        }
+
+        let alpha_cutoff = match self.alpha_mode {
+            AlphaMode::Mask(cutoff) => cutoff,
+            _ => 0.0,
+        };
+
+        ObjectData {
+            model: self.model,
+            model_view_projection: self.model_view_projection,
+            previous_model_view_projection: self.previous_model_view_projection,
+            bounding_sphere: bounding_sphere.center.push(bounding_sphere.radius),
+            first_index: self.mesh.first_index,
+            index_count: self.mesh.index_count,
+            vertex_offset: self.mesh.vertex_offset,
+            flags,
+            base_color: self.base_color,
+            alpha_cutoff,
+            _padding: [0.0; 3],
         }
     }
 }
 
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CullingStats {
+    pub visible: u32,
+    pub total: u32,
+}
+
 pub struct DrawData {
     pub directional_light: DirectionalLight,
+    pub geometry_buffers: GeometryBuffers,
+    // Draw call index doubles as the object index on the GPU
     pub draw_calls: Vec<DrawCall>,
+    // Indices into draw_calls that pass the camera frustum test
+    visible_draw_calls: Vec<usize>,
+    pub camera_frustum: Frustum,
+    pub camera_position: nalgebra::Vector3<f32>,
+    pub camera_forward: nalgebra::Vector3<f32>,
     pub view: Matrix4<f32>,
     pub projection: Matrix4<f32>,
-    pub deferred_pipeline_layout: vk::PipelineLayout, // TODO: Why tf part of DrawData?
+    // Without the TAA jitter
+    pub unjittered_projection: Matrix4<f32>,
+    // Unjittered view projection of the previous frame, the current one after a history reset
+    pub previous_view_projection: Matrix4<f32>,
+    // Resolved TAA output of the previous frame, None drops the history after camera cuts or
+    // when the previous frame didn't resolve TAA
+    pub previous_taa_output: Option<vk::Image>,
+    pub reverse_z: bool,
+    pub settings: RenderSettings,
+    // Tags the GPU timings of this frame
+    pub frame_index: u32,
+    pub debug_draw: DebugDraw,
+    pub debug_ui: DebugUiDrawData,
 }
 
 impl DrawData {
-    pub fn new(camera: &Camera, deferred_pipeline_layout: vk::PipelineLayout) -> Self {
+    pub fn new(
+        camera: &Camera,
+        geometry_buffers: &GeometryBuffers,
+        settings: &RenderSettings,
+    ) -> Self {
         Self {
             directional_light: DirectionalLight::new(
                 nalgebra::Vector3::new(0.0, 0.0, -5.0),
                 nalgebra::Vector3::new(0.0, 0.0, 0.0),
             ),
+            geometry_buffers: geometry_buffers.clone(),
             draw_calls: vec![],
+            visible_draw_calls: vec![],
+            camera_frustum: camera.get_frustum(),
+            camera_position: camera.get_position().coords,
+            camera_forward: camera.get_forward(),
             view: camera.get_view(),
             projection: *camera.get_projection(),
-            deferred_pipeline_layout,
+            unjittered_projection: *camera.get_unjittered_projection(),
+            previous_view_projection: camera.get_unjittered_projection() * camera.get_view(),
+            previous_taa_output: None,
+            reverse_z: camera.is_reverse_z(),
+            settings: *settings,
+            frame_index: 0,
+            debug_draw: DebugDraw::default(),
+            debug_ui: DebugUiDrawData::default(),
         }
     }
 
-    pub fn add_draw_call(&mut self, draw_call: DrawCall) {
+    pub fn add_draw_call(&mut self, mut draw_call: DrawCall) {
+        draw_call.model_view_projection = self.get_unjittered_view_projection() * draw_call.model;
+        draw_call.previous_model_view_projection =
+            self.previous_view_projection * draw_call.previous_model;
+
+        if draw_call.is_inside(&self.camera_frustum) {
+            self.visible_draw_calls.push(self.draw_calls.len());
+        }
+
         self.draw_calls.push(draw_call);
     }
+
+    pub fn get_unjittered_view_projection(&self) -> Matrix4<f32> {
+        self.unjittered_projection * self.view
+    }
+
+    // Yields (object index, draw call)
+    pub fn get_visible_draw_calls(&self) -> impl Iterator<Item = (u32, &DrawCall)> {
+        self.visible_draw_calls
+            .iter()
+            .map(|&index| (index as u32, &self.draw_calls[index]))
+    }
+
+    // Back to front along the camera forward, yields (object index, draw call)
+    pub fn get_sorted_transparent_draw_calls(&self) -> Vec<(u32, &DrawCall)> {
+        let mut transparent_draw_calls: Vec<(f32, u32, &DrawCall)> = self
+            .get_visible_draw_calls()
+            .filter(|(_, draw_call)| draw_call.is_transparent())
+            .map(|(index, draw_call)| {
+                let center = draw_call.get_world_bounding_sphere().center;
+                let depth = (center - self.camera_position).dot(&self.camera_forward);
+
+                (depth, index, draw_call)
+            })
+            .collect();
+
+        transparent_draw_calls.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        transparent_draw_calls
+            .into_iter()
+            .map(|(_, index, draw_call)| (index, draw_call))
+            .collect()
+    }
+
+    pub fn get_object_data(&self) -> Vec<ObjectData> {
+        self.draw_calls
+            .iter()
+            .map(|draw_call| draw_call.get_object_data())
+            .collect()
+    }
+
+    pub fn get_culling_stats(&self) -> CullingStats {
+        CullingStats {
+            visible: self.visible_draw_calls.len() as u32,
+            total: self.draw_calls.len() as u32,
+        }
+    }
+
+    // Of the draw calls that pass the camera frustum test
+    pub fn get_triangle_count(&self) -> u64 {
+        self.visible_draw_calls
+            .iter()
+            .map(|&index| self.draw_calls[index].mesh.index_count as u64 / 3)
+            .sum()
+    }
+
+    // Per light (or cascade) list of casters that can affect the light's frustum
+    // Yields (object index, draw call)
+    pub fn get_shadow_casters(&self, light_frustum: &Frustum) -> Vec<(u32, &DrawCall)> {
+        self.draw_calls
+            .iter()
+            .enumerate()
+            .filter(|(_, draw_call)| draw_call.cast_shadows && draw_call.is_inside(light_frustum))
+            .map(|(index, draw_call)| (index as u32, draw_call))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+
+    use super::*;
+
+    fn create_draw_call(alpha_mode: AlphaMode) -> DrawCall {
+        let positions = [Vector3::repeat(-1.0), Vector3::repeat(1.0)];
+        let mesh = MeshData::new(
+            36,
+            0,
+            0,
+            Aabb::from_positions(&positions),
+            BoundingSphere::from_positions(&positions),
+        );
+
+        let mut draw_call = DrawCall::new(&mesh, Matrix4::identity(), vk::Pipeline::null());
+        draw_call.alpha_mode = alpha_mode;
+        draw_call
+    }
+
+    #[test]
+    fn alpha_cutoff_is_packed_for_masked_objects_only() {
+        let masked = create_draw_call(AlphaMode::Mask(0.25)).get_object_data();
+        assert_eq!(masked.alpha_cutoff, 0.25);
+        assert_eq!(masked.flags & object_flags::TRANSPARENT, 0);
+        assert_ne!(masked.flags & object_flags::ALPHA_TESTED, 0);
+
+        let opaque = create_draw_call(AlphaMode::Opaque).get_object_data();
+        assert_eq!(opaque.alpha_cutoff, 0.0);
+        assert_eq!(opaque.flags & object_flags::ALPHA_TESTED, 0);
+
+        let blended = create_draw_call(AlphaMode::Blend).get_object_data();
+        assert_eq!(blended.alpha_cutoff, 0.0);
+        assert_ne!(blended.flags & object_flags::TRANSPARENT, 0);
+        assert_eq!(blended.flags & object_flags::ALPHA_TESTED, 0);
+    }
+
+    #[test]
+    fn object_data_matches_the_std430_layout() {
+        assert_eq!(std::mem::offset_of!(ObjectData, base_color), 224);
+        assert_eq!(std::mem::offset_of!(ObjectData, alpha_cutoff), 240);
+        // Padded to the 16 byte alignment of the vec4 members
+        assert_eq!(std::mem::size_of::<ObjectData>(), 256);
+    }
 }
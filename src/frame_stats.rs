@@ -0,0 +1,182 @@
+use std::{
+    collections::VecDeque,
+    env, fmt, thread,
+    time::{Duration, Instant},
+};
+
+// Frames per second, unset or "0" renders as fast as the swapchain allows
+pub const FRAME_LIMIT_ENV_VAR: &str = "SR_ENGINE_FRAME_LIMIT";
+
+// Frames the averages and percentiles are computed over
+const HISTORY_LENGTH: usize = 240;
+
+// Sleeping overshoots by up to a scheduler tick, the rest is spun
+const SPIN_DURATION: Duration = Duration::from_millis(1);
+
+pub fn get_frame_limit_from_env() -> Option<f32> {
+    let value = env::var(FRAME_LIMIT_ENV_VAR).ok()?;
+
+    match value.parse::<f32>() {
+        Ok(limit) if limit > 0.0 => Some(limit),
+        Ok(_) => None,
+        Err(_) => {
+            log::warn!(
+                "Invalid {}={}, expected frames per second",
+                FRAME_LIMIT_ENV_VAR,
+                value
+            );
+            None
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct FrameSample {
+    // From the start of the previous frame
+    frame_ms: f32,
+    // Spent in Renderer::render, including waits on the GPU
+    cpu_ms: f32,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameStatsSummary {
+    pub fps: f32,
+    // Averages over the history
+    pub frame_ms: f32,
+    pub cpu_ms: f32,
+    // Of the latest frame with timestamps, 0 without timestamp support
+    pub gpu_ms: f32,
+    // Frame time percentiles, p99 tracks stutter that the average hides
+    pub frame_ms_p50: f32,
+    pub frame_ms_p95: f32,
+    pub frame_ms_p99: f32,
+    // Of the last frame, after frustum culling
+    pub draw_calls: u32,
+    pub triangles: u64,
+}
+
+impl fmt::Display for FrameStatsSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.0} fps, {:.2} ms (p99 {:.2} ms), CPU {:.2} ms, GPU {:.2} ms, {} draws, {} triangles",
+            self.fps,
+            self.frame_ms,
+            self.frame_ms_p99,
+            self.cpu_ms,
+            self.gpu_ms,
+            self.draw_calls,
+            self.triangles
+        )
+    }
+}
+
+#[derive(Default)]
+pub struct FrameStats {
+    samples: VecDeque<FrameSample>,
+    previous_frame_start: Option<Instant>,
+    gpu_ms: f32,
+    draw_calls: u32,
+    triangles: u64,
+}
+
+impl FrameStats {
+    // At the end of a frame that started at `frame_start`
+    pub fn record_frame(&mut self, frame_start: Instant, draw_calls: u32, triangles: u64) {
+        let now = Instant::now();
+        let cpu_ms = (now - frame_start).as_secs_f32() * 1000.0;
+
+        // The first frame has nothing to measure its interval against
+        if let Some(previous_frame_start) = self.previous_frame_start {
+            if self.samples.len() == HISTORY_LENGTH {
+                self.samples.pop_front();
+            }
+
+            self.samples.push_back(FrameSample {
+                frame_ms: (frame_start - previous_frame_start).as_secs_f32() * 1000.0,
+                cpu_ms,
+            });
+        }
+
+        self.previous_frame_start = Some(frame_start);
+        self.draw_calls = draw_calls;
+        self.triangles = triangles;
+    }
+
+    pub fn set_gpu_ms(&mut self, gpu_ms: f32) {
+        self.gpu_ms = gpu_ms;
+    }
+
+    pub fn get_summary(&self) -> FrameStatsSummary {
+        let mut summary = FrameStatsSummary {
+            gpu_ms: self.gpu_ms,
+            draw_calls: self.draw_calls,
+            triangles: self.triangles,
+            ..Default::default()
+        };
+
+        if self.samples.is_empty() {
+            return summary;
+        }
+
+        let count = self.samples.len() as f32;
+        summary.frame_ms = self
+            .samples
+            .iter()
+            .map(|sample| sample.frame_ms)
+            .sum::<f32>()
+            / count;
+        summary.cpu_ms = self.samples.iter().map(|sample| sample.cpu_ms).sum::<f32>() / count;
+        summary.fps = 1000.0 / summary.frame_ms;
+
+        let mut frame_times: Vec<f32> = self.samples.iter().map(|sample| sample.frame_ms).collect();
+        frame_times.sort_by(f32::total_cmp);
+
+        let percentile = |fraction: f32| {
+            frame_times[((frame_times.len() - 1) as f32 * fraction).round() as usize]
+        };
+        summary.frame_ms_p50 = percentile(0.5);
+        summary.frame_ms_p95 = percentile(0.95);
+        summary.frame_ms_p99 = percentile(0.99);
+
+        summary
+    }
+}
+
+// Holds frames to a fixed rate by sleeping before the next frame starts
+#[derive(Default)]
+pub struct FrameLimiter {
+    next_frame: Option<Instant>,
+}
+
+impl FrameLimiter {
+    pub fn wait(&mut self, limit: Option<f32>) {
+        let Some(limit) = limit.filter(|&limit| limit > 0.0) else {
+            self.next_frame = None;
+            return;
+        };
+        let frame_duration = Duration::from_secs_f32(1.0 / limit);
+
+        let now = Instant::now();
+        let target = self.next_frame.unwrap_or(now);
+
+        if target > now {
+            let remaining = target - now;
+            if remaining > SPIN_DURATION {
+                thread::sleep(remaining - SPIN_DURATION);
+            }
+            while Instant::now() < target {
+                std::hint::spin_loop();
+            }
+        }
+
+        // Keeps the cadence after short hitches, a frame that is late by more than a whole frame
+        // restarts it instead of rendering the missed frames back to back
+        let now = Instant::now();
+        self.next_frame = Some(if now > target + frame_duration {
+            now + frame_duration
+        } else {
+            target + frame_duration
+        });
+    }
+}
@@ -2,30 +2,50 @@ use ash::{khr::swapchain, vk, Device};
 use gpu_allocator::vulkan::Allocator;
 
 use crate::{
-    deferred_lightning_render_pass::DeferredLightningRenderPass,
-    deferred_render_pass::DeferredRenderPass, draw_data::DrawData, image::Image,
-    pipeline_manager::PipelineManager, shadow_map_render_pass::ShadowMapRenderPass,
+    bloom_render_pass::BloomRenderPass,
+    culling_pass::CullingPass,
+    debug_draw_render_pass::DebugDrawRenderPass,
+    debug_markers,
+    debug_ui_render_pass::DebugUiRenderPass,
+    deferred_lightning_render_pass::{
+        DeferredLightningRenderPass, DeferredLightningRenderPassInput,
+    },
+    deferred_render_pass::DeferredRenderPass,
+    draw_data::DrawData,
+    fxaa_render_pass::FxaaRenderPass,
+    gpu_profiler::{GpuProfiler, GpuTimings, TimestampPeriod},
+    image_based_lighting::ImageBasedLightingOutput,
+    pipeline_manager::PipelineManager,
+    render_pass_attachment_output::RenderPassAttachmentOutput,
+    render_settings::{AntiAliasing, DebugView},
+    renderer_error::Result,
+    shadow_map_render_pass::ShadowMapRenderPass,
+    skybox_render_pass::SkyboxRenderPass,
+    ssao_render_pass::SsaoRenderPass,
+    taa_render_pass::{TaaRenderPass, TaaRenderPassOutput},
+    tonemapping_render_pass::TonemappingRenderPass,
+    transparent_render_pass::TransparentRenderPass,
 };
 
 // TODO: Some helper library
-fn create_fence(device: &Device, flags: vk::FenceCreateFlags) -> vk::Fence {
+fn create_fence(device: &Device, flags: vk::FenceCreateFlags) -> Result<vk::Fence> {
     let create_info = vk::FenceCreateInfo::default().flags(flags);
 
-    unsafe { device.create_fence(&create_info, None).unwrap() }
+    Ok(unsafe { device.create_fence(&create_info, None)? })
 }
 
-fn create_semaphore(device: &Device) -> vk::Semaphore {
+fn create_semaphore(device: &Device) -> Result<vk::Semaphore> {
     let create_info = vk::SemaphoreCreateInfo::default();
 
-    unsafe { device.create_semaphore(&create_info, None).unwrap() }
+    Ok(unsafe { device.create_semaphore(&create_info, None)? })
 }
 
-fn create_command_pool(device: &Device, queue_family_index: u32) -> vk::CommandPool {
+fn create_command_pool(device: &Device, queue_family_index: u32) -> Result<vk::CommandPool> {
     let create_info = vk::CommandPoolCreateInfo::default()
         .flags(vk::CommandPoolCreateFlags::TRANSIENT)
         .queue_family_index(queue_family_index);
 
-    unsafe { device.create_command_pool(&create_info, None).unwrap() }
+    Ok(unsafe { device.create_command_pool(&create_info, None)? })
 }
 
 struct Synchronization {
@@ -35,16 +55,16 @@ struct Synchronization {
 }
 
 impl Synchronization {
-    fn new(device: &Device) -> Self {
-        let queue_fence = create_fence(device, vk::FenceCreateFlags::SIGNALED);
-        let image_acquire_semaphore = create_semaphore(device);
-        let present_semaphore = create_semaphore(device);
+    fn new(device: &Device) -> Result<Self> {
+        let queue_fence = create_fence(device, vk::FenceCreateFlags::SIGNALED)?;
+        let image_acquire_semaphore = create_semaphore(device)?;
+        let present_semaphore = create_semaphore(device)?;
 
-        Self {
+        Ok(Self {
             queue_fence,
             image_acquire_semaphore,
             present_semaphore,
-        }
+        })
     }
 
     fn destroy(&self, device: &Device) {
@@ -58,13 +78,15 @@ impl Synchronization {
         }
     }
 
-    fn wait_queue(&self, device: &Device) {
+    fn wait_queue(&self, device: &Device) -> Result<()> {
         let fences = [self.queue_fence];
 
         unsafe {
-            device.wait_for_fences(&fences, true, u64::MAX).unwrap();
-            device.reset_fences(&fences).unwrap();
+            device.wait_for_fences(&fences, true, u64::MAX)?;
+            device.reset_fences(&fences)?;
         };
+
+        Ok(())
     }
 
     fn replace_semaphores(
@@ -83,6 +105,17 @@ impl Synchronization {
     }
 }
 
+// Created by the renderer and shared by every worker
+#[derive(Clone, Copy)]
+pub struct FrameWorkerResources<'a> {
+    pub queue_family_index: u32,
+    pub render_area: &'a vk::Rect2D,
+    pub ssao_noise: vk::ImageView,
+    pub masked_base_color: vk::ImageView,
+    pub image_based_lighting_output: &'a ImageBasedLightingOutput,
+    pub timestamp_period: Option<TimestampPeriod>,
+}
+
 pub struct FrameWorker {
     device: Device,
     // TODO: replace with Image
@@ -91,9 +124,20 @@ pub struct FrameWorker {
     synchronization: Synchronization,
     command_pool: vk::CommandPool,
     command_buffer: vk::CommandBuffer,
+    gpu_profiler: GpuProfiler,
+    culling_pass: CullingPass,
     shadow_map_render_pass: ShadowMapRenderPass,
     deferred_render_pass: DeferredRenderPass,
+    ssao_render_pass: SsaoRenderPass,
     deferred_lightning_render_pass: DeferredLightningRenderPass,
+    skybox_render_pass: SkyboxRenderPass,
+    transparent_render_pass: TransparentRenderPass,
+    debug_draw_render_pass: DebugDrawRenderPass,
+    taa_render_pass: TaaRenderPass,
+    bloom_render_pass: BloomRenderPass,
+    tonemapping_render_pass: TonemappingRenderPass,
+    fxaa_render_pass: FxaaRenderPass,
+    debug_ui_render_pass: DebugUiRenderPass,
 }
 
 impl FrameWorker {
@@ -101,46 +145,152 @@ impl FrameWorker {
         device: Device,
         allocator: &mut Allocator,
         pipeline_manager: &mut PipelineManager,
+        index: usize,
         swapchain_image: vk::Image,
         swapchain_image_view: vk::ImageView,
-        queue_family_index: u32,
-        render_area: &vk::Rect2D,
-    ) -> Self {
-        let command_pool = create_command_pool(&device, queue_family_index);
+        resources: &FrameWorkerResources,
+    ) -> Result<Self> {
+        let &FrameWorkerResources {
+            queue_family_index,
+            render_area,
+            ssao_noise,
+            masked_base_color,
+            image_based_lighting_output,
+            timestamp_period,
+        } = resources;
+
+        let command_pool = create_command_pool(&device, queue_family_index)?;
 
         let allocate_info = vk::CommandBufferAllocateInfo::default()
             .command_pool(command_pool)
             .level(vk::CommandBufferLevel::PRIMARY)
             .command_buffer_count(1);
 
-        let command_buffer = unsafe { device.allocate_command_buffers(&allocate_info).unwrap()[0] };
+        let command_buffer = unsafe { device.allocate_command_buffers(&allocate_info)?[0] };
+        debug_markers::set_object_name(command_buffer, &format!("frame_{}", index));
+
+        let synchronization = Synchronization::new(&device)?;
 
-        let synchronization = Synchronization::new(&device);
+        let gpu_profiler = GpuProfiler::new(&device, timestamp_period)?;
 
         let shadow_map_render_pass =
-            ShadowMapRenderPass::new(device.clone(), allocator, pipeline_manager);
+            ShadowMapRenderPass::new(device.clone(), allocator, pipeline_manager)?;
+
+        let culling_pass = CullingPass::new(&device, allocator, pipeline_manager)?;
+
+        let deferred_render_pass = DeferredRenderPass::new(
+            &device,
+            allocator,
+            pipeline_manager,
+            render_area,
+            masked_base_color,
+        )?;
 
-        let deferred_render_pass = DeferredRenderPass::new(&device, allocator, render_area);
+        let ssao_render_pass = SsaoRenderPass::new(
+            &device,
+            allocator,
+            pipeline_manager,
+            render_area,
+            &deferred_render_pass.get_output(),
+            ssao_noise,
+        )?;
 
         let deferred_lightning_render_pass = DeferredLightningRenderPass::new(
+            &device,
+            allocator,
+            pipeline_manager,
+            render_area,
+            &DeferredLightningRenderPassInput {
+                deferred: &deferred_render_pass.get_output(),
+                shadow_map: &shadow_map_render_pass.get_output(),
+                ssao: &ssao_render_pass.get_output(),
+                image_based_lighting: image_based_lighting_output,
+            },
+        )?;
+
+        let skybox_render_pass = SkyboxRenderPass::new(
+            &device,
+            pipeline_manager,
+            render_area,
+            &deferred_render_pass.get_output(),
+            &deferred_lightning_render_pass.get_output(),
+            image_based_lighting_output,
+        )?;
+
+        let transparent_render_pass = TransparentRenderPass::new(
             &device,
             pipeline_manager,
             render_area,
             &deferred_render_pass.get_output(),
+            &deferred_lightning_render_pass.get_output(),
             &shadow_map_render_pass.get_output(),
-        );
+            image_based_lighting_output,
+        )?;
+
+        let debug_draw_render_pass = DebugDrawRenderPass::new(
+            &device,
+            allocator,
+            pipeline_manager,
+            render_area,
+            &deferred_render_pass.get_output(),
+            &deferred_lightning_render_pass.get_output(),
+        )?;
+
+        let taa_render_pass = TaaRenderPass::new(
+            &device,
+            allocator,
+            pipeline_manager,
+            render_area,
+            &deferred_render_pass.get_output(),
+            &deferred_lightning_render_pass.get_output(),
+        )?;
+
+        let bloom_render_pass = BloomRenderPass::new(
+            &device,
+            allocator,
+            pipeline_manager,
+            render_area,
+            &deferred_lightning_render_pass.get_output(),
+            &taa_render_pass.get_output(),
+        )?;
+
+        let tonemapping_render_pass = TonemappingRenderPass::new(
+            &device,
+            pipeline_manager,
+            render_area,
+            &deferred_lightning_render_pass.get_output(),
+            &taa_render_pass.get_output(),
+            &bloom_render_pass.get_output(),
+        )?;
+
+        let fxaa_render_pass =
+            FxaaRenderPass::new(&device, allocator, pipeline_manager, render_area)?;
+
+        let debug_ui_render_pass =
+            DebugUiRenderPass::new(&device, allocator, pipeline_manager, render_area)?;
 
-        Self {
+        Ok(Self {
             device,
             swapchain_image,
             swapchain_image_view,
             command_pool,
             command_buffer,
+            gpu_profiler,
             synchronization,
+            culling_pass,
             shadow_map_render_pass,
             deferred_render_pass,
+            ssao_render_pass,
             deferred_lightning_render_pass,
-        }
+            skybox_render_pass,
+            transparent_render_pass,
+            debug_draw_render_pass,
+            taa_render_pass,
+            bloom_render_pass,
+            tonemapping_render_pass,
+            fxaa_render_pass,
+            debug_ui_render_pass,
+        })
     }
 
     pub fn destroy(&mut self, allocator: &mut Allocator) {
@@ -153,10 +303,40 @@ impl FrameWorker {
             self.device.destroy_command_pool(self.command_pool, None);
         }
 
+        self.gpu_profiler.destroy(&self.device);
+
+        self.culling_pass.destroy(allocator);
         self.shadow_map_render_pass.destroy(allocator);
         self.deferred_render_pass.destroy(allocator);
+        self.ssao_render_pass.destroy(allocator);
+        self.deferred_lightning_render_pass.destroy(allocator);
+        self.debug_draw_render_pass.destroy(allocator);
+        self.taa_render_pass.destroy(allocator);
+        self.bloom_render_pass.destroy(allocator);
+        self.fxaa_render_pass.destroy(allocator);
+        self.debug_ui_render_pass.destroy(allocator);
+    }
+
+    // Copied into the TAA history of whichever worker draws the next frame
+    pub fn get_taa_output(&self) -> TaaRenderPassOutput {
+        self.taa_render_pass.get_output()
+    }
+
+    // Debug label and GPU timestamps around a pass
+    fn begin_pass(&mut self, name: &'static str) {
+        debug_markers::begin_label(self.command_buffer, name);
+        self.gpu_profiler
+            .begin_scope(&self.device, self.command_buffer, name);
+    }
+
+    fn end_pass(&mut self) {
+        self.gpu_profiler
+            .end_scope(&self.device, self.command_buffer);
+        debug_markers::end_label(self.command_buffer);
     }
 
+    // Returns the GPU timings of the previous frame drawn by this worker and whether the swapchain
+    // is suboptimal or out of date
     pub fn draw(
         &mut self,
         swapchain_loader: &swapchain::Device,
@@ -166,15 +346,19 @@ impl FrameWorker {
         image_acquire_semaphore: vk::Semaphore,
         graphics_queue: vk::Queue,
         draw_data: &DrawData,
-    ) {
-        let present_semaphore = create_semaphore(&self.device);
+    ) -> Result<(Option<GpuTimings>, bool)> {
+        let present_semaphore = create_semaphore(&self.device)?;
+
+        self.synchronization.wait_queue(&self.device)?;
 
-        self.synchronization.wait_queue(&self.device);
+        // Safe to overwrite once the previous submission of this worker has finished
+        self.culling_pass.update_objects(draw_data);
+        self.debug_draw_render_pass.update_vertices(draw_data);
+        self.debug_ui_render_pass.update_meshes(draw_data);
 
         unsafe {
             self.device
-                .reset_command_pool(self.command_pool, vk::CommandPoolResetFlags::empty())
-                .unwrap();
+                .reset_command_pool(self.command_pool, vk::CommandPoolResetFlags::empty())?;
         }
 
         let begin_info = vk::CommandBufferBeginInfo::default()
@@ -182,32 +366,124 @@ impl FrameWorker {
 
         unsafe {
             self.device
-                .begin_command_buffer(self.command_buffer, &begin_info)
-                .unwrap()
+                .begin_command_buffer(self.command_buffer, &begin_info)?
         };
 
-        self.deferred_render_pass
-            .render(self.command_buffer, draw_data);
+        let gpu_timings = self.gpu_profiler.begin_frame(
+            &self.device,
+            self.command_buffer,
+            draw_data.frame_index,
+        )?;
 
-        self.shadow_map_render_pass
-            .render(self.command_buffer, draw_data);
+        if draw_data.settings.gpu_driven {
+            self.begin_pass("Culling");
+            self.culling_pass.render(self.command_buffer, draw_data);
+            self.end_pass();
+        }
 
-        // const glm::vec3 viewDirection = glm::inverse(-drawData.view)[2];
+        let objects_set = self.culling_pass.get_objects_set();
 
-        //let view = -draw_data.view;
-        let view = draw_data.view.try_inverse().unwrap();
-        let view_direction = nalgebra::Vector3::new(view[(2, 0)], view[(2, 1)], view[(2, 2)]);
+        self.begin_pass("Deferred");
+        self.deferred_render_pass.render(
+            self.command_buffer,
+            draw_data,
+            objects_set,
+            &self.culling_pass.get_camera_output(),
+            &self.culling_pass.get_camera_masked_output(),
+        );
+        self.end_pass();
 
-        self.deferred_lightning_render_pass.render(
+        self.begin_pass("ShadowMap");
+        self.shadow_map_render_pass.render(
             self.command_buffer,
-            self.swapchain_image,
-            self.swapchain_image_view,
-            &(draw_data.directional_light.get_projection()
-                * draw_data.directional_light.get_view()),
-            &view_direction,
+            draw_data,
+            objects_set,
+            &self.culling_pass.get_shadow_output(),
         );
+        self.end_pass();
+
+        self.begin_pass("Ssao");
+        self.ssao_render_pass.render(self.command_buffer, draw_data);
+        self.end_pass();
+
+        self.begin_pass("DeferredLightning");
+        self.deferred_lightning_render_pass
+            .render(self.command_buffer, draw_data);
+        self.end_pass();
+
+        // Debug views show the G-buffer and shadow channels unobstructed
+        if draw_data.settings.debug_view == DebugView::None {
+            self.begin_pass("Skybox");
+            self.skybox_render_pass
+                .render(self.command_buffer, draw_data);
+            self.end_pass();
+
+            self.begin_pass("Transparent");
+            self.transparent_render_pass
+                .render(self.command_buffer, draw_data, objects_set);
+            self.end_pass();
+        }
+
+        if self.debug_draw_render_pass.has_draws() {
+            self.begin_pass("DebugDraw");
+            self.debug_draw_render_pass
+                .render(self.command_buffer, draw_data);
+            self.end_pass();
+        }
 
-        unsafe { self.device.end_command_buffer(self.command_buffer).unwrap() };
+        if draw_data.settings.anti_aliasing == AntiAliasing::Taa {
+            self.begin_pass("Taa");
+            self.taa_render_pass.render(self.command_buffer, draw_data);
+            self.end_pass();
+        }
+
+        self.begin_pass("Bloom");
+        self.bloom_render_pass
+            .render(self.command_buffer, &draw_data.settings);
+        self.end_pass();
+
+        self.begin_pass("Tonemapping");
+        if draw_data.settings.anti_aliasing == AntiAliasing::Fxaa {
+            self.tonemapping_render_pass.render(
+                self.command_buffer,
+                &self.fxaa_render_pass.get_input(),
+                &draw_data.settings,
+            );
+            self.end_pass();
+
+            self.begin_pass("Fxaa");
+            self.fxaa_render_pass.render(
+                self.command_buffer,
+                self.swapchain_image,
+                self.swapchain_image_view,
+            );
+        } else {
+            let swapchain_target = RenderPassAttachmentOutput {
+                image: self.swapchain_image,
+                image_view: self.swapchain_image_view,
+                image_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+            };
+
+            self.tonemapping_render_pass.render(
+                self.command_buffer,
+                &swapchain_target,
+                &draw_data.settings,
+            );
+        }
+        self.end_pass();
+
+        // Drawn over the final image so it isn't tonemapped or anti-aliased
+        if self.debug_ui_render_pass.has_draws() {
+            self.begin_pass("DebugUi");
+            self.debug_ui_render_pass.render(
+                self.command_buffer,
+                self.swapchain_image,
+                self.swapchain_image_view,
+            );
+            self.end_pass();
+        }
+
+        unsafe { self.device.end_command_buffer(self.command_buffer)? };
 
         let image_acquire_semaphore_submit_infos = [vk::SemaphoreSubmitInfo::default()
             .semaphore(image_acquire_semaphore)
@@ -226,13 +502,11 @@ impl FrameWorker {
             .signal_semaphore_infos(&present_semaphore_submit_infos);
 
         unsafe {
-            self.device
-                .queue_submit2(
-                    graphics_queue,
-                    &[submit_info],
-                    self.synchronization.queue_fence,
-                )
-                .unwrap()
+            self.device.queue_submit2(
+                graphics_queue,
+                &[submit_info],
+                self.synchronization.queue_fence,
+            )?
         };
 
         let present_semaphores = [present_semaphore];
@@ -244,18 +518,19 @@ impl FrameWorker {
             .swapchains(&swapchains)
             .image_indices(&image_indices);
 
-        unsafe {
-            swapchain_loader
-                .queue_present(graphics_queue, &present_info)
-                .unwrap()
-        };
+        // The semaphore wait still happens when the swapchain is out of date
+        let suboptimal =
+            match unsafe { swapchain_loader.queue_present(graphics_queue, &present_info) } {
+                Ok(suboptimal) => suboptimal,
+                Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => true,
+                Err(error) => return Err(error.into()),
+            };
 
         let present_fences = [present_fence];
         unsafe {
             self.device
-                .wait_for_fences(&present_fences, true, u64::MAX)
-                .unwrap();
-            self.device.reset_fences(&present_fences).unwrap();
+                .wait_for_fences(&present_fences, true, u64::MAX)?;
+            self.device.reset_fences(&present_fences)?;
         };
 
         self.synchronization.replace_semaphores(
@@ -263,5 +538,7 @@ impl FrameWorker {
             image_acquire_semaphore,
             present_semaphore,
         );
+
+        Ok((gpu_timings, suboptimal))
     }
 }
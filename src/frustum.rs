@@ -0,0 +1,203 @@
+use nalgebra::{Matrix4, Vector4};
+
+use crate::bounding_volume::{Aabb, BoundingSphere};
+
+// Plane order: left, right, bottom, top, near, far
+const NEAR_PLANE: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DepthRange {
+    // nalgebra projections, used by the lights
+    NegativeOneToOne,
+    // Camera projections, also covers reverse-Z since only the plane order differs
+    ZeroToOne,
+}
+
+// Planes are stored as (normal, distance) with normals pointing inside
+#[derive(Clone, Copy, Debug)]
+pub struct Frustum {
+    planes: [Vector4<f32>; 6],
+    ignore_near: bool,
+}
+
+impl Frustum {
+    // Gribb & Hartmann
+    pub fn from_matrix(view_projection: &Matrix4<f32>, depth_range: DepthRange) -> Self {
+        let row = |i: usize| -> Vector4<f32> { view_projection.row(i).transpose() };
+
+        let near = match depth_range {
+            DepthRange::NegativeOneToOne => row(3) + row(2),
+            DepthRange::ZeroToOne => row(2),
+        };
+
+        // Infinite far plane degenerates to zero and always passes
+        let mut planes = [
+            row(3) + row(0),
+            row(3) - row(0),
+            row(3) + row(1),
+            row(3) - row(1),
+            near,
+            row(3) - row(2),
+        ];
+
+        for plane in &mut planes {
+            let length = plane.xyz().norm();
+            if length > 0.0 {
+                *plane /= length;
+            }
+        }
+
+        Self {
+            planes,
+            ignore_near: false,
+        }
+    }
+
+    // Shadow casters behind the light's near plane can still cast into the frustum
+    pub fn without_near_plane(mut self) -> Self {
+        self.ignore_near = true;
+        self
+    }
+
+    fn active_planes(&self) -> impl Iterator<Item = &Vector4<f32>> {
+        self.planes
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !(self.ignore_near && *i == NEAR_PLANE))
+            .map(|(_, plane)| plane)
+    }
+
+    // Disabled planes are zeroed, which always passes the distance test
+    pub fn get_planes(&self) -> [Vector4<f32>; 6] {
+        let mut planes = self.planes;
+        if self.ignore_near {
+            planes[NEAR_PLANE] = Vector4::zeros();
+        }
+
+        planes
+    }
+
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.active_planes()
+            .all(|plane| plane.xyz().dot(&sphere.center) + plane.w >= -sphere.radius)
+    }
+
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        let center = aabb.get_center();
+        let extents = aabb.get_extents();
+
+        self.active_planes().all(|plane| {
+            let normal = plane.xyz();
+            let radius = extents.dot(&normal.abs());
+            normal.dot(&center) + plane.w >= -radius
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Perspective3, Vector3};
+
+    use super::*;
+
+    const NEAR: f32 = 0.1;
+    const FAR: f32 = 100.0;
+
+    // 90 degree field of view with a square aspect, like Camera::calculate_projection
+    fn perspective(near: f32, far: f32) -> Matrix4<f32> {
+        let mut projection = Matrix4::zeros();
+        projection[(0, 0)] = 1.0;
+        projection[(1, 1)] = 1.0;
+        projection[(2, 2)] = far / (near - far);
+        projection[(2, 3)] = near * far / (near - far);
+        projection[(3, 2)] = -1.0;
+        projection
+    }
+
+    fn reverse_z_infinite(near: f32) -> Matrix4<f32> {
+        let mut projection = Matrix4::zeros();
+        projection[(0, 0)] = 1.0;
+        projection[(1, 1)] = 1.0;
+        projection[(2, 3)] = near;
+        projection[(3, 2)] = -1.0;
+        projection
+    }
+
+    fn assert_plane(actual: Vector4<f32>, expected: Vector4<f32>) {
+        assert!(
+            (actual - expected).norm() < 1e-3,
+            "expected plane {:?}, got {:?}",
+            expected,
+            actual
+        );
+    }
+
+    fn contains_point(frustum: &Frustum, x: f32, y: f32, z: f32) -> bool {
+        frustum.intersects_sphere(&BoundingSphere {
+            center: Vector3::new(x, y, z),
+            radius: 0.0,
+        })
+    }
+
+    #[test]
+    fn zero_to_one_planes() {
+        let planes =
+            Frustum::from_matrix(&perspective(NEAR, FAR), DepthRange::ZeroToOne).get_planes();
+        let diagonal = std::f32::consts::FRAC_1_SQRT_2;
+
+        assert_plane(planes[0], Vector4::new(diagonal, 0.0, -diagonal, 0.0));
+        assert_plane(planes[1], Vector4::new(-diagonal, 0.0, -diagonal, 0.0));
+        assert_plane(planes[2], Vector4::new(0.0, diagonal, -diagonal, 0.0));
+        assert_plane(planes[3], Vector4::new(0.0, -diagonal, -diagonal, 0.0));
+        assert_plane(planes[NEAR_PLANE], Vector4::new(0.0, 0.0, -1.0, -NEAR));
+        assert_plane(planes[5], Vector4::new(0.0, 0.0, 1.0, FAR));
+    }
+
+    #[test]
+    fn negative_one_to_one_planes() {
+        let projection = Perspective3::new(1.0, std::f32::consts::FRAC_PI_2, NEAR, FAR);
+        let planes =
+            Frustum::from_matrix(&projection.to_homogeneous(), DepthRange::NegativeOneToOne)
+                .get_planes();
+
+        assert_plane(planes[NEAR_PLANE], Vector4::new(0.0, 0.0, -1.0, -NEAR));
+        assert_plane(planes[5], Vector4::new(0.0, 0.0, 1.0, FAR));
+    }
+
+    #[test]
+    fn reverse_z_infinite_planes() {
+        let frustum = Frustum::from_matrix(&reverse_z_infinite(NEAR), DepthRange::ZeroToOne);
+        let planes = frustum.get_planes();
+
+        // Depth only reaches 0 at infinity, the far plane slot clips against the near plane
+        assert_plane(planes[NEAR_PLANE], Vector4::new(0.0, 0.0, 0.0, NEAR));
+        assert_plane(planes[5], Vector4::new(0.0, 0.0, -1.0, -NEAR));
+
+        assert!(contains_point(&frustum, 0.0, 0.0, -1.0e6));
+        assert!(!contains_point(&frustum, 0.0, 0.0, -NEAR * 0.5));
+        assert!(!contains_point(&frustum, 0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn points_inside_and_outside() {
+        let frustum = Frustum::from_matrix(&perspective(NEAR, FAR), DepthRange::ZeroToOne);
+
+        assert!(contains_point(&frustum, 0.0, 0.0, -5.0));
+        assert!(contains_point(&frustum, 4.9, -4.9, -5.0));
+        assert!(!contains_point(&frustum, 5.1, 0.0, -5.0));
+        assert!(!contains_point(&frustum, 0.0, 0.0, -NEAR * 0.5));
+        assert!(!contains_point(&frustum, 0.0, 0.0, -FAR * 1.5));
+        assert!(!contains_point(&frustum, 0.0, 0.0, 5.0));
+    }
+
+    #[test]
+    fn without_near_plane() {
+        let frustum = Frustum::from_matrix(&perspective(NEAR, FAR), DepthRange::ZeroToOne)
+            .without_near_plane();
+
+        assert_eq!(frustum.get_planes()[NEAR_PLANE], Vector4::zeros());
+        assert!(contains_point(&frustum, 0.0, 0.0, -NEAR * 0.5));
+        // The side planes still meet at the camera
+        assert!(!contains_point(&frustum, 0.0, 0.0, 5.0));
+    }
+}
@@ -0,0 +1,178 @@
+use ash::{vk, Device};
+use gpu_allocator::vulkan::Allocator;
+use nalgebra::Vector2;
+
+use crate::{
+    command_buffer_helpers,
+    image::{Image, ImageCreateInfo},
+    pipeline_manager::{FxaaMaterial, PipelineManager},
+    push_constants_data::FxaaPushConstantsData,
+    render_pass_attachment_output::RenderPassAttachmentOutput,
+    renderer_error::Result,
+};
+
+const SWAPCHAIN_CLEAR_VALUE: vk::ClearValue = vk::ClearValue {
+    color: vk::ClearColorValue {
+        float32: [0.0, 0.0, 0.0, 0.0],
+    },
+};
+
+// FXAA 3.11 on the tonemapped image, from an intermediate target into the swapchain
+pub struct FxaaRenderPass {
+    device: Device,
+    render_area: vk::Rect2D,
+    // Same format as the swapchain so TonemappingRenderPass can draw into either
+    input_image: Image,
+    material: FxaaMaterial,
+    linear_input: bool,
+}
+
+impl FxaaRenderPass {
+    pub fn new(
+        device: &Device,
+        allocator: &mut Allocator,
+        pipeline_manager: &mut PipelineManager,
+        render_area: &vk::Rect2D,
+    ) -> Result<Self> {
+        let format = pipeline_manager.get_swapchain_format();
+
+        let create_info = ImageCreateInfo {
+            extent: vk::Extent3D::default()
+                .width(render_area.extent.width)
+                .height(render_area.extent.height)
+                .depth(1),
+            image_type: vk::ImageType::TYPE_2D,
+            format,
+            usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            view_type: vk::ImageViewType::TYPE_2D,
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_levels: 1,
+            array_layers: 1,
+        };
+
+        let input_image = Image::new(device, allocator, &create_info, "fxaa_input")?;
+
+        Ok(Self {
+            device: device.clone(),
+            render_area: *render_area,
+            material: pipeline_manager.create_fxaa_material(input_image.image_view)?,
+            input_image,
+            linear_input: pipeline_manager.get_display_encoding().is_linear(),
+        })
+    }
+
+    pub fn destroy(&mut self, allocator: &mut Allocator) {
+        self.input_image.destroy(&self.device, allocator);
+    }
+
+    // Where TonemappingRenderPass draws when FXAA is enabled
+    pub fn get_input(&self) -> RenderPassAttachmentOutput {
+        RenderPassAttachmentOutput {
+            image: self.input_image.image,
+            image_view: self.input_image.image_view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        }
+    }
+
+    pub fn render(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        swapchain_image: vk::Image,
+        swapchain_image_view: vk::ImageView,
+    ) {
+        self.begin_render_pass(command_buffer, swapchain_image, swapchain_image_view);
+
+        let extent = self.render_area.extent;
+        let push_data = FxaaPushConstantsData::new(
+            &Vector2::new(1.0 / extent.width as f32, 1.0 / extent.height as f32),
+            self.linear_input,
+        );
+
+        unsafe {
+            self.device.cmd_push_constants(
+                command_buffer,
+                self.material.layout,
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                push_data.get(),
+            );
+
+            self.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.material.pipeline,
+            );
+
+            self.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.material.layout,
+                0,
+                &[self.material.set],
+                &[],
+            );
+
+            self.device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        };
+
+        self.end_render_pass(command_buffer, swapchain_image);
+    }
+
+    fn begin_render_pass(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        swapchain_image: vk::Image,
+        swapchain_image_view: vk::ImageView,
+    ) {
+        let swapchain_attachments = [vk::RenderingAttachmentInfo::default()
+            .image_view(swapchain_image_view)
+            .image_layout(vk::ImageLayout::ATTACHMENT_OPTIMAL)
+            .resolve_mode(vk::ResolveModeFlags::NONE)
+            .resolve_image_view(vk::ImageView::null())
+            .resolve_image_layout(vk::ImageLayout::UNDEFINED)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .clear_value(SWAPCHAIN_CLEAR_VALUE)];
+
+        command_buffer_helpers::single_image_pipeline_barrier(
+            &self.device,
+            command_buffer,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::AccessFlags::NONE,
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            swapchain_image,
+            vk::ImageAspectFlags::COLOR,
+        );
+
+        let rendering_info = vk::RenderingInfo::default()
+            .render_area(self.render_area)
+            .layer_count(1)
+            .view_mask(0)
+            .color_attachments(&swapchain_attachments);
+
+        unsafe {
+            self.device
+                .cmd_begin_rendering(command_buffer, &rendering_info);
+        }
+    }
+
+    fn end_render_pass(&self, command_buffer: vk::CommandBuffer, swapchain_image: vk::Image) {
+        unsafe { self.device.cmd_end_rendering(command_buffer) };
+
+        command_buffer_helpers::single_image_pipeline_barrier(
+            &self.device,
+            command_buffer,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            vk::AccessFlags::NONE,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::PRESENT_SRC_KHR,
+            swapchain_image,
+            vk::ImageAspectFlags::COLOR,
+        );
+    }
+}
@@ -0,0 +1,94 @@
+// Single definition of the G-buffer color targets. shaders/GBufferLayout.glsl mirrors it for the
+// shaders, a test checks the two match so the Rust formats and the GLSL packing can't diverge.
+// World position isn't stored, it is reconstructed from the depth buffer
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GBufferTarget {
+    // Base color, alpha is unused
+    Albedo,
+    // Octahedral encoded world normal
+    Normal,
+    // Metallic, roughness
+    Material,
+    // UV motion since the previous frame, for TAA
+    Velocity,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GBufferFormat {
+    R8G8B8A8Unorm,
+    R16G16Snorm,
+    R8G8Unorm,
+    R16G16Sfloat,
+}
+
+pub struct GBufferTargetLayout {
+    pub target: GBufferTarget,
+    pub format: GBufferFormat,
+}
+
+// Color attachment locations follow this order
+pub const GBUFFER_LAYOUT: [GBufferTargetLayout; 4] = [
+    GBufferTargetLayout {
+        target: GBufferTarget::Albedo,
+        format: GBufferFormat::R8G8B8A8Unorm,
+    },
+    GBufferTargetLayout {
+        target: GBufferTarget::Normal,
+        format: GBufferFormat::R16G16Snorm,
+    },
+    GBufferTargetLayout {
+        target: GBufferTarget::Material,
+        format: GBufferFormat::R8G8Unorm,
+    },
+    GBufferTargetLayout {
+        target: GBufferTarget::Velocity,
+        format: GBufferFormat::R16G16Sfloat,
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Signed formats store [-1, 1] directly, unsigned normalized ones need a remap
+    fn is_signed(format: GBufferFormat) -> bool {
+        match format {
+            GBufferFormat::R16G16Snorm | GBufferFormat::R16G16Sfloat => true,
+            GBufferFormat::R8G8B8A8Unorm | GBufferFormat::R8G8Unorm => false,
+        }
+    }
+
+    fn generate_glsl() -> String {
+        let mut glsl =
+            String::from("// Mirrors src/gbuffer_layout.rs, checked by the test there\n\n");
+
+        for (location, layout) in GBUFFER_LAYOUT.iter().enumerate() {
+            let name = format!("{:?}", layout.target).to_uppercase();
+
+            glsl += &format!("// {:?}\n", layout.format);
+            glsl += &format!("#define GBUFFER_{name} {location}\n");
+            glsl += &format!(
+                "#define GBUFFER_{name}_SIGNED {}\n\n",
+                is_signed(layout.format) as u32
+            );
+        }
+
+        glsl += &format!("#define GBUFFER_TARGET_COUNT {}\n", GBUFFER_LAYOUT.len());
+
+        glsl
+    }
+
+    // The shaders are compiled separately by shaders.sh, so the header is checked in
+    #[test]
+    fn glsl_header_matches_the_layout() {
+        let expected = generate_glsl();
+
+        assert_eq!(
+            include_str!("../shaders/GBufferLayout.glsl"),
+            expected,
+            "shaders/GBufferLayout.glsl is out of date, replace it with:\n{}",
+            expected
+        );
+    }
+}
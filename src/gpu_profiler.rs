@@ -0,0 +1,254 @@
+use std::{fmt::Write as _, fs, io, path::Path};
+
+use ash::{vk, Device, Instance};
+
+use crate::renderer_error::Result;
+
+// Path of a Chrome tracing JSON written when the renderer is dropped, open it in
+// chrome://tracing or https://ui.perfetto.dev
+pub const GPU_TRACE_ENV_VAR: &str = "SR_ENGINE_GPU_TRACE";
+
+// Begin and end timestamp per scope
+const MAX_SCOPES: u32 = 32;
+
+// None when the queue can't write timestamps
+pub fn get_timestamp_period(
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+    queue_family_index: u32,
+) -> Option<TimestampPeriod> {
+    let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+    let queue_families =
+        unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
+    let valid_bits = queue_families[queue_family_index as usize].timestamp_valid_bits;
+
+    if valid_bits == 0 || properties.limits.timestamp_period == 0.0 {
+        return None;
+    }
+
+    Some(TimestampPeriod {
+        nanoseconds: properties.limits.timestamp_period as f64,
+        mask: if valid_bits >= 64 {
+            u64::MAX
+        } else {
+            (1 << valid_bits) - 1
+        },
+    })
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TimestampPeriod {
+    // Per tick
+    nanoseconds: f64,
+    // Timestamps wrap around after the valid bits
+    mask: u64,
+}
+
+impl TimestampPeriod {
+    fn to_milliseconds(self, begin: u64, end: u64) -> f64 {
+        (end.wrapping_sub(begin) & self.mask) as f64 * self.nanoseconds / 1_000_000.0
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PassTiming {
+    pub name: &'static str,
+    // Relative to the first pass of the frame
+    pub start_ms: f64,
+    pub duration_ms: f64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct GpuTimings {
+    pub frame_index: u32,
+    pub passes: Vec<PassTiming>,
+}
+
+impl GpuTimings {
+    // From the start of the first pass to the end of the last one
+    pub fn get_total_ms(&self) -> f64 {
+        self.passes
+            .iter()
+            .map(|pass| pass.start_ms + pass.duration_ms)
+            .fold(0.0, f64::max)
+    }
+}
+
+// Timestamps of one frame worker. Queries are read back when the worker is reused, its fence
+// guarantees the previous submission has finished by then
+pub struct GpuProfiler {
+    query_pool: vk::QueryPool,
+    period: Option<TimestampPeriod>,
+    // Of the frame recorded into the query pool
+    frame_index: u32,
+    scopes: Vec<&'static str>,
+    open_scope: Option<u32>,
+}
+
+impl GpuProfiler {
+    pub fn new(device: &Device, period: Option<TimestampPeriod>) -> Result<Self> {
+        let query_pool = if period.is_some() {
+            let create_info = vk::QueryPoolCreateInfo::default()
+                .query_type(vk::QueryType::TIMESTAMP)
+                .query_count(MAX_SCOPES * 2);
+
+            unsafe { device.create_query_pool(&create_info, None)? }
+        } else {
+            vk::QueryPool::null()
+        };
+
+        Ok(Self {
+            query_pool,
+            period,
+            frame_index: 0,
+            scopes: Vec::new(),
+            open_scope: None,
+        })
+    }
+
+    pub fn destroy(&mut self, device: &Device) {
+        unsafe { device.destroy_query_pool(self.query_pool, None) };
+    }
+
+    // Returns the timings of the previous frame recorded by this profiler and resets the queries
+    pub fn begin_frame(
+        &mut self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        frame_index: u32,
+    ) -> Result<Option<GpuTimings>> {
+        let Some(period) = self.period else {
+            return Ok(None);
+        };
+
+        let timings = self.read_timings(device, period)?;
+
+        unsafe { device.cmd_reset_query_pool(command_buffer, self.query_pool, 0, MAX_SCOPES * 2) };
+
+        self.frame_index = frame_index;
+        self.scopes.clear();
+        self.open_scope = None;
+
+        Ok(timings)
+    }
+
+    fn read_timings(&self, device: &Device, period: TimestampPeriod) -> Result<Option<GpuTimings>> {
+        if self.scopes.is_empty() {
+            return Ok(None);
+        }
+
+        let mut timestamps = vec![0u64; self.scopes.len() * 2];
+        let result = unsafe {
+            device.get_query_pool_results(
+                self.query_pool,
+                0,
+                &mut timestamps,
+                vk::QueryResultFlags::TYPE_64,
+            )
+        };
+
+        // The worker's fence has been waited on, so this only happens when a scope wasn't ended
+        if result == Err(vk::Result::NOT_READY) {
+            return Ok(None);
+        }
+        result?;
+
+        let frame_begin = timestamps[0];
+        let passes = self
+            .scopes
+            .iter()
+            .zip(timestamps.chunks_exact(2))
+            .map(|(&name, scope)| PassTiming {
+                name,
+                start_ms: period.to_milliseconds(frame_begin, scope[0]),
+                duration_ms: period.to_milliseconds(scope[0], scope[1]),
+            })
+            .collect();
+
+        Ok(Some(GpuTimings {
+            frame_index: self.frame_index,
+            passes,
+        }))
+    }
+
+    // Scopes don't nest, scopes past MAX_SCOPES are ignored
+    pub fn begin_scope(
+        &mut self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        name: &'static str,
+    ) {
+        if self.period.is_none() || self.scopes.len() as u32 >= MAX_SCOPES {
+            return;
+        }
+
+        let index = self.scopes.len() as u32;
+        self.scopes.push(name);
+        self.open_scope = Some(index);
+
+        unsafe {
+            device.cmd_write_timestamp2(
+                command_buffer,
+                vk::PipelineStageFlags2::ALL_COMMANDS,
+                self.query_pool,
+                index * 2,
+            )
+        };
+    }
+
+    pub fn end_scope(&mut self, device: &Device, command_buffer: vk::CommandBuffer) {
+        let Some(index) = self.open_scope.take() else {
+            return;
+        };
+
+        unsafe {
+            device.cmd_write_timestamp2(
+                command_buffer,
+                vk::PipelineStageFlags2::ALL_COMMANDS,
+                self.query_pool,
+                index * 2 + 1,
+            )
+        };
+    }
+}
+
+// Collects timings of many frames, frames are laid out back to back since the timestamps of
+// different frame workers don't share a clock origin
+#[derive(Default)]
+pub struct ChromeTrace {
+    events: String,
+    // Microseconds
+    time: f64,
+}
+
+impl ChromeTrace {
+    pub fn add_frame(&mut self, timings: &GpuTimings) {
+        for pass in &timings.passes {
+            if !self.events.is_empty() {
+                self.events += ",\n";
+            }
+
+            // Pass names are identifiers, they need no escaping
+            let _ = write!(
+                self.events,
+                "{{\"name\":\"{}\",\"cat\":\"gpu\",\"ph\":\"X\",\"ts\":{:.3},\"dur\":{:.3},\"pid\":0,\"tid\":0,\"args\":{{\"frame\":{}}}}}",
+                pass.name,
+                self.time + pass.start_ms * 1000.0,
+                pass.duration_ms * 1000.0,
+                timings.frame_index,
+            );
+        }
+
+        self.time += timings.get_total_ms() * 1000.0;
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        fs::write(
+            path,
+            format!(
+                "{{\"displayTimeUnit\":\"ms\",\"traceEvents\":[\n{}\n]}}\n",
+                self.events
+            ),
+        )
+    }
+}
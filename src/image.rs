@@ -4,6 +4,13 @@ use gpu_allocator::{
     MemoryLocation,
 };
 
+use crate::{
+    buffer::{Buffer, VulkanResource},
+    command_buffer_helpers, debug_markers,
+    renderer_error::Result,
+};
+
+#[derive(Clone, Copy)]
 pub struct ImageCreateInfo {
     pub extent: vk::Extent3D,
     pub image_type: vk::ImageType,
@@ -11,22 +18,42 @@ pub struct ImageCreateInfo {
     pub usage: vk::ImageUsageFlags,
     pub view_type: vk::ImageViewType,
     pub aspect_mask: vk::ImageAspectFlags,
+    pub mip_levels: u32,
+    // 6 for cubemaps
+    pub array_layers: u32,
 }
 
 pub struct Image {
     pub image: vk::Image,
+    // Covers every mip level
     pub image_view: vk::ImageView,
+    // One view per mip level, to render into or sample a single level
+    mip_image_views: Vec<vk::ImageView>,
+    pub mip_levels: u32,
+    pub array_layers: u32,
     allocation: Option<Allocation>,
 }
 
 impl Image {
-    pub fn new(device: &Device, allocator: &mut Allocator, create_info: &ImageCreateInfo) -> Self {
+    pub fn new(
+        device: &Device,
+        allocator: &mut Allocator,
+        create_info: &ImageCreateInfo,
+        name: &str,
+    ) -> Result<Self> {
+        let flags = if create_info.view_type == vk::ImageViewType::CUBE {
+            vk::ImageCreateFlags::CUBE_COMPATIBLE
+        } else {
+            vk::ImageCreateFlags::empty()
+        };
+
         let image_create_info = vk::ImageCreateInfo::default()
+            .flags(flags)
             .image_type(create_info.image_type)
             .format(create_info.format)
             .extent(create_info.extent)
-            .mip_levels(1)
-            .array_layers(1)
+            .mip_levels(create_info.mip_levels)
+            .array_layers(create_info.array_layers)
             .samples(vk::SampleCountFlags::TYPE_1)
             .tiling(vk::ImageTiling::OPTIMAL)
             .usage(create_info.usage)
@@ -34,12 +61,13 @@ impl Image {
             .queue_family_indices(&[])
             .initial_layout(vk::ImageLayout::UNDEFINED);
 
-        let image = unsafe { device.create_image(&image_create_info, None).unwrap() };
+        let image = unsafe { device.create_image(&image_create_info, None)? };
+        debug_markers::set_object_name(image, name);
+
         let requirements = unsafe { device.get_image_memory_requirements(image) };
 
         let description = AllocationCreateDesc {
-            name: "image",
-            // TODO: name: &format!("{}_staging", name),
+            name,
             requirements,
             location: MemoryLocation::GpuOnly,
             linear: false,
@@ -47,15 +75,49 @@ impl Image {
             allocation_scheme: AllocationScheme::GpuAllocatorManaged,
         };
 
-        let allocation = allocator.allocate(&description).unwrap();
+        let allocation = allocator.allocate(&description)?;
 
         // TODO: ?
-        unsafe {
-            device
-                .bind_image_memory(image, allocation.memory(), allocation.offset())
-                .unwrap()
+        unsafe { device.bind_image_memory(image, allocation.memory(), allocation.offset())? };
+
+        let image_view =
+            Self::create_image_view(device, image, create_info, 0, create_info.mip_levels)?;
+        debug_markers::set_object_name(image_view, name);
+
+        let mip_image_views = if create_info.mip_levels > 1 {
+            (0..create_info.mip_levels)
+                .map(|mip_level| {
+                    let image_view =
+                        Self::create_image_view(device, image, create_info, mip_level, 1)?;
+                    debug_markers::set_object_name(
+                        image_view,
+                        &format!("{}_mip{}", name, mip_level),
+                    );
+
+                    Ok(image_view)
+                })
+                .collect::<Result<_>>()?
+        } else {
+            Vec::new()
         };
 
+        Ok(Self {
+            image,
+            image_view,
+            mip_image_views,
+            mip_levels: create_info.mip_levels,
+            array_layers: create_info.array_layers,
+            allocation: Some(allocation),
+        })
+    }
+
+    fn create_image_view(
+        device: &Device,
+        image: vk::Image,
+        create_info: &ImageCreateInfo,
+        base_mip_level: u32,
+        level_count: u32,
+    ) -> Result<vk::ImageView> {
         let view_create_info = vk::ImageViewCreateInfo::default()
             .image(image)
             .view_type(create_info.view_type)
@@ -70,25 +132,121 @@ impl Image {
             .subresource_range(
                 vk::ImageSubresourceRange::default()
                     .aspect_mask(create_info.aspect_mask)
-                    .base_mip_level(0)
-                    .level_count(1)
+                    .base_mip_level(base_mip_level)
+                    .level_count(level_count)
                     .base_array_layer(0)
-                    .layer_count(1),
+                    .layer_count(create_info.array_layers),
             );
 
-        let image_view = unsafe { device.create_image_view(&view_create_info, None).unwrap() };
+        Ok(unsafe { device.create_image_view(&view_create_info, None)? })
+    }
 
-        Self {
-            image,
-            image_view,
-            allocation: Some(allocation),
+    pub fn get_mip_image_view(&self, mip_level: u32) -> vk::ImageView {
+        if self.mip_levels == 1 {
+            return self.image_view;
+        }
+
+        self.mip_image_views[mip_level as usize]
+    }
+
+    // Extent of a mip level, never smaller than a texel
+    pub fn get_mip_extent(extent: vk::Extent2D, mip_level: u32) -> vk::Extent2D {
+        vk::Extent2D {
+            width: (extent.width >> mip_level).max(1),
+            height: (extent.height >> mip_level).max(1),
         }
     }
 
+    // Uploads `data` into mip 0 through a staging buffer and leaves it ready for sampling
+    pub fn new_with_data<T>(
+        device: &Device,
+        allocator: &mut Allocator,
+        queue: vk::Queue,
+        command_pool: vk::CommandPool,
+        create_info: &ImageCreateInfo,
+        data: &[T],
+        name: &str,
+    ) -> Result<Self> {
+        let create_info = ImageCreateInfo {
+            usage: create_info.usage | vk::ImageUsageFlags::TRANSFER_DST,
+            ..*create_info
+        };
+        let image = Self::new(device, allocator, &create_info, name)?;
+
+        let mut staging_buffer = Buffer::new_empty(
+            device,
+            allocator,
+            std::mem::size_of_val(data) as vk::DeviceSize,
+            &format!("{}_staging", name),
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            MemoryLocation::CpuToGpu,
+        )?;
+        staging_buffer.write(data);
+
+        command_buffer_helpers::immediate_submit(device, queue, command_pool, |command_buffer| {
+            command_buffer_helpers::single_image_pipeline_barrier(
+                device,
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::NONE,
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                image.image,
+                create_info.aspect_mask,
+            );
+
+            let region = vk::BufferImageCopy::default()
+                .buffer_offset(0)
+                .buffer_row_length(0)
+                .buffer_image_height(0)
+                .image_subresource(
+                    vk::ImageSubresourceLayers::default()
+                        .aspect_mask(create_info.aspect_mask)
+                        .mip_level(0)
+                        .base_array_layer(0)
+                        .layer_count(1),
+                )
+                .image_offset(vk::Offset3D::default())
+                .image_extent(create_info.extent);
+
+            unsafe {
+                device.cmd_copy_buffer_to_image(
+                    command_buffer,
+                    staging_buffer.buffer,
+                    image.image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &[region],
+                )
+            };
+
+            command_buffer_helpers::single_image_pipeline_barrier(
+                device,
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::AccessFlags::SHADER_READ,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                image.image,
+                create_info.aspect_mask,
+            );
+        })?;
+
+        staging_buffer.release(device, allocator);
+
+        Ok(image)
+    }
+
     pub fn destroy(&mut self, device: &Device, allocator: &mut Allocator) {
         allocator.free(self.allocation.take().unwrap());
 
         unsafe {
+            for &mip_image_view in &self.mip_image_views {
+                device.destroy_image_view(mip_image_view, None);
+            }
             device.destroy_image_view(self.image_view, None);
             device.destroy_image(self.image, None);
         }
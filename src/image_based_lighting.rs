@@ -0,0 +1,552 @@
+use ash::{vk, Device};
+use gpu_allocator::vulkan::Allocator;
+use half::f16;
+
+use crate::{
+    command_buffer_helpers::{self, ImageTransition},
+    image::{Image, ImageCreateInfo},
+    pipeline_manager::{ImageBasedLightingMaterial, PipelineManager},
+    push_constants_data::ImageBasedLightingPushConstantsData,
+    renderer_error::Result,
+};
+
+pub mod image_based_lighting_consts {
+    use ash::vk;
+
+    // Written as storage images, two channel float storage formats are optional so the BRDF LUT uses it too
+    pub const FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+    pub const ENVIRONMENT_SIZE: u32 = 512;
+    pub const IRRADIANCE_SIZE: u32 = 32;
+    pub const PREFILTERED_SIZE: u32 = 128;
+    // Roughness goes from 0 at mip 0 to 1 at the last mip
+    pub const PREFILTERED_MIP_COUNT: u32 = 5;
+    pub const BRDF_LUT_SIZE: u32 = 512;
+    // Matches local_size_x and local_size_y of the compute shaders
+    pub const WORKGROUP_SIZE: u32 = 8;
+}
+
+const CUBE_FACE_COUNT: u32 = 6;
+
+// Procedural fallback when no environment map could be loaded
+const FALLBACK_WIDTH: u32 = 128;
+const FALLBACK_HEIGHT: u32 = 64;
+
+#[derive(Clone, Copy)]
+pub struct ImageBasedLightingOutput {
+    // Radiance cubemap with a full mip chain
+    pub environment: vk::ImageView,
+    // Cosine convolved environment for diffuse lighting
+    pub irradiance: vk::ImageView,
+    // GGX convolved environment, one roughness per mip
+    pub prefiltered: vk::ImageView,
+    // Split sum scale and bias of F0 by NoV and roughness
+    pub brdf_lut: vk::ImageView,
+}
+
+// One compute shader run writing a single mip of its target
+struct Dispatch {
+    pipeline: vk::Pipeline,
+    set: vk::DescriptorSet,
+    roughness: f32,
+    // Of the target mip
+    size: u32,
+    layer_count: u32,
+}
+
+// Precomputes split sum image based lighting from an equirectangular HDR environment map once,
+// every image is left in SHADER_READ_ONLY_OPTIMAL
+pub struct ImageBasedLighting {
+    device: Device,
+    environment: Image,
+    irradiance: Image,
+    prefiltered: Image,
+    brdf_lut: Image,
+}
+
+impl ImageBasedLighting {
+    pub fn new(
+        device: &Device,
+        allocator: &mut Allocator,
+        pipeline_manager: &mut PipelineManager,
+        queue: vk::Queue,
+        command_pool: vk::CommandPool,
+        path: &str,
+    ) -> Result<Self> {
+        let mut equirectangular =
+            Self::load_equirectangular(device, allocator, queue, command_pool, path)?;
+
+        let environment_size = image_based_lighting_consts::ENVIRONMENT_SIZE;
+        let environment = Self::create_cube_image(
+            device,
+            allocator,
+            environment_size,
+            32 - environment_size.leading_zeros(),
+            vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST,
+            "environment",
+        )?;
+        let irradiance = Self::create_cube_image(
+            device,
+            allocator,
+            image_based_lighting_consts::IRRADIANCE_SIZE,
+            1,
+            vk::ImageUsageFlags::empty(),
+            "irradiance",
+        )?;
+        let prefiltered = Self::create_cube_image(
+            device,
+            allocator,
+            image_based_lighting_consts::PREFILTERED_SIZE,
+            image_based_lighting_consts::PREFILTERED_MIP_COUNT,
+            vk::ImageUsageFlags::empty(),
+            "prefiltered",
+        )?;
+        let brdf_lut = Self::create_brdf_lut_image(device, allocator)?;
+
+        let equirectangular_set = pipeline_manager.create_image_based_lighting_set(
+            equirectangular.image_view,
+            environment.get_mip_image_view(0),
+            "equirectangular_to_cube",
+        )?;
+        let irradiance_set = pipeline_manager.create_image_based_lighting_set(
+            environment.image_view,
+            irradiance.image_view,
+            "irradiance",
+        )?;
+        let prefilter_sets: Vec<vk::DescriptorSet> = (0..prefiltered.mip_levels)
+            .map(|mip| {
+                pipeline_manager.create_image_based_lighting_set(
+                    environment.image_view,
+                    prefiltered.get_mip_image_view(mip),
+                    &format!("prefilter_mip{}", mip),
+                )
+            })
+            .collect::<Result<_>>()?;
+        // The BRDF LUT doesn't sample anything, the source only keeps the set complete
+        let brdf_lut_set = pipeline_manager.create_image_based_lighting_set(
+            environment.image_view,
+            brdf_lut.image_view,
+            "brdf_lut",
+        )?;
+
+        let material = pipeline_manager.image_based_lighting_material.clone();
+
+        command_buffer_helpers::immediate_submit(device, queue, command_pool, |command_buffer| {
+            Self::begin_storage_write(device, command_buffer, &environment, 0, 1);
+            Self::dispatch(
+                device,
+                command_buffer,
+                &material,
+                &Dispatch {
+                    pipeline: material.equirectangular_to_cube_pipeline,
+                    set: equirectangular_set,
+                    roughness: 0.0,
+                    size: environment_size,
+                    layer_count: CUBE_FACE_COUNT,
+                },
+            );
+
+            Self::generate_mips(device, command_buffer, &environment);
+
+            Self::begin_storage_write(device, command_buffer, &irradiance, 0, 1);
+            Self::dispatch(
+                device,
+                command_buffer,
+                &material,
+                &Dispatch {
+                    pipeline: material.irradiance_pipeline,
+                    set: irradiance_set,
+                    roughness: 0.0,
+                    size: image_based_lighting_consts::IRRADIANCE_SIZE,
+                    layer_count: CUBE_FACE_COUNT,
+                },
+            );
+            Self::end_storage_write(device, command_buffer, &irradiance);
+
+            Self::begin_storage_write(
+                device,
+                command_buffer,
+                &prefiltered,
+                0,
+                prefiltered.mip_levels,
+            );
+            for (mip, &set) in prefilter_sets.iter().enumerate() {
+                let roughness = mip as f32 / (prefiltered.mip_levels - 1).max(1) as f32;
+
+                Self::dispatch(
+                    device,
+                    command_buffer,
+                    &material,
+                    &Dispatch {
+                        pipeline: material.prefilter_pipeline,
+                        set,
+                        roughness,
+                        size: (image_based_lighting_consts::PREFILTERED_SIZE >> mip).max(1),
+                        layer_count: CUBE_FACE_COUNT,
+                    },
+                );
+            }
+            Self::end_storage_write(device, command_buffer, &prefiltered);
+
+            Self::begin_storage_write(device, command_buffer, &brdf_lut, 0, 1);
+            Self::dispatch(
+                device,
+                command_buffer,
+                &material,
+                &Dispatch {
+                    pipeline: material.brdf_lut_pipeline,
+                    set: brdf_lut_set,
+                    roughness: 0.0,
+                    size: image_based_lighting_consts::BRDF_LUT_SIZE,
+                    layer_count: 1,
+                },
+            );
+            Self::end_storage_write(device, command_buffer, &brdf_lut);
+        })?;
+
+        equirectangular.destroy(device, allocator);
+
+        Ok(Self {
+            device: device.clone(),
+            environment,
+            irradiance,
+            prefiltered,
+            brdf_lut,
+        })
+    }
+
+    pub fn destroy(&mut self, allocator: &mut Allocator) {
+        self.environment.destroy(&self.device, allocator);
+        self.irradiance.destroy(&self.device, allocator);
+        self.prefiltered.destroy(&self.device, allocator);
+        self.brdf_lut.destroy(&self.device, allocator);
+    }
+
+    pub fn get_output(&self) -> ImageBasedLightingOutput {
+        ImageBasedLightingOutput {
+            environment: self.environment.image_view,
+            irradiance: self.irradiance.image_view,
+            prefiltered: self.prefiltered.image_view,
+            brdf_lut: self.brdf_lut.image_view,
+        }
+    }
+
+    fn dispatch(
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        material: &ImageBasedLightingMaterial,
+        dispatch: &Dispatch,
+    ) {
+        let push_data = ImageBasedLightingPushConstantsData::new(dispatch.roughness);
+        let group_count = dispatch
+            .size
+            .div_ceil(image_based_lighting_consts::WORKGROUP_SIZE);
+
+        unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                dispatch.pipeline,
+            );
+
+            device.cmd_push_constants(
+                command_buffer,
+                material.layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                push_data.get(),
+            );
+
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                material.layout,
+                0,
+                &[dispatch.set],
+                &[],
+            );
+
+            device.cmd_dispatch(
+                command_buffer,
+                group_count,
+                group_count,
+                dispatch.layer_count,
+            );
+        }
+    }
+
+    fn begin_storage_write(
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        image: &Image,
+        base_mip_level: u32,
+        level_count: u32,
+    ) {
+        command_buffer_helpers::image_range_pipeline_barrier(
+            device,
+            command_buffer,
+            &ImageTransition {
+                src_stage_mask: vk::PipelineStageFlags::TOP_OF_PIPE,
+                dst_stage_mask: vk::PipelineStageFlags::COMPUTE_SHADER,
+                src_access_mask: vk::AccessFlags::NONE,
+                dst_access_mask: vk::AccessFlags::SHADER_WRITE,
+                old_layout: vk::ImageLayout::UNDEFINED,
+                new_layout: vk::ImageLayout::GENERAL,
+            },
+            image.image,
+            Self::get_subresource_range(image, base_mip_level, level_count),
+        );
+    }
+
+    fn end_storage_write(device: &Device, command_buffer: vk::CommandBuffer, image: &Image) {
+        command_buffer_helpers::image_range_pipeline_barrier(
+            device,
+            command_buffer,
+            &ImageTransition {
+                src_stage_mask: vk::PipelineStageFlags::COMPUTE_SHADER,
+                dst_stage_mask: vk::PipelineStageFlags::COMPUTE_SHADER
+                    | vk::PipelineStageFlags::FRAGMENT_SHADER,
+                src_access_mask: vk::AccessFlags::SHADER_WRITE,
+                dst_access_mask: vk::AccessFlags::SHADER_READ,
+                old_layout: vk::ImageLayout::GENERAL,
+                new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            },
+            image.image,
+            Self::get_subresource_range(image, 0, image.mip_levels),
+        );
+    }
+
+    // Blits every mip from the previous one, mip 0 is expected in GENERAL after a compute write.
+    // The mips let the prefiltering sample a lower resolution where its samples are sparse
+    fn generate_mips(device: &Device, command_buffer: vk::CommandBuffer, image: &Image) {
+        command_buffer_helpers::image_range_pipeline_barrier(
+            device,
+            command_buffer,
+            &ImageTransition {
+                src_stage_mask: vk::PipelineStageFlags::COMPUTE_SHADER,
+                dst_stage_mask: vk::PipelineStageFlags::TRANSFER,
+                src_access_mask: vk::AccessFlags::SHADER_WRITE,
+                dst_access_mask: vk::AccessFlags::TRANSFER_READ,
+                old_layout: vk::ImageLayout::GENERAL,
+                new_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            },
+            image.image,
+            Self::get_subresource_range(image, 0, 1),
+        );
+
+        let size = image_based_lighting_consts::ENVIRONMENT_SIZE;
+
+        for mip in 1..image.mip_levels {
+            command_buffer_helpers::image_range_pipeline_barrier(
+                device,
+                command_buffer,
+                &ImageTransition {
+                    src_stage_mask: vk::PipelineStageFlags::TOP_OF_PIPE,
+                    dst_stage_mask: vk::PipelineStageFlags::TRANSFER,
+                    src_access_mask: vk::AccessFlags::NONE,
+                    dst_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+                    old_layout: vk::ImageLayout::UNDEFINED,
+                    new_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                },
+                image.image,
+                Self::get_subresource_range(image, mip, 1),
+            );
+
+            let source_size = (size >> (mip - 1)).max(1) as i32;
+            let target_size = (size >> mip).max(1) as i32;
+
+            let region = vk::ImageBlit::default()
+                .src_subresource(Self::get_subresource_layers(image, mip - 1))
+                .src_offsets([
+                    vk::Offset3D::default(),
+                    vk::Offset3D::default().x(source_size).y(source_size).z(1),
+                ])
+                .dst_subresource(Self::get_subresource_layers(image, mip))
+                .dst_offsets([
+                    vk::Offset3D::default(),
+                    vk::Offset3D::default().x(target_size).y(target_size).z(1),
+                ]);
+
+            unsafe {
+                device.cmd_blit_image(
+                    command_buffer,
+                    image.image,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    image.image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &[region],
+                    vk::Filter::LINEAR,
+                )
+            };
+
+            command_buffer_helpers::image_range_pipeline_barrier(
+                device,
+                command_buffer,
+                &ImageTransition {
+                    src_stage_mask: vk::PipelineStageFlags::TRANSFER,
+                    dst_stage_mask: vk::PipelineStageFlags::TRANSFER,
+                    src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+                    dst_access_mask: vk::AccessFlags::TRANSFER_READ,
+                    old_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    new_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                },
+                image.image,
+                Self::get_subresource_range(image, mip, 1),
+            );
+        }
+
+        command_buffer_helpers::image_range_pipeline_barrier(
+            device,
+            command_buffer,
+            &ImageTransition {
+                src_stage_mask: vk::PipelineStageFlags::TRANSFER,
+                dst_stage_mask: vk::PipelineStageFlags::COMPUTE_SHADER
+                    | vk::PipelineStageFlags::FRAGMENT_SHADER,
+                src_access_mask: vk::AccessFlags::TRANSFER_READ | vk::AccessFlags::TRANSFER_WRITE,
+                dst_access_mask: vk::AccessFlags::SHADER_READ,
+                old_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            },
+            image.image,
+            Self::get_subresource_range(image, 0, image.mip_levels),
+        );
+    }
+
+    fn get_subresource_range(
+        image: &Image,
+        base_mip_level: u32,
+        level_count: u32,
+    ) -> vk::ImageSubresourceRange {
+        vk::ImageSubresourceRange::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_mip_level(base_mip_level)
+            .level_count(level_count)
+            .base_array_layer(0)
+            .layer_count(image.array_layers)
+    }
+
+    fn get_subresource_layers(image: &Image, mip_level: u32) -> vk::ImageSubresourceLayers {
+        vk::ImageSubresourceLayers::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .mip_level(mip_level)
+            .base_array_layer(0)
+            .layer_count(image.array_layers)
+    }
+
+    fn create_cube_image(
+        device: &Device,
+        allocator: &mut Allocator,
+        size: u32,
+        mip_levels: u32,
+        additional_usage: vk::ImageUsageFlags,
+        name: &str,
+    ) -> Result<Image> {
+        let create_info = ImageCreateInfo {
+            extent: vk::Extent3D::default().width(size).height(size).depth(1),
+            image_type: vk::ImageType::TYPE_2D,
+            format: image_based_lighting_consts::FORMAT,
+            usage: vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED | additional_usage,
+            view_type: vk::ImageViewType::CUBE,
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_levels,
+            array_layers: CUBE_FACE_COUNT,
+        };
+
+        Image::new(device, allocator, &create_info, name)
+    }
+
+    fn create_brdf_lut_image(device: &Device, allocator: &mut Allocator) -> Result<Image> {
+        let size = image_based_lighting_consts::BRDF_LUT_SIZE;
+
+        let create_info = ImageCreateInfo {
+            extent: vk::Extent3D::default().width(size).height(size).depth(1),
+            image_type: vk::ImageType::TYPE_2D,
+            format: image_based_lighting_consts::FORMAT,
+            usage: vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED,
+            view_type: vk::ImageViewType::TYPE_2D,
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_levels: 1,
+            array_layers: 1,
+        };
+
+        Image::new(device, allocator, &create_info, "brdf_lut")
+    }
+
+    // Falls back to a procedural sky when `path` can't be loaded
+    fn load_equirectangular(
+        device: &Device,
+        allocator: &mut Allocator,
+        queue: vk::Queue,
+        command_pool: vk::CommandPool,
+        path: &str,
+    ) -> Result<Image> {
+        let (width, height, pixels) = match image::open(path) {
+            Ok(image) => {
+                let image = image.into_rgba32f();
+                let pixels: Vec<[f16; 4]> = image
+                    .pixels()
+                    .map(|pixel| pixel.0.map(f16::from_f32))
+                    .collect();
+
+                (image.width(), image.height(), pixels)
+            }
+            Err(error) => {
+                log::warn!(
+                    "Failed to load environment map {}: {}, using a procedural sky",
+                    path,
+                    error
+                );
+
+                (
+                    FALLBACK_WIDTH,
+                    FALLBACK_HEIGHT,
+                    Self::create_fallback_sky(FALLBACK_WIDTH, FALLBACK_HEIGHT),
+                )
+            }
+        };
+
+        let create_info = ImageCreateInfo {
+            extent: vk::Extent3D::default().width(width).height(height).depth(1),
+            image_type: vk::ImageType::TYPE_2D,
+            format: vk::Format::R16G16B16A16_SFLOAT,
+            usage: vk::ImageUsageFlags::SAMPLED,
+            view_type: vk::ImageViewType::TYPE_2D,
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_levels: 1,
+            array_layers: 1,
+        };
+
+        Image::new_with_data(
+            device,
+            allocator,
+            queue,
+            command_pool,
+            &create_info,
+            &pixels,
+            "equirectangular",
+        )
+    }
+
+    // Sky gradient over a dark ground, rows go from +Y at the top to -Y at the bottom
+    fn create_fallback_sky(width: u32, height: u32) -> Vec<[f16; 4]> {
+        let zenith = [0.2, 0.4, 1.0];
+        let horizon = [1.0, 0.95, 0.9];
+        let ground = [0.2, 0.18, 0.15];
+
+        (0..height)
+            .flat_map(|y| {
+                let elevation = 1.0 - 2.0 * (y as f32 + 0.5) / height as f32;
+
+                let color: [f32; 3] = if elevation > 0.0 {
+                    let t = elevation.sqrt();
+                    std::array::from_fn(|i| horizon[i] + (zenith[i] - horizon[i]) * t)
+                } else {
+                    ground
+                };
+
+                let pixel = [color[0], color[1], color[2], 1.0].map(f16::from_f32);
+
+                (0..width).map(move |_| pixel)
+            })
+            .collect()
+    }
+}
@@ -1,40 +1,244 @@
 extern crate core;
 
+use std::env;
+use std::time::{Duration, Instant};
+
+use nalgebra::{Matrix4, Vector3};
 use winit::application::ApplicationHandler;
 use winit::dpi::PhysicalSize;
+use winit::event::{ElementState, KeyEvent};
 use winit::event_loop::{ActiveEventLoop, ControlFlow};
+use winit::keyboard::{KeyCode, PhysicalKey};
 use winit::window::{Window, WindowAttributes, WindowId};
 use winit::{event::WindowEvent, event_loop::EventLoop};
 
+use crate::camera::{Camera, ProjectionMode};
+use crate::camera_controller::{CameraController, FreeFlyController, InputState, OrbitController};
+use crate::debug_ui::DebugUi;
 use crate::renderer::Renderer;
+use crate::renderer_config::{ConfigSource, RendererConfig};
+use crate::renderer_error::RendererError;
 
+mod bloom_render_pass;
+mod bounding_volume;
 mod buffer;
 mod buffer_manager;
 mod camera;
+mod camera_controller;
 mod command_buffer;
 mod command_buffer_helpers;
+mod culling_pass;
+mod debug_draw;
+mod debug_draw_render_pass;
+mod debug_markers;
+mod debug_ui;
+mod debug_ui_render_pass;
 mod deferred_lightning_render_pass;
 mod deferred_render_pass;
+mod device_selection;
 mod draw_data;
+mod frame_stats;
 mod frame_worker;
+mod frustum;
+mod fxaa_render_pass;
+mod gbuffer_layout;
+mod gpu_profiler;
 mod image;
+mod image_based_lighting;
+mod mesh_manager;
 mod patched_sphere;
 mod pipeline_manager;
 mod push_constants_data;
 mod render_pass_attachment_output;
+mod render_settings;
 mod renderer;
+mod renderer_config;
+mod renderer_error;
 mod shader_manager;
 mod shadow_map_render_pass;
+mod skybox_render_pass;
+mod ssao_render_pass;
+mod swapchain_config;
+mod taa_render_pass;
+mod tonemapping_render_pass;
+mod transparent_render_pass;
+mod validation;
+
+const TITLE_UPDATE_INTERVAL: Duration = Duration::from_millis(500);
 
 #[derive(Default)]
 struct State {
+    config_source: ConfigSource,
+    config: RendererConfig,
     window: Option<Window>,
     renderer: Option<Renderer>,
+    debug_ui: Option<DebugUi>,
+    input: InputState,
+    camera_controller: Option<Box<dyn CameraController>>,
+    orbit_camera: bool,
+    last_frame: Option<Instant>,
+    last_title_update: Option<Instant>,
 }
 
 impl State {
-    fn create_renderer(&mut self, window: &Window) {
-        self.renderer = Some(Renderer::new(window));
+    fn create_renderer(&mut self, window: &Window) -> Result<(), RendererError> {
+        let mut renderer = Renderer::new(window, &self.config)?;
+        self.camera_controller = Some(Self::create_camera_controller(
+            self.orbit_camera,
+            renderer.get_camera_mut(),
+        ));
+        self.renderer = Some(renderer);
+        self.debug_ui = Some(DebugUi::new(window));
+
+        Ok(())
+    }
+
+    // Both controllers start from the current camera position, looking at the origin
+    fn create_camera_controller(orbit: bool, camera: &Camera) -> Box<dyn CameraController> {
+        let position = camera.get_position().coords;
+        let target = Vector3::zeros();
+
+        if orbit {
+            Box::new(OrbitController::new(position, target))
+        } else {
+            Box::new(FreeFlyController::new(position, target))
+        }
+    }
+
+    fn toggle_camera_controller(&mut self) {
+        if let Some(renderer) = &mut self.renderer {
+            self.orbit_camera = !self.orbit_camera;
+            self.camera_controller = Some(Self::create_camera_controller(
+                self.orbit_camera,
+                renderer.get_camera_mut(),
+            ));
+
+            // The new controller can snap the camera to look at the origin
+            renderer.reset_history();
+        }
+    }
+
+    fn cycle_projection_mode(&mut self) {
+        if let Some(renderer) = &mut self.renderer {
+            let camera = renderer.get_camera_mut();
+            let projection_mode = match camera.get_projection_mode() {
+                ProjectionMode::Perspective => ProjectionMode::ReverseZInfinite,
+                ProjectionMode::ReverseZInfinite => ProjectionMode::Orthographic { height: 10.0 },
+                ProjectionMode::Orthographic { .. } => ProjectionMode::Perspective,
+            };
+
+            log::info!("Projection mode: {:?}", projection_mode);
+            camera.set_projection_mode(projection_mode);
+        }
+    }
+
+    fn toggle_gpu_driven(&mut self) {
+        if let Some(renderer) = &mut self.renderer {
+            let settings = renderer.get_settings_mut();
+            settings.gpu_driven = !settings.gpu_driven;
+
+            log::info!("GPU driven: {}", settings.gpu_driven);
+        }
+    }
+
+    fn cycle_tonemapping_operator(&mut self) {
+        if let Some(renderer) = &mut self.renderer {
+            let settings = renderer.get_settings_mut();
+            settings.tonemapping_operator = settings.tonemapping_operator.next();
+
+            log::info!("Tonemapping operator: {:?}", settings.tonemapping_operator);
+        }
+    }
+
+    fn toggle_bloom(&mut self) {
+        if let Some(renderer) = &mut self.renderer {
+            let settings = renderer.get_settings_mut();
+            settings.bloom = !settings.bloom;
+
+            log::info!("Bloom: {}", settings.bloom);
+        }
+    }
+
+    fn toggle_ssao(&mut self) {
+        if let Some(renderer) = &mut self.renderer {
+            let settings = renderer.get_settings_mut();
+            settings.ssao = !settings.ssao;
+
+            log::info!("SSAO: {}", settings.ssao);
+        }
+    }
+
+    fn cycle_anti_aliasing(&mut self) {
+        if let Some(renderer) = &mut self.renderer {
+            let settings = renderer.get_settings_mut();
+            settings.anti_aliasing = settings.anti_aliasing.next();
+
+            log::info!("Anti-aliasing: {:?}", settings.anti_aliasing);
+        }
+    }
+
+    fn cycle_debug_view(&mut self) {
+        if let Some(renderer) = &mut self.renderer {
+            let settings = renderer.get_settings_mut();
+            settings.debug_view = settings.debug_view.next();
+
+            log::info!("Debug view: {:?}", settings.debug_view);
+        }
+    }
+
+    fn change_exposure(&mut self, stops: f32) {
+        if let Some(renderer) = &mut self.renderer {
+            let settings = renderer.get_settings_mut();
+            settings.exposure += stops;
+
+            log::info!("Exposure: {:+.1} EV", settings.exposure);
+        }
+    }
+
+    // Picks up edits to the config file, the command line overrides still apply on top
+    fn reload_config(&mut self) {
+        match RendererConfig::load(&self.config_source) {
+            Ok(config) => {
+                if let Some(renderer) = &mut self.renderer {
+                    renderer.apply_config(&config);
+                }
+                self.config = config;
+
+                log::info!("Config reloaded from {}", self.config_source.path.display());
+            }
+            Err(error) => log::error!("Failed to reload the config: {}", error),
+        }
+    }
+
+    fn toggle_debug_ui(&mut self) {
+        if let Some(debug_ui) = &mut self.debug_ui {
+            debug_ui.toggle();
+        }
+    }
+
+    fn print_gpu_timings(&self) {
+        if let Some(renderer) = &self.renderer {
+            let gpu_timings = renderer.get_gpu_timings();
+
+            log::info!(
+                "GPU frame {}: {:.3} ms",
+                gpu_timings.frame_index,
+                gpu_timings.get_total_ms()
+            );
+            for pass in &gpu_timings.passes {
+                log::info!("  {:<20} {:.3} ms", pass.name, pass.duration_ms);
+            }
+        }
+    }
+
+    fn get_delta_time(&mut self) -> f32 {
+        let now = Instant::now();
+        let delta_time = self
+            .last_frame
+            .map_or(0.0, |last_frame| (now - last_frame).as_secs_f32());
+        self.last_frame = Some(now);
+
+        delta_time
     }
 }
 
@@ -45,13 +249,16 @@ impl ApplicationHandler for State {
             let window = event_loop
                 .create_window(
                     Window::default_attributes()
-                        .with_inner_size(PhysicalSize::new(1920, 1080))
+                        .with_inner_size(PhysicalSize::new(self.config.width, self.config.height))
                         .with_resizable(false),
                 )
                 .ok();
 
             if let Some(window) = &window {
-                self.create_renderer(window);
+                if let Err(error) = self.create_renderer(window) {
+                    log::error!("Failed to create the renderer: {}", error);
+                    event_loop.exit();
+                }
             }
 
             self.window = window;
@@ -66,17 +273,147 @@ impl ApplicationHandler for State {
     ) {
         // `unwrap` is fine, the window will always be available when
         // receiving a window event.
-        let _window = self.window.as_ref().unwrap();
+        let window = self.window.as_ref().unwrap();
+
+        // Clicks and keys meant for the debug UI don't reach the camera or the shortcuts. Releases
+        // and focus loss always do, or keys held while the UI took over would stay pressed
+        if let Some(debug_ui) = &mut self.debug_ui {
+            let consumed = debug_ui.on_window_event(window, &event);
+            let always_handled = matches!(
+                event,
+                WindowEvent::RedrawRequested
+                    | WindowEvent::CloseRequested
+                    | WindowEvent::Focused(false)
+                    | WindowEvent::KeyboardInput {
+                        event: KeyEvent {
+                            state: ElementState::Released,
+                            ..
+                        },
+                        ..
+                    }
+                    | WindowEvent::MouseInput {
+                        state: ElementState::Released,
+                        ..
+                    }
+            );
+
+            if consumed && !always_handled {
+                return;
+            }
+        }
+
+        self.input.handle_window_event(&event);
 
         match event {
             WindowEvent::CloseRequested => {
                 event_loop.exit();
             }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key_code),
+                        state: ElementState::Pressed,
+                        repeat,
+                        ..
+                    },
+                ..
+            } => match key_code {
+                // Exposure keeps stepping while held, everything else toggles once per press
+                KeyCode::Equal => self.change_exposure(0.5),
+                KeyCode::Minus => self.change_exposure(-0.5),
+                _ if repeat => (),
+                KeyCode::Tab => self.toggle_camera_controller(),
+                KeyCode::KeyP => self.cycle_projection_mode(),
+                KeyCode::KeyG => self.toggle_gpu_driven(),
+                KeyCode::KeyT => self.cycle_tonemapping_operator(),
+                KeyCode::KeyB => self.toggle_bloom(),
+                KeyCode::KeyO => self.toggle_ssao(),
+                KeyCode::KeyY => self.cycle_anti_aliasing(),
+                KeyCode::KeyV => self.cycle_debug_view(),
+                KeyCode::F1 => self.print_gpu_timings(),
+                KeyCode::F2 => self.toggle_debug_ui(),
+                KeyCode::F5 => self.reload_config(),
+                _ => (),
+            },
             WindowEvent::RedrawRequested => {
+                let delta_time = self.get_delta_time();
+
                 if let Some(renderer) = &mut self.renderer {
-                    renderer.render();
+                    if let Some(camera_controller) = &mut self.camera_controller {
+                        camera_controller.update(
+                            renderer.get_camera_mut(),
+                            &mut self.input,
+                            delta_time,
+                        );
+                    }
+
+                    // World origin, on top of the bounds the renderer draws
+                    if renderer.get_settings_mut().debug_bounds {
+                        let debug_draw = renderer.get_debug_draw_mut();
+                        debug_draw.set_depth_test(false);
+                        debug_draw.axes(&Matrix4::identity(), 1.0);
+                    }
+
+                    if let Some(debug_ui) = &mut self.debug_ui {
+                        let output = debug_ui.run(self.window.as_ref().unwrap(), |context| {
+                            debug_ui::draw_settings_window(context, renderer)
+                        });
+
+                        if let Err(error) = renderer.update_debug_ui(output) {
+                            log::error!("Updating the debug UI failed: {}", error);
+                            event_loop.exit();
+                            return;
+                        }
+                    }
+
+                    let recreated = match renderer.render() {
+                        Ok(()) if renderer.is_swapchain_suboptimal() => {
+                            Some(renderer.recreate_swapchain())
+                        }
+                        Ok(()) => None,
+                        Err(RendererError::OutOfDate) => Some(renderer.recreate_swapchain()),
+                        Err(RendererError::SurfaceLost) => {
+                            Some(renderer.recreate_surface(self.window.as_ref().unwrap()))
+                        }
+                        Err(error @ (RendererError::DeviceLost | RendererError::OutOfMemory)) => {
+                            log::error!("Rendering failed: {}", error);
+                            event_loop.exit();
+                            return;
+                        }
+                        // Only this frame is dropped
+                        Err(error) => {
+                            log::error!("Rendering failed: {}", error);
+                            None
+                        }
+                    };
+
+                    if let Some(Err(error)) = recreated {
+                        log::error!("Recreating the swapchain failed: {}", error);
+                        event_loop.exit();
+                        return;
+                    }
+
+                    // Throttled, a title that changes every frame is unreadable and slow on
+                    // some window managers
+                    let now = Instant::now();
+                    let update_title = self.last_title_update.is_none_or(|last_title_update| {
+                        now - last_title_update >= TITLE_UPDATE_INTERVAL
+                    });
+
+                    if update_title {
+                        let culling_stats = renderer.get_culling_stats();
+                        let title = format!(
+                            "sr-engine - {}/{} objects visible - {}",
+                            culling_stats.visible,
+                            culling_stats.total,
+                            renderer.get_frame_stats()
+                        );
+
+                        self.window.as_ref().unwrap().set_title(&title);
+                        self.last_title_update = Some(now);
+                    }
                 }
-                _window.request_redraw();
+                self.window.as_ref().unwrap().request_redraw();
             }
             _ => (),
         }
@@ -89,8 +426,25 @@ impl ApplicationHandler for State {
 }
 
 fn main() {
+    // Renderer messages are shown from info, RUST_LOG overrides it, e.g. RUST_LOG=vulkan=trace
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let loaded = ConfigSource::from_args(env::args().skip(1))
+        .and_then(|source| RendererConfig::load(&source).map(|config| (source, config)));
+    let (config_source, config) = match loaded {
+        Ok(loaded) => loaded,
+        Err(error) => {
+            log::error!("{}", error);
+            return;
+        }
+    };
+
     let event_loop = EventLoop::new().unwrap();
     event_loop.set_control_flow(ControlFlow::Poll);
-    let mut state = State::default();
+    let mut state = State {
+        config_source,
+        config,
+        ..Default::default()
+    };
     let _ = event_loop.run_app(&mut state);
 }
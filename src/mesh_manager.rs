@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+
+use ash::vk;
+use gpu_allocator::vulkan::Allocator;
+use nalgebra::Vector3;
+
+use crate::{
+    bounding_volume::{Aabb, BoundingSphere},
+    buffer_manager::BufferManager,
+    draw_data::{GeometryBuffers, MeshData},
+    renderer_error::Result,
+};
+
+const INDICES_BUFFER_NAME: &str = "meshIndices";
+const POSITIONS_BUFFER_NAME: &str = "meshPositions";
+const NORMALS_BUFFER_NAME: &str = "meshNormals";
+
+// Merges every mesh into shared index/vertex buffers so a single bind
+// covers all draws, which indirect drawing relies on
+#[derive(Default)]
+pub struct MeshManager {
+    meshes: HashMap<String, MeshData>,
+    indices: Vec<u32>,
+    positions: Vec<Vector3<f32>>,
+    normals: Vec<Vector3<f32>>,
+    geometry_buffers: Option<GeometryBuffers>,
+}
+
+impl MeshManager {
+    pub fn add_mesh(
+        &mut self,
+        name: &str,
+        indices: &[u32],
+        positions: &[Vector3<f32>],
+        normals: &[Vector3<f32>],
+    ) {
+        assert!(
+            self.geometry_buffers.is_none(),
+            "Meshes have to be added before upload"
+        );
+
+        if self.meshes.contains_key(name) {
+            return;
+        }
+
+        let mesh = MeshData::new(
+            indices.len() as u32,
+            self.indices.len() as u32,
+            self.positions.len() as i32,
+            Aabb::from_positions(positions),
+            BoundingSphere::from_positions(positions),
+        );
+
+        self.indices.extend_from_slice(indices);
+        self.positions.extend_from_slice(positions);
+        self.normals.extend_from_slice(normals);
+
+        self.meshes.insert(name.to_string(), mesh);
+    }
+
+    pub fn upload(
+        &mut self,
+        buffer_manager: &mut BufferManager,
+        allocator: &mut Allocator,
+        queue: vk::Queue,
+    ) -> Result<()> {
+        buffer_manager.add_buffer(
+            INDICES_BUFFER_NAME,
+            allocator,
+            queue,
+            &self.indices,
+            vk::BufferUsageFlags::INDEX_BUFFER,
+        )?;
+
+        buffer_manager.add_buffer(
+            POSITIONS_BUFFER_NAME,
+            allocator,
+            queue,
+            &self.positions,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+        )?;
+
+        buffer_manager.add_buffer(
+            NORMALS_BUFFER_NAME,
+            allocator,
+            queue,
+            &self.normals,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+        )?;
+
+        self.geometry_buffers = Some(GeometryBuffers {
+            index_buffer: buffer_manager.get_buffer(INDICES_BUFFER_NAME).buffer,
+            positions_buffer: buffer_manager.get_buffer(POSITIONS_BUFFER_NAME).buffer,
+            attributes_buffer: buffer_manager.get_buffer(NORMALS_BUFFER_NAME).buffer,
+        });
+
+        Ok(())
+    }
+
+    pub fn get_mesh(&self, name: &str) -> &MeshData {
+        self.meshes.get(name).unwrap()
+    }
+
+    pub fn get_geometry_buffers(&self) -> &GeometryBuffers {
+        self.geometry_buffers
+            .as_ref()
+            .expect("Meshes have to be uploaded first")
+    }
+}
@@ -7,9 +7,27 @@ use std::ffi::CStr;
 use ash::{vk, Device};
 
 use crate::{
-    push_constants_data::{LightningPushConstantsData, PushConstantsData},
+    debug_draw::DebugLineVertex,
+    debug_markers,
+    deferred_lightning_render_pass::{
+        lightning_renderpass_consts, DeferredLightningRenderPassInput,
+    },
+    gbuffer_layout::GBUFFER_LAYOUT,
+    image_based_lighting::ImageBasedLightingOutput,
+    push_constants_data::{
+        BloomPushConstantsData, CullingPushConstantsData, DebugDrawPushConstantsData,
+        DebugUiPushConstantsData, FxaaPushConstantsData, ImageBasedLightingPushConstantsData,
+        LightningPushConstantsData, PushConstantsData, SkyboxPushConstantsData,
+        SsaoBlurPushConstantsData, SsaoPushConstantsData, TaaPushConstantsData,
+        TonemappingPushConstantsData, TransparentPushConstantsData,
+    },
+    render_pass_attachment_output::RenderPassAttachmentOutput,
+    renderer_config::RendererConfig,
+    renderer_error::Result,
     shader_manager::{Shader, ShaderManager},
-    shadow_map_render_pass::{deferred_renderpass_consts, shadowmap_renderpass_consts},
+    shadow_map_render_pass::deferred_renderpass_consts,
+    ssao_render_pass::ssao_renderpass_consts,
+    tonemapping_render_pass::DisplayEncoding,
 };
 
 pub struct DeferredLightningMaterial {
@@ -18,30 +36,192 @@ pub struct DeferredLightningMaterial {
     pub set: vk::DescriptorSet,
 }
 
+// Objects set is bound separately, at set 0
+pub struct TransparentMaterial {
+    pub layout: vk::PipelineLayout,
+    pub pipeline: vk::Pipeline,
+    pub set: vk::DescriptorSet,
+}
+
+pub struct SkyboxMaterial {
+    pub layout: vk::PipelineLayout,
+    pub pipeline: vk::Pipeline,
+    pub set: vk::DescriptorSet,
+}
+
+pub struct TaaMaterial {
+    pub layout: vk::PipelineLayout,
+    pub pipeline: vk::Pipeline,
+    pub set: vk::DescriptorSet,
+}
+
+pub struct FxaaMaterial {
+    pub layout: vk::PipelineLayout,
+    pub pipeline: vk::Pipeline,
+    pub set: vk::DescriptorSet,
+}
+
+pub struct TonemappingMaterial {
+    pub layout: vk::PipelineLayout,
+    pub pipeline: vk::Pipeline,
+    pub set: vk::DescriptorSet,
+}
+
+pub struct SsaoMaterial {
+    pub layout: vk::PipelineLayout,
+    pub pipeline: vk::Pipeline,
+    pub set: vk::DescriptorSet,
+    pub blur_layout: vk::PipelineLayout,
+    pub blur_pipeline: vk::Pipeline,
+    pub blur_set: vk::DescriptorSet,
+}
+
+pub struct BloomMaterial {
+    pub layout: vk::PipelineLayout,
+    pub downsample_pipeline: vk::Pipeline,
+    pub upsample_pipeline: vk::Pipeline,
+    // Lightning and TAA outputs followed by every bloom mip
+    pub source_sets: Vec<vk::DescriptorSet>,
+}
+
+#[derive(Clone)]
+pub struct DebugDrawMaterial {
+    pub layout: vk::PipelineLayout,
+    pub pipeline: vk::Pipeline,
+}
+
+// Sets are created per texture, see create_debug_ui_set
+#[derive(Clone)]
+pub struct DebugUiMaterial {
+    pub layout: vk::PipelineLayout,
+    pub pipeline: vk::Pipeline,
+}
+
+#[derive(Clone)]
+pub struct DeferredMaterial {
+    pub layout: vk::PipelineLayout,
+    pub pipeline: vk::Pipeline,
+}
+
+// Objects set is bound separately, at set 0
+pub struct DeferredMaskedMaterial {
+    pub layout: vk::PipelineLayout,
+    pub pipeline: vk::Pipeline,
+    pub set: vk::DescriptorSet,
+}
+
 #[derive(Clone)]
 pub struct ShadowMapMaterial {
     pub layout: vk::PipelineLayout,
     pub pipeline: vk::Pipeline,
 }
 
+#[derive(Clone)]
+pub struct CullingMaterial {
+    pub layout: vk::PipelineLayout,
+    pub pipeline: vk::Pipeline,
+}
+
+// Compute pipelines sharing one layout, a sampled source and a storage image target
+#[derive(Clone)]
+pub struct ImageBasedLightingMaterial {
+    pub layout: vk::PipelineLayout,
+    pub equirectangular_to_cube_pipeline: vk::Pipeline,
+    pub irradiance_pipeline: vk::Pipeline,
+    pub prefilter_pipeline: vk::Pipeline,
+    pub brdf_lut_pipeline: vk::Pipeline,
+}
+
+// What differs between the graphics pipelines, the remaining state is shared
+#[derive(Clone, Copy)]
+struct PipelineCreateInfo<'a> {
+    render_area: vk::Rect2D,
+    shader: &'a Shader,
+    // Debug name
+    name: &'a str,
+    vertex_binding_descriptions: &'a [vk::VertexInputBindingDescription],
+    vertex_input_attribute_descriptions: &'a [vk::VertexInputAttributeDescription],
+    color_blend_attachments: &'a [vk::PipelineColorBlendAttachmentState],
+    color_attachemnt_formats: &'a [vk::Format],
+    // UNDEFINED without a depth attachment
+    depth_attachment_format: vk::Format,
+    pipeline_layout: vk::PipelineLayout,
+    topology: vk::PrimitiveTopology,
+    cull_mode: vk::CullModeFlags,
+    depth_compare_op: vk::CompareOp,
+    dynamic_states: &'a [vk::DynamicState],
+}
+
 pub struct PipelineManager {
     device: Device,
     render_area: vk::Rect2D,
+    swapchain_format: vk::Format,
+    display_encoding: DisplayEncoding,
+    shadow_map_dimensions: vk::Rect2D,
     shader_manager: ShaderManager,
     default_sampler: vk::Sampler,
+    // Image based lighting and debug UI sets, they live as long as the manager
     descriptor_pool: vk::DescriptorPool,
-    pub deferred_pipeline_layout: vk::PipelineLayout,
-    pub deferred_pipeline: vk::Pipeline,
+    // Sets of the frame worker passes, recreated with the swapchain
+    swapchain_descriptor_pool: vk::DescriptorPool,
+    objects_descriptor_set_layout: vk::DescriptorSetLayout,
+    culling_descriptor_set_layout: vk::DescriptorSetLayout,
+    image_based_lighting_descriptor_set_layout: vk::DescriptorSetLayout,
+    pub deferred_material: DeferredMaterial,
+    deferred_masked_descriptor_set_layout: vk::DescriptorSetLayout,
+    deferred_masked_pipeline_layout: vk::PipelineLayout,
+    deferred_masked_pipeline: vk::Pipeline,
     deferred_lightning_descriptor_set_layout: vk::DescriptorSetLayout,
     deferred_lightning_pipeline_layout: vk::PipelineLayout,
     deferred_lightning_pipeline: vk::Pipeline,
-    deferred_lightning_sets: Vec<vk::DescriptorSet>,
+    skybox_descriptor_set_layout: vk::DescriptorSetLayout,
+    skybox_pipeline_layout: vk::PipelineLayout,
+    skybox_pipeline: vk::Pipeline,
+    transparent_descriptor_set_layout: vk::DescriptorSetLayout,
+    transparent_pipeline_layout: vk::PipelineLayout,
+    transparent_pipeline: vk::Pipeline,
+    taa_descriptor_set_layout: vk::DescriptorSetLayout,
+    taa_pipeline_layout: vk::PipelineLayout,
+    taa_pipeline: vk::Pipeline,
+    tonemapping_descriptor_set_layout: vk::DescriptorSetLayout,
+    tonemapping_pipeline_layout: vk::PipelineLayout,
+    tonemapping_pipeline: vk::Pipeline,
+    fxaa_descriptor_set_layout: vk::DescriptorSetLayout,
+    fxaa_pipeline_layout: vk::PipelineLayout,
+    fxaa_pipeline: vk::Pipeline,
+    bloom_descriptor_set_layout: vk::DescriptorSetLayout,
+    bloom_pipeline_layout: vk::PipelineLayout,
+    bloom_downsample_pipeline: vk::Pipeline,
+    bloom_upsample_pipeline: vk::Pipeline,
+    ssao_descriptor_set_layout: vk::DescriptorSetLayout,
+    ssao_pipeline_layout: vk::PipelineLayout,
+    ssao_pipeline: vk::Pipeline,
+    ssao_blur_descriptor_set_layout: vk::DescriptorSetLayout,
+    ssao_blur_pipeline_layout: vk::PipelineLayout,
+    ssao_blur_pipeline: vk::Pipeline,
+    pub debug_draw_material: DebugDrawMaterial,
+    debug_ui_descriptor_set_layout: vk::DescriptorSetLayout,
+    pub debug_ui_material: DebugUiMaterial,
     pub shadow_map_material: ShadowMapMaterial,
+    pub culling_material: CullingMaterial,
+    pub image_based_lighting_material: ImageBasedLightingMaterial,
 }
 
 impl PipelineManager {
-    pub fn new(device: Device, swapchain_format: vk::Format, render_area: vk::Rect2D) -> Self {
-        let mut shader_manager = ShaderManager::new(device.clone());
+    pub fn new(
+        device: Device,
+        config: &RendererConfig,
+        surface_format: vk::SurfaceFormatKHR,
+        render_area: vk::Rect2D,
+    ) -> Result<Self> {
+        let swapchain_format = surface_format.format;
+        let shadow_map_dimensions = vk::Rect2D::default().extent(
+            vk::Extent2D::default()
+                .width(config.shadow_map_resolution)
+                .height(config.shadow_map_resolution),
+        );
+
+        let mut shader_manager = ShaderManager::new(device.clone())?;
 
         let deferred_push_constant_range = vk::PushConstantRange::default()
             .stage_flags(vk::ShaderStageFlags::VERTEX)
@@ -53,85 +233,521 @@ impl PipelineManager {
             .offset(0)
             .size(std::mem::size_of::<LightningPushConstantsData>() as u32);
 
-        let default_sampler = Self::create_default_sampler(&device);
+        // The vertex shader places the triangle on the far plane
+        let skybox_push_constant_range = vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
+            .offset(0)
+            .size(std::mem::size_of::<SkyboxPushConstantsData>() as u32);
 
-        let descriptor_pool = Self::create_descriptor_pool(&device);
-        let deferred_lightning_descriptor_set_layout = Self::create_descriptor_set_layout(&device);
-        let deferred_pipeline_layout =
-            Self::create_pipeline_layout(&device, &[], &[deferred_push_constant_range]);
+        let transparent_push_constant_range = vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
+            .offset(0)
+            .size(std::mem::size_of::<TransparentPushConstantsData>() as u32);
+
+        let taa_push_constant_range = vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .offset(0)
+            .size(std::mem::size_of::<TaaPushConstantsData>() as u32);
+
+        let tonemapping_push_constant_range = vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .offset(0)
+            .size(std::mem::size_of::<TonemappingPushConstantsData>() as u32);
+
+        let fxaa_push_constant_range = vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .offset(0)
+            .size(std::mem::size_of::<FxaaPushConstantsData>() as u32);
+
+        let bloom_push_constant_range = vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .offset(0)
+            .size(std::mem::size_of::<BloomPushConstantsData>() as u32);
+
+        let ssao_push_constant_range = vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .offset(0)
+            .size(std::mem::size_of::<SsaoPushConstantsData>() as u32);
+
+        let ssao_blur_push_constant_range = vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .offset(0)
+            .size(std::mem::size_of::<SsaoBlurPushConstantsData>() as u32);
+
+        let debug_draw_push_constant_range = vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::VERTEX)
+            .offset(0)
+            .size(std::mem::size_of::<DebugDrawPushConstantsData>() as u32);
+
+        let debug_ui_push_constant_range = vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
+            .offset(0)
+            .size(std::mem::size_of::<DebugUiPushConstantsData>() as u32);
+
+        let culling_push_constant_range = vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .offset(0)
+            .size(std::mem::size_of::<CullingPushConstantsData>() as u32);
+
+        let image_based_lighting_push_constant_range = vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .offset(0)
+            .size(std::mem::size_of::<ImageBasedLightingPushConstantsData>() as u32);
+
+        let default_sampler = Self::create_default_sampler(&device)?;
+
+        let descriptor_pool = Self::create_descriptor_pool(&device)?;
+        // Albedo, normal, material, depth, shadow map, ambient occlusion,
+        // irradiance, prefiltered environment, BRDF LUT
+        let deferred_lightning_descriptor_set_layout = Self::create_sampler_set_layout(&device, 9)?;
+        // Base color texture
+        let deferred_masked_descriptor_set_layout = Self::create_sampler_set_layout(&device, 1)?;
+        // Environment
+        let skybox_descriptor_set_layout = Self::create_sampler_set_layout(&device, 1)?;
+        // Shadow map, irradiance, prefiltered environment, BRDF LUT
+        let transparent_descriptor_set_layout = Self::create_sampler_set_layout(&device, 4)?;
+        // HDR color, velocity, depth, history
+        let taa_descriptor_set_layout = Self::create_sampler_set_layout(&device, 4)?;
+        // HDR color, bloom
+        let tonemapping_descriptor_set_layout = Self::create_sampler_set_layout(&device, 2)?;
+        // Tonemapped color
+        let fxaa_descriptor_set_layout = Self::create_sampler_set_layout(&device, 1)?;
+        let bloom_descriptor_set_layout = Self::create_sampler_set_layout(&device, 1)?;
+        // Normal, depth, noise
+        let ssao_descriptor_set_layout = Self::create_sampler_set_layout(&device, 3)?;
+        // Ambient occlusion, depth
+        let ssao_blur_descriptor_set_layout = Self::create_sampler_set_layout(&device, 2)?;
+        // A single egui texture
+        let debug_ui_descriptor_set_layout = Self::create_sampler_set_layout(&device, 1)?;
+        let objects_descriptor_set_layout =
+            Self::create_storage_buffer_set_layout(&device, 1, vk::ShaderStageFlags::VERTEX)?;
+        // Objects, indirect draw commands, draw count
+        let culling_descriptor_set_layout =
+            Self::create_storage_buffer_set_layout(&device, 3, vk::ShaderStageFlags::COMPUTE)?;
+        let image_based_lighting_descriptor_set_layout =
+            Self::create_compute_image_set_layout(&device)?;
+        let deferred_pipeline_layout = Self::create_pipeline_layout(
+            &device,
+            &[objects_descriptor_set_layout],
+            &[deferred_push_constant_range],
+        )?;
+        let deferred_masked_pipeline_layout = Self::create_pipeline_layout(
+            &device,
+            &[
+                objects_descriptor_set_layout,
+                deferred_masked_descriptor_set_layout,
+            ],
+            &[deferred_push_constant_range],
+        )?;
         let deferred_lightning_pipeline_layout = Self::create_pipeline_layout(
             &device,
             &[deferred_lightning_descriptor_set_layout],
             &[deferred_lightning_push_constant_range],
-        );
+        )?;
+        let skybox_pipeline_layout = Self::create_pipeline_layout(
+            &device,
+            &[skybox_descriptor_set_layout],
+            &[skybox_push_constant_range],
+        )?;
+        let transparent_pipeline_layout = Self::create_pipeline_layout(
+            &device,
+            &[
+                objects_descriptor_set_layout,
+                transparent_descriptor_set_layout,
+            ],
+            &[transparent_push_constant_range],
+        )?;
+        let taa_pipeline_layout = Self::create_pipeline_layout(
+            &device,
+            &[taa_descriptor_set_layout],
+            &[taa_push_constant_range],
+        )?;
+        let tonemapping_pipeline_layout = Self::create_pipeline_layout(
+            &device,
+            &[tonemapping_descriptor_set_layout],
+            &[tonemapping_push_constant_range],
+        )?;
+        let fxaa_pipeline_layout = Self::create_pipeline_layout(
+            &device,
+            &[fxaa_descriptor_set_layout],
+            &[fxaa_push_constant_range],
+        )?;
+        let bloom_pipeline_layout = Self::create_pipeline_layout(
+            &device,
+            &[bloom_descriptor_set_layout],
+            &[bloom_push_constant_range],
+        )?;
+        let ssao_pipeline_layout = Self::create_pipeline_layout(
+            &device,
+            &[ssao_descriptor_set_layout],
+            &[ssao_push_constant_range],
+        )?;
+        let ssao_blur_pipeline_layout = Self::create_pipeline_layout(
+            &device,
+            &[ssao_blur_descriptor_set_layout],
+            &[ssao_blur_push_constant_range],
+        )?;
         let deferred_pipeline = Self::create_deferred_pipeline(
             &device,
             &mut shader_manager,
+            "Deferred",
             render_area,
             deferred_pipeline_layout,
-        );
+        )?;
+        let deferred_masked_pipeline = Self::create_deferred_pipeline(
+            &device,
+            &mut shader_manager,
+            "DeferredMasked",
+            render_area,
+            deferred_masked_pipeline_layout,
+        )?;
         let deferred_lightning_pipeline = Self::create_deferred_lightning_pipeline(
             &device,
             &mut shader_manager,
             render_area,
-            swapchain_format,
             deferred_lightning_pipeline_layout,
-        );
+        )?;
+        let skybox_pipeline = Self::create_skybox_pipeline(
+            &device,
+            &mut shader_manager,
+            render_area,
+            skybox_pipeline_layout,
+        )?;
+        let transparent_pipeline = Self::create_transparent_pipeline(
+            &device,
+            &mut shader_manager,
+            render_area,
+            transparent_pipeline_layout,
+        )?;
+        let taa_pipeline = Self::create_fullscreen_pipeline(
+            &device,
+            &mut shader_manager,
+            "TemporalAntiAliasing",
+            render_area,
+            lightning_renderpass_consts::HDR_COLOR,
+            taa_pipeline_layout,
+        )?;
+        let tonemapping_pipeline = Self::create_fullscreen_pipeline(
+            &device,
+            &mut shader_manager,
+            "Tonemapping",
+            render_area,
+            swapchain_format,
+            tonemapping_pipeline_layout,
+        )?;
+        let fxaa_pipeline = Self::create_fullscreen_pipeline(
+            &device,
+            &mut shader_manager,
+            "Fxaa",
+            render_area,
+            swapchain_format,
+            fxaa_pipeline_layout,
+        )?;
+        let bloom_downsample_pipeline = Self::create_bloom_pipeline(
+            &device,
+            &mut shader_manager,
+            "BloomDownsample",
+            render_area,
+            bloom_pipeline_layout,
+            Self::create_pipeline_color_blend_attachment_state(),
+        )?;
+        let bloom_upsample_pipeline = Self::create_bloom_pipeline(
+            &device,
+            &mut shader_manager,
+            "BloomUpsample",
+            render_area,
+            bloom_pipeline_layout,
+            Self::create_pipeline_additive_color_blend_attachment_state(),
+        )?;
+        let ssao_pipeline = Self::create_fullscreen_pipeline(
+            &device,
+            &mut shader_manager,
+            "Ssao",
+            render_area,
+            ssao_renderpass_consts::AMBIENT_OCCLUSION,
+            ssao_pipeline_layout,
+        )?;
+        let ssao_blur_pipeline = Self::create_fullscreen_pipeline(
+            &device,
+            &mut shader_manager,
+            "SsaoBlur",
+            render_area,
+            ssao_renderpass_consts::AMBIENT_OCCLUSION,
+            ssao_blur_pipeline_layout,
+        )?;
+
+        let debug_draw_layout =
+            Self::create_pipeline_layout(&device, &[], &[debug_draw_push_constant_range])?;
+        let debug_draw_material = DebugDrawMaterial {
+            layout: debug_draw_layout,
+            pipeline: Self::create_debug_draw_pipeline(
+                &device,
+                &mut shader_manager,
+                render_area,
+                debug_draw_layout,
+            )?,
+        };
+
+        let debug_ui_layout = Self::create_pipeline_layout(
+            &device,
+            &[debug_ui_descriptor_set_layout],
+            &[debug_ui_push_constant_range],
+        )?;
+        let debug_ui_material = DebugUiMaterial {
+            layout: debug_ui_layout,
+            pipeline: Self::create_debug_ui_pipeline(
+                &device,
+                &mut shader_manager,
+                render_area,
+                swapchain_format,
+                debug_ui_layout,
+            )?,
+        };
 
         // TODO: shadowmap pushrange/uniform
-        let shadow_map_layout =
-            Self::create_pipeline_layout(&device, &[], &[deferred_push_constant_range]);
+        let shadow_map_layout = Self::create_pipeline_layout(
+            &device,
+            &[objects_descriptor_set_layout],
+            &[deferred_push_constant_range],
+        )?;
         let shadow_map_material = ShadowMapMaterial {
             layout: shadow_map_layout,
             pipeline: Self::create_shadow_map_pipeline(
                 &device,
                 &mut shader_manager,
-                shadowmap_renderpass_consts::SHADOW_MAP_DIMENSIONS,
+                shadow_map_dimensions,
                 shadow_map_layout,
-            ),
+            )?,
+        };
+
+        let culling_layout = Self::create_pipeline_layout(
+            &device,
+            &[culling_descriptor_set_layout],
+            &[culling_push_constant_range],
+        )?;
+        let culling_material = CullingMaterial {
+            layout: culling_layout,
+            pipeline: Self::create_compute_pipeline(
+                &device,
+                &mut shader_manager,
+                "Culling",
+                culling_layout,
+            )?,
+        };
+
+        let image_based_lighting_layout = Self::create_pipeline_layout(
+            &device,
+            &[image_based_lighting_descriptor_set_layout],
+            &[image_based_lighting_push_constant_range],
+        )?;
+        let image_based_lighting_material = ImageBasedLightingMaterial {
+            layout: image_based_lighting_layout,
+            equirectangular_to_cube_pipeline: Self::create_compute_pipeline(
+                &device,
+                &mut shader_manager,
+                "EquirectangularToCube",
+                image_based_lighting_layout,
+            )?,
+            irradiance_pipeline: Self::create_compute_pipeline(
+                &device,
+                &mut shader_manager,
+                "Irradiance",
+                image_based_lighting_layout,
+            )?,
+            prefilter_pipeline: Self::create_compute_pipeline(
+                &device,
+                &mut shader_manager,
+                "Prefilter",
+                image_based_lighting_layout,
+            )?,
+            brdf_lut_pipeline: Self::create_compute_pipeline(
+                &device,
+                &mut shader_manager,
+                "BrdfLut",
+                image_based_lighting_layout,
+            )?,
         };
 
-        Self {
+        Ok(Self {
             device,
             render_area,
+            swapchain_format,
+            display_encoding: DisplayEncoding::from_surface_format(surface_format),
+            shadow_map_dimensions,
             shader_manager,
             default_sampler,
             descriptor_pool,
-            deferred_pipeline_layout,
-            deferred_pipeline,
+            swapchain_descriptor_pool: vk::DescriptorPool::null(),
+            objects_descriptor_set_layout,
+            culling_descriptor_set_layout,
+            image_based_lighting_descriptor_set_layout,
+            deferred_material: DeferredMaterial {
+                layout: deferred_pipeline_layout,
+                pipeline: deferred_pipeline,
+            },
+            deferred_masked_descriptor_set_layout,
+            deferred_masked_pipeline_layout,
+            deferred_masked_pipeline,
             deferred_lightning_descriptor_set_layout,
             deferred_lightning_pipeline_layout,
             deferred_lightning_pipeline,
-            deferred_lightning_sets: Vec::new(),
+            skybox_descriptor_set_layout,
+            skybox_pipeline_layout,
+            skybox_pipeline,
+            transparent_descriptor_set_layout,
+            transparent_pipeline_layout,
+            transparent_pipeline,
+            taa_descriptor_set_layout,
+            taa_pipeline_layout,
+            taa_pipeline,
+            tonemapping_descriptor_set_layout,
+            tonemapping_pipeline_layout,
+            tonemapping_pipeline,
+            fxaa_descriptor_set_layout,
+            fxaa_pipeline_layout,
+            fxaa_pipeline,
+            bloom_descriptor_set_layout,
+            bloom_pipeline_layout,
+            bloom_downsample_pipeline,
+            bloom_upsample_pipeline,
+            ssao_descriptor_set_layout,
+            ssao_pipeline_layout,
+            ssao_pipeline,
+            ssao_blur_descriptor_set_layout,
+            ssao_blur_pipeline_layout,
+            ssao_blur_pipeline,
+            debug_draw_material,
+            debug_ui_descriptor_set_layout,
+            debug_ui_material,
             shadow_map_material,
-        }
+            culling_material,
+            image_based_lighting_material,
+        })
     }
 
     pub fn destroy(&mut self) {
         unsafe {
+            let image_based_lighting_material = &self.image_based_lighting_material;
+            for pipeline in [
+                image_based_lighting_material.equirectangular_to_cube_pipeline,
+                image_based_lighting_material.irradiance_pipeline,
+                image_based_lighting_material.prefilter_pipeline,
+                image_based_lighting_material.brdf_lut_pipeline,
+            ] {
+                self.device.destroy_pipeline(pipeline, None);
+            }
+            self.device
+                .destroy_pipeline_layout(image_based_lighting_material.layout, None);
+            self.device.destroy_descriptor_set_layout(
+                self.image_based_lighting_descriptor_set_layout,
+                None,
+            );
+
+            self.device
+                .destroy_pipeline(self.debug_draw_material.pipeline, None);
+            self.device
+                .destroy_pipeline_layout(self.debug_draw_material.layout, None);
+
+            self.device
+                .destroy_pipeline(self.debug_ui_material.pipeline, None);
+            self.device
+                .destroy_pipeline_layout(self.debug_ui_material.layout, None);
+            self.device
+                .destroy_descriptor_set_layout(self.debug_ui_descriptor_set_layout, None);
+
+            self.device.destroy_pipeline(self.ssao_pipeline, None);
+            self.device
+                .destroy_pipeline_layout(self.ssao_pipeline_layout, None);
+            self.device
+                .destroy_descriptor_set_layout(self.ssao_descriptor_set_layout, None);
+            self.device.destroy_pipeline(self.ssao_blur_pipeline, None);
+            self.device
+                .destroy_pipeline_layout(self.ssao_blur_pipeline_layout, None);
+            self.device
+                .destroy_descriptor_set_layout(self.ssao_blur_descriptor_set_layout, None);
+
+            self.device
+                .destroy_pipeline(self.bloom_downsample_pipeline, None);
+            self.device
+                .destroy_pipeline(self.bloom_upsample_pipeline, None);
+            self.device
+                .destroy_pipeline_layout(self.bloom_pipeline_layout, None);
+            self.device
+                .destroy_descriptor_set_layout(self.bloom_descriptor_set_layout, None);
+
+            self.device.destroy_pipeline(self.skybox_pipeline, None);
+            self.device
+                .destroy_pipeline_layout(self.skybox_pipeline_layout, None);
+            self.device
+                .destroy_descriptor_set_layout(self.skybox_descriptor_set_layout, None);
+
+            self.device
+                .destroy_pipeline(self.transparent_pipeline, None);
+            self.device
+                .destroy_pipeline_layout(self.transparent_pipeline_layout, None);
+            self.device
+                .destroy_descriptor_set_layout(self.transparent_descriptor_set_layout, None);
+
+            self.device.destroy_pipeline(self.taa_pipeline, None);
+            self.device
+                .destroy_pipeline_layout(self.taa_pipeline_layout, None);
+            self.device
+                .destroy_descriptor_set_layout(self.taa_descriptor_set_layout, None);
+
+            self.device.destroy_pipeline(self.fxaa_pipeline, None);
+            self.device
+                .destroy_pipeline_layout(self.fxaa_pipeline_layout, None);
+            self.device
+                .destroy_descriptor_set_layout(self.fxaa_descriptor_set_layout, None);
+
+            self.device
+                .destroy_pipeline(self.tonemapping_pipeline, None);
+            self.device
+                .destroy_pipeline_layout(self.tonemapping_pipeline_layout, None);
+            self.device
+                .destroy_descriptor_set_layout(self.tonemapping_descriptor_set_layout, None);
+
+            self.device
+                .destroy_pipeline(self.culling_material.pipeline, None);
+            self.device
+                .destroy_pipeline_layout(self.culling_material.layout, None);
+
             self.device
                 .destroy_pipeline(self.shadow_map_material.pipeline, None);
             self.device
                 .destroy_pipeline_layout(self.shadow_map_material.layout, None);
 
-            self.device.destroy_pipeline(self.deferred_pipeline, None);
+            self.device
+                .destroy_pipeline(self.deferred_material.pipeline, None);
+            self.device
+                .destroy_pipeline(self.deferred_masked_pipeline, None);
             self.device
                 .destroy_pipeline(self.deferred_lightning_pipeline, None);
             self.device
-                .destroy_pipeline_layout(self.deferred_pipeline_layout, None);
+                .destroy_pipeline_layout(self.deferred_material.layout, None);
+            self.device
+                .destroy_pipeline_layout(self.deferred_masked_pipeline_layout, None);
             self.device
                 .destroy_pipeline_layout(self.deferred_lightning_pipeline_layout, None);
+            self.device
+                .destroy_descriptor_set_layout(self.deferred_masked_descriptor_set_layout, None);
             self.device
                 .destroy_descriptor_set_layout(self.deferred_lightning_descriptor_set_layout, None);
+            self.device
+                .destroy_descriptor_set_layout(self.objects_descriptor_set_layout, None);
+            self.device
+                .destroy_descriptor_set_layout(self.culling_descriptor_set_layout, None);
             self.device
                 .destroy_descriptor_pool(self.descriptor_pool, None);
+            self.device
+                .destroy_descriptor_pool(self.swapchain_descriptor_pool, None);
             self.device.destroy_sampler(self.default_sampler, None);
         }
 
         self.shader_manager.destroy();
     }
 
-    fn create_default_sampler(device: &Device) -> vk::Sampler {
+    fn create_default_sampler(device: &Device) -> Result<vk::Sampler> {
         let create_info = vk::SamplerCreateInfo::default()
             .mag_filter(vk::Filter::LINEAR)
             .min_filter(vk::Filter::LINEAR)
@@ -145,136 +761,534 @@ impl PipelineManager {
             .compare_enable(false)
             .compare_op(vk::CompareOp::NEVER)
             .min_lod(0.0)
-            .max_lod(0.0)
+            // Single mip views are unaffected, mipmapped ones like the prefiltered environment need it
+            .max_lod(vk::LOD_CLAMP_NONE)
             .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
             .unnormalized_coordinates(false);
 
-        unsafe { device.create_sampler(&create_info, None).unwrap() }
+        Ok(unsafe { device.create_sampler(&create_info, None)? })
+    }
+
+    pub fn create_deferred_masked_material(
+        &mut self,
+        base_color: vk::ImageView,
+    ) -> Result<DeferredMaskedMaterial> {
+        let set = self.create_sampler_set(
+            self.deferred_masked_descriptor_set_layout,
+            &[base_color],
+            "deferred_masked",
+        )?;
+
+        Ok(DeferredMaskedMaterial {
+            layout: self.deferred_masked_pipeline_layout,
+            pipeline: self.deferred_masked_pipeline,
+            set,
+        })
+    }
+
+    // For draw calls of alpha tested objects
+    pub fn get_deferred_masked_pipeline(&self) -> vk::Pipeline {
+        self.deferred_masked_pipeline
     }
 
     pub fn create_deferred_lightning_material(
         &mut self,
-        color: vk::ImageView,
-        normal: vk::ImageView,
-        position: vk::ImageView,
+        input: &DeferredLightningRenderPassInput,
+    ) -> Result<DeferredLightningMaterial> {
+        let shader_read = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+        let deferred = input.deferred;
+        let image_based_lighting = input.image_based_lighting;
+        let set = self.create_sampler_set_with_layouts(
+            self.deferred_lightning_descriptor_set_layout,
+            &[
+                (deferred.albedo.image_view, shader_read),
+                (deferred.normal.image_view, shader_read),
+                (deferred.material.image_view, shader_read),
+                (deferred.depth.image_view, deferred.depth.image_layout),
+                (input.shadow_map.depth.image_view, shader_read),
+                (input.ssao.ambient_occlusion.image_view, shader_read),
+                (image_based_lighting.irradiance, shader_read),
+                (image_based_lighting.prefiltered, shader_read),
+                (image_based_lighting.brdf_lut, shader_read),
+            ],
+            "deferred_lightning",
+        )?;
+
+        Ok(DeferredLightningMaterial {
+            layout: self.deferred_lightning_pipeline_layout,
+            pipeline: self.deferred_lightning_pipeline,
+            set,
+        })
+    }
+
+    pub fn create_skybox_material(&mut self, environment: vk::ImageView) -> Result<SkyboxMaterial> {
+        let set =
+            self.create_sampler_set(self.skybox_descriptor_set_layout, &[environment], "skybox")?;
+
+        Ok(SkyboxMaterial {
+            layout: self.skybox_pipeline_layout,
+            pipeline: self.skybox_pipeline,
+            set,
+        })
+    }
+
+    pub fn create_tonemapping_material(
+        &mut self,
+        hdr_color: vk::ImageView,
+        bloom: vk::ImageView,
+    ) -> Result<TonemappingMaterial> {
+        let set = self.create_sampler_set(
+            self.tonemapping_descriptor_set_layout,
+            &[hdr_color, bloom],
+            "tonemapping",
+        )?;
+
+        Ok(TonemappingMaterial {
+            layout: self.tonemapping_pipeline_layout,
+            pipeline: self.tonemapping_pipeline,
+            set,
+        })
+    }
+
+    pub fn create_transparent_material(
+        &mut self,
         shadow_map: vk::ImageView,
-    ) -> DeferredLightningMaterial {
-        let image_infos = [
-            vk::DescriptorImageInfo {
-                sampler: self.default_sampler,
-                image_view: color,
-                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-            },
-            vk::DescriptorImageInfo {
-                sampler: self.default_sampler,
-                image_view: normal,
-                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-            },
-            vk::DescriptorImageInfo {
-                sampler: self.default_sampler,
-                image_view: position,
-                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-            },
-            vk::DescriptorImageInfo {
-                sampler: self.default_sampler,
-                image_view: shadow_map,
-                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-            },
+        image_based_lighting_output: &ImageBasedLightingOutput,
+    ) -> Result<TransparentMaterial> {
+        let set = self.create_sampler_set(
+            self.transparent_descriptor_set_layout,
+            &[
+                shadow_map,
+                image_based_lighting_output.irradiance,
+                image_based_lighting_output.prefiltered,
+                image_based_lighting_output.brdf_lut,
+            ],
+            "transparent",
+        )?;
+
+        Ok(TransparentMaterial {
+            layout: self.transparent_pipeline_layout,
+            pipeline: self.transparent_pipeline,
+            set,
+        })
+    }
+
+    pub fn create_taa_material(
+        &mut self,
+        hdr_color: vk::ImageView,
+        velocity: vk::ImageView,
+        depth: &RenderPassAttachmentOutput,
+        history: vk::ImageView,
+    ) -> Result<TaaMaterial> {
+        let shader_read = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+        let set = self.create_sampler_set_with_layouts(
+            self.taa_descriptor_set_layout,
+            &[
+                (hdr_color, shader_read),
+                (velocity, shader_read),
+                (depth.image_view, depth.image_layout),
+                (history, shader_read),
+            ],
+            "taa",
+        )?;
+
+        Ok(TaaMaterial {
+            layout: self.taa_pipeline_layout,
+            pipeline: self.taa_pipeline,
+            set,
+        })
+    }
+
+    pub fn create_fxaa_material(&mut self, color: vk::ImageView) -> Result<FxaaMaterial> {
+        let set = self.create_sampler_set(self.fxaa_descriptor_set_layout, &[color], "fxaa")?;
+
+        Ok(FxaaMaterial {
+            layout: self.fxaa_pipeline_layout,
+            pipeline: self.fxaa_pipeline,
+            set,
+        })
+    }
+
+    pub fn create_bloom_material(
+        &mut self,
+        source_image_views: &[vk::ImageView],
+    ) -> Result<BloomMaterial> {
+        let source_sets = source_image_views
+            .iter()
+            .enumerate()
+            .map(|(index, &image_view)| {
+                self.create_sampler_set(
+                    self.bloom_descriptor_set_layout,
+                    &[image_view],
+                    &format!("bloom_source{}", index),
+                )
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(BloomMaterial {
+            layout: self.bloom_pipeline_layout,
+            downsample_pipeline: self.bloom_downsample_pipeline,
+            upsample_pipeline: self.bloom_upsample_pipeline,
+            source_sets,
+        })
+    }
+
+    pub fn create_ssao_material(
+        &mut self,
+        normal: vk::ImageView,
+        depth: &RenderPassAttachmentOutput,
+        noise: vk::ImageView,
+        ambient_occlusion: vk::ImageView,
+    ) -> Result<SsaoMaterial> {
+        let shader_read = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+        let set = self.create_sampler_set_with_layouts(
+            self.ssao_descriptor_set_layout,
+            &[
+                (normal, shader_read),
+                (depth.image_view, depth.image_layout),
+                (noise, shader_read),
+            ],
+            "ssao",
+        )?;
+        let blur_set = self.create_sampler_set_with_layouts(
+            self.ssao_blur_descriptor_set_layout,
+            &[
+                (ambient_occlusion, shader_read),
+                (depth.image_view, depth.image_layout),
+            ],
+            "ssao_blur",
+        )?;
+
+        Ok(SsaoMaterial {
+            layout: self.ssao_pipeline_layout,
+            pipeline: self.ssao_pipeline,
+            set,
+            blur_layout: self.ssao_blur_pipeline_layout,
+            blur_pipeline: self.ssao_blur_pipeline,
+            blur_set,
+        })
+    }
+
+    pub fn get_swapchain_format(&self) -> vk::Format {
+        self.swapchain_format
+    }
+
+    pub fn get_display_encoding(&self) -> DisplayEncoding {
+        self.display_encoding
+    }
+
+    pub fn get_shadow_map_dimensions(&self) -> vk::Rect2D {
+        self.shadow_map_dimensions
+    }
+
+    // Called before the frame workers of a new swapchain are created
+    pub fn create_swapchain_descriptor_pool(&mut self, image_count: u32) -> Result<()> {
+        // Per frame worker, with some headroom over the 21 sets, 36 samplers and 10 storage
+        // buffers its passes allocate
+        static DESCRIPTOR_SET_COUNT: u32 = 24;
+        static COMBINED_IMAGE_SAMPLER_COUNT: u32 = 40;
+        static STORAGE_BUFFER_COUNT: u32 = 12;
+
+        let descriptor_pool_sizes = [
+            vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(COMBINED_IMAGE_SAMPLER_COUNT * image_count),
+            vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(STORAGE_BUFFER_COUNT * image_count),
+        ];
+
+        let create_info = vk::DescriptorPoolCreateInfo::default()
+            .max_sets(DESCRIPTOR_SET_COUNT * image_count)
+            .pool_sizes(&descriptor_pool_sizes);
+
+        self.swapchain_descriptor_pool =
+            unsafe { self.device.create_descriptor_pool(&create_info, None)? };
+
+        Ok(())
+    }
+
+    // Frees every set of the frame workers at once, the workers must be destroyed
+    pub fn destroy_swapchain_descriptor_pool(&mut self) {
+        unsafe {
+            self.device
+                .destroy_descriptor_pool(self.swapchain_descriptor_pool, None)
+        };
+        self.swapchain_descriptor_pool = vk::DescriptorPool::null();
+    }
+
+    fn create_descriptor_pool(device: &Device) -> Result<vk::DescriptorPool> {
+        // TODO: Configurable
+        // Sized for the image based lighting precomputation and a few debug UI textures
+        static DESCRIPTOR_SET_COUNT: u32 = 32;
+        static COMBINED_IMAGE_SAMPLER_COUNT: u32 = 32;
+        static STORAGE_IMAGE_COUNT: u32 = 16;
+
+        let descriptor_pool_sizes = [
+            vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(COMBINED_IMAGE_SAMPLER_COUNT),
+            vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::STORAGE_IMAGE)
+                .descriptor_count(STORAGE_IMAGE_COUNT),
+        ];
+
+        let create_info = vk::DescriptorPoolCreateInfo::default()
+            .max_sets(DESCRIPTOR_SET_COUNT)
+            .pool_sizes(&descriptor_pool_sizes);
+
+        Ok(unsafe { device.create_descriptor_pool(&create_info, None)? })
+    }
+
+    fn allocate_set(
+        &self,
+        descriptor_pool: vk::DescriptorPool,
+        set_layout: vk::DescriptorSetLayout,
+        name: &str,
+    ) -> Result<vk::DescriptorSet> {
+        let set_layouts = [set_layout];
+        let allocate_info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&set_layouts);
+
+        let set = unsafe { self.device.allocate_descriptor_sets(&allocate_info)?[0] };
+        debug_markers::set_object_name(set, name);
+
+        Ok(set)
+    }
+
+    // TODO: try immutable samplers
+    fn create_sampler_set_layout(
+        device: &Device,
+        binding_count: u32,
+    ) -> Result<vk::DescriptorSetLayout> {
+        let bindings: Vec<vk::DescriptorSetLayoutBinding> = (0..binding_count)
+            .map(|binding| {
+                vk::DescriptorSetLayoutBinding::default()
+                    .binding(binding)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .descriptor_count(1)
+                    .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            })
+            .collect();
+
+        let create_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings);
+
+        Ok(unsafe { device.create_descriptor_set_layout(&create_info, None)? })
+    }
+
+    fn create_storage_buffer_set_layout(
+        device: &Device,
+        binding_count: u32,
+        stage_flags: vk::ShaderStageFlags,
+    ) -> Result<vk::DescriptorSetLayout> {
+        let bindings: Vec<vk::DescriptorSetLayoutBinding> = (0..binding_count)
+            .map(|binding| {
+                vk::DescriptorSetLayoutBinding::default()
+                    .binding(binding)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .descriptor_count(1)
+                    .stage_flags(stage_flags)
+            })
+            .collect();
+
+        let create_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings);
+
+        Ok(unsafe { device.create_descriptor_set_layout(&create_info, None)? })
+    }
+
+    // A sampled source at binding 0 and a storage image target at binding 1
+    fn create_compute_image_set_layout(device: &Device) -> Result<vk::DescriptorSetLayout> {
+        let bindings = [
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE),
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE),
         ];
 
-        let set = self.create_deferred_lightning_set(
-            self.descriptor_pool,
-            self.deferred_lightning_descriptor_set_layout,
-        );
-        self.deferred_lightning_sets.push(set);
+        let create_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings);
+
+        Ok(unsafe { device.create_descriptor_set_layout(&create_info, None)? })
+    }
+
+    // `source` is sampled in SHADER_READ_ONLY_OPTIMAL, `target` is written in GENERAL
+    pub fn create_image_based_lighting_set(
+        &mut self,
+        source: vk::ImageView,
+        target: vk::ImageView,
+        name: &str,
+    ) -> Result<vk::DescriptorSet> {
+        let set = self.allocate_set(
+            self.descriptor_pool,
+            self.image_based_lighting_descriptor_set_layout,
+            name,
+        )?;
+
+        let source_infos = [vk::DescriptorImageInfo {
+            sampler: self.default_sampler,
+            image_view: source,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        }];
+
+        let target_infos = [vk::DescriptorImageInfo {
+            sampler: vk::Sampler::null(),
+            image_view: target,
+            image_layout: vk::ImageLayout::GENERAL,
+        }];
+
+        let descriptor_writes = [
+            vk::WriteDescriptorSet::default()
+                .dst_set(set)
+                .dst_binding(0)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(&source_infos),
+            vk::WriteDescriptorSet::default()
+                .dst_set(set)
+                .dst_binding(1)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .image_info(&target_infos),
+        ];
+
+        unsafe { self.device.update_descriptor_sets(&descriptor_writes, &[]) };
+
+        Ok(set)
+    }
+
+    fn create_storage_buffer_set(
+        &self,
+        set_layout: vk::DescriptorSetLayout,
+        buffers: &[vk::Buffer],
+        name: &str,
+    ) -> Result<vk::DescriptorSet> {
+        let set = self.allocate_set(self.swapchain_descriptor_pool, set_layout, name)?;
+
+        let buffer_infos: Vec<vk::DescriptorBufferInfo> = buffers
+            .iter()
+            .map(|&buffer| {
+                vk::DescriptorBufferInfo::default()
+                    .buffer(buffer)
+                    .offset(0)
+                    .range(vk::WHOLE_SIZE)
+            })
+            .collect();
 
         let descriptor_write = vk::WriteDescriptorSet::default()
             .dst_set(set)
             .dst_binding(0)
             .dst_array_element(0)
-            .descriptor_count(image_infos.len() as u32)
-            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-            .image_info(&image_infos);
+            .descriptor_count(buffer_infos.len() as u32)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .buffer_info(&buffer_infos);
 
         unsafe { self.device.update_descriptor_sets(&[descriptor_write], &[]) };
 
-        DeferredLightningMaterial {
-            layout: self.deferred_lightning_pipeline_layout,
-            pipeline: self.deferred_lightning_pipeline,
-            set,
-        }
+        Ok(set)
     }
 
-    fn create_descriptor_pool(device: &Device) -> vk::DescriptorPool {
-        // TODO: Configurable and per type
-        // Currently should == swapchain images
-        static DESCRIPTOR_SET_COUNT: u32 = 4;
+    pub fn create_debug_ui_set(
+        &mut self,
+        image_view: vk::ImageView,
+        name: &str,
+    ) -> Result<vk::DescriptorSet> {
+        let set = self.allocate_set(
+            self.descriptor_pool,
+            self.debug_ui_descriptor_set_layout,
+            name,
+        )?;
+        self.update_debug_ui_set(set, image_view);
 
-        let descriptor_pool_sizes = [vk::DescriptorPoolSize::default()
-            .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-            .descriptor_count(DESCRIPTOR_SET_COUNT)];
+        Ok(set)
+    }
 
-        let create_info = vk::DescriptorPoolCreateInfo::default()
-            .max_sets(DESCRIPTOR_SET_COUNT)
-            .pool_sizes(&descriptor_pool_sizes);
+    // Points a set from create_debug_ui_set at another texture, the set must not be in use
+    pub fn update_debug_ui_set(&self, set: vk::DescriptorSet, image_view: vk::ImageView) {
+        let image_infos = [vk::DescriptorImageInfo {
+            sampler: self.default_sampler,
+            image_view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        }];
 
-        unsafe { device.create_descriptor_pool(&create_info, None).unwrap() }
-    }
+        let descriptor_write = vk::WriteDescriptorSet::default()
+            .dst_set(set)
+            .dst_binding(0)
+            .dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&image_infos);
 
-    fn create_descriptor_set_layout(device: &Device) -> vk::DescriptorSetLayout {
-        // TODO: try immutable samplers for deferred lightning
+        unsafe { self.device.update_descriptor_sets(&[descriptor_write], &[]) };
+    }
 
-        let bindings = [
-            vk::DescriptorSetLayoutBinding::default()
-                .binding(0)
-                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                .descriptor_count(1)
-                .stage_flags(vk::ShaderStageFlags::FRAGMENT),
-            //.immutable_samplers(&[]),
-            vk::DescriptorSetLayoutBinding::default()
-                .binding(1)
-                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                .descriptor_count(1)
-                .stage_flags(vk::ShaderStageFlags::FRAGMENT),
-            //.immutable_samplers(&[]),
-            vk::DescriptorSetLayoutBinding::default()
-                .binding(2)
-                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                .descriptor_count(1)
-                .stage_flags(vk::ShaderStageFlags::FRAGMENT),
-            //.immutable_samplers(&[]),
-            vk::DescriptorSetLayoutBinding::default()
-                .binding(3)
-                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                .descriptor_count(1)
-                .stage_flags(vk::ShaderStageFlags::FRAGMENT),
-            //.immutable_samplers(&[]),
-        ];
+    // Per object data read by the deferred and shadow map vertex shaders
+    pub fn create_objects_set(&mut self, objects: vk::Buffer) -> Result<vk::DescriptorSet> {
+        self.create_storage_buffer_set(self.objects_descriptor_set_layout, &[objects], "objects")
+    }
 
-        let create_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings);
+    pub fn create_culling_set(
+        &mut self,
+        objects: vk::Buffer,
+        draw_commands: vk::Buffer,
+        draw_count: vk::Buffer,
+        name: &str,
+    ) -> Result<vk::DescriptorSet> {
+        self.create_storage_buffer_set(
+            self.culling_descriptor_set_layout,
+            &[objects, draw_commands, draw_count],
+            name,
+        )
+    }
 
-        unsafe {
-            device
-                .create_descriptor_set_layout(&create_info, None)
-                .unwrap()
-        }
+    // Image views are sampled with the default sampler, one binding each
+    fn create_sampler_set(
+        &self,
+        set_layout: vk::DescriptorSetLayout,
+        image_views: &[vk::ImageView],
+        name: &str,
+    ) -> Result<vk::DescriptorSet> {
+        let images: Vec<(vk::ImageView, vk::ImageLayout)> = image_views
+            .iter()
+            .map(|&image_view| (image_view, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL))
+            .collect();
+
+        self.create_sampler_set_with_layouts(set_layout, &images, name)
     }
 
-    fn create_deferred_lightning_set(
+    // For images that are sampled outside of SHADER_READ_ONLY_OPTIMAL, like the G-buffer depth
+    fn create_sampler_set_with_layouts(
         &self,
-        descriptor_pool: vk::DescriptorPool,
-        deferred_lightning_descriptor_set_layout: vk::DescriptorSetLayout,
-    ) -> vk::DescriptorSet {
-        let set_layouts = [deferred_lightning_descriptor_set_layout];
-        let allocate_info = vk::DescriptorSetAllocateInfo::default()
-            .descriptor_pool(descriptor_pool)
-            .set_layouts(&set_layouts);
+        set_layout: vk::DescriptorSetLayout,
+        images: &[(vk::ImageView, vk::ImageLayout)],
+        name: &str,
+    ) -> Result<vk::DescriptorSet> {
+        let set = self.allocate_set(self.swapchain_descriptor_pool, set_layout, name)?;
+
+        let image_infos: Vec<vk::DescriptorImageInfo> = images
+            .iter()
+            .map(|&(image_view, image_layout)| vk::DescriptorImageInfo {
+                sampler: self.default_sampler,
+                image_view,
+                image_layout,
+            })
+            .collect();
 
-        unsafe {
-            self.device
-                .allocate_descriptor_sets(&allocate_info)
-                .unwrap()[0]
-        }
+        let descriptor_write = vk::WriteDescriptorSet::default()
+            .dst_set(set)
+            .dst_binding(0)
+            .dst_array_element(0)
+            .descriptor_count(image_infos.len() as u32)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&image_infos);
+
+        unsafe { self.device.update_descriptor_sets(&[descriptor_write], &[]) };
+
+        Ok(set)
     }
 
     // TODO: verify
@@ -282,21 +1296,23 @@ impl PipelineManager {
         device: &Device,
         set_layouts: &[vk::DescriptorSetLayout],
         push_constant_ranges: &[vk::PushConstantRange],
-    ) -> vk::PipelineLayout {
+    ) -> Result<vk::PipelineLayout> {
         let create_info = vk::PipelineLayoutCreateInfo::default()
             .set_layouts(set_layouts)
             .push_constant_ranges(push_constant_ranges);
 
-        unsafe { device.create_pipeline_layout(&create_info, None).unwrap() }
+        Ok(unsafe { device.create_pipeline_layout(&create_info, None)? })
     }
 
+    // `name` is the fragment shader, the variants share the Deferred vertex shader
     fn create_deferred_pipeline(
         device: &Device,
         shader_manager: &mut ShaderManager,
+        name: &str,
         render_area: vk::Rect2D,
         deferred_pipeline_layout: vk::PipelineLayout,
-    ) -> vk::Pipeline {
-        if let Some(shader) = shader_manager.get_shader("Deferred") {
+    ) -> Result<vk::Pipeline> {
+        if let Some(shader) = shader_manager.get_shader_variant("Deferred", name)? {
             let vertex_binding_descriptions = [
                 vk::VertexInputBindingDescription::default()
                     .binding(0)
@@ -321,61 +1337,363 @@ impl PipelineManager {
                     .offset(0),
             ];
 
-            let color_blend_attachments = [
-                Self::create_pipeline_color_blend_attachment_state(),
-                Self::create_pipeline_color_blend_attachment_state(),
-                Self::create_pipeline_color_blend_attachment_state(),
-            ];
+            let color_blend_attachments =
+                GBUFFER_LAYOUT.map(|_| Self::create_pipeline_color_blend_attachment_state());
 
-            // TODO: manage format
-            let color_attachemnt_formats = [
-                deferred_renderpass_consts::COLOR,
-                deferred_renderpass_consts::NORMAL,
-                deferred_renderpass_consts::POSITION,
-            ];
+            let color_attachemnt_formats = GBUFFER_LAYOUT.map(|layout| layout.format.to_vk());
 
             return Self::create_pipeline(
                 device,
-                render_area,
-                &shader,
-                &vertex_binding_descriptions,
-                &vertex_input_attribute_descriptions,
-                &color_blend_attachments,
-                &color_attachemnt_formats,
-                deferred_pipeline_layout,
-                vk::CullModeFlags::NONE,
+                &PipelineCreateInfo {
+                    render_area,
+                    shader: &shader,
+                    name,
+                    vertex_binding_descriptions: &vertex_binding_descriptions,
+                    vertex_input_attribute_descriptions: &vertex_input_attribute_descriptions,
+                    color_blend_attachments: &color_blend_attachments,
+                    color_attachemnt_formats: &color_attachemnt_formats,
+                    depth_attachment_format: deferred_renderpass_consts::DEPTH,
+                    pipeline_layout: deferred_pipeline_layout,
+                    topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                    cull_mode: vk::CullModeFlags::NONE,
+                    // Depends on the camera's projection mode, set in DeferredRenderPass
+                    depth_compare_op: vk::CompareOp::LESS_OR_EQUAL,
+                    dynamic_states: &[vk::DynamicState::DEPTH_COMPARE_OP],
+                },
             );
         }
 
-        vk::Pipeline::null()
+        Ok(vk::Pipeline::null())
     }
 
     fn create_deferred_lightning_pipeline(
         device: &Device,
         shader_manager: &mut ShaderManager,
         render_area: vk::Rect2D,
-        swapchain_format: vk::Format,
         deferred_lightning_pipeline_layout: vk::PipelineLayout,
-    ) -> vk::Pipeline {
-        if let Some(shader) = shader_manager.get_shader("DeferredLightning") {
+    ) -> Result<vk::Pipeline> {
+        if let Some(shader) = shader_manager.get_shader("DeferredLightning")? {
+            let color_blend_attachments = [Self::create_pipeline_color_blend_attachment_state()];
+
+            let color_attachemnt_formats = [lightning_renderpass_consts::HDR_COLOR];
+
+            return Self::create_pipeline(
+                device,
+                &PipelineCreateInfo {
+                    render_area,
+                    shader: &shader,
+                    name: "DeferredLightning",
+                    vertex_binding_descriptions: &[],
+                    vertex_input_attribute_descriptions: &[],
+                    color_blend_attachments: &color_blend_attachments,
+                    color_attachemnt_formats: &color_attachemnt_formats,
+                    depth_attachment_format: vk::Format::UNDEFINED,
+                    pipeline_layout: deferred_lightning_pipeline_layout,
+                    topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                    cull_mode: vk::CullModeFlags::NONE,
+                    depth_compare_op: vk::CompareOp::LESS_OR_EQUAL,
+                    dynamic_states: &[],
+                },
+            );
+        }
+
+        Ok(vk::Pipeline::null())
+    }
+
+    // Blended over the lightning output, tested against the G-buffer depth without writing it
+    fn create_transparent_pipeline(
+        device: &Device,
+        shader_manager: &mut ShaderManager,
+        render_area: vk::Rect2D,
+        pipeline_layout: vk::PipelineLayout,
+    ) -> Result<vk::Pipeline> {
+        if let Some(shader) = shader_manager.get_shader("Transparent")? {
+            let vertex_binding_descriptions = [
+                vk::VertexInputBindingDescription::default()
+                    .binding(0)
+                    .stride(std::mem::size_of::<nalgebra::Vector3<f32>>() as u32)
+                    .input_rate(vk::VertexInputRate::VERTEX),
+                vk::VertexInputBindingDescription::default()
+                    .binding(1)
+                    .stride(std::mem::size_of::<nalgebra::Vector3<f32>>() as u32)
+                    .input_rate(vk::VertexInputRate::VERTEX),
+            ];
+
+            let vertex_input_attribute_descriptions = [
+                vk::VertexInputAttributeDescription::default()
+                    .location(0)
+                    .binding(0)
+                    .format(vk::Format::R32G32B32_SFLOAT)
+                    .offset(0),
+                vk::VertexInputAttributeDescription::default()
+                    .location(1)
+                    .binding(1)
+                    .format(vk::Format::R32G32B32_SFLOAT)
+                    .offset(0),
+            ];
+
+            let color_blend_attachments =
+                [Self::create_pipeline_alpha_blend_color_blend_attachment_state()];
+
+            let color_attachemnt_formats = [lightning_renderpass_consts::HDR_COLOR];
+
+            return Self::create_pipeline(
+                device,
+                &PipelineCreateInfo {
+                    render_area,
+                    shader: &shader,
+                    name: "Transparent",
+                    vertex_binding_descriptions: &vertex_binding_descriptions,
+                    vertex_input_attribute_descriptions: &vertex_input_attribute_descriptions,
+                    color_blend_attachments: &color_blend_attachments,
+                    color_attachemnt_formats: &color_attachemnt_formats,
+                    depth_attachment_format: deferred_renderpass_consts::DEPTH,
+                    pipeline_layout,
+                    topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                    cull_mode: vk::CullModeFlags::NONE,
+                    depth_compare_op: vk::CompareOp::LESS_OR_EQUAL,
+                    // Back faces are drawn before front faces, see TransparentRenderPass
+                    dynamic_states: &[
+                        vk::DynamicState::DEPTH_COMPARE_OP,
+                        vk::DynamicState::DEPTH_WRITE_ENABLE,
+                        vk::DynamicState::CULL_MODE,
+                    ],
+                },
+            );
+        }
+
+        Ok(vk::Pipeline::null())
+    }
+
+    // Fullscreen triangle on the far plane, only passes where the G-buffer depth was never written
+    fn create_skybox_pipeline(
+        device: &Device,
+        shader_manager: &mut ShaderManager,
+        render_area: vk::Rect2D,
+        pipeline_layout: vk::PipelineLayout,
+    ) -> Result<vk::Pipeline> {
+        if let Some(shader) = shader_manager.get_shader("Skybox")? {
+            let color_blend_attachments = [Self::create_pipeline_color_blend_attachment_state()];
+
+            let color_attachemnt_formats = [lightning_renderpass_consts::HDR_COLOR];
+
+            return Self::create_pipeline(
+                device,
+                &PipelineCreateInfo {
+                    render_area,
+                    shader: &shader,
+                    name: "Skybox",
+                    vertex_binding_descriptions: &[],
+                    vertex_input_attribute_descriptions: &[],
+                    color_blend_attachments: &color_blend_attachments,
+                    color_attachemnt_formats: &color_attachemnt_formats,
+                    depth_attachment_format: deferred_renderpass_consts::DEPTH,
+                    pipeline_layout,
+                    topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                    cull_mode: vk::CullModeFlags::NONE,
+                    // The far plane depth matches the clear value in every projection mode
+                    depth_compare_op: vk::CompareOp::EQUAL,
+                    // The G-buffer depth is bound read only, see SkyboxRenderPass
+                    dynamic_states: &[vk::DynamicState::DEPTH_WRITE_ENABLE],
+                },
+            );
+        }
+
+        Ok(vk::Pipeline::null())
+    }
+
+    // Post processing passes: fullscreen triangle, single color attachment, no depth
+    fn create_fullscreen_pipeline(
+        device: &Device,
+        shader_manager: &mut ShaderManager,
+        name: &str,
+        render_area: vk::Rect2D,
+        color_attachment_format: vk::Format,
+        pipeline_layout: vk::PipelineLayout,
+    ) -> Result<vk::Pipeline> {
+        if let Some(shader) = shader_manager.get_fullscreen_shader(name)? {
             let color_blend_attachments = [Self::create_pipeline_color_blend_attachment_state()];
 
+            let color_attachemnt_formats = [color_attachment_format];
+
+            return Self::create_pipeline(
+                device,
+                &PipelineCreateInfo {
+                    render_area,
+                    shader: &shader,
+                    name,
+                    vertex_binding_descriptions: &[],
+                    vertex_input_attribute_descriptions: &[],
+                    color_blend_attachments: &color_blend_attachments,
+                    color_attachemnt_formats: &color_attachemnt_formats,
+                    depth_attachment_format: vk::Format::UNDEFINED,
+                    pipeline_layout,
+                    topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                    cull_mode: vk::CullModeFlags::NONE,
+                    depth_compare_op: vk::CompareOp::LESS_OR_EQUAL,
+                    dynamic_states: &[],
+                },
+            );
+        }
+
+        Ok(vk::Pipeline::null())
+    }
+
+    // Renders into every mip of the bloom chain, so the viewport is dynamic
+    fn create_bloom_pipeline(
+        device: &Device,
+        shader_manager: &mut ShaderManager,
+        name: &str,
+        render_area: vk::Rect2D,
+        pipeline_layout: vk::PipelineLayout,
+        color_blend_attachment: vk::PipelineColorBlendAttachmentState,
+    ) -> Result<vk::Pipeline> {
+        if let Some(shader) = shader_manager.get_fullscreen_shader(name)? {
+            let color_blend_attachments = [color_blend_attachment];
+
+            let color_attachemnt_formats = [lightning_renderpass_consts::HDR_COLOR];
+
+            return Self::create_pipeline(
+                device,
+                &PipelineCreateInfo {
+                    render_area,
+                    shader: &shader,
+                    name,
+                    vertex_binding_descriptions: &[],
+                    vertex_input_attribute_descriptions: &[],
+                    color_blend_attachments: &color_blend_attachments,
+                    color_attachemnt_formats: &color_attachemnt_formats,
+                    depth_attachment_format: vk::Format::UNDEFINED,
+                    pipeline_layout,
+                    topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                    cull_mode: vk::CullModeFlags::NONE,
+                    depth_compare_op: vk::CompareOp::LESS_OR_EQUAL,
+                    dynamic_states: &[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR],
+                },
+            );
+        }
+
+        Ok(vk::Pipeline::null())
+    }
+
+    // egui meshes over the final image, clipped with a dynamic scissor per mesh
+    // Line list blended over the lightning output, depth testing against the G-buffer depth is
+    // toggled per draw by DebugDrawRenderPass
+    fn create_debug_draw_pipeline(
+        device: &Device,
+        shader_manager: &mut ShaderManager,
+        render_area: vk::Rect2D,
+        pipeline_layout: vk::PipelineLayout,
+    ) -> Result<vk::Pipeline> {
+        if let Some(shader) = shader_manager.get_shader("DebugDraw")? {
+            let vertex_binding_descriptions = [vk::VertexInputBindingDescription::default()
+                .binding(0)
+                .stride(std::mem::size_of::<DebugLineVertex>() as u32)
+                .input_rate(vk::VertexInputRate::VERTEX)];
+
+            let vertex_input_attribute_descriptions = [
+                vk::VertexInputAttributeDescription::default()
+                    .location(0)
+                    .binding(0)
+                    .format(vk::Format::R32G32B32_SFLOAT)
+                    .offset(std::mem::offset_of!(DebugLineVertex, position) as u32),
+                vk::VertexInputAttributeDescription::default()
+                    .location(1)
+                    .binding(0)
+                    .format(vk::Format::R32G32B32A32_SFLOAT)
+                    .offset(std::mem::offset_of!(DebugLineVertex, color) as u32),
+            ];
+
+            let color_blend_attachments =
+                [Self::create_pipeline_alpha_blend_color_blend_attachment_state()];
+
+            let color_attachemnt_formats = [lightning_renderpass_consts::HDR_COLOR];
+
+            return Self::create_pipeline(
+                device,
+                &PipelineCreateInfo {
+                    render_area,
+                    shader: &shader,
+                    name: "DebugDraw",
+                    vertex_binding_descriptions: &vertex_binding_descriptions,
+                    vertex_input_attribute_descriptions: &vertex_input_attribute_descriptions,
+                    color_blend_attachments: &color_blend_attachments,
+                    color_attachemnt_formats: &color_attachemnt_formats,
+                    depth_attachment_format: deferred_renderpass_consts::DEPTH,
+                    pipeline_layout,
+                    topology: vk::PrimitiveTopology::LINE_LIST,
+                    cull_mode: vk::CullModeFlags::NONE,
+                    depth_compare_op: vk::CompareOp::LESS_OR_EQUAL,
+                    dynamic_states: &[
+                        vk::DynamicState::DEPTH_TEST_ENABLE,
+                        vk::DynamicState::DEPTH_COMPARE_OP,
+                        vk::DynamicState::DEPTH_WRITE_ENABLE,
+                    ],
+                },
+            );
+        }
+
+        Ok(vk::Pipeline::null())
+    }
+
+    fn create_debug_ui_pipeline(
+        device: &Device,
+        shader_manager: &mut ShaderManager,
+        render_area: vk::Rect2D,
+        swapchain_format: vk::Format,
+        pipeline_layout: vk::PipelineLayout,
+    ) -> Result<vk::Pipeline> {
+        if let Some(shader) = shader_manager.get_shader("DebugUi")? {
+            let vertex_binding_descriptions = [vk::VertexInputBindingDescription::default()
+                .binding(0)
+                .stride(std::mem::size_of::<egui::epaint::Vertex>() as u32)
+                .input_rate(vk::VertexInputRate::VERTEX)];
+
+            // The field order depends on egui's features
+            let vertex_input_attribute_descriptions = [
+                vk::VertexInputAttributeDescription::default()
+                    .location(0)
+                    .binding(0)
+                    .format(vk::Format::R32G32_SFLOAT)
+                    .offset(std::mem::offset_of!(egui::epaint::Vertex, pos) as u32),
+                vk::VertexInputAttributeDescription::default()
+                    .location(1)
+                    .binding(0)
+                    .format(vk::Format::R32G32_SFLOAT)
+                    .offset(std::mem::offset_of!(egui::epaint::Vertex, uv) as u32),
+                vk::VertexInputAttributeDescription::default()
+                    .location(2)
+                    .binding(0)
+                    .format(vk::Format::R8G8B8A8_UNORM)
+                    .offset(std::mem::offset_of!(egui::epaint::Vertex, color) as u32),
+            ];
+
+            let color_blend_attachments =
+                [Self::create_pipeline_premultiplied_alpha_blend_color_blend_attachment_state()];
+
             let color_attachemnt_formats = [swapchain_format];
 
             return Self::create_pipeline(
                 device,
-                render_area,
-                &shader,
-                &[],
-                &[],
-                &color_blend_attachments,
-                &color_attachemnt_formats,
-                deferred_lightning_pipeline_layout,
-                vk::CullModeFlags::NONE,
+                &PipelineCreateInfo {
+                    render_area,
+                    shader: &shader,
+                    name: "DebugUi",
+                    vertex_binding_descriptions: &vertex_binding_descriptions,
+                    vertex_input_attribute_descriptions: &vertex_input_attribute_descriptions,
+                    color_blend_attachments: &color_blend_attachments,
+                    color_attachemnt_formats: &color_attachemnt_formats,
+                    depth_attachment_format: vk::Format::UNDEFINED,
+                    pipeline_layout,
+                    topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                    cull_mode: vk::CullModeFlags::NONE,
+                    depth_compare_op: vk::CompareOp::LESS_OR_EQUAL,
+                    dynamic_states: &[vk::DynamicState::SCISSOR],
+                },
             );
         }
 
-        vk::Pipeline::null()
+        Ok(vk::Pipeline::null())
     }
 
     fn create_shadow_map_pipeline(
@@ -383,8 +1701,8 @@ impl PipelineManager {
         shader_manager: &mut ShaderManager,
         render_area: vk::Rect2D,
         shadow_map_layout: vk::PipelineLayout,
-    ) -> vk::Pipeline {
-        if let Some(shader) = shader_manager.get_shader("ShadowMap") {
+    ) -> Result<vk::Pipeline> {
+        if let Some(shader) = shader_manager.get_shader("ShadowMap")? {
             let vertex_binding_descriptions = [vk::VertexInputBindingDescription::default()
                 .binding(0)
                 .stride(std::mem::size_of::<nalgebra::Vector3<f32>>() as u32)
@@ -409,31 +1727,71 @@ impl PipelineManager {
 
             return Self::create_pipeline(
                 device,
-                render_area,
-                &shader,
-                &vertex_binding_descriptions,
-                &vertex_input_attribute_descriptions,
-                &color_blend_attachments,
-                &color_attachemnt_formats,
-                shadow_map_layout,
-                vk::CullModeFlags::NONE,
+                &PipelineCreateInfo {
+                    render_area,
+                    shader: &shader,
+                    name: "ShadowMap",
+                    vertex_binding_descriptions: &vertex_binding_descriptions,
+                    vertex_input_attribute_descriptions: &vertex_input_attribute_descriptions,
+                    color_blend_attachments: &color_blend_attachments,
+                    color_attachemnt_formats: &color_attachemnt_formats,
+                    depth_attachment_format: deferred_renderpass_consts::DEPTH,
+                    pipeline_layout: shadow_map_layout,
+                    topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                    cull_mode: vk::CullModeFlags::NONE,
+                    depth_compare_op: vk::CompareOp::LESS_OR_EQUAL,
+                    dynamic_states: &[],
+                },
             );
         }
 
-        vk::Pipeline::null()
+        Ok(vk::Pipeline::null())
     }
 
-    fn create_pipeline(
+    fn create_compute_pipeline(
         device: &Device,
-        render_area: vk::Rect2D,
-        shader: &Shader,
-        vertex_binding_descriptions: &[vk::VertexInputBindingDescription],
-        vertex_input_attribute_descriptions: &[vk::VertexInputAttributeDescription],
-        color_blend_attachments: &[vk::PipelineColorBlendAttachmentState],
-        color_attachemnt_formats: &[vk::Format],
+        shader_manager: &mut ShaderManager,
+        name: &str,
         pipeline_layout: vk::PipelineLayout,
-        cull_mode: vk::CullModeFlags,
-    ) -> vk::Pipeline {
+    ) -> Result<vk::Pipeline> {
+        if let Some(module) = shader_manager.get_compute_shader(name)? {
+            let create_info = vk::ComputePipelineCreateInfo::default()
+                .stage(Self::create_pipeline_shader_stage_create_info(
+                    vk::ShaderStageFlags::COMPUTE,
+                    module,
+                ))
+                .layout(pipeline_layout);
+
+            let pipeline = unsafe {
+                device
+                    .create_compute_pipelines(vk::PipelineCache::null(), &[create_info], None)
+                    .map_err(|(_, result)| result)?[0]
+            };
+            debug_markers::set_object_name(pipeline, name);
+
+            return Ok(pipeline);
+        }
+
+        Ok(vk::Pipeline::null())
+    }
+
+    fn create_pipeline(device: &Device, create_info: &PipelineCreateInfo) -> Result<vk::Pipeline> {
+        let &PipelineCreateInfo {
+            render_area,
+            shader,
+            name,
+            vertex_binding_descriptions,
+            vertex_input_attribute_descriptions,
+            color_blend_attachments,
+            color_attachemnt_formats,
+            depth_attachment_format,
+            pipeline_layout,
+            topology,
+            cull_mode,
+            depth_compare_op,
+            dynamic_states,
+        } = create_info;
+
         let shader_stage_create_infos = [
             Self::create_pipeline_shader_stage_create_info(
                 vk::ShaderStageFlags::VERTEX,
@@ -450,7 +1808,7 @@ impl PipelineManager {
             vertex_input_attribute_descriptions,
         );
 
-        let input_assembly_state = Self::create_pipeline_input_assembly_state_create_info();
+        let input_assembly_state = Self::create_pipeline_input_assembly_state_create_info(topology);
         let tessellation_state = Self::create_pipeline_tessellation_state_create_info();
 
         let viewports = [vk::Viewport::default()
@@ -465,12 +1823,16 @@ impl PipelineManager {
         let viewport_state = Self::create_viewport_state_create_info(&viewports, &render_areas);
         let rasterization_state = Self::create_rasterization_state_create_info(&cull_mode);
         let multisample_state = Self::create_pipeline_multisample_state_create_info();
-        let depth_stencil_state = Self::create_pipeline_depth_stencil_state_create_info();
+        let depth_stencil_state =
+            Self::create_pipeline_depth_stencil_state_create_info(depth_compare_op);
         let color_blend_state =
             Self::create_pipeline_color_blend_state_create_info(color_blend_attachments);
-        let dynamic_state = Self::create_pipeline_dynamic_state_create_info();
+        let dynamic_state = Self::create_pipeline_dynamic_state_create_info(dynamic_states);
         let mut pipeline_rendering_create_info_khr =
-            Self::create_pipeline_rendering_create_info_khr(color_attachemnt_formats);
+            Self::create_pipeline_rendering_create_info_khr(
+                color_attachemnt_formats,
+                depth_attachment_format,
+            );
 
         let create_info = vk::GraphicsPipelineCreateInfo::default()
             .push_next(&mut pipeline_rendering_create_info_khr)
@@ -490,11 +1852,14 @@ impl PipelineManager {
             .base_pipeline_handle(vk::Pipeline::null())
             .base_pipeline_index(0);
 
-        unsafe {
+        let pipeline = unsafe {
             device
                 .create_graphics_pipelines(vk::PipelineCache::null(), &[create_info], None)
-                .unwrap()[0]
-        }
+                .map_err(|(_, result)| result)?[0]
+        };
+        debug_markers::set_object_name(pipeline, name);
+
+        Ok(pipeline)
     }
 
     fn create_pipeline_shader_stage_create_info(
@@ -533,9 +1898,10 @@ impl PipelineManager {
     }
 
     fn create_pipeline_input_assembly_state_create_info(
+        topology: vk::PrimitiveTopology,
     ) -> vk::PipelineInputAssemblyStateCreateInfo<'static> {
         vk::PipelineInputAssemblyStateCreateInfo::default()
-            .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+            .topology(topology)
             .primitive_restart_enable(false)
     }
 
@@ -565,12 +1931,14 @@ impl PipelineManager {
             .alpha_to_one_enable(false)
     }
 
+    // Reverse-Z needs GREATER_OR_EQUAL
     fn create_pipeline_depth_stencil_state_create_info(
+        depth_compare_op: vk::CompareOp,
     ) -> vk::PipelineDepthStencilStateCreateInfo<'static> {
         vk::PipelineDepthStencilStateCreateInfo::default()
             .depth_test_enable(true)
             .depth_write_enable(true)
-            .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL)
+            .depth_compare_op(depth_compare_op)
             .depth_bounds_test_enable(true)
             .stencil_test_enable(true)
             .front(vk::StencilOpState::default())
@@ -596,6 +1964,62 @@ impl PipelineManager {
             )
     }
 
+    fn create_pipeline_additive_color_blend_attachment_state(
+    ) -> vk::PipelineColorBlendAttachmentState {
+        vk::PipelineColorBlendAttachmentState::default()
+            .blend_enable(true)
+            .src_color_blend_factor(vk::BlendFactor::ONE)
+            .dst_color_blend_factor(vk::BlendFactor::ONE)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(vk::BlendFactor::ONE)
+            .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+            .alpha_blend_op(vk::BlendOp::ADD)
+            .color_write_mask(
+                vk::ColorComponentFlags::R
+                    | vk::ColorComponentFlags::G
+                    | vk::ColorComponentFlags::B
+                    | vk::ColorComponentFlags::A,
+            )
+    }
+
+    // Straight alpha, the destination alpha is kept
+    fn create_pipeline_alpha_blend_color_blend_attachment_state(
+    ) -> vk::PipelineColorBlendAttachmentState {
+        vk::PipelineColorBlendAttachmentState::default()
+            .blend_enable(true)
+            .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(vk::BlendFactor::ZERO)
+            .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+            .alpha_blend_op(vk::BlendOp::ADD)
+            .color_write_mask(
+                vk::ColorComponentFlags::R
+                    | vk::ColorComponentFlags::G
+                    | vk::ColorComponentFlags::B
+                    | vk::ColorComponentFlags::A,
+            )
+    }
+
+    // egui colors are premultiplied
+    fn create_pipeline_premultiplied_alpha_blend_color_blend_attachment_state(
+    ) -> vk::PipelineColorBlendAttachmentState {
+        vk::PipelineColorBlendAttachmentState::default()
+            .blend_enable(true)
+            .src_color_blend_factor(vk::BlendFactor::ONE)
+            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_DST_ALPHA)
+            .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+            .alpha_blend_op(vk::BlendOp::ADD)
+            .color_write_mask(
+                vk::ColorComponentFlags::R
+                    | vk::ColorComponentFlags::G
+                    | vk::ColorComponentFlags::B
+                    | vk::ColorComponentFlags::A,
+            )
+    }
+
     fn create_pipeline_color_blend_state_create_info(
         color_blend_attachments: &[vk::PipelineColorBlendAttachmentState],
     ) -> vk::PipelineColorBlendStateCreateInfo {
@@ -605,16 +2029,19 @@ impl PipelineManager {
             .attachments(color_blend_attachments)
     }
 
-    fn create_pipeline_dynamic_state_create_info() -> vk::PipelineDynamicStateCreateInfo<'static> {
-        vk::PipelineDynamicStateCreateInfo::default()
+    fn create_pipeline_dynamic_state_create_info(
+        dynamic_states: &[vk::DynamicState],
+    ) -> vk::PipelineDynamicStateCreateInfo<'_> {
+        vk::PipelineDynamicStateCreateInfo::default().dynamic_states(dynamic_states)
     }
 
     fn create_pipeline_rendering_create_info_khr(
         color_attachment_formats: &[vk::Format],
+        depth_attachment_format: vk::Format,
     ) -> vk::PipelineRenderingCreateInfoKHR {
         vk::PipelineRenderingCreateInfoKHR::default()
             .color_attachment_formats(color_attachment_formats)
-            .depth_attachment_format(deferred_renderpass_consts::DEPTH)
+            .depth_attachment_format(depth_attachment_format)
             .stencil_attachment_format(vk::Format::UNDEFINED)
     }
 }
@@ -1,17 +1,18 @@
-use nalgebra::{Matrix4, Vector3};
+use nalgebra::{Matrix4, Vector2, Vector3, Vector4};
 use std::slice;
 
+use crate::render_settings::RenderSettings;
+
+// Model matrices come from the object storage buffer
 #[repr(C)]
 pub struct PushConstantsData {
-    model: Matrix4<f32>,
     view: Matrix4<f32>,
     projection: Matrix4<f32>,
 }
 
 impl PushConstantsData {
-    pub fn new(model: &Matrix4<f32>, view: &Matrix4<f32>, projection: &Matrix4<f32>) -> Self {
+    pub fn new(view: &Matrix4<f32>, projection: &Matrix4<f32>) -> Self {
         Self {
-            model: *model,
             view: *view,
             projection: *projection,
         }
@@ -30,14 +31,31 @@ impl PushConstantsData {
 #[repr(C)]
 pub struct LightningPushConstantsData {
     light_space: Matrix4<f32>,
-    view: Vector3<f32>,
+    // Reconstructs world positions from the G-buffer depth
+    inverse_view_projection: Matrix4<f32>,
+    camera_position: Vector3<f32>,
+    environment_intensity: f32,
+    // Depth of the far plane, 0 with reverse-Z
+    far_depth: f32,
+    debug_view: u32,
 }
 
 impl LightningPushConstantsData {
-    pub fn new(light_space: &Matrix4<f32>, view: &Vector3<f32>) -> Self {
+    pub fn new(
+        light_space: &Matrix4<f32>,
+        inverse_view_projection: &Matrix4<f32>,
+        camera_position: &Vector3<f32>,
+        environment_intensity: f32,
+        far_depth: f32,
+        debug_view: u32,
+    ) -> Self {
         Self {
             light_space: *light_space,
-            view: *view,
+            inverse_view_projection: *inverse_view_projection,
+            camera_position: *camera_position,
+            environment_intensity,
+            far_depth,
+            debug_view,
         }
     }
 
@@ -50,3 +68,370 @@ impl LightningPushConstantsData {
         }
     }
 }
+
+#[repr(C)]
+pub struct CullingPushConstantsData {
+    planes: [Vector4<f32>; 6],
+    object_count: u32,
+    required_flags: u32,
+    excluded_flags: u32,
+}
+
+impl CullingPushConstantsData {
+    pub fn new(
+        planes: &[Vector4<f32>; 6],
+        object_count: u32,
+        required_flags: u32,
+        excluded_flags: u32,
+    ) -> Self {
+        Self {
+            planes: *planes,
+            object_count,
+            required_flags,
+            excluded_flags,
+        }
+    }
+
+    pub fn get(&self) -> &[u8] {
+        unsafe {
+            slice::from_raw_parts(
+                self as *const CullingPushConstantsData as *const u8,
+                std::mem::size_of::<CullingPushConstantsData>(),
+            )
+        }
+    }
+}
+
+#[repr(C)]
+pub struct TonemappingPushConstantsData {
+    exposure: f32,
+    tonemapping_operator: u32,
+    display_encoding: u32,
+    bloom_intensity: f32,
+}
+
+impl TonemappingPushConstantsData {
+    pub fn new(
+        exposure: f32,
+        tonemapping_operator: u32,
+        display_encoding: u32,
+        bloom_intensity: f32,
+    ) -> Self {
+        Self {
+            exposure,
+            tonemapping_operator,
+            display_encoding,
+            bloom_intensity,
+        }
+    }
+
+    pub fn get(&self) -> &[u8] {
+        unsafe {
+            slice::from_raw_parts(
+                self as *const TonemappingPushConstantsData as *const u8,
+                std::mem::size_of::<TonemappingPushConstantsData>(),
+            )
+        }
+    }
+}
+
+#[repr(C)]
+pub struct BloomPushConstantsData {
+    source_texel_size: Vector2<f32>,
+    threshold: f32,
+    knee: f32,
+    prefilter: u32,
+}
+
+impl BloomPushConstantsData {
+    pub fn new(
+        source_texel_size: &Vector2<f32>,
+        threshold: f32,
+        knee: f32,
+        prefilter: bool,
+    ) -> Self {
+        Self {
+            source_texel_size: *source_texel_size,
+            threshold,
+            knee,
+            prefilter: prefilter as u32,
+        }
+    }
+
+    pub fn get(&self) -> &[u8] {
+        unsafe {
+            slice::from_raw_parts(
+                self as *const BloomPushConstantsData as *const u8,
+                std::mem::size_of::<BloomPushConstantsData>(),
+            )
+        }
+    }
+}
+
+#[repr(C)]
+pub struct SsaoPushConstantsData {
+    view_projection: Matrix4<f32>,
+    inverse_view_projection: Matrix4<f32>,
+    camera_position: Vector3<f32>,
+    radius: f32,
+    camera_forward: Vector3<f32>,
+    bias: f32,
+    power: f32,
+    far_depth: f32,
+}
+
+impl SsaoPushConstantsData {
+    pub fn new(
+        view_projection: &Matrix4<f32>,
+        inverse_view_projection: &Matrix4<f32>,
+        camera_position: &Vector3<f32>,
+        camera_forward: &Vector3<f32>,
+        settings: &RenderSettings,
+        far_depth: f32,
+    ) -> Self {
+        Self {
+            view_projection: *view_projection,
+            inverse_view_projection: *inverse_view_projection,
+            camera_position: *camera_position,
+            radius: settings.ssao_radius,
+            camera_forward: *camera_forward,
+            bias: settings.ssao_bias,
+            power: settings.ssao_power,
+            far_depth,
+        }
+    }
+
+    pub fn get(&self) -> &[u8] {
+        unsafe {
+            slice::from_raw_parts(
+                self as *const SsaoPushConstantsData as *const u8,
+                std::mem::size_of::<SsaoPushConstantsData>(),
+            )
+        }
+    }
+}
+
+#[repr(C)]
+pub struct SsaoBlurPushConstantsData {
+    inverse_view_projection: Matrix4<f32>,
+    camera_position: Vector3<f32>,
+    sharpness: f32,
+    camera_forward: Vector3<f32>,
+    far_depth: f32,
+}
+
+impl SsaoBlurPushConstantsData {
+    pub fn new(
+        inverse_view_projection: &Matrix4<f32>,
+        camera_position: &Vector3<f32>,
+        camera_forward: &Vector3<f32>,
+        sharpness: f32,
+        far_depth: f32,
+    ) -> Self {
+        Self {
+            inverse_view_projection: *inverse_view_projection,
+            camera_position: *camera_position,
+            sharpness,
+            camera_forward: *camera_forward,
+            far_depth,
+        }
+    }
+
+    pub fn get(&self) -> &[u8] {
+        unsafe {
+            slice::from_raw_parts(
+                self as *const SsaoBlurPushConstantsData as *const u8,
+                std::mem::size_of::<SsaoBlurPushConstantsData>(),
+            )
+        }
+    }
+}
+
+#[repr(C)]
+pub struct ImageBasedLightingPushConstantsData {
+    // Of the prefiltered mip being written
+    roughness: f32,
+}
+
+impl ImageBasedLightingPushConstantsData {
+    pub fn new(roughness: f32) -> Self {
+        Self { roughness }
+    }
+
+    pub fn get(&self) -> &[u8] {
+        unsafe {
+            slice::from_raw_parts(
+                self as *const ImageBasedLightingPushConstantsData as *const u8,
+                std::mem::size_of::<ImageBasedLightingPushConstantsData>(),
+            )
+        }
+    }
+}
+
+#[repr(C)]
+pub struct SkyboxPushConstantsData {
+    inverse_view_projection: Matrix4<f32>,
+    // Depth of the far plane, 0 with reverse-Z
+    far_depth: f32,
+    intensity: f32,
+}
+
+impl SkyboxPushConstantsData {
+    pub fn new(inverse_view_projection: &Matrix4<f32>, far_depth: f32, intensity: f32) -> Self {
+        Self {
+            inverse_view_projection: *inverse_view_projection,
+            far_depth,
+            intensity,
+        }
+    }
+
+    pub fn get(&self) -> &[u8] {
+        unsafe {
+            slice::from_raw_parts(
+                self as *const SkyboxPushConstantsData as *const u8,
+                std::mem::size_of::<SkyboxPushConstantsData>(),
+            )
+        }
+    }
+}
+
+#[repr(C)]
+pub struct TaaPushConstantsData {
+    // Current to previous clip space without jitter, reprojects pixels that have no velocity
+    reprojection: Matrix4<f32>,
+    // Depth of the far plane, 0 with reverse-Z
+    far_depth: f32,
+    // Weight of the current frame in the history
+    blend_factor: f32,
+    reset_history: u32,
+}
+
+impl TaaPushConstantsData {
+    pub fn new(
+        reprojection: &Matrix4<f32>,
+        far_depth: f32,
+        blend_factor: f32,
+        reset_history: bool,
+    ) -> Self {
+        Self {
+            reprojection: *reprojection,
+            far_depth,
+            blend_factor,
+            reset_history: reset_history as u32,
+        }
+    }
+
+    pub fn get(&self) -> &[u8] {
+        unsafe {
+            slice::from_raw_parts(
+                self as *const TaaPushConstantsData as *const u8,
+                std::mem::size_of::<TaaPushConstantsData>(),
+            )
+        }
+    }
+}
+
+#[repr(C)]
+pub struct FxaaPushConstantsData {
+    texel_size: Vector2<f32>,
+    // Set for sRGB formats, which are decoded when sampled
+    linear_input: u32,
+}
+
+impl FxaaPushConstantsData {
+    pub fn new(texel_size: &Vector2<f32>, linear_input: bool) -> Self {
+        Self {
+            texel_size: *texel_size,
+            linear_input: linear_input as u32,
+        }
+    }
+
+    pub fn get(&self) -> &[u8] {
+        unsafe {
+            slice::from_raw_parts(
+                self as *const FxaaPushConstantsData as *const u8,
+                std::mem::size_of::<FxaaPushConstantsData>(),
+            )
+        }
+    }
+}
+
+#[repr(C)]
+pub struct TransparentPushConstantsData {
+    view_projection: Matrix4<f32>,
+    light_space: Matrix4<f32>,
+    camera_position: Vector3<f32>,
+    environment_intensity: f32,
+}
+
+impl TransparentPushConstantsData {
+    pub fn new(
+        view_projection: &Matrix4<f32>,
+        light_space: &Matrix4<f32>,
+        camera_position: &Vector3<f32>,
+        environment_intensity: f32,
+    ) -> Self {
+        Self {
+            view_projection: *view_projection,
+            light_space: *light_space,
+            camera_position: *camera_position,
+            environment_intensity,
+        }
+    }
+
+    pub fn get(&self) -> &[u8] {
+        unsafe {
+            slice::from_raw_parts(
+                self as *const TransparentPushConstantsData as *const u8,
+                std::mem::size_of::<TransparentPushConstantsData>(),
+            )
+        }
+    }
+}
+
+#[repr(C)]
+pub struct DebugDrawPushConstantsData {
+    view_projection: Matrix4<f32>,
+}
+
+impl DebugDrawPushConstantsData {
+    pub fn new(view_projection: &Matrix4<f32>) -> Self {
+        Self {
+            view_projection: *view_projection,
+        }
+    }
+
+    pub fn get(&self) -> &[u8] {
+        unsafe {
+            slice::from_raw_parts(
+                self as *const DebugDrawPushConstantsData as *const u8,
+                std::mem::size_of::<DebugDrawPushConstantsData>(),
+            )
+        }
+    }
+}
+
+#[repr(C)]
+pub struct DebugUiPushConstantsData {
+    // In points
+    screen_size: Vector2<f32>,
+    display_encoding: u32,
+}
+
+impl DebugUiPushConstantsData {
+    pub fn new(screen_size: &Vector2<f32>, display_encoding: u32) -> Self {
+        Self {
+            screen_size: *screen_size,
+            display_encoding,
+        }
+    }
+
+    pub fn get(&self) -> &[u8] {
+        unsafe {
+            slice::from_raw_parts(
+                self as *const DebugUiPushConstantsData as *const u8,
+                std::mem::size_of::<DebugUiPushConstantsData>(),
+            )
+        }
+    }
+}
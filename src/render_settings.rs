@@ -0,0 +1,148 @@
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TonemappingOperator {
+    Reinhard,
+    Aces,
+    AgX,
+}
+
+impl TonemappingOperator {
+    pub fn next(self) -> Self {
+        match self {
+            TonemappingOperator::Reinhard => TonemappingOperator::Aces,
+            TonemappingOperator::Aces => TonemappingOperator::AgX,
+            TonemappingOperator::AgX => TonemappingOperator::Reinhard,
+        }
+    }
+
+    // Matches the TONEMAPPING_* defines in shaders/Tonemapping.frag
+    pub fn get_shader_index(self) -> u32 {
+        match self {
+            TonemappingOperator::Reinhard => 0,
+            TonemappingOperator::Aces => 1,
+            TonemappingOperator::AgX => 2,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AntiAliasing {
+    None,
+    // Jittered rendering resolved against the reprojected previous frames
+    Taa,
+    // Single pass on the tonemapped output, for when TAA is too expensive
+    Fxaa,
+}
+
+impl AntiAliasing {
+    pub fn next(self) -> Self {
+        match self {
+            AntiAliasing::None => AntiAliasing::Taa,
+            AntiAliasing::Taa => AntiAliasing::Fxaa,
+            AntiAliasing::Fxaa => AntiAliasing::None,
+        }
+    }
+}
+
+// Replaces the lit output of the lightning pass with a single G-buffer or shadow channel
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DebugView {
+    None,
+    Albedo,
+    Normals,
+    WorldPosition,
+    Metallic,
+    Roughness,
+    Depth,
+    ShadowFactor,
+    ShadowMap,
+    CascadeIndex,
+}
+
+impl DebugView {
+    pub fn next(self) -> Self {
+        match self {
+            DebugView::None => DebugView::Albedo,
+            DebugView::Albedo => DebugView::Normals,
+            DebugView::Normals => DebugView::WorldPosition,
+            DebugView::WorldPosition => DebugView::Metallic,
+            DebugView::Metallic => DebugView::Roughness,
+            DebugView::Roughness => DebugView::Depth,
+            DebugView::Depth => DebugView::ShadowFactor,
+            DebugView::ShadowFactor => DebugView::ShadowMap,
+            DebugView::ShadowMap => DebugView::CascadeIndex,
+            DebugView::CascadeIndex => DebugView::None,
+        }
+    }
+
+    // Matches the DEBUG_VIEW_* defines in shaders/DeferredLightning.frag
+    pub fn get_shader_index(self) -> u32 {
+        match self {
+            DebugView::None => 0,
+            DebugView::Albedo => 1,
+            DebugView::Normals => 2,
+            DebugView::WorldPosition => 3,
+            DebugView::Metallic => 4,
+            DebugView::Roughness => 5,
+            DebugView::Depth => 6,
+            DebugView::ShadowFactor => 7,
+            DebugView::ShadowMap => 8,
+            DebugView::CascadeIndex => 9,
+        }
+    }
+}
+
+// Settings that can be changed between frames, copied into DrawData
+#[derive(Clone, Copy, Debug)]
+pub struct RenderSettings {
+    // GPU culling with indirect draws or CPU culling with direct draws
+    pub gpu_driven: bool,
+    // In stops, the HDR color is scaled by 2^exposure before tonemapping
+    pub exposure: f32,
+    pub tonemapping_operator: TonemappingOperator,
+    pub bloom: bool,
+    // Fraction of the blurred image mixed into the scene
+    pub bloom_intensity: f32,
+    // Luminance where bloom starts, 0 lets everything bloom which is the physically based option
+    pub bloom_threshold: f32,
+    // Width of the soft transition around the threshold, relative to it
+    pub bloom_knee: f32,
+    pub ssao: bool,
+    // World space radius of the sampled hemisphere
+    pub ssao_radius: f32,
+    // Depth difference below which samples don't occlude, avoids self occlusion acne
+    pub ssao_bias: f32,
+    // Exponent applied to the resolved occlusion
+    pub ssao_power: f32,
+    pub anti_aliasing: AntiAliasing,
+    // Scales the image based lighting from the environment map, before ambient occlusion
+    pub environment_intensity: f32,
+    // Skybox, transparency, bloom and tonemapping are skipped while one is shown
+    pub debug_view: DebugView,
+    // Frames per second, None renders as fast as the swapchain allows
+    pub frame_limit: Option<f32>,
+    // World AABBs of the draw calls and the light frustum, drawn with DebugDraw
+    pub debug_bounds: bool,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            gpu_driven: true,
+            exposure: 0.0,
+            tonemapping_operator: TonemappingOperator::Aces,
+            bloom: true,
+            bloom_intensity: 0.04,
+            bloom_threshold: 0.0,
+            bloom_knee: 0.5,
+            ssao: true,
+            ssao_radius: 0.5,
+            ssao_bias: 0.025,
+            ssao_power: 1.5,
+            anti_aliasing: AntiAliasing::Taa,
+            environment_intensity: 1.0,
+            debug_view: DebugView::None,
+            frame_limit: None,
+            debug_bounds: false,
+        }
+    }
+}
@@ -1,36 +1,80 @@
 use crate::buffer_manager::BufferManager;
-use crate::camera::Camera;
-use crate::draw_data::{DrawCall, DrawData, MeshData};
-use crate::frame_worker::FrameWorker;
+use crate::camera::{Camera, ProjectionMode};
+use crate::debug_draw::DebugDraw;
+use crate::debug_markers;
+use crate::debug_ui::DebugUiOutput;
+use crate::debug_ui_render_pass::{DebugUiDrawData, DebugUiMesh, DebugUiTextures};
+use crate::deferred_render_pass::DeferredRenderPass;
+use crate::device_selection::{self, SelectedDevice};
+use crate::draw_data::{AlphaMode, CullingStats, DrawCall, DrawData};
+use crate::frame_stats::{self, FrameLimiter, FrameStats, FrameStatsSummary};
+use crate::frame_worker::{FrameWorker, FrameWorkerResources};
+use crate::frustum::DepthRange;
+use crate::gpu_profiler::{self, ChromeTrace, GpuTimings, TimestampPeriod};
+use crate::image::Image;
+use crate::image_based_lighting::ImageBasedLighting;
+use crate::mesh_manager::MeshManager;
 use crate::patched_sphere::PatchedSphere;
 use crate::pipeline_manager::PipelineManager;
-use ash::ext::debug_utils;
+use crate::render_settings::{AntiAliasing, RenderSettings};
+use crate::renderer_config::RendererConfig;
+use crate::renderer_error::{RendererError, Result};
+use crate::ssao_render_pass::SsaoRenderPass;
+use crate::swapchain_config::{self, SelectedSwapchain};
+use crate::taa_render_pass::{self, taa_renderpass_consts};
+use crate::validation::{self, DebugMessenger, ValidationSettings};
+use ash::ext::{debug_utils, swapchain_colorspace};
 use ash::khr::{surface, swapchain};
 use ash::{vk, Device, Entry, Instance};
 use gpu_allocator::vulkan::{Allocator, AllocatorCreateDesc};
-use nalgebra::{Matrix4, RealField};
+use nalgebra::{Matrix4, Point3, Vector2, Vector3, Vector4};
 use raw_window_handle::{HasDisplayHandle, HasWindowHandle};
-use std::borrow::Cow;
+use std::env;
 use std::ffi::CStr;
+use std::path::PathBuf;
+use std::time::Instant;
 use winit::window::Window;
 
+// Equirectangular, a procedural sky is used when it's missing
+const ENVIRONMENT_MAP_PATH: &str = "assets/environment.hdr";
+
+// Camera movement between two frames that counts as a cut and drops the TAA history
+const CAMERA_CUT_DISTANCE: f32 = 2.0;
+// Cosine of the rotation angle, about 45 degrees
+const CAMERA_CUT_MIN_FORWARD_DOT: f32 = 0.7;
+
+// What TAA needs from the last rendered frame
+struct PreviousFrame {
+    view_projection: Matrix4<f32>,
+    // Indexed like the draw calls
+    models: Vec<Matrix4<f32>>,
+    camera_position: Point3<f32>,
+    camera_forward: Vector3<f32>,
+    projection_mode: ProjectionMode,
+    // Frame worker holding the resolved TAA output, None when TAA was disabled
+    taa_frame_worker: Option<usize>,
+}
+
 pub struct Renderer {
-    _entry: Entry,
-    _instance: Instance,
-    _debug_utils: debug_utils::Instance,
+    entry: Entry,
+    instance: Instance,
     device: Device,
     surface_loader: surface::Instance,
     swapchain_loader: swapchain::Device,
 
     surface: vk::SurfaceKHR,
-    _surface_format: vk::SurfaceFormatKHR,
+    surface_format: vk::SurfaceFormatKHR,
     swapchain: vk::SwapchainKHR,
     swapchain_images: Vec<vk::Image>,
     swapchain_image_views: Vec<vk::ImageView>,
+    // Reported by acquire or present, the swapchain still works but should be recreated
+    swapchain_suboptimal: bool,
 
-    _physical_device: vk::PhysicalDevice,
+    physical_device: vk::PhysicalDevice,
 
-    _debug_utils_messenger: vk::DebugUtilsMessengerEXT,
+    // Only when the instance supports debug utils
+    debug_messenger: Option<DebugMessenger>,
+    panic_on_validation_error: bool,
     allocator: std::mem::ManuallyDrop<Allocator>, // Could be Option?
 
     graphics_queue_family_index: u32,
@@ -39,77 +83,49 @@ pub struct Renderer {
     command_pool: vk::CommandPool,
 
     render_area: vk::Rect2D,
+    // Last applied, see `apply_config`
+    config: RendererConfig,
 
     camera: Camera,
-    sphere: PatchedSphere,
 
+    // One per swapchain image
     frame_workers: Vec<FrameWorker>,
+    timestamp_period: Option<TimestampPeriod>,
     pipeline_manager: PipelineManager,
 
     buffer_manager: BufferManager,
+    mesh_manager: MeshManager,
+    ssao_noise: Image,
+    masked_base_color: Image,
+    image_based_lighting: ImageBasedLighting,
+    debug_ui_textures: DebugUiTextures,
+
+    settings: RenderSettings,
+    culling_stats: CullingStats,
+    frame_stats: FrameStats,
+    frame_limiter: FrameLimiter,
+    gpu_timings: GpuTimings,
+    // Written on drop
+    gpu_trace: Option<(PathBuf, ChromeTrace)>,
+    // Cleared every frame
+    debug_draw: DebugDraw,
+    // Drawn over every following frame until the next update
+    debug_ui: DebugUiDrawData,
+
+    frame_index: u32,
+    previous_frame: Option<PreviousFrame>,
+    reset_history: bool,
 }
 
 impl Renderer {
-    unsafe extern "system" fn vulkan_debug_callback(
-        message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
-        message_type: vk::DebugUtilsMessageTypeFlagsEXT,
-        p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
-        _user_data: *mut std::os::raw::c_void,
-    ) -> vk::Bool32 {
-        let callback_data = *p_callback_data;
-        let message_id_number = callback_data.message_id_number;
-
-        let message_id_name = if callback_data.p_message_id_name.is_null() {
-            Cow::from("")
-        } else {
-            CStr::from_ptr(callback_data.p_message_id_name).to_string_lossy()
-        };
-
-        let message = if callback_data.p_message.is_null() {
-            Cow::from("")
-        } else {
-            CStr::from_ptr(callback_data.p_message).to_string_lossy()
-        };
-
-        println!(
-            "[{:?}] [{:?}] [{} ({})] : {}",
-            message_severity, message_type, message_id_name, message_id_number, message,
-        );
-
-        vk::FALSE
-    }
-
-    fn create_debug_utils(entry: &Entry, instance: &Instance) -> debug_utils::Instance {
-        debug_utils::Instance::new(entry, instance)
-    }
-
-    // TODO: static?
-    fn create_debug_info() -> vk::DebugUtilsMessengerCreateInfoEXT<'static> {
-        vk::DebugUtilsMessengerCreateInfoEXT::default()
-            .message_severity(
-                vk::DebugUtilsMessageSeverityFlagsEXT::ERROR
-                    | vk::DebugUtilsMessageSeverityFlagsEXT::WARNING, //| vk::DebugUtilsMessageSeverityFlagsEXT::INFO,
-            )
-            .message_type(
-                vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE
-                    | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION, //| vk::DebugUtilsMessageTypeFlagsEXT::GENERAL,
-            )
-            .pfn_user_callback(Some(Self::vulkan_debug_callback))
-    }
-
-    fn create_debug_utils_messenger(
-        debug_utils: &debug_utils::Instance,
-    ) -> vk::DebugUtilsMessengerEXT {
-        let debug_info = Self::create_debug_info();
-
-        unsafe {
-            debug_utils
-                .create_debug_utils_messenger(&debug_info, None)
-                .unwrap()
-        }
-    }
-
-    fn create_instance(entry: &Entry, window: &Window) -> Instance {
+    // The debug info also reports messages from instance creation and destruction
+    fn create_instance(
+        entry: &Entry,
+        window: &Window,
+        validation_layer: bool,
+        colorspace_extension: bool,
+        debug_info: Option<&mut vk::DebugUtilsMessengerCreateInfoEXT<'static>>,
+    ) -> Result<Instance> {
         let app_name = unsafe { CStr::from_bytes_with_nul_unchecked(b"VulkanTriangle\0") };
         let application_info = vk::ApplicationInfo::default()
             .application_version(0)
@@ -118,43 +134,49 @@ impl Renderer {
             .engine_version(0)
             .engine_name(app_name);
 
-        let layer_names = unsafe {
-            [CStr::from_bytes_with_nul_unchecked(b"VK_LAYER_KHRONOS_validation\0").as_ptr()]
-        };
-        let mut extension_names =
-            ash_window::enumerate_required_extensions(window.display_handle().unwrap().as_raw())
-                .unwrap()
-                .to_vec();
-        extension_names.push(debug_utils::NAME.as_ptr());
+        let mut layer_names = Vec::new();
+        if validation_layer {
+            layer_names.push(validation::VALIDATION_LAYER_NAME.as_ptr());
+        }
 
-        let mut debug_info = Self::create_debug_info();
+        let mut extension_names =
+            ash_window::enumerate_required_extensions(window.display_handle()?.as_raw())?.to_vec();
+        if debug_info.is_some() {
+            extension_names.push(debug_utils::NAME.as_ptr());
+        }
+        if colorspace_extension {
+            extension_names.push(swapchain_colorspace::NAME.as_ptr());
+        }
 
-        let instance_create_info = vk::InstanceCreateInfo::default()
+        let mut instance_create_info = vk::InstanceCreateInfo::default()
             .application_info(&application_info)
             .enabled_layer_names(&layer_names)
-            .enabled_extension_names(&extension_names)
-            .push_next(&mut debug_info);
+            .enabled_extension_names(&extension_names);
 
-        unsafe { entry.create_instance(&instance_create_info, None).unwrap() }
-    }
+        if let Some(debug_info) = debug_info {
+            instance_create_info = instance_create_info.push_next(debug_info);
+        }
 
-    fn select_physical_device(instance: &Instance) -> vk::PhysicalDevice {
-        let physical_devices = unsafe { instance.enumerate_physical_devices().unwrap() };
-        *physical_devices.first().unwrap()
+        Ok(unsafe { entry.create_instance(&instance_create_info, None)? })
     }
 
     fn create_device(
         instance: &Instance,
         physical_device: vk::PhysicalDevice,
         graphics_queue_family_index: u32,
-    ) -> Device {
+    ) -> Result<Device> {
         let device_queue_create_info = vk::DeviceQueueCreateInfo::default()
             .queue_family_index(graphics_queue_family_index)
             .queue_priorities(&[1.0_f32]);
 
-        // For gpu-allocator
-        let mut vulkan_12_features =
-            vk::PhysicalDeviceVulkan12Features::default().buffer_device_address(true);
+        // Culling writes the object index into firstInstance of the indirect draws
+        let mut features = vk::PhysicalDeviceFeatures2::default()
+            .features(vk::PhysicalDeviceFeatures::default().draw_indirect_first_instance(true));
+
+        // For gpu-allocator and indirect draws
+        let mut vulkan_12_features = vk::PhysicalDeviceVulkan12Features::default()
+            .buffer_device_address(true)
+            .draw_indirect_count(true);
 
         let mut vulkan_13_features = vk::PhysicalDeviceVulkan13Features::default()
             .dynamic_rendering(true)
@@ -165,64 +187,64 @@ impl Renderer {
         let enabled_extension_names = [swapchain::NAME.as_ptr()];
 
         let device_create_info = vk::DeviceCreateInfo::default()
+            .push_next(&mut features)
             .push_next(&mut vulkan_12_features)
             .push_next(&mut vulkan_13_features)
             .queue_create_infos(&device_queue_create_infos)
             .enabled_extension_names(&enabled_extension_names);
 
-        unsafe {
-            instance
-                .create_device(physical_device, &device_create_info, None)
-                .unwrap()
-        }
+        Ok(unsafe { instance.create_device(physical_device, &device_create_info, None)? })
     }
 
-    fn create_surface(entry: &Entry, instance: &Instance, window: &Window) -> vk::SurfaceKHR {
-        unsafe {
+    fn create_surface(
+        entry: &Entry,
+        instance: &Instance,
+        window: &Window,
+    ) -> Result<vk::SurfaceKHR> {
+        Ok(unsafe {
             ash_window::create_surface(
                 entry,
                 instance,
-                window.display_handle().unwrap().as_raw(),
-                window.window_handle().unwrap().as_raw(),
+                window.display_handle()?.as_raw(),
+                window.window_handle()?.as_raw(),
                 None,
-            )
-            .unwrap()
-        }
+            )?
+        })
     }
 
     fn create_swapchain(
         swapchain: &swapchain::Device,
         surface: vk::SurfaceKHR,
-        surface_format: vk::SurfaceFormatKHR,
+        selected: &SelectedSwapchain,
         surface_transform: vk::SurfaceTransformFlagsKHR,
         render_area: vk::Rect2D,
         queue_family_indices: &[u32],
-    ) -> vk::SwapchainKHR {
+    ) -> Result<vk::SwapchainKHR> {
         let create_info = vk::SwapchainCreateInfoKHR::default()
             .surface(surface)
-            .min_image_count(3)
-            .image_format(surface_format.format)
-            .image_color_space(surface_format.color_space)
+            .min_image_count(selected.image_count)
+            .image_format(selected.surface_format.format)
+            .image_color_space(selected.surface_format.color_space)
             .image_extent(render_area.extent)
             .image_array_layers(1)
             .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
             .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
             .queue_family_indices(queue_family_indices)
             .pre_transform(surface_transform) // TODO: from surface
-            .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
-            .present_mode(vk::PresentModeKHR::FIFO)
+            .composite_alpha(selected.composite_alpha)
+            .present_mode(selected.present_mode)
             .clipped(true)
             .old_swapchain(vk::SwapchainKHR::null());
 
-        unsafe { swapchain.create_swapchain(&create_info, None).unwrap() }
+        Ok(unsafe { swapchain.create_swapchain(&create_info, None)? })
     }
 
     fn create_allocator(
         instance: &Instance,
         device: &Device,
         physical_device: vk::PhysicalDevice,
-    ) -> Allocator {
-        Allocator::new(&AllocatorCreateDesc {
+    ) -> Result<Allocator> {
+        Ok(Allocator::new(&AllocatorCreateDesc {
             // TODO: clone?
             instance: instance.clone(),
             device: device.clone(),
@@ -230,84 +252,237 @@ impl Renderer {
             debug_settings: Default::default(),
             buffer_device_address: true,
             allocation_sizes: Default::default(),
-        })
-        .unwrap()
+        })?)
     }
 
-    fn get_graphics_queue_family_index(
-        queue_families: &[vk::QueueFamilyProperties],
-    ) -> Option<u32> {
-        // TODO: rewrite without for (find_map? need index)?
-        for (i, queue_family) in queue_families.iter().enumerate() {
-            if queue_family.queue_flags.contains(vk::QueueFlags::GRAPHICS) {
-                return Some(i as u32);
-            }
-        }
-
-        None
-    }
-
-    fn create_command_pool(device: &Device, graphics_queue_family_index: u32) -> vk::CommandPool {
+    fn create_command_pool(
+        device: &Device,
+        graphics_queue_family_index: u32,
+    ) -> Result<vk::CommandPool> {
         let command_pool_create_info =
             vk::CommandPoolCreateInfo::default().queue_family_index(graphics_queue_family_index);
-        unsafe {
-            device
-                .create_command_pool(&command_pool_create_info, None)
-                .unwrap()
-        }
+        Ok(unsafe { device.create_command_pool(&command_pool_create_info, None)? })
     }
 
-    pub fn new(window: &Window) -> Self {
-        let width = 1920;
-        let height = 1080;
-        let render_area =
-            vk::Rect2D::default().extent(vk::Extent2D::default().width(width).height(height));
+    pub fn new(window: &Window, config: &RendererConfig) -> Result<Self> {
+        let render_area = vk::Rect2D::default().extent(
+            vk::Extent2D::default()
+                .width(config.width)
+                .height(config.height),
+        );
 
         let entry = Entry::linked();
-        let instance = Self::create_instance(&entry, window);
-        let debug_utils = Self::create_debug_utils(&entry, &instance);
-        let debug_utils_messenger = Self::create_debug_utils_messenger(&debug_utils);
-        let physical_device = Self::select_physical_device(&instance);
-
-        let queue_families =
-            unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
-        let graphics_queue_family_index =
-            Self::get_graphics_queue_family_index(&queue_families).unwrap();
 
-        let device = Self::create_device(&instance, physical_device, graphics_queue_family_index);
+        let validation_settings = ValidationSettings::from_env();
+        let validation_layer =
+            validation_settings.enabled && validation::is_layer_available(&entry)?;
+        if validation_settings.enabled && !validation_layer {
+            log::warn!("Validation requested but the validation layer isn't installed");
+        }
+        log::info!("Validation layer: {}", validation_layer);
+
+        let debug_utils = validation::is_debug_utils_available(&entry)?;
+        let colorspace_extension = swapchain_config::is_colorspace_extension_available(&entry)?;
+        let mut debug_info = validation::create_debug_info();
+        let instance = Self::create_instance(
+            &entry,
+            window,
+            validation_layer,
+            colorspace_extension,
+            debug_utils.then_some(&mut debug_info),
+        )?;
+        let debug_messenger = if debug_utils {
+            Some(DebugMessenger::new(&entry, &instance)?)
+        } else {
+            None
+        };
 
         let surface_loader = surface::Instance::new(&entry, &instance);
+        let surface = Self::create_surface(&entry, &instance, window)?;
+
+        let device_preference = env::var(device_selection::DEVICE_ENV_VAR).ok();
+        let SelectedDevice {
+            physical_device,
+            queue_family_index: graphics_queue_family_index,
+        } = device_selection::select_physical_device(
+            &instance,
+            &surface_loader,
+            surface,
+            device_preference.as_deref(),
+        )?;
+
+        let device = Self::create_device(&instance, physical_device, graphics_queue_family_index)?;
+        // Object names and labels only need the instance extension, not the validation layer
+        if debug_utils {
+            debug_markers::init(&instance, &device);
+        }
         let swapchain_loader = swapchain::Device::new(&instance, &device);
 
-        let surface = Self::create_surface(&entry, &instance, window);
-        let surface_format = unsafe {
-            surface_loader
-                .get_physical_device_surface_formats(physical_device, surface)
-                .unwrap()[0]
-        };
+        let selected_swapchain = swapchain_config::select_swapchain(
+            &surface_loader,
+            physical_device,
+            surface,
+            &config.swapchain,
+        )?;
+        let surface_format = selected_swapchain.surface_format;
 
         let surface_capabilities = unsafe {
             surface_loader.get_physical_device_surface_capabilities(physical_device, surface)
-        }
-        .unwrap();
+        }?;
 
         let swapchain = Self::create_swapchain(
             &swapchain_loader,
             surface,
-            surface_format,
+            &selected_swapchain,
             surface_capabilities.current_transform,
             render_area,
             &[graphics_queue_family_index],
+        )?;
+
+        let swapchain_images = unsafe { swapchain_loader.get_swapchain_images(swapchain)? };
+        let swapchain_image_views =
+            Self::create_swapchain_image_views(&device, &swapchain_images, surface_format.format)?;
+
+        let mut allocator = Self::create_allocator(&instance, &device, physical_device)?;
+
+        let mut pipeline_manager =
+            PipelineManager::new(device.clone(), config, surface_format, render_area)?;
+
+        let graphics_queue = unsafe { device.get_device_queue(graphics_queue_family_index, 0) };
+        let command_pool = Self::create_command_pool(&device, graphics_queue_family_index)?;
+
+        let ssao_noise = SsaoRenderPass::create_noise_image(
+            &device,
+            &mut allocator,
+            graphics_queue,
+            command_pool,
+        )?;
+        let masked_base_color = DeferredRenderPass::create_masked_base_color_image(
+            &device,
+            &mut allocator,
+            graphics_queue,
+            command_pool,
+        )?;
+
+        let image_based_lighting = ImageBasedLighting::new(
+            &device,
+            &mut allocator,
+            &mut pipeline_manager,
+            graphics_queue,
+            command_pool,
+            ENVIRONMENT_MAP_PATH,
+        )?;
+
+        let timestamp_period = gpu_profiler::get_timestamp_period(
+            &instance,
+            physical_device,
+            graphics_queue_family_index,
+        );
+        if timestamp_period.is_none() {
+            log::warn!("The graphics queue doesn't support timestamps, GPU timings are disabled");
+        }
+
+        let mut buffer_manager = BufferManager::new(&device, command_pool);
+
+        let mut camera = Camera::new(
+            0.0,
+            0.0,
+            -5.0,
+            config.width as f32,
+            config.height as f32,
+            config.fov.to_radians(),
+            config.near,
+            config.far,
         );
+        camera.look_at(&Point3::new(0.0, 0.0, -5.0), &Point3::origin());
+
+        let sphere = PatchedSphere::new(config.sphere_subdivisions);
+
+        let mut mesh_manager = MeshManager::default();
+        mesh_manager.add_mesh(
+            "sphere",
+            &sphere.indices,
+            &sphere.positions,
+            &sphere.normals,
+        );
+        mesh_manager.upload(&mut buffer_manager, &mut allocator, graphics_queue)?;
+
+        // renderer->addBuffer("planeIndices", VK_BUFFER_USAGE_INDEX_BUFFER_BIT,
+        //         planeIndices.size() * sizeof(uint32_t), planeIndices.data());
+        // renderer->addBuffer("planeVertices", VK_BUFFER_USAGE_VERTEX_BUFFER_BIT,
+        //         planePositions.size() * sizeof(glm::vec3),
+        //         planePositions.data());
+        // renderer->addBuffer("planeNormals", VK_BUFFER_USAGE_VERTEX_BUFFER_BIT,
+        //         planeNormals.size() * sizeof(glm::vec3),
+        //         planeNormals.data());
+
+        let mut renderer = Self {
+            entry,
+            instance,
+            device,
+            surface_loader,
+            swapchain_loader,
+            surface,
+            surface_format,
+            swapchain,
+            swapchain_images,
+            swapchain_image_views,
+            swapchain_suboptimal: false,
+            physical_device,
+            debug_messenger,
+            panic_on_validation_error: validation_settings.panic_on_error,
+            allocator: std::mem::ManuallyDrop::new(allocator),
+            graphics_queue_family_index,
+            graphics_queue,
+            command_pool,
+            render_area,
+            config: config.clone(),
+            camera,
+            frame_workers: Vec::new(),
+            timestamp_period,
+            pipeline_manager,
+            buffer_manager,
+            mesh_manager,
+            ssao_noise,
+            masked_base_color,
+            image_based_lighting,
+            debug_ui_textures: DebugUiTextures::default(),
+            settings: RenderSettings {
+                frame_limit: frame_stats::get_frame_limit_from_env(),
+                ..Default::default()
+            },
+            culling_stats: CullingStats::default(),
+            frame_stats: FrameStats::default(),
+            frame_limiter: FrameLimiter::default(),
+            gpu_timings: GpuTimings::default(),
+            gpu_trace: env::var_os(gpu_profiler::GPU_TRACE_ENV_VAR)
+                .map(|path| (PathBuf::from(path), ChromeTrace::default())),
+            debug_draw: DebugDraw::default(),
+            debug_ui: DebugUiDrawData::default(),
+            frame_index: 0,
+            previous_frame: None,
+            reset_history: true,
+        };
+        renderer.create_frame_workers()?;
+
+        Ok(renderer)
+    }
 
-        let swapchain_images = unsafe { swapchain_loader.get_swapchain_images(swapchain).unwrap() };
-        let swapchain_image_views: Vec<vk::ImageView> = swapchain_images
+    fn create_swapchain_image_views(
+        device: &Device,
+        swapchain_images: &[vk::Image],
+        format: vk::Format,
+    ) -> Result<Vec<vk::ImageView>> {
+        swapchain_images
             .iter()
-            .map(|&image| {
+            .enumerate()
+            .map(|(index, &image)| {
+                debug_markers::set_object_name(image, &format!("swapchain_{}", index));
+
                 let image_view_create_info = vk::ImageViewCreateInfo::default()
                     .image(image)
                     .view_type(vk::ImageViewType::TYPE_2D)
-                    .format(surface_format.format)
+                    .format(format)
                     .components(
                         vk::ComponentMapping::default()
                             .r(vk::ComponentSwizzle::R)
@@ -324,127 +499,321 @@ impl Renderer {
                             .layer_count(1),
                     );
 
-                unsafe {
-                    device
-                        .create_image_view(&image_view_create_info, None)
-                        .unwrap()
-                }
-            })
-            .collect();
+                let image_view =
+                    unsafe { device.create_image_view(&image_view_create_info, None)? };
+                debug_markers::set_object_name(image_view, &format!("swapchain_{}", index));
 
-        let mut allocator = Self::create_allocator(&instance, &device, physical_device);
+                Ok(image_view)
+            })
+            .collect()
+    }
 
-        let mut pipeline_manager =
-            PipelineManager::new(device.clone(), surface_format.format, render_area);
+    fn create_frame_workers(&mut self) -> Result<()> {
+        self.pipeline_manager
+            .create_swapchain_descriptor_pool(self.swapchain_images.len() as u32)?;
+
+        let image_based_lighting_output = self.image_based_lighting.get_output();
+        let resources = FrameWorkerResources {
+            queue_family_index: self.graphics_queue_family_index,
+            render_area: &self.render_area,
+            ssao_noise: self.ssao_noise.image_view,
+            masked_base_color: self.masked_base_color.image_view,
+            image_based_lighting_output: &image_based_lighting_output,
+            timestamp_period: self.timestamp_period,
+        };
 
         // TODO: Remove device clone
-        let frame_workers = swapchain_images
+        self.frame_workers = self
+            .swapchain_images
             .iter()
-            .zip(swapchain_image_views.iter())
-            .map(|(&image, &image_view)| {
+            .zip(self.swapchain_image_views.iter())
+            .enumerate()
+            .map(|(index, (&image, &image_view))| {
                 FrameWorker::new(
-                    device.clone(),
-                    &mut allocator,
-                    &mut pipeline_manager,
+                    self.device.clone(),
+                    &mut self.allocator,
+                    &mut self.pipeline_manager,
+                    index,
                     image,
                     image_view,
-                    graphics_queue_family_index,
-                    &render_area,
+                    &resources,
                 )
             })
+            .collect::<Result<_>>()?;
+
+        Ok(())
+    }
+
+    // The caller waits for the device to be idle
+    fn destroy_swapchain(&mut self) {
+        for mut frame_worker in self.frame_workers.drain(..) {
+            frame_worker.destroy(&mut self.allocator);
+        }
+        self.pipeline_manager.destroy_swapchain_descriptor_pool();
+
+        unsafe {
+            for image_view in self.swapchain_image_views.drain(..) {
+                self.device.destroy_image_view(image_view, None);
+            }
+
+            self.swapchain_loader
+                .destroy_swapchain(self.swapchain, None);
+        }
+        self.swapchain = vk::SwapchainKHR::null();
+        self.swapchain_images.clear();
+    }
+
+    fn create_swapchain_and_workers(&mut self) -> Result<()> {
+        let selected_swapchain = swapchain_config::select_swapchain(
+            &self.surface_loader,
+            self.physical_device,
+            self.surface,
+            &self.config.swapchain,
+        )?;
+
+        // The pipelines are created for the original format
+        if selected_swapchain.surface_format != self.surface_format {
+            return Err(RendererError::Vulkan(
+                vk::Result::ERROR_FORMAT_NOT_SUPPORTED,
+            ));
+        }
+
+        let surface_capabilities = unsafe {
+            self.surface_loader
+                .get_physical_device_surface_capabilities(self.physical_device, self.surface)
+        }?;
+
+        self.swapchain = Self::create_swapchain(
+            &self.swapchain_loader,
+            self.surface,
+            &selected_swapchain,
+            surface_capabilities.current_transform,
+            self.render_area,
+            &[self.graphics_queue_family_index],
+        )?;
+        self.swapchain_images =
+            unsafe { self.swapchain_loader.get_swapchain_images(self.swapchain)? };
+        self.swapchain_image_views = Self::create_swapchain_image_views(
+            &self.device,
+            &self.swapchain_images,
+            self.surface_format.format,
+        )?;
+        self.swapchain_suboptimal = false;
+
+        // The render targets of the workers are kept per swapchain image
+        self.create_frame_workers()?;
+        self.reset_history();
+
+        Ok(())
+    }
+
+    // After `RendererError::OutOfDate` or when `is_swapchain_suboptimal`
+    pub fn recreate_swapchain(&mut self) -> Result<()> {
+        unsafe { self.device.device_wait_idle()? };
+
+        self.destroy_swapchain();
+        self.create_swapchain_and_workers()
+    }
+
+    // After `RendererError::SurfaceLost`, the window has to outlive the renderer
+    pub fn recreate_surface(&mut self, window: &Window) -> Result<()> {
+        unsafe { self.device.device_wait_idle()? };
+
+        self.destroy_swapchain();
+        unsafe { self.surface_loader.destroy_surface(self.surface, None) };
+        self.surface = vk::SurfaceKHR::null();
+
+        self.surface = Self::create_surface(&self.entry, &self.instance, window)?;
+        let supported = unsafe {
+            self.surface_loader.get_physical_device_surface_support(
+                self.physical_device,
+                self.graphics_queue_family_index,
+                self.surface,
+            )?
+        };
+        if !supported {
+            return Err(RendererError::SurfaceLost);
+        }
+
+        self.create_swapchain_and_workers()
+    }
+
+    pub fn is_swapchain_suboptimal(&self) -> bool {
+        self.swapchain_suboptimal
+    }
+
+    pub fn get_camera_mut(&mut self) -> &mut Camera {
+        &mut self.camera
+    }
+
+    // Applies what can change without recreating the renderer, warns about the rest
+    pub fn apply_config(&mut self, config: &RendererConfig) {
+        let restart_changes = config.get_restart_changes(&self.config);
+        if !restart_changes.is_empty() {
+            log::warn!(
+                "Changes to {} only apply after a restart",
+                restart_changes.join(", ")
+            );
+        }
+
+        self.camera
+            .set_perspective(config.fov.to_radians(), config.near, config.far);
+        self.reset_history();
+
+        let applied = self.config.with_runtime_changes(config);
+        // Recreated by the caller after the next frame, like a suboptimal swapchain
+        self.swapchain_suboptimal |= applied.swapchain != self.config.swapchain;
+        self.config = applied;
+    }
+
+    // Applied from the next rendered frame
+    pub fn get_settings_mut(&mut self) -> &mut RenderSettings {
+        &mut self.settings
+    }
+
+    // Of the last rendered frame
+    pub fn get_culling_stats(&self) -> CullingStats {
+        self.culling_stats
+    }
+
+    // Timings over the last few seconds and counts of the last frame
+    pub fn get_frame_stats(&self) -> FrameStatsSummary {
+        self.frame_stats.get_summary()
+    }
+
+    // Per pass, of the latest frame whose timestamps have been read back. That frame is a few
+    // frames behind the one being rendered, see `GpuTimings::frame_index`
+    pub fn get_gpu_timings(&self) -> &GpuTimings {
+        &self.gpu_timings
+    }
+
+    // Shapes added before a frame is rendered are drawn in that frame only
+    pub fn get_debug_draw_mut(&mut self) -> &mut DebugDraw {
+        &mut self.debug_draw
+    }
+
+    // Uploads the textures egui changed and keeps the meshes for the following frames
+    pub fn update_debug_ui(&mut self, output: DebugUiOutput) -> Result<()> {
+        self.debug_ui_textures.update(
+            &self.device,
+            &mut self.allocator,
+            &mut self.pipeline_manager,
+            self.graphics_queue,
+            self.command_pool,
+            &output.textures_delta,
+        )?;
+
+        let meshes = output
+            .primitives
+            .into_iter()
+            .filter_map(|primitive| {
+                // Paint callbacks aren't supported
+                let egui::epaint::Primitive::Mesh(mesh) = primitive.primitive else {
+                    return None;
+                };
+                let set = self.debug_ui_textures.get_set(mesh.texture_id)?;
+
+                Some(DebugUiMesh {
+                    clip_rect: primitive.clip_rect,
+                    vertices: mesh.vertices,
+                    indices: mesh.indices,
+                    set,
+                })
+            })
             .collect();
 
-        let graphics_queue = unsafe { device.get_device_queue(graphics_queue_family_index, 0) };
-        let command_pool = Self::create_command_pool(&device, graphics_queue_family_index);
+        self.debug_ui = DebugUiDrawData {
+            meshes,
+            pixels_per_point: output.pixels_per_point,
+        };
 
-        let mut buffer_manager = BufferManager::new(&device, command_pool);
+        Ok(())
+    }
 
-        let sphere = PatchedSphere::new(3);
+    // Drops the TAA history on the next frame, for example after teleporting the camera
+    pub fn reset_history(&mut self) {
+        self.reset_history = true;
+    }
 
-        buffer_manager.add_buffer(
-            "sphereIndices",
-            &mut allocator,
-            graphics_queue,
-            &sphere.indices,
-            vk::BufferUsageFlags::INDEX_BUFFER,
-        );
+    fn is_camera_cut(&self, previous_frame: &PreviousFrame) -> bool {
+        let distance = (self.camera.get_position() - previous_frame.camera_position).norm();
+        let forward_dot = self
+            .camera
+            .get_forward()
+            .dot(&previous_frame.camera_forward);
 
-        buffer_manager.add_buffer(
-            "sphereVertices",
-            &mut allocator,
-            graphics_queue,
-            &sphere.positions,
-            vk::BufferUsageFlags::VERTEX_BUFFER,
-        );
+        self.camera.get_projection_mode() != previous_frame.projection_mode
+            || distance > CAMERA_CUT_DISTANCE
+            || forward_dot < CAMERA_CUT_MIN_FORWARD_DOT
+    }
 
-        buffer_manager.add_buffer(
-            "sphereNormals",
-            &mut allocator,
-            graphics_queue,
-            &sphere.normals,
-            vk::BufferUsageFlags::VERTEX_BUFFER,
-        );
+    // Halton(2, 3) offsets in [-0.5, 0.5] pixels, none without TAA
+    fn update_jitter(&mut self) {
+        let jitter = if self.settings.anti_aliasing == AntiAliasing::Taa {
+            let index = self.frame_index % taa_renderpass_consts::JITTER_SEQUENCE_LENGTH + 1;
+            Vector2::new(
+                taa_render_pass::halton(index, 2) - 0.5,
+                taa_render_pass::halton(index, 3) - 0.5,
+            )
+        } else {
+            Vector2::zeros()
+        };
 
-        // renderer->addBuffer("planeIndices", VK_BUFFER_USAGE_INDEX_BUFFER_BIT,
-        //         planeIndices.size() * sizeof(uint32_t), planeIndices.data());
-        // renderer->addBuffer("planeVertices", VK_BUFFER_USAGE_VERTEX_BUFFER_BIT,
-        //         planePositions.size() * sizeof(glm::vec3),
-        //         planePositions.data());
-        // renderer->addBuffer("planeNormals", VK_BUFFER_USAGE_VERTEX_BUFFER_BIT,
-        //         planeNormals.size() * sizeof(glm::vec3),
-        //         planeNormals.data());
+        self.camera.set_jitter(jitter);
+    }
 
-        Self {
-            _entry: entry,
-            _instance: instance,
-            _debug_utils: debug_utils,
-            device,
-            surface_loader,
-            swapchain_loader,
-            surface,
-            _surface_format: surface_format,
-            swapchain,
-            swapchain_images,
-            swapchain_image_views,
-            _physical_device: physical_device,
-            _debug_utils_messenger: debug_utils_messenger,
-            allocator: std::mem::ManuallyDrop::new(allocator),
-            graphics_queue_family_index,
-            graphics_queue,
-            command_pool,
-            render_area,
-            camera: Camera::new(
-                0.0,
-                0.0,
-                0.0,
-                width as f32,
-                height as f32,
-                f32::pi() / 2.0,
-                0.1,
-                100.0,
-            ),
-            sphere,
-            frame_workers,
-            pipeline_manager,
-            buffer_manager,
+    fn draw_debug_bounds(&mut self, draw_data: &DrawData) {
+        let aabb_color = Vector4::new(0.0, 1.0, 0.0, 1.0);
+        let sphere_color = Vector4::new(0.0, 0.5, 1.0, 1.0);
+        for draw_call in &draw_data.draw_calls {
+            let bounding_sphere = draw_call.get_world_bounding_sphere();
+
+            self.debug_draw
+                .aabb(&draw_call.get_world_aabb(), &aabb_color);
+            self.debug_draw.sphere(
+                &bounding_sphere.center,
+                bounding_sphere.radius,
+                &sphere_color,
+            );
         }
+
+        // Visible through the scene, the light usually sits behind geometry
+        let light = &draw_data.directional_light;
+        let light_color = Vector4::new(1.0, 1.0, 0.0, 1.0);
+        self.debug_draw.set_depth_test(false);
+        self.debug_draw.frustum(
+            &(light.get_projection() * light.get_view()),
+            DepthRange::NegativeOneToOne,
+            &light_color,
+        );
+        self.debug_draw
+            .arrow(&light.get_position(), &light.get_target(), &light_color);
+        self.debug_draw.set_depth_test(true);
     }
 
     // TODO: Some helper library
-    fn create_semaphore(&self) -> vk::Semaphore {
+    fn create_semaphore(&self) -> Result<vk::Semaphore> {
         let create_info = vk::SemaphoreCreateInfo::default();
 
-        unsafe { self.device.create_semaphore(&create_info, None).unwrap() }
+        Ok(unsafe { self.device.create_semaphore(&create_info, None)? })
     }
 
-    pub fn render(&mut self) {
+    pub fn render(&mut self) -> Result<()> {
+        self.frame_limiter.wait(self.settings.frame_limit);
+        let frame_start = Instant::now();
+
         let present_fence = unsafe {
             self.device
-                .create_fence(&vk::FenceCreateInfo::default(), None)
-                .unwrap()
+                .create_fence(&vk::FenceCreateInfo::default(), None)?
         };
 
-        let acquire_image_semaphore = self.create_semaphore();
+        let acquire_image_semaphore = match self.create_semaphore() {
+            Ok(semaphore) => semaphore,
+            Err(error) => {
+                unsafe { self.device.destroy_fence(present_fence, None) };
+                return Err(error);
+            }
+        };
 
         let acquire_image_result = unsafe {
             self.swapchain_loader.acquire_next_image(
@@ -455,36 +824,95 @@ impl Renderer {
             )
         };
 
-        // match acquire_image_result {
-        //     Ok((image_index, b)) => {
-        //         println!("{}: {}", image_index, b);
-        //     }
-        //     Err(error) => {
-        //         println!("{}", error);
-        //     }
-        // }
-
-        let (next_image, _) = acquire_image_result.expect("Acquiring next image failed");
+        let (next_image, suboptimal) = match acquire_image_result {
+            Ok(result) => result,
+            Err(error) => {
+                // Neither is signaled when acquiring fails
+                unsafe {
+                    self.device.destroy_fence(present_fence, None);
+                    self.device.destroy_semaphore(acquire_image_semaphore, None);
+                }
+                return Err(error.into());
+            }
+        };
+        self.swapchain_suboptimal |= suboptimal;
 
-        let mut draw_data =
-            DrawData::new(&self.camera, self.pipeline_manager.deferred_pipeline_layout);
+        self.update_jitter();
 
-        let pipeline = self.pipeline_manager.deferred_pipeline;
+        let previous_frame = self.previous_frame.take().filter(|previous_frame| {
+            !self.reset_history
+                && self.settings.anti_aliasing == AntiAliasing::Taa
+                && !self.is_camera_cut(previous_frame)
+        });
 
-        let sphere_mesh = MeshData::new(
-            self.sphere.indices.len() as u32,
-            self.buffer_manager.get_buffer("sphereIndices").buffer,
-            self.buffer_manager.get_buffer("sphereVertices").buffer,
-            self.buffer_manager.get_buffer("sphereNormals").buffer,
+        let mut draw_data = DrawData::new(
+            &self.camera,
+            self.mesh_manager.get_geometry_buffers(),
+            &self.settings,
         );
 
+        if let Some(previous_frame) = &previous_frame {
+            draw_data.previous_view_projection = previous_frame.view_projection;
+            draw_data.previous_taa_output = previous_frame
+                .taa_frame_worker
+                .and_then(|index| self.frame_workers.get(index))
+                .map(|frame_worker| frame_worker.get_taa_output().color.image);
+        }
+
+        let pipeline = self.pipeline_manager.deferred_material.pipeline;
+
+        let sphere_mesh = self.mesh_manager.get_mesh("sphere");
+
         let model = Matrix4::identity().append_translation(&nalgebra::Vector3::new(0.0, 0.0, 0.0));
 
-        let sphere_draw_call = DrawCall::new(&sphere_mesh, model, pipeline);
-        draw_data.add_draw_call(sphere_draw_call);
+        // In front of the opaque sphere, drawn by the forward transparency pass
+        let glass_model =
+            Matrix4::new_scaling(0.6).append_translation(&nalgebra::Vector3::new(1.5, 0.0, -1.5));
+        let mut glass_draw_call = DrawCall::new(sphere_mesh, glass_model, pipeline);
+        glass_draw_call.base_color = Vector4::new(0.2, 0.4, 1.0, 0.4);
+        glass_draw_call.alpha_mode = AlphaMode::Blend;
+        glass_draw_call.cast_shadows = false;
+
+        // Alpha tested against the holes of its base color texture
+        let masked_model =
+            Matrix4::new_scaling(0.6).append_translation(&nalgebra::Vector3::new(-1.5, 0.0, -1.5));
+        let mut masked_draw_call = DrawCall::new(
+            sphere_mesh,
+            masked_model,
+            self.pipeline_manager.get_deferred_masked_pipeline(),
+        );
+        masked_draw_call.base_color = Vector4::new(0.2, 1.0, 0.4, 0.8);
+        masked_draw_call.alpha_mode = AlphaMode::Mask(0.5);
+
+        let draw_calls = [
+            DrawCall::new(sphere_mesh, model, pipeline),
+            glass_draw_call,
+            masked_draw_call,
+        ];
+
+        let models = draw_calls.iter().map(|draw_call| draw_call.model).collect();
+
+        for (index, mut draw_call) in draw_calls.into_iter().enumerate() {
+            if let Some(previous_model) = previous_frame
+                .as_ref()
+                .and_then(|previous_frame| previous_frame.models.get(index))
+            {
+                draw_call.previous_model = *previous_model;
+            }
+
+            draw_data.add_draw_call(draw_call);
+        }
+
+        self.culling_stats = draw_data.get_culling_stats();
+        draw_data.frame_index = self.frame_index;
+        if self.settings.debug_bounds {
+            self.draw_debug_bounds(&draw_data);
+        }
+        draw_data.debug_draw = std::mem::take(&mut self.debug_draw);
+        draw_data.debug_ui = self.debug_ui.clone();
 
         if let Some(frame_worker) = self.frame_workers.get_mut(next_image as usize) {
-            frame_worker.draw(
+            let draw_result = frame_worker.draw(
                 &self.swapchain_loader,
                 self.swapchain,
                 present_fence,
@@ -493,33 +921,104 @@ impl Renderer {
                 self.graphics_queue,
                 &draw_data,
             );
+
+            let (gpu_timings, suboptimal) = match draw_result {
+                Ok(result) => result,
+                Err(error) => {
+                    // The acquire still signals the fence, it can't be destroyed while pending.
+                    // Waiting fails right away when the device is lost
+                    unsafe {
+                        let _ = self
+                            .device
+                            .wait_for_fences(&[present_fence], true, u64::MAX);
+                        self.device.destroy_fence(present_fence, None);
+                    }
+                    return Err(error);
+                }
+            };
+            self.swapchain_suboptimal |= suboptimal;
+
+            if let Some(gpu_timings) = gpu_timings {
+                if let Some((_, gpu_trace)) = &mut self.gpu_trace {
+                    gpu_trace.add_frame(&gpu_timings);
+                }
+                self.frame_stats
+                    .set_gpu_ms(gpu_timings.get_total_ms() as f32);
+                self.gpu_timings = gpu_timings;
+            }
+        }
+
+        if self.panic_on_validation_error {
+            if let Some(message) = validation::take_error() {
+                panic!("Vulkan validation error: {}", message);
+            }
         }
 
         unsafe { self.device.destroy_fence(present_fence, None) };
+
+        self.previous_frame = Some(PreviousFrame {
+            view_projection: draw_data.get_unjittered_view_projection(),
+            models,
+            camera_position: self.camera.get_position(),
+            camera_forward: self.camera.get_forward(),
+            projection_mode: self.camera.get_projection_mode(),
+            taa_frame_worker: (self.settings.anti_aliasing == AntiAliasing::Taa)
+                .then_some(next_image as usize),
+        });
+        self.reset_history = false;
+        self.frame_index = self.frame_index.wrapping_add(1);
+
+        self.frame_stats.record_frame(
+            frame_start,
+            self.culling_stats.visible,
+            draw_data.get_triangle_count(),
+        );
+
+        Ok(())
     }
 }
 
 impl Drop for Renderer {
     fn drop(&mut self) {
-        println!("{:?}", &self.allocator);
+        log::debug!("{:?}", &self.allocator);
 
-        unsafe {
-            for &image_view in self.swapchain_image_views.iter() {
-                self.device.destroy_image_view(image_view, None);
+        if let Some((path, gpu_trace)) = &self.gpu_trace {
+            match gpu_trace.write(path) {
+                Ok(()) => log::info!("Wrote GPU trace to {}", path.display()),
+                Err(error) => {
+                    log::error!("Failed to write GPU trace {}: {}", path.display(), error)
+                }
             }
+        }
 
-            self.device.destroy_command_pool(self.command_pool, None);
+        // Frames in flight may still use any of the resources
+        if let Err(error) = unsafe { self.device.device_wait_idle() } {
+            log::error!("Waiting for the device failed: {}", error);
+        }
 
-            self.swapchain_loader
-                .destroy_swapchain(self.swapchain, None);
+        self.destroy_swapchain();
+
+        self.debug_ui_textures
+            .destroy(&self.device, &mut self.allocator);
+        self.image_based_lighting.destroy(&mut self.allocator);
+        self.ssao_noise.destroy(&self.device, &mut self.allocator);
+        self.masked_base_color
+            .destroy(&self.device, &mut self.allocator);
+        self.buffer_manager.destroy(&mut self.allocator);
+        self.pipeline_manager.destroy();
+
+        unsafe {
+            self.device.destroy_command_pool(self.command_pool, None);
             self.surface_loader.destroy_surface(self.surface, None);
 
             std::mem::ManuallyDrop::drop(&mut self.allocator);
 
+            debug_markers::shutdown();
             self.device.destroy_device(None);
-            self._debug_utils
-                .destroy_debug_utils_messenger(self._debug_utils_messenger, None);
-            self._instance.destroy_instance(None);
+            if let Some(debug_messenger) = &mut self.debug_messenger {
+                debug_messenger.destroy();
+            }
+            self.instance.destroy_instance(None);
         }
     }
 }
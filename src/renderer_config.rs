@@ -0,0 +1,416 @@
+use std::{fs, io, path::PathBuf};
+
+use serde::Deserialize;
+
+use crate::{
+    renderer_error::{RendererError, Result},
+    swapchain_config::SwapchainConfig,
+};
+
+// Loaded when it exists, `--config` points to another one
+pub const DEFAULT_CONFIG_PATH: &str = "sr-engine.toml";
+
+const USAGE: &str = "sr-engine [--config <path>] [key=value]...";
+
+// Chosen when the renderer is created, see RenderSettings for what can change between frames.
+// `Renderer::apply_config` picks up the camera fields and recreates the swapchain for vsync and
+// image_count, the others need a restart
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RendererConfig {
+    // Of the window and every render target
+    pub width: u32,
+    pub height: u32,
+    // Vertical, in degrees
+    pub fov: f32,
+    pub near: f32,
+    // Unused by the infinite reverse Z projection
+    pub far: f32,
+    pub shadow_map_resolution: u32,
+    // Of the sphere mesh, per cube face edge
+    pub sphere_subdivisions: u32,
+    pub swapchain: SwapchainConfig,
+}
+
+impl Default for RendererConfig {
+    fn default() -> Self {
+        Self {
+            width: 1920,
+            height: 1080,
+            fov: 90.0,
+            near: 0.1,
+            far: 100.0,
+            shadow_map_resolution: 2048,
+            sphere_subdivisions: 3,
+            swapchain: SwapchainConfig::default(),
+        }
+    }
+}
+
+// Where the config is read from, kept to reload it while running
+#[derive(Clone, Debug)]
+pub struct ConfigSource {
+    pub path: PathBuf,
+    // The default file is optional, one passed on the command line isn't
+    pub required: bool,
+    // `key=value` in TOML syntax, dotted keys reach into tables, e.g. `swapchain.vsync=false`
+    pub overrides: Vec<String>,
+}
+
+impl Default for ConfigSource {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from(DEFAULT_CONFIG_PATH),
+            required: false,
+            overrides: Vec::new(),
+        }
+    }
+}
+
+impl ConfigSource {
+    // Without the program name
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        let mut source = Self::default();
+
+        while let Some(arg) = args.next() {
+            if arg == "--config" {
+                let path = args.next().ok_or_else(|| {
+                    RendererError::Config(format!("--config expects a path, usage: {}", USAGE))
+                })?;
+                source.path = PathBuf::from(path);
+                source.required = true;
+            } else if arg.contains('=') && !arg.starts_with('-') {
+                source.overrides.push(arg);
+            } else {
+                return Err(RendererError::Config(format!(
+                    "Unexpected argument {}, usage: {}",
+                    arg, USAGE
+                )));
+            }
+        }
+
+        Ok(source)
+    }
+}
+
+impl RendererConfig {
+    // The defaults, overridden by the file, overridden by the command line
+    pub fn load(source: &ConfigSource) -> Result<Self> {
+        let config_error = |message: String| {
+            RendererError::Config(format!("{}: {}", source.path.display(), message))
+        };
+
+        let mut table = match fs::read_to_string(&source.path) {
+            Ok(text) => text
+                .parse::<toml::Table>()
+                .map_err(|error| config_error(error.to_string()))?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound && !source.required => {
+                toml::Table::new()
+            }
+            Err(error) => return Err(config_error(error.to_string())),
+        };
+
+        for config_override in &source.overrides {
+            Self::apply_override(&mut table, config_override)?;
+        }
+
+        let config: Self = table
+            .try_into()
+            .map_err(|error: toml::de::Error| config_error(error.message().to_string()))?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn apply_override(table: &mut toml::Table, config_override: &str) -> Result<()> {
+        let invalid = || RendererError::Config(format!("Invalid override {}", config_override));
+
+        let (key, value) = config_override.split_once('=').ok_or_else(invalid)?;
+        let value = value.trim();
+
+        // Strings don't need quotes on the command line
+        let value = format!("value = {}", value)
+            .parse::<toml::Table>()
+            .ok()
+            .and_then(|mut parsed| parsed.remove("value"))
+            .unwrap_or_else(|| toml::Value::String(value.to_string()));
+
+        let mut keys = key.trim().split('.').peekable();
+        let mut table = table;
+
+        while let Some(key) = keys.next() {
+            if keys.peek().is_none() {
+                table.insert(key.to_string(), value);
+                break;
+            }
+
+            table = table
+                .entry(key)
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+                .as_table_mut()
+                .ok_or_else(invalid)?;
+        }
+
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<()> {
+        let checks = [
+            (
+                self.width > 0 && self.height > 0,
+                "width and height must be positive",
+            ),
+            (
+                self.fov > 0.0 && self.fov < 180.0,
+                "fov must be between 0 and 180 degrees",
+            ),
+            (
+                self.near > 0.0 && self.far > self.near,
+                "near must be positive and below far",
+            ),
+            (
+                self.shadow_map_resolution > 0,
+                "shadow_map_resolution must be positive",
+            ),
+        ];
+
+        if let Some((_, message)) = checks.iter().find(|(valid, _)| !valid) {
+            return Err(RendererError::Config(message.to_string()));
+        }
+
+        Ok(())
+    }
+
+    // Names of the fields that only take effect when the renderer is created
+    pub fn get_restart_changes(&self, other: &Self) -> Vec<&'static str> {
+        [
+            ("width", self.width != other.width),
+            ("height", self.height != other.height),
+            (
+                "shadow_map_resolution",
+                self.shadow_map_resolution != other.shadow_map_resolution,
+            ),
+            (
+                "sphere_subdivisions",
+                self.sphere_subdivisions != other.sphere_subdivisions,
+            ),
+            (
+                "swapchain.format",
+                self.swapchain.format != other.swapchain.format,
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(name, changed)| changed.then_some(name))
+        .collect()
+    }
+
+    // This config with the fields of `other` that can change while running, the rest is
+    // reported by `get_restart_changes`
+    pub fn with_runtime_changes(&self, other: &Self) -> Self {
+        Self {
+            fov: other.fov,
+            near: other.near,
+            far: other.far,
+            swapchain: SwapchainConfig {
+                vsync: other.swapchain.vsync,
+                image_count: other.swapchain.image_count,
+                ..self.swapchain
+            },
+            ..self.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+    use crate::swapchain_config::SwapchainFormat;
+
+    fn get_temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("sr-engine-{}-{}.toml", process::id(), name))
+    }
+
+    fn load_overrides(overrides: &[&str]) -> Result<RendererConfig> {
+        RendererConfig::load(&ConfigSource {
+            path: get_temp_path("missing"),
+            required: false,
+            overrides: overrides.iter().map(|arg| arg.to_string()).collect(),
+        })
+    }
+
+    #[test]
+    fn from_args() {
+        let args = ["--config", "custom.toml", "fov=60", "swapchain.vsync=false"];
+        let source = ConfigSource::from_args(args.into_iter().map(String::from)).unwrap();
+
+        assert_eq!(source.path, PathBuf::from("custom.toml"));
+        assert!(source.required);
+        assert_eq!(source.overrides, ["fov=60", "swapchain.vsync=false"]);
+
+        assert!(ConfigSource::from_args(["--config"].into_iter().map(String::from)).is_err());
+        assert!(ConfigSource::from_args(["--fov=60"].into_iter().map(String::from)).is_err());
+        assert!(ConfigSource::from_args(["fov"].into_iter().map(String::from)).is_err());
+    }
+
+    #[test]
+    fn missing_default_file_loads_the_defaults() {
+        let config = load_overrides(&[]).unwrap();
+
+        assert_eq!(config.width, RendererConfig::default().width);
+        assert_eq!(config.swapchain, SwapchainConfig::default());
+    }
+
+    #[test]
+    fn missing_required_file_is_an_error() {
+        let source = ConfigSource {
+            path: get_temp_path("required"),
+            required: true,
+            overrides: Vec::new(),
+        };
+
+        assert!(matches!(
+            RendererConfig::load(&source),
+            Err(RendererError::Config(_))
+        ));
+    }
+
+    #[test]
+    fn overrides_reach_into_tables() {
+        let config = load_overrides(&[
+            "fov=60",
+            "shadow_map_resolution = 1024",
+            "swapchain.vsync=false",
+            // Strings don't need quotes
+            "swapchain.format=hdr10",
+        ])
+        .unwrap();
+
+        assert_eq!(config.fov, 60.0);
+        assert_eq!(config.shadow_map_resolution, 1024);
+        assert!(!config.swapchain.vsync);
+        assert_eq!(config.swapchain.format, SwapchainFormat::Hdr10);
+        // Untouched fields of the table keep their defaults
+        assert_eq!(
+            config.swapchain.image_count,
+            SwapchainConfig::default().image_count
+        );
+    }
+
+    #[test]
+    fn overrides_win_over_the_file() {
+        let path = get_temp_path("file");
+        fs::write(
+            &path,
+            "width = 1280\nheight = 720\n\n[swapchain]\nimage_count = 2\n",
+        )
+        .unwrap();
+
+        let config = RendererConfig::load(&ConfigSource {
+            path: path.clone(),
+            required: true,
+            overrides: vec!["width=640".to_string()],
+        });
+        fs::remove_file(&path).unwrap();
+        let config = config.unwrap();
+
+        assert_eq!(config.width, 640);
+        assert_eq!(config.height, 720);
+        assert_eq!(config.swapchain.image_count, 2);
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        for config_override in ["unknown=1", "swapchain.unknown=1", "camera.fov=60"] {
+            assert!(
+                matches!(
+                    load_overrides(&[config_override]),
+                    Err(RendererError::Config(_))
+                ),
+                "{} was accepted",
+                config_override
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_overrides_are_rejected() {
+        for config_override in [
+            // Not a table
+            "fov.degrees=60",
+            "fov=wide",
+            "fov=180",
+            "near=0",
+            "width=0",
+            "swapchain.format=hdr",
+            // A quoted number stays a string
+            "swapchain.image_count=\"2\"",
+        ] {
+            assert!(
+                matches!(
+                    load_overrides(&[config_override]),
+                    Err(RendererError::Config(_))
+                ),
+                "{} was accepted",
+                config_override
+            );
+        }
+    }
+
+    #[test]
+    fn restart_changes() {
+        let config = RendererConfig::default();
+
+        let mut camera_only = config.clone();
+        camera_only.fov = 60.0;
+        camera_only.near = 0.5;
+        camera_only.far = 50.0;
+        assert!(config.get_restart_changes(&camera_only).is_empty());
+
+        let mut swapchain = camera_only.clone();
+        swapchain.swapchain.vsync = false;
+        swapchain.swapchain.image_count = 2;
+        assert!(config.get_restart_changes(&swapchain).is_empty());
+
+        let mut restart = swapchain.clone();
+        restart.height = 720;
+        restart.sphere_subdivisions = 5;
+        restart.swapchain.format = SwapchainFormat::Hdr10;
+        assert_eq!(
+            config.get_restart_changes(&restart),
+            ["height", "sphere_subdivisions", "swapchain.format"]
+        );
+    }
+
+    #[test]
+    fn runtime_changes() {
+        let config = RendererConfig::default();
+
+        let mut changed = config.clone();
+        changed.fov = 60.0;
+        changed.near = 0.5;
+        changed.far = 50.0;
+        changed.width = 1280;
+        changed.shadow_map_resolution = 1024;
+        changed.swapchain.vsync = false;
+        changed.swapchain.image_count = 2;
+        changed.swapchain.format = SwapchainFormat::ScRgb;
+
+        let applied = config.with_runtime_changes(&changed);
+        assert_eq!(applied.fov, 60.0);
+        assert_eq!(applied.near, 0.5);
+        assert_eq!(applied.far, 50.0);
+        assert!(!applied.swapchain.vsync);
+        assert_eq!(applied.swapchain.image_count, 2);
+
+        // Every field is either applied or reported
+        assert_eq!(
+            applied.get_restart_changes(&changed),
+            ["width", "shadow_map_resolution", "swapchain.format"]
+        );
+        assert_eq!(applied.width, config.width);
+        assert_eq!(applied.shadow_map_resolution, config.shadow_map_resolution);
+        assert_eq!(applied.swapchain.format, config.swapchain.format);
+    }
+}
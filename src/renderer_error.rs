@@ -0,0 +1,87 @@
+use std::{fmt, io};
+
+use ash::vk;
+use gpu_allocator::AllocationError;
+
+pub type Result<T> = std::result::Result<T, RendererError>;
+
+// Everything the renderer can fail with. Device and surface loss are split out from the
+// remaining Vulkan errors because they are the ones an application can recover from
+#[derive(Debug)]
+pub enum RendererError {
+    DeviceLost,
+    SurfaceLost,
+    // The swapchain no longer matches the surface, it has to be recreated
+    OutOfDate,
+    // Host, device or allocator memory
+    OutOfMemory,
+    MissingShader { path: String, source: io::Error },
+    ShaderCompilation { name: String, message: String },
+    NoSuitableDevice,
+    Window(String),
+    // Invalid config file or command line
+    Config(String),
+    Vulkan(vk::Result),
+    Allocation(AllocationError),
+}
+
+impl fmt::Display for RendererError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RendererError::DeviceLost => write!(f, "Vulkan device lost"),
+            RendererError::SurfaceLost => write!(f, "Window surface lost"),
+            RendererError::OutOfDate => write!(f, "Swapchain out of date"),
+            RendererError::OutOfMemory => write!(f, "Out of memory"),
+            RendererError::MissingShader { path, source } => {
+                write!(f, "Missing shader {}: {}", path, source)
+            }
+            RendererError::ShaderCompilation { name, message } => {
+                write!(f, "Invalid shader {}: {}", name, message)
+            }
+            RendererError::NoSuitableDevice => write!(f, "No suitable Vulkan device found"),
+            RendererError::Window(message) => write!(f, "Window error: {}", message),
+            RendererError::Config(message) => write!(f, "Config error: {}", message),
+            RendererError::Vulkan(result) => write!(f, "Vulkan error: {}", result),
+            RendererError::Allocation(error) => write!(f, "Allocation error: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for RendererError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RendererError::MissingShader { source, .. } => Some(source),
+            RendererError::Allocation(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<vk::Result> for RendererError {
+    fn from(result: vk::Result) -> Self {
+        match result {
+            vk::Result::ERROR_DEVICE_LOST => RendererError::DeviceLost,
+            vk::Result::ERROR_SURFACE_LOST_KHR => RendererError::SurfaceLost,
+            vk::Result::ERROR_OUT_OF_DATE_KHR => RendererError::OutOfDate,
+            vk::Result::ERROR_OUT_OF_HOST_MEMORY | vk::Result::ERROR_OUT_OF_DEVICE_MEMORY => {
+                RendererError::OutOfMemory
+            }
+            result => RendererError::Vulkan(result),
+        }
+    }
+}
+
+impl From<AllocationError> for RendererError {
+    fn from(error: AllocationError) -> Self {
+        match error {
+            AllocationError::OutOfMemory => RendererError::OutOfMemory,
+            error => RendererError::Allocation(error),
+        }
+    }
+}
+
+impl From<raw_window_handle::HandleError> for RendererError {
+    fn from(error: raw_window_handle::HandleError) -> Self {
+        RendererError::Window(error.to_string())
+    }
+}
@@ -1,9 +1,20 @@
-use std::{collections::HashMap, fs::File};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, Read, Seek},
+};
 
 use ash::{util, vk, Device};
 
+use crate::renderer_error::{RendererError, Result};
+
 // TODO: Compile ti SPIR-V during runtime
 
+const FULLSCREEN_VERTEX_SHADER: &str = "Fullscreen";
+
+// First word of every SPIR-V module
+const SPIRV_MAGIC_NUMBER: u32 = 0x0723_0203;
+
 #[derive(Clone)]
 pub struct Shader {
     pub vert: vk::ShaderModule,
@@ -12,20 +23,20 @@ pub struct Shader {
 
 pub struct ShaderManager {
     device: Device,
-    compiler: shaderc::Compiler,
     shaders: HashMap<String, Shader>,
+    compute_shaders: HashMap<String, vk::ShaderModule>,
 }
 
 impl ShaderManager {
-    pub fn new(device: Device) -> Self {
-        let compiler = shaderc::Compiler::new().unwrap();
+    pub fn new(device: Device) -> Result<Self> {
         let shaders = HashMap::new();
+        let compute_shaders = HashMap::new();
 
-        Self {
+        Ok(Self {
             device,
-            compiler,
             shaders,
-        }
+            compute_shaders,
+        })
     }
 
     pub fn destroy(&mut self) {
@@ -35,45 +46,170 @@ impl ShaderManager {
                 self.device.destroy_shader_module(shader.frag, None);
             }
         }
+
+        for &module in self.compute_shaders.values() {
+            unsafe { self.device.destroy_shader_module(module, None) };
+        }
     }
 
-    fn load_shader_code(name: &str, shader_stage: vk::ShaderStageFlags) -> Vec<u32> {
+    fn load_shader_code(name: &str, shader_stage: vk::ShaderStageFlags) -> Result<Vec<u32>> {
         let extension = match shader_stage {
             vk::ShaderStageFlags::VERTEX => "vert",
             vk::ShaderStageFlags::FRAGMENT => "frag",
+            vk::ShaderStageFlags::COMPUTE => "comp",
             _ => "invalid",
         };
-        let _file_name = format!("{}.{}.spv", name, extension);
         let file_path = format!("shaders/{}.{}.spv", name, extension);
-        let mut file = File::open(file_path).unwrap();
+        let mut file = File::open(&file_path).map_err(|source| RendererError::MissingShader {
+            path: file_path.clone(),
+            source,
+        })?;
 
-        util::read_spv(&mut file).unwrap()
+        Self::read_shader_code(&mut file, &file_path)
+    }
+
+    // Files that aren't SPIR-V are reported like SPIR-V rejected by the driver
+    fn read_shader_code(reader: &mut (impl Read + Seek), path: &str) -> Result<Vec<u32>> {
+        let code = util::read_spv(reader).map_err(|source| match source.kind() {
+            // Not a multiple of 4 bytes
+            io::ErrorKind::InvalidData => RendererError::ShaderCompilation {
+                name: path.to_string(),
+                message: source.to_string(),
+            },
+            _ => RendererError::MissingShader {
+                path: path.to_string(),
+                source,
+            },
+        })?;
+
+        // read_spv already swapped big endian modules
+        if code.first() != Some(&SPIRV_MAGIC_NUMBER) {
+            return Err(RendererError::ShaderCompilation {
+                name: path.to_string(),
+                message: "missing the SPIR-V magic number".to_string(),
+            });
+        }
+
+        Ok(code)
     }
 
     fn create_shader_module(
         device: &Device,
         name: &str,
         shader_stage: vk::ShaderStageFlags,
-    ) -> vk::ShaderModule {
-        let code = Self::load_shader_code(name, shader_stage);
+    ) -> Result<vk::ShaderModule> {
+        let code = Self::load_shader_code(name, shader_stage)?;
         let create_info = vk::ShaderModuleCreateInfo::default().code(&code[..]);
 
-        unsafe { device.create_shader_module(&create_info, None).unwrap() }
+        // SPIR-V rejected by the driver is reported as an invalid shader rather than a generic
+        // Vulkan error
+        unsafe { device.create_shader_module(&create_info, None) }.map_err(|result| match result {
+            vk::Result::ERROR_INVALID_SHADER_NV => RendererError::ShaderCompilation {
+                name: name.to_string(),
+                message: result.to_string(),
+            },
+            result => result.into(),
+        })
     }
 
     // TODO: return Option<&Shader>?
-    pub fn get_shader(&mut self, name: &str) -> Option<Shader> {
+    pub fn get_shader(&mut self, name: &str) -> Result<Option<Shader>> {
         if name.is_empty() {
-            return None;
+            return Ok(None);
         }
 
         if let Some(shader) = self.shaders.get(name) {
-            return Some(shader.clone());
+            return Ok(Some(shader.clone()));
         }
 
-        let vert = Self::create_shader_module(&self.device, name, vk::ShaderStageFlags::VERTEX);
-        let frag = Self::create_shader_module(&self.device, name, vk::ShaderStageFlags::FRAGMENT);
+        let vert = Self::create_shader_module(&self.device, name, vk::ShaderStageFlags::VERTEX)?;
+        let frag = Self::create_shader_module(&self.device, name, vk::ShaderStageFlags::FRAGMENT)?;
+
+        Ok(Some(Shader { vert, frag }))
+    }
+
+    // Pairs the shared fullscreen triangle vertex shader with the `name` fragment shader
+    pub fn get_fullscreen_shader(&mut self, name: &str) -> Result<Option<Shader>> {
+        self.get_shader_variant(FULLSCREEN_VERTEX_SHADER, name)
+    }
+
+    // Pairs the `vertex_name` vertex shader with the `name` fragment shader, for fragment
+    // shader variants sharing a vertex shader
+    pub fn get_shader_variant(&mut self, vertex_name: &str, name: &str) -> Result<Option<Shader>> {
+        if name.is_empty() {
+            return Ok(None);
+        }
+
+        if let Some(shader) = self.shaders.get(name) {
+            return Ok(Some(shader.clone()));
+        }
+
+        let vert =
+            Self::create_shader_module(&self.device, vertex_name, vk::ShaderStageFlags::VERTEX)?;
+        let frag = Self::create_shader_module(&self.device, name, vk::ShaderStageFlags::FRAGMENT)?;
+
+        let shader = Shader { vert, frag };
+        self.shaders.insert(name.to_string(), shader.clone());
+
+        Ok(Some(shader))
+    }
+
+    pub fn get_compute_shader(&mut self, name: &str) -> Result<Option<vk::ShaderModule>> {
+        if name.is_empty() {
+            return Ok(None);
+        }
+
+        if let Some(&module) = self.compute_shaders.get(name) {
+            return Ok(Some(module));
+        }
+
+        let module = Self::create_shader_module(&self.device, name, vk::ShaderStageFlags::COMPUTE)?;
+        self.compute_shaders.insert(name.to_string(), module);
+
+        Ok(Some(module))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn to_bytes(words: &[u32]) -> Vec<u8> {
+        words.iter().flat_map(|word| word.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn spirv_is_read_as_words() {
+        let words = [SPIRV_MAGIC_NUMBER, 0x0001_0000, 0, 1, 0];
+        let code = ShaderManager::read_shader_code(&mut Cursor::new(to_bytes(&words)), "valid");
+
+        assert_eq!(code.unwrap(), words);
+    }
+
+    #[test]
+    fn truncated_spirv_is_invalid() {
+        let mut bytes = to_bytes(&[SPIRV_MAGIC_NUMBER, 0x0001_0000]);
+        bytes.pop();
+
+        let result = ShaderManager::read_shader_code(&mut Cursor::new(bytes), "truncated");
+        assert!(matches!(
+            result,
+            Err(RendererError::ShaderCompilation { .. })
+        ));
+    }
+
+    #[test]
+    fn glsl_source_is_invalid() {
+        // A multiple of 4 bytes, rejected by the magic number
+        let source = b"#version 450\nvoid main() {}\n";
+        assert_eq!(source.len() % 4, 0);
 
-        Some(Shader { vert, frag })
+        let result = ShaderManager::read_shader_code(&mut Cursor::new(source.to_vec()), "glsl");
+        assert!(matches!(
+            result,
+            Err(RendererError::ShaderCompilation { .. })
+        ));
     }
 }
@@ -1,14 +1,16 @@
 use ash::{vk, Device};
 use gpu_allocator::vulkan::Allocator;
-use shadowmap_renderpass_consts::{DEPTH_CLEAR_VALUE, SHADOW_MAP_DIMENSIONS};
+use shadowmap_renderpass_consts::DEPTH_CLEAR_VALUE;
 
 use crate::{
     command_buffer_helpers,
+    culling_pass::IndirectDraws,
     draw_data::DrawData,
     image::{Image, ImageCreateInfo},
     pipeline_manager::{PipelineManager, ShadowMapMaterial},
     push_constants_data::PushConstantsData,
     render_pass_attachment_output::RenderPassAttachmentOutput,
+    renderer_error::Result,
 };
 
 // TODO: put under renderpasses and drop render_pass from name
@@ -16,30 +18,19 @@ use crate::{
 pub mod deferred_renderpass_consts {
     use ash::vk;
 
-    pub const COLOR: vk::Format = vk::Format::R8G8B8A8_UNORM;
-    pub const NORMAL: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
-    pub const POSITION: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+    // Color targets are defined in gbuffer_layout
     pub const DEPTH: vk::Format = vk::Format::D32_SFLOAT;
 }
 
 pub mod shadowmap_renderpass_consts {
     use ash::vk;
 
-    pub const SHADOW_MAP_RESOLUTION: u32 = 2048;
     pub const DEPTH_CLEAR_VALUE: vk::ClearValue = vk::ClearValue {
         depth_stencil: vk::ClearDepthStencilValue {
             depth: 1.0,
             stencil: 0,
         },
     };
-
-    pub const SHADOW_MAP_DIMENSIONS: vk::Rect2D = vk::Rect2D {
-        offset: vk::Offset2D { x: 0, y: 0 },
-        extent: vk::Extent2D {
-            width: SHADOW_MAP_RESOLUTION,
-            height: SHADOW_MAP_RESOLUTION,
-        },
-    };
 }
 
 #[derive(Clone)]
@@ -50,6 +41,8 @@ pub struct ShadowMapRenderPassOutput {
 pub struct ShadowMapRenderPass {
     // TODO: ref to device?
     device: Device,
+    // From RendererConfig::shadow_map_resolution
+    render_area: vk::Rect2D,
     depth_image: Image,
     shadow_map_material: ShadowMapMaterial,
 }
@@ -59,66 +52,102 @@ impl ShadowMapRenderPass {
         device: Device,
         allocator: &mut Allocator,
         pipeline_manager: &PipelineManager,
-    ) -> Self {
-        let depth_image = Self::create_depth_image(&device, allocator);
+    ) -> Result<Self> {
+        let render_area = pipeline_manager.get_shadow_map_dimensions();
+        let depth_image = Self::create_depth_image(&device, allocator, &render_area)?;
 
-        Self {
+        Ok(Self {
             device,
+            render_area,
             depth_image,
             shadow_map_material: pipeline_manager.shadow_map_material.clone(),
-        }
+        })
     }
 
     pub fn destroy(&mut self, allocator: &mut Allocator) {
         self.depth_image.destroy(&self.device, allocator);
     }
 
-    pub fn render(&self, command_buffer: vk::CommandBuffer, draw_data: &DrawData) {
+    pub fn render(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        draw_data: &DrawData,
+        objects_set: vk::DescriptorSet,
+        indirect_draws: &IndirectDraws,
+    ) {
         self.begin_render_pass(command_buffer);
 
         let view = draw_data.directional_light.get_view();
         let projection = draw_data.directional_light.get_projection();
 
-        for draw_call in &draw_data.draw_calls {
-            let push_data = PushConstantsData::new(&draw_call.model, &view, &projection);
+        let push_data = PushConstantsData::new(&view, &projection);
 
-            let buffers = [draw_call.mesh.positions_buffer];
-            let offsets = [0];
+        let buffers = [draw_data.geometry_buffers.positions_buffer];
+        let offsets = [0];
 
-            unsafe {
-                self.device.cmd_push_constants(
-                    command_buffer,
-                    self.shadow_map_material.layout,
-                    vk::ShaderStageFlags::VERTEX,
-                    0,
-                    push_data.get(),
-                );
-
-                self.device.cmd_bind_pipeline(
-                    command_buffer,
-                    vk::PipelineBindPoint::GRAPHICS,
-                    self.shadow_map_material.pipeline,
-                );
-
-                self.device
-                    .cmd_bind_vertex_buffers(command_buffer, 0, &buffers, &offsets);
+        unsafe {
+            self.device.cmd_push_constants(
+                command_buffer,
+                self.shadow_map_material.layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                push_data.get(),
+            );
+
+            self.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.shadow_map_material.pipeline,
+            );
+
+            self.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.shadow_map_material.layout,
+                0,
+                &[objects_set],
+                &[],
+            );
 
-                self.device.cmd_bind_index_buffer(
-                    command_buffer,
-                    draw_call.mesh.index_buffer,
-                    0,
-                    vk::IndexType::UINT32,
-                );
+            self.device
+                .cmd_bind_vertex_buffers(command_buffer, 0, &buffers, &offsets);
+
+            self.device.cmd_bind_index_buffer(
+                command_buffer,
+                draw_data.geometry_buffers.index_buffer,
+                0,
+                vk::IndexType::UINT32,
+            );
+        }
 
-                self.device.cmd_draw_indexed(
+        if draw_data.settings.gpu_driven {
+            unsafe {
+                self.device.cmd_draw_indexed_indirect_count(
                     command_buffer,
-                    draw_call.mesh.index_count,
-                    1,
-                    0,
+                    indirect_draws.draw_commands,
                     0,
+                    indirect_draws.draw_count,
                     0,
+                    indirect_draws.max_draw_count,
+                    IndirectDraws::STRIDE,
                 );
-            };
+            }
+        } else {
+            let light_frustum = draw_data.directional_light.get_frustum();
+
+            // First instance selects the object data in the vertex shader
+            for (object_index, draw_call) in draw_data.get_shadow_casters(&light_frustum) {
+                unsafe {
+                    self.device.cmd_draw_indexed(
+                        command_buffer,
+                        draw_call.mesh.index_count,
+                        1,
+                        draw_call.mesh.first_index,
+                        draw_call.mesh.vertex_offset,
+                        object_index,
+                    );
+                };
+            }
         }
 
         self.end_render_pass(command_buffer);
@@ -149,7 +178,7 @@ impl ShadowMapRenderPass {
         );
 
         let rendering_info = vk::RenderingInfo::default()
-            .render_area(SHADOW_MAP_DIMENSIONS)
+            .render_area(self.render_area)
             .layer_count(1)
             .view_mask(0)
             .color_attachments(&[])
@@ -166,28 +195,26 @@ impl ShadowMapRenderPass {
         unsafe { self.device.cmd_end_rendering(command_buffer) };
     }
 
-    fn create_depth_image(device: &Device, allocator: &mut Allocator) -> Image {
+    fn create_depth_image(
+        device: &Device,
+        allocator: &mut Allocator,
+        render_area: &vk::Rect2D,
+    ) -> Result<Image> {
         let create_info = &ImageCreateInfo {
             extent: vk::Extent3D::default()
-                .width(
-                    shadowmap_renderpass_consts::SHADOW_MAP_DIMENSIONS
-                        .extent
-                        .width,
-                )
-                .height(
-                    shadowmap_renderpass_consts::SHADOW_MAP_DIMENSIONS
-                        .extent
-                        .height,
-                )
+                .width(render_area.extent.width)
+                .height(render_area.extent.height)
                 .depth(1),
             image_type: vk::ImageType::TYPE_2D,
             format: deferred_renderpass_consts::DEPTH,
             usage: vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
             view_type: vk::ImageViewType::TYPE_2D,
             aspect_mask: vk::ImageAspectFlags::DEPTH,
+            mip_levels: 1,
+            array_layers: 1,
         };
 
-        Image::new(device, allocator, create_info)
+        Image::new(device, allocator, create_info, "shadow_map")
     }
 
     pub fn get_output(&self) -> ShadowMapRenderPassOutput {
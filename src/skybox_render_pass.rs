@@ -0,0 +1,157 @@
+use ash::{vk, Device};
+
+use crate::{
+    command_buffer_helpers,
+    deferred_lightning_render_pass::DeferredLightningRenderPassOutput,
+    deferred_render_pass::DeferredRenderPassOutput,
+    draw_data::DrawData,
+    image_based_lighting::ImageBasedLightingOutput,
+    pipeline_manager::{PipelineManager, SkyboxMaterial},
+    push_constants_data::SkyboxPushConstantsData,
+    render_pass_attachment_output::RenderPassAttachmentOutput,
+    renderer_error::Result,
+};
+
+// Draws the environment cubemap into the lightning output wherever the G-buffer depth is still
+// at the far plane, the rest was already shaded by the lightning pass
+pub struct SkyboxRenderPass {
+    device: Device,
+    render_area: vk::Rect2D,
+    color: RenderPassAttachmentOutput,
+    depth: RenderPassAttachmentOutput,
+    material: SkyboxMaterial,
+}
+
+impl SkyboxRenderPass {
+    pub fn new(
+        device: &Device,
+        pipeline_manager: &mut PipelineManager,
+        render_area: &vk::Rect2D,
+        deferred_render_pass_output: &DeferredRenderPassOutput,
+        lightning_render_pass_output: &DeferredLightningRenderPassOutput,
+        image_based_lighting_output: &ImageBasedLightingOutput,
+    ) -> Result<Self> {
+        Ok(Self {
+            device: device.clone(),
+            render_area: *render_area,
+            color: lightning_render_pass_output.color.clone(),
+            depth: deferred_render_pass_output.depth.clone(),
+            material: pipeline_manager
+                .create_skybox_material(image_based_lighting_output.environment)?,
+        })
+    }
+
+    pub fn render(&self, command_buffer: vk::CommandBuffer, draw_data: &DrawData) {
+        self.begin_render_pass(command_buffer);
+
+        // Matches the depth clear value of DeferredRenderPass
+        let far_depth = if draw_data.reverse_z { 0.0 } else { 1.0 };
+
+        let inverse_view_projection = (draw_data.projection * draw_data.view)
+            .try_inverse()
+            .unwrap();
+
+        let push_data = SkyboxPushConstantsData::new(
+            &inverse_view_projection,
+            far_depth,
+            draw_data.settings.environment_intensity,
+        );
+
+        unsafe {
+            self.device.cmd_push_constants(
+                command_buffer,
+                self.material.layout,
+                vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                0,
+                push_data.get(),
+            );
+
+            self.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.material.pipeline,
+            );
+
+            self.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.material.layout,
+                0,
+                &[self.material.set],
+                &[],
+            );
+
+            self.device
+                .cmd_set_depth_write_enable(command_buffer, false);
+
+            self.device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        };
+
+        self.end_render_pass(command_buffer);
+    }
+
+    fn begin_render_pass(&self, command_buffer: vk::CommandBuffer) {
+        let color_attachments = [vk::RenderingAttachmentInfo::default()
+            .image_view(self.color.image_view)
+            .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .resolve_mode(vk::ResolveModeFlags::NONE)
+            .resolve_image_view(vk::ImageView::null())
+            .resolve_image_layout(vk::ImageLayout::UNDEFINED)
+            .load_op(vk::AttachmentLoadOp::LOAD)
+            .store_op(vk::AttachmentStoreOp::STORE)];
+
+        let depth_attachment = vk::RenderingAttachmentInfo::default()
+            .image_view(self.depth.image_view)
+            .image_layout(self.depth.image_layout)
+            .resolve_mode(vk::ResolveModeFlags::NONE)
+            .resolve_image_view(vk::ImageView::null())
+            .resolve_image_layout(vk::ImageLayout::UNDEFINED)
+            .load_op(vk::AttachmentLoadOp::LOAD)
+            // Read only, made visible by the barrier at the end of DeferredRenderPass
+            .store_op(vk::AttachmentStoreOp::NONE);
+
+        // Left readable by the lightning pass
+        command_buffer_helpers::single_image_pipeline_barrier(
+            &self.device,
+            command_buffer,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            self.color.image_layout,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            self.color.image,
+            vk::ImageAspectFlags::COLOR,
+        );
+
+        let rendering_info = vk::RenderingInfo::default()
+            .render_area(self.render_area)
+            .layer_count(1)
+            .view_mask(0)
+            .color_attachments(&color_attachments)
+            .depth_attachment(&depth_attachment);
+
+        unsafe {
+            self.device
+                .cmd_begin_rendering(command_buffer, &rendering_info);
+        }
+    }
+
+    // Hands the lightning output back to the following passes in the layout it reports
+    fn end_render_pass(&self, command_buffer: vk::CommandBuffer) {
+        unsafe { self.device.cmd_end_rendering(command_buffer) };
+
+        command_buffer_helpers::single_image_pipeline_barrier(
+            &self.device,
+            command_buffer,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            vk::AccessFlags::SHADER_READ,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            self.color.image_layout,
+            self.color.image,
+            vk::ImageAspectFlags::COLOR,
+        );
+    }
+}
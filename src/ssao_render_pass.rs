@@ -0,0 +1,311 @@
+use ash::{vk, Device};
+use gpu_allocator::vulkan::Allocator;
+
+use crate::{
+    command_buffer_helpers,
+    deferred_render_pass::DeferredRenderPassOutput,
+    draw_data::DrawData,
+    image::{Image, ImageCreateInfo},
+    pipeline_manager::{PipelineManager, SsaoMaterial},
+    push_constants_data::{SsaoBlurPushConstantsData, SsaoPushConstantsData},
+    render_pass_attachment_output::RenderPassAttachmentOutput,
+    renderer_error::Result,
+};
+
+pub mod ssao_renderpass_consts {
+    use ash::vk;
+
+    pub const AMBIENT_OCCLUSION: vk::Format = vk::Format::R8_UNORM;
+    // Random rotations around the normal, xy of a unit vector
+    pub const NOISE: vk::Format = vk::Format::R8G8_SNORM;
+    // Tiled over the screen, the blur kernel covers exactly one tile
+    pub const NOISE_SIZE: u32 = 4;
+    // Higher values keep the blur from crossing depth discontinuities
+    pub const BLUR_SHARPNESS: f32 = 16.0;
+}
+
+const UNOCCLUDED_CLEAR_VALUE: vk::ClearValue = vk::ClearValue {
+    color: vk::ClearColorValue {
+        float32: [1.0, 1.0, 1.0, 1.0],
+    },
+};
+
+#[derive(Clone)]
+pub struct SsaoRenderPassOutput {
+    pub ambient_occlusion: RenderPassAttachmentOutput,
+}
+
+// Hemisphere sampled ambient occlusion from the G-buffer, followed by a depth aware blur
+pub struct SsaoRenderPass {
+    device: Device,
+    render_area: vk::Rect2D,
+    occlusion_image: Image,
+    blurred_image: Image,
+    material: SsaoMaterial,
+}
+
+impl SsaoRenderPass {
+    pub fn new(
+        device: &Device,
+        allocator: &mut Allocator,
+        pipeline_manager: &mut PipelineManager,
+        render_area: &vk::Rect2D,
+        deferred_render_pass_output: &DeferredRenderPassOutput,
+        noise: vk::ImageView,
+    ) -> Result<Self> {
+        let occlusion_image =
+            Self::create_occlusion_image(device, allocator, render_area, "ssao_occlusion")?;
+        let blurred_image =
+            Self::create_occlusion_image(device, allocator, render_area, "ssao_blurred")?;
+
+        Ok(Self {
+            device: device.clone(),
+            render_area: *render_area,
+            material: pipeline_manager.create_ssao_material(
+                deferred_render_pass_output.normal.image_view,
+                &deferred_render_pass_output.depth,
+                noise,
+                occlusion_image.image_view,
+            )?,
+            occlusion_image,
+            blurred_image,
+        })
+    }
+
+    pub fn destroy(&mut self, allocator: &mut Allocator) {
+        self.occlusion_image.destroy(&self.device, allocator);
+        self.blurred_image.destroy(&self.device, allocator);
+    }
+
+    pub fn render(&self, command_buffer: vk::CommandBuffer, draw_data: &DrawData) {
+        let settings = &draw_data.settings;
+
+        if !settings.ssao {
+            // Cleared to fully unoccluded, nothing is drawn
+            self.draw(
+                command_buffer,
+                &self.blurred_image,
+                vk::Pipeline::null(),
+                self.material.blur_layout,
+                self.material.blur_set,
+                &[],
+            );
+
+            return;
+        }
+
+        // Jittered like the G-buffer depth the positions are reconstructed from
+        let view_projection = draw_data.projection * draw_data.view;
+        let inverse_view_projection = view_projection.try_inverse().unwrap();
+
+        // Matches the depth clear value of DeferredRenderPass
+        let far_depth = if draw_data.reverse_z { 0.0 } else { 1.0 };
+
+        let push_data = SsaoPushConstantsData::new(
+            &view_projection,
+            &inverse_view_projection,
+            &draw_data.camera_position,
+            &draw_data.camera_forward,
+            settings,
+            far_depth,
+        );
+
+        self.draw(
+            command_buffer,
+            &self.occlusion_image,
+            self.material.pipeline,
+            self.material.layout,
+            self.material.set,
+            push_data.get(),
+        );
+
+        let blur_push_data = SsaoBlurPushConstantsData::new(
+            &inverse_view_projection,
+            &draw_data.camera_position,
+            &draw_data.camera_forward,
+            ssao_renderpass_consts::BLUR_SHARPNESS,
+            far_depth,
+        );
+
+        self.draw(
+            command_buffer,
+            &self.blurred_image,
+            self.material.blur_pipeline,
+            self.material.blur_layout,
+            self.material.blur_set,
+            blur_push_data.get(),
+        );
+    }
+
+    // Leaves the target readable by the following passes
+    fn draw(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        target: &Image,
+        pipeline: vk::Pipeline,
+        layout: vk::PipelineLayout,
+        set: vk::DescriptorSet,
+        push_data: &[u8],
+    ) {
+        let color_attachments = [vk::RenderingAttachmentInfo::default()
+            .image_view(target.image_view)
+            .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .resolve_mode(vk::ResolveModeFlags::NONE)
+            .resolve_image_view(vk::ImageView::null())
+            .resolve_image_layout(vk::ImageLayout::UNDEFINED)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .clear_value(UNOCCLUDED_CLEAR_VALUE)];
+
+        command_buffer_helpers::single_image_pipeline_barrier(
+            &self.device,
+            command_buffer,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::AccessFlags::NONE,
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            target.image,
+            vk::ImageAspectFlags::COLOR,
+        );
+
+        let rendering_info = vk::RenderingInfo::default()
+            .render_area(self.render_area)
+            .layer_count(1)
+            .view_mask(0)
+            .color_attachments(&color_attachments);
+
+        unsafe {
+            self.device
+                .cmd_begin_rendering(command_buffer, &rendering_info);
+
+            if pipeline != vk::Pipeline::null() {
+                self.device.cmd_push_constants(
+                    command_buffer,
+                    layout,
+                    vk::ShaderStageFlags::FRAGMENT,
+                    0,
+                    push_data,
+                );
+
+                self.device.cmd_bind_pipeline(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pipeline,
+                );
+
+                self.device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    layout,
+                    0,
+                    &[set],
+                    &[],
+                );
+
+                self.device.cmd_draw(command_buffer, 3, 1, 0, 0);
+            }
+
+            self.device.cmd_end_rendering(command_buffer);
+        }
+
+        command_buffer_helpers::single_image_pipeline_barrier(
+            &self.device,
+            command_buffer,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            vk::AccessFlags::SHADER_READ,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            target.image,
+            vk::ImageAspectFlags::COLOR,
+        );
+    }
+
+    fn create_occlusion_image(
+        device: &Device,
+        allocator: &mut Allocator,
+        render_area: &vk::Rect2D,
+        name: &str,
+    ) -> Result<Image> {
+        let create_info = ImageCreateInfo {
+            extent: vk::Extent3D::default()
+                .width(render_area.extent.width)
+                .height(render_area.extent.height)
+                .depth(1),
+            image_type: vk::ImageType::TYPE_2D,
+            format: ssao_renderpass_consts::AMBIENT_OCCLUSION,
+            usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            view_type: vk::ImageViewType::TYPE_2D,
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_levels: 1,
+            array_layers: 1,
+        };
+
+        Image::new(device, allocator, &create_info, name)
+    }
+
+    // Shared by every frame worker, it never changes
+    pub fn create_noise_image(
+        device: &Device,
+        allocator: &mut Allocator,
+        queue: vk::Queue,
+        command_pool: vk::CommandPool,
+    ) -> Result<Image> {
+        let size = ssao_renderpass_consts::NOISE_SIZE;
+
+        // Fixed seed, the pattern doesn't need to change between runs
+        let mut state = 0x9e37_79b9_u32;
+        let noise: Vec<[i8; 2]> = (0..size * size)
+            .map(|_| {
+                let angle = next_random(&mut state) * std::f32::consts::TAU;
+                [
+                    (angle.cos() * i8::MAX as f32) as i8,
+                    (angle.sin() * i8::MAX as f32) as i8,
+                ]
+            })
+            .collect();
+
+        let create_info = ImageCreateInfo {
+            extent: vk::Extent3D::default().width(size).height(size).depth(1),
+            image_type: vk::ImageType::TYPE_2D,
+            format: ssao_renderpass_consts::NOISE,
+            usage: vk::ImageUsageFlags::SAMPLED,
+            view_type: vk::ImageViewType::TYPE_2D,
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_levels: 1,
+            array_layers: 1,
+        };
+
+        Image::new_with_data(
+            device,
+            allocator,
+            queue,
+            command_pool,
+            &create_info,
+            &noise,
+            "ssao_noise",
+        )
+    }
+
+    pub fn get_output(&self) -> SsaoRenderPassOutput {
+        SsaoRenderPassOutput {
+            ambient_occlusion: RenderPassAttachmentOutput {
+                image: self.blurred_image.image,
+                image_view: self.blurred_image.image_view,
+                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            },
+        }
+    }
+}
+
+// Xorshift, uniform in [0, 1)
+fn next_random(state: &mut u32) -> f32 {
+    *state ^= *state << 13;
+    *state ^= *state >> 17;
+    *state ^= *state << 5;
+
+    (*state >> 8) as f32 / (1 << 24) as f32
+}
@@ -0,0 +1,178 @@
+use ash::{ext::swapchain_colorspace, khr::surface, vk, Entry};
+use serde::Deserialize;
+
+use crate::{renderer_error::Result, tonemapping_render_pass};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SwapchainFormat {
+    // 8 bit, encoded by the hardware on write
+    Srgb,
+    // 8 bit, encoded by the tonemapping pass
+    Unorm,
+    // 10 bit BT.2020 with the PQ transfer function
+    Hdr10,
+    // 16 bit float linear BT.709
+    ScRgb,
+}
+
+impl SwapchainFormat {
+    fn matches(self, surface_format: &vk::SurfaceFormatKHR) -> bool {
+        let format = surface_format.format;
+
+        match self {
+            SwapchainFormat::Srgb => {
+                surface_format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
+                    && tonemapping_render_pass::is_srgb_format(format)
+            }
+            SwapchainFormat::Unorm => {
+                surface_format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
+                    && matches!(
+                        format,
+                        vk::Format::B8G8R8A8_UNORM
+                            | vk::Format::R8G8B8A8_UNORM
+                            | vk::Format::A8B8G8R8_UNORM_PACK32
+                    )
+            }
+            SwapchainFormat::Hdr10 => {
+                surface_format.color_space == vk::ColorSpaceKHR::HDR10_ST2084_EXT
+                    && matches!(
+                        format,
+                        vk::Format::A2B10G10R10_UNORM_PACK32 | vk::Format::A2R10G10B10_UNORM_PACK32
+                    )
+            }
+            SwapchainFormat::ScRgb => {
+                surface_format.color_space == vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT
+                    && format == vk::Format::R16G16B16A16_SFLOAT
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SwapchainConfig {
+    // FIFO when enabled, otherwise MAILBOX falling back to IMMEDIATE
+    pub vsync: bool,
+    // Clamped to the surface capabilities, every image gets a frame worker
+    pub image_count: u32,
+    // Falls back to the SDR formats when the surface doesn't support it
+    pub format: SwapchainFormat,
+}
+
+impl Default for SwapchainConfig {
+    fn default() -> Self {
+        Self {
+            vsync: true,
+            image_count: 3,
+            format: SwapchainFormat::Srgb,
+        }
+    }
+}
+
+// Surfaces only report the HDR color spaces when the instance enables it
+pub fn is_colorspace_extension_available(entry: &Entry) -> Result<bool> {
+    let extensions = unsafe { entry.enumerate_instance_extension_properties(None)? };
+
+    Ok(extensions
+        .iter()
+        .any(|extension| extension.extension_name_as_c_str() == Ok(swapchain_colorspace::NAME)))
+}
+
+pub struct SelectedSwapchain {
+    pub surface_format: vk::SurfaceFormatKHR,
+    pub present_mode: vk::PresentModeKHR,
+    pub image_count: u32,
+    pub composite_alpha: vk::CompositeAlphaFlagsKHR,
+}
+
+fn select_surface_format(
+    surface_formats: &[vk::SurfaceFormatKHR],
+    preference: SwapchainFormat,
+) -> vk::SurfaceFormatKHR {
+    let fallbacks = [SwapchainFormat::Srgb, SwapchainFormat::Unorm];
+
+    let selected = [preference]
+        .iter()
+        .chain(fallbacks.iter())
+        .find_map(|format| {
+            surface_formats
+                .iter()
+                .find(|surface_format| format.matches(surface_format))
+        });
+
+    if !selected.is_some_and(|surface_format| preference.matches(surface_format)) {
+        log::warn!("The surface doesn't support {:?} swapchains", preference);
+    }
+
+    // Only surfaces without any 8 bit SDR format end up here
+    *selected.unwrap_or(&surface_formats[0])
+}
+
+fn select_present_mode(present_modes: &[vk::PresentModeKHR], vsync: bool) -> vk::PresentModeKHR {
+    if vsync {
+        return vk::PresentModeKHR::FIFO;
+    }
+
+    // MAILBOX doesn't tear, IMMEDIATE does but is more widely supported
+    [vk::PresentModeKHR::MAILBOX, vk::PresentModeKHR::IMMEDIATE]
+        .into_iter()
+        .find(|present_mode| present_modes.contains(present_mode))
+        .unwrap_or_else(|| {
+            log::warn!("The surface only supports vsync");
+            // Always supported
+            vk::PresentModeKHR::FIFO
+        })
+}
+
+pub fn select_swapchain(
+    surface_loader: &surface::Instance,
+    physical_device: vk::PhysicalDevice,
+    surface: vk::SurfaceKHR,
+    config: &SwapchainConfig,
+) -> Result<SelectedSwapchain> {
+    let (surface_formats, present_modes, capabilities) = unsafe {
+        (
+            surface_loader.get_physical_device_surface_formats(physical_device, surface)?,
+            surface_loader.get_physical_device_surface_present_modes(physical_device, surface)?,
+            surface_loader.get_physical_device_surface_capabilities(physical_device, surface)?,
+        )
+    };
+
+    // 0 means there is no maximum
+    let mut image_count = config.image_count.max(capabilities.min_image_count);
+    if capabilities.max_image_count > 0 {
+        image_count = image_count.min(capabilities.max_image_count);
+    }
+
+    let composite_alpha = [
+        vk::CompositeAlphaFlagsKHR::OPAQUE,
+        vk::CompositeAlphaFlagsKHR::INHERIT,
+        vk::CompositeAlphaFlagsKHR::PRE_MULTIPLIED,
+        vk::CompositeAlphaFlagsKHR::POST_MULTIPLIED,
+    ]
+    .into_iter()
+    .find(|&composite_alpha| {
+        capabilities
+            .supported_composite_alpha
+            .contains(composite_alpha)
+    })
+    .unwrap_or(vk::CompositeAlphaFlagsKHR::OPAQUE);
+
+    let selected = SelectedSwapchain {
+        surface_format: select_surface_format(&surface_formats, config.format),
+        present_mode: select_present_mode(&present_modes, config.vsync),
+        image_count,
+        composite_alpha,
+    };
+
+    log::info!(
+        "Swapchain: {:?} {:?}, {:?}, {} images",
+        selected.surface_format.format,
+        selected.surface_format.color_space,
+        selected.present_mode,
+        selected.image_count
+    );
+
+    Ok(selected)
+}
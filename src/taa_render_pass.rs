@@ -0,0 +1,347 @@
+use ash::{vk, Device};
+use gpu_allocator::vulkan::Allocator;
+
+use crate::{
+    command_buffer_helpers,
+    deferred_lightning_render_pass::{
+        lightning_renderpass_consts, DeferredLightningRenderPassOutput,
+    },
+    deferred_render_pass::DeferredRenderPassOutput,
+    draw_data::DrawData,
+    image::{Image, ImageCreateInfo},
+    pipeline_manager::{PipelineManager, TaaMaterial},
+    push_constants_data::TaaPushConstantsData,
+    render_pass_attachment_output::RenderPassAttachmentOutput,
+    renderer_error::Result,
+};
+
+pub mod taa_renderpass_consts {
+    // Weight of the current frame, lower is smoother but ghosts longer
+    pub const BLEND_FACTOR: f32 = 0.1;
+    // Length of the Halton jitter sequence before it repeats
+    pub const JITTER_SEQUENCE_LENGTH: u32 = 8;
+}
+
+#[derive(Clone)]
+pub struct TaaRenderPassOutput {
+    pub color: RenderPassAttachmentOutput,
+}
+
+// Blends the jittered lightning output with the reprojected result of the previous frame
+pub struct TaaRenderPass {
+    device: Device,
+    render_area: vk::Rect2D,
+    resolved_image: Image,
+    // Owned by each frame worker, filled from the previous frame's resolved image before the
+    // resolve so no image is written by two frames in flight
+    history: Image,
+    material: TaaMaterial,
+}
+
+impl TaaRenderPass {
+    pub fn new(
+        device: &Device,
+        allocator: &mut Allocator,
+        pipeline_manager: &mut PipelineManager,
+        render_area: &vk::Rect2D,
+        deferred_render_pass_output: &DeferredRenderPassOutput,
+        lightning_render_pass_output: &DeferredLightningRenderPassOutput,
+    ) -> Result<Self> {
+        let resolved_image = Self::create_color_image(
+            device,
+            allocator,
+            render_area,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT
+                | vk::ImageUsageFlags::SAMPLED
+                | vk::ImageUsageFlags::TRANSFER_SRC,
+            "taa_resolved",
+        )?;
+
+        let history = Self::create_color_image(
+            device,
+            allocator,
+            render_area,
+            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+            "taa_history",
+        )?;
+
+        Ok(Self {
+            device: device.clone(),
+            render_area: *render_area,
+            material: pipeline_manager.create_taa_material(
+                lightning_render_pass_output.color.image_view,
+                deferred_render_pass_output.velocity.image_view,
+                &deferred_render_pass_output.depth,
+                history.image_view,
+            )?,
+            resolved_image,
+            history,
+        })
+    }
+
+    pub fn destroy(&mut self, allocator: &mut Allocator) {
+        self.resolved_image.destroy(&self.device, allocator);
+        self.history.destroy(&self.device, allocator);
+    }
+
+    // Skipped by FrameWorker while TAA is disabled
+    pub fn render(&self, command_buffer: vk::CommandBuffer, draw_data: &DrawData) {
+        let reset_history = match draw_data.previous_taa_output {
+            Some(previous_output) => {
+                self.copy_history(command_buffer, previous_output);
+                false
+            }
+            None => {
+                self.discard_history(command_buffer);
+                true
+            }
+        };
+
+        // Matches the depth clear value of DeferredRenderPass
+        let far_depth = if draw_data.reverse_z { 0.0 } else { 1.0 };
+
+        let reprojection = draw_data.previous_view_projection
+            * draw_data
+                .get_unjittered_view_projection()
+                .try_inverse()
+                .unwrap();
+
+        let push_data = TaaPushConstantsData::new(
+            &reprojection,
+            far_depth,
+            taa_renderpass_consts::BLEND_FACTOR,
+            reset_history,
+        );
+
+        self.begin_render_pass(command_buffer);
+
+        unsafe {
+            self.device.cmd_push_constants(
+                command_buffer,
+                self.material.layout,
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                push_data.get(),
+            );
+
+            self.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.material.pipeline,
+            );
+
+            self.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.material.layout,
+                0,
+                &[self.material.set],
+                &[],
+            );
+
+            self.device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        };
+
+        self.end_render_pass(command_buffer);
+    }
+
+    pub fn get_output(&self) -> TaaRenderPassOutput {
+        TaaRenderPassOutput {
+            color: RenderPassAttachmentOutput {
+                image: self.resolved_image.image,
+                image_view: self.resolved_image.image_view,
+                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            },
+        }
+    }
+
+    fn begin_render_pass(&self, command_buffer: vk::CommandBuffer) {
+        let color_attachments = [vk::RenderingAttachmentInfo::default()
+            .image_view(self.resolved_image.image_view)
+            .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .resolve_mode(vk::ResolveModeFlags::NONE)
+            .resolve_image_view(vk::ImageView::null())
+            .resolve_image_layout(vk::ImageLayout::UNDEFINED)
+            .load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .store_op(vk::AttachmentStoreOp::STORE)];
+
+        command_buffer_helpers::single_image_pipeline_barrier(
+            &self.device,
+            command_buffer,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::AccessFlags::NONE,
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            self.resolved_image.image,
+            vk::ImageAspectFlags::COLOR,
+        );
+
+        let rendering_info = vk::RenderingInfo::default()
+            .render_area(self.render_area)
+            .layer_count(1)
+            .view_mask(0)
+            .color_attachments(&color_attachments);
+
+        unsafe {
+            self.device
+                .cmd_begin_rendering(command_buffer, &rendering_info);
+        }
+    }
+
+    fn end_render_pass(&self, command_buffer: vk::CommandBuffer) {
+        unsafe { self.device.cmd_end_rendering(command_buffer) };
+
+        command_buffer_helpers::single_image_pipeline_barrier(
+            &self.device,
+            command_buffer,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            vk::AccessFlags::SHADER_READ,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            self.resolved_image.image,
+            vk::ImageAspectFlags::COLOR,
+        );
+    }
+
+    // The previous frame was resolved by another frame worker, or by this one, into its own
+    // image which is left readable
+    fn copy_history(&self, command_buffer: vk::CommandBuffer, previous_output: vk::Image) {
+        command_buffer_helpers::single_image_pipeline_barrier(
+            &self.device,
+            command_buffer,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::SHADER_READ,
+            vk::AccessFlags::TRANSFER_READ,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            previous_output,
+            vk::ImageAspectFlags::COLOR,
+        );
+
+        command_buffer_helpers::single_image_pipeline_barrier(
+            &self.device,
+            command_buffer,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::NONE,
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            self.history.image,
+            vk::ImageAspectFlags::COLOR,
+        );
+
+        let subresource = vk::ImageSubresourceLayers::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .mip_level(0)
+            .base_array_layer(0)
+            .layer_count(1);
+
+        let region = vk::ImageCopy::default()
+            .src_subresource(subresource)
+            .dst_subresource(subresource)
+            .extent(
+                vk::Extent3D::default()
+                    .width(self.render_area.extent.width)
+                    .height(self.render_area.extent.height)
+                    .depth(1),
+            );
+
+        unsafe {
+            self.device.cmd_copy_image(
+                command_buffer,
+                previous_output,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                self.history.image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[region],
+            );
+        }
+
+        command_buffer_helpers::single_image_pipeline_barrier(
+            &self.device,
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::AccessFlags::SHADER_READ,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            self.history.image,
+            vk::ImageAspectFlags::COLOR,
+        );
+
+        // Also orders the copy before the resolve when the previous output is this worker's
+        command_buffer_helpers::single_image_pipeline_barrier(
+            &self.device,
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::AccessFlags::TRANSFER_READ,
+            vk::AccessFlags::SHADER_READ,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            previous_output,
+            vk::ImageAspectFlags::COLOR,
+        );
+    }
+
+    // The contents are never read after a reset, but the descriptor still needs a valid layout
+    fn discard_history(&self, command_buffer: vk::CommandBuffer) {
+        command_buffer_helpers::single_image_pipeline_barrier(
+            &self.device,
+            command_buffer,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::AccessFlags::NONE,
+            vk::AccessFlags::SHADER_READ,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            self.history.image,
+            vk::ImageAspectFlags::COLOR,
+        );
+    }
+
+    fn create_color_image(
+        device: &Device,
+        allocator: &mut Allocator,
+        render_area: &vk::Rect2D,
+        usage: vk::ImageUsageFlags,
+        name: &str,
+    ) -> Result<Image> {
+        let create_info = ImageCreateInfo {
+            extent: vk::Extent3D::default()
+                .width(render_area.extent.width)
+                .height(render_area.extent.height)
+                .depth(1),
+            image_type: vk::ImageType::TYPE_2D,
+            format: lightning_renderpass_consts::HDR_COLOR,
+            usage,
+            view_type: vk::ImageViewType::TYPE_2D,
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_levels: 1,
+            array_layers: 1,
+        };
+
+        Image::new(device, allocator, &create_info, name)
+    }
+}
+
+// Low discrepancy sequence, spreads the jitter evenly over the pixel
+pub fn halton(mut index: u32, base: u32) -> f32 {
+    let mut fraction = 1.0;
+    let mut result = 0.0;
+
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+
+    result
+}
@@ -0,0 +1,251 @@
+use ash::{vk, Device};
+
+use crate::{
+    bloom_render_pass::BloomRenderPassOutput,
+    command_buffer_helpers,
+    deferred_lightning_render_pass::DeferredLightningRenderPassOutput,
+    pipeline_manager::{PipelineManager, TonemappingMaterial},
+    push_constants_data::TonemappingPushConstantsData,
+    render_pass_attachment_output::RenderPassAttachmentOutput,
+    render_settings::{AntiAliasing, DebugView, RenderSettings},
+    renderer_error::Result,
+    taa_render_pass::TaaRenderPassOutput,
+};
+
+// Matches TONEMAPPING_NONE in shaders/Tonemapping.frag, the switch leaves the color unchanged
+const PASSTHROUGH_SHADER_INDEX: u32 = 3;
+
+const SWAPCHAIN_CLEAR_VALUE: vk::ClearValue = vk::ClearValue {
+    color: vk::ClearColorValue {
+        float32: [0.0, 0.0, 0.0, 0.0],
+    },
+};
+
+// The hardware encodes on write for *_SRGB formats, otherwise the shader has to
+pub fn is_srgb_format(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::R8_SRGB
+            | vk::Format::R8G8_SRGB
+            | vk::Format::R8G8B8_SRGB
+            | vk::Format::B8G8R8_SRGB
+            | vk::Format::R8G8B8A8_SRGB
+            | vk::Format::B8G8R8A8_SRGB
+            | vk::Format::A8B8G8R8_SRGB_PACK32
+    )
+}
+
+// How the final passes encode colors for the swapchain
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DisplayEncoding {
+    // *_SRGB formats, the hardware encodes on write
+    Linear,
+    // UNORM formats in the sRGB color space
+    Srgb,
+    // HDR10, BT.2020 primaries with the ST 2084 (PQ) transfer function
+    Pq,
+    // Linear BT.709 where 1.0 is 80 nits, values above it are brighter than SDR white
+    ScRgb,
+}
+
+impl DisplayEncoding {
+    pub fn from_surface_format(surface_format: vk::SurfaceFormatKHR) -> Self {
+        match surface_format.color_space {
+            vk::ColorSpaceKHR::HDR10_ST2084_EXT => DisplayEncoding::Pq,
+            vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT => DisplayEncoding::ScRgb,
+            _ if is_srgb_format(surface_format.format) => DisplayEncoding::Linear,
+            _ => DisplayEncoding::Srgb,
+        }
+    }
+
+    // Sampled values are proportional to light, perceptual effects need to encode them first
+    pub fn is_linear(self) -> bool {
+        matches!(self, DisplayEncoding::Linear | DisplayEncoding::ScRgb)
+    }
+
+    // Matches the DISPLAY_* defines in shaders/Tonemapping.frag and shaders/DebugUi.frag
+    pub fn get_shader_index(self) -> u32 {
+        match self {
+            DisplayEncoding::Linear => 0,
+            DisplayEncoding::Srgb => 1,
+            DisplayEncoding::Pq => 2,
+            DisplayEncoding::ScRgb => 3,
+        }
+    }
+}
+
+// Exposure, tonemapping and display encoding of the HDR color, resolved by TAA when enabled,
+// into the swapchain or into the input of FxaaRenderPass which has the same format
+pub struct TonemappingRenderPass {
+    device: Device,
+    render_area: vk::Rect2D,
+    material: TonemappingMaterial,
+    taa_material: TonemappingMaterial,
+    display_encoding: DisplayEncoding,
+}
+
+impl TonemappingRenderPass {
+    pub fn new(
+        device: &Device,
+        pipeline_manager: &mut PipelineManager,
+        render_area: &vk::Rect2D,
+        lightning_render_pass_output: &DeferredLightningRenderPassOutput,
+        taa_render_pass_output: &TaaRenderPassOutput,
+        bloom_render_pass_output: &BloomRenderPassOutput,
+    ) -> Result<Self> {
+        Ok(Self {
+            device: device.clone(),
+            render_area: *render_area,
+            // Every input is left readable by the pass producing it
+            material: pipeline_manager.create_tonemapping_material(
+                lightning_render_pass_output.color.image_view,
+                bloom_render_pass_output.bloom.image_view,
+            )?,
+            taa_material: pipeline_manager.create_tonemapping_material(
+                taa_render_pass_output.color.image_view,
+                bloom_render_pass_output.bloom.image_view,
+            )?,
+            display_encoding: pipeline_manager.get_display_encoding(),
+        })
+    }
+
+    // The target is left in its image_layout, PRESENT_SRC_KHR for the swapchain
+    pub fn render(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        target: &RenderPassAttachmentOutput,
+        settings: &RenderSettings,
+    ) {
+        self.begin_render_pass(command_buffer, target);
+
+        let material = if settings.anti_aliasing == AntiAliasing::Taa {
+            &self.taa_material
+        } else {
+            &self.material
+        };
+
+        // Debug views are shown as written, without exposure, bloom or a curve
+        let push_data = if settings.debug_view != DebugView::None {
+            TonemappingPushConstantsData::new(
+                1.0,
+                PASSTHROUGH_SHADER_INDEX,
+                self.display_encoding.get_shader_index(),
+                0.0,
+            )
+        } else {
+            TonemappingPushConstantsData::new(
+                settings.exposure.exp2(),
+                settings.tonemapping_operator.get_shader_index(),
+                self.display_encoding.get_shader_index(),
+                if settings.bloom {
+                    settings.bloom_intensity
+                } else {
+                    0.0
+                },
+            )
+        };
+
+        unsafe {
+            self.device.cmd_push_constants(
+                command_buffer,
+                material.layout,
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                push_data.get(),
+            );
+
+            self.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                material.pipeline,
+            );
+
+            self.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                material.layout,
+                0,
+                &[material.set],
+                &[],
+            );
+
+            self.device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        };
+
+        self.end_render_pass(command_buffer, target);
+    }
+
+    fn begin_render_pass(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        target: &RenderPassAttachmentOutput,
+    ) {
+        let target_attachments = [vk::RenderingAttachmentInfo::default()
+            .image_view(target.image_view)
+            .image_layout(vk::ImageLayout::ATTACHMENT_OPTIMAL)
+            .resolve_mode(vk::ResolveModeFlags::NONE)
+            .resolve_image_view(vk::ImageView::null())
+            .resolve_image_layout(vk::ImageLayout::UNDEFINED)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .clear_value(SWAPCHAIN_CLEAR_VALUE)];
+
+        command_buffer_helpers::single_image_pipeline_barrier(
+            &self.device,
+            command_buffer,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::AccessFlags::NONE,
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            target.image,
+            vk::ImageAspectFlags::COLOR,
+        );
+
+        let rendering_info = vk::RenderingInfo::default()
+            .render_area(self.render_area)
+            .layer_count(1)
+            .view_mask(0)
+            .color_attachments(&target_attachments);
+
+        unsafe {
+            self.device
+                .cmd_begin_rendering(command_buffer, &rendering_info);
+        }
+    }
+
+    fn end_render_pass(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        target: &RenderPassAttachmentOutput,
+    ) {
+        unsafe { self.device.cmd_end_rendering(command_buffer) };
+
+        // Either presented or sampled by the next pass
+        let (dst_stage, dst_access) = if target.image_layout == vk::ImageLayout::PRESENT_SRC_KHR {
+            (
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::AccessFlags::NONE,
+            )
+        } else {
+            (
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::AccessFlags::SHADER_READ,
+            )
+        };
+
+        command_buffer_helpers::single_image_pipeline_barrier(
+            &self.device,
+            command_buffer,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            dst_stage,
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            dst_access,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            target.image_layout,
+            target.image,
+            vk::ImageAspectFlags::COLOR,
+        );
+    }
+}
@@ -0,0 +1,208 @@
+use ash::{vk, Device};
+
+use crate::{
+    command_buffer_helpers,
+    deferred_lightning_render_pass::DeferredLightningRenderPassOutput,
+    deferred_render_pass::DeferredRenderPassOutput,
+    draw_data::DrawData,
+    image_based_lighting::ImageBasedLightingOutput,
+    pipeline_manager::{PipelineManager, TransparentMaterial},
+    push_constants_data::TransparentPushConstantsData,
+    render_pass_attachment_output::RenderPassAttachmentOutput,
+    renderer_error::Result,
+    shadow_map_render_pass::ShadowMapRenderPassOutput,
+};
+
+// Forward shaded alpha blended objects, drawn back to front over the lightning output and
+// tested against the opaque G-buffer depth
+pub struct TransparentRenderPass {
+    device: Device,
+    render_area: vk::Rect2D,
+    color: RenderPassAttachmentOutput,
+    depth: RenderPassAttachmentOutput,
+    material: TransparentMaterial,
+}
+
+impl TransparentRenderPass {
+    pub fn new(
+        device: &Device,
+        pipeline_manager: &mut PipelineManager,
+        render_area: &vk::Rect2D,
+        deferred_render_pass_output: &DeferredRenderPassOutput,
+        lightning_render_pass_output: &DeferredLightningRenderPassOutput,
+        shadow_map_render_pass_output: &ShadowMapRenderPassOutput,
+        image_based_lighting_output: &ImageBasedLightingOutput,
+    ) -> Result<Self> {
+        Ok(Self {
+            device: device.clone(),
+            render_area: *render_area,
+            color: lightning_render_pass_output.color.clone(),
+            depth: deferred_render_pass_output.depth.clone(),
+            material: pipeline_manager.create_transparent_material(
+                shadow_map_render_pass_output.depth.image_view,
+                image_based_lighting_output,
+            )?,
+        })
+    }
+
+    // Always CPU sorted direct draws, also when the opaque geometry is GPU driven
+    pub fn render(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        draw_data: &DrawData,
+        objects_set: vk::DescriptorSet,
+    ) {
+        let draw_calls = draw_data.get_sorted_transparent_draw_calls();
+
+        if draw_calls.is_empty() {
+            return;
+        }
+
+        self.begin_render_pass(command_buffer);
+
+        let depth_compare_op = if draw_data.reverse_z {
+            vk::CompareOp::GREATER_OR_EQUAL
+        } else {
+            vk::CompareOp::LESS_OR_EQUAL
+        };
+
+        let push_data = TransparentPushConstantsData::new(
+            &(draw_data.projection * draw_data.view),
+            &(draw_data.directional_light.get_projection()
+                * draw_data.directional_light.get_view()),
+            &draw_data.camera_position,
+            draw_data.settings.environment_intensity,
+        );
+        let buffers = [
+            draw_data.geometry_buffers.positions_buffer,
+            draw_data.geometry_buffers.attributes_buffer,
+        ];
+        let offsets = [0, 0];
+
+        unsafe {
+            self.device.cmd_push_constants(
+                command_buffer,
+                self.material.layout,
+                vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                0,
+                push_data.get(),
+            );
+
+            self.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.material.pipeline,
+            );
+
+            self.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.material.layout,
+                0,
+                &[objects_set, self.material.set],
+                &[],
+            );
+
+            self.device
+                .cmd_bind_vertex_buffers(command_buffer, 0, &buffers, &offsets);
+
+            self.device.cmd_bind_index_buffer(
+                command_buffer,
+                draw_data.geometry_buffers.index_buffer,
+                0,
+                vk::IndexType::UINT32,
+            );
+
+            self.device
+                .cmd_set_depth_compare_op(command_buffer, depth_compare_op);
+            self.device
+                .cmd_set_depth_write_enable(command_buffer, false);
+        }
+
+        // Inside faces first so the near side of the same object blends over them
+        for (object_index, draw_call) in draw_calls {
+            for cull_mode in [vk::CullModeFlags::FRONT, vk::CullModeFlags::BACK] {
+                unsafe {
+                    self.device.cmd_set_cull_mode(command_buffer, cull_mode);
+
+                    // First instance selects the object data in the vertex shader
+                    self.device.cmd_draw_indexed(
+                        command_buffer,
+                        draw_call.mesh.index_count,
+                        1,
+                        draw_call.mesh.first_index,
+                        draw_call.mesh.vertex_offset,
+                        object_index,
+                    );
+                }
+            }
+        }
+
+        self.end_render_pass(command_buffer);
+    }
+
+    fn begin_render_pass(&self, command_buffer: vk::CommandBuffer) {
+        let color_attachments = [vk::RenderingAttachmentInfo::default()
+            .image_view(self.color.image_view)
+            .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .resolve_mode(vk::ResolveModeFlags::NONE)
+            .resolve_image_view(vk::ImageView::null())
+            .resolve_image_layout(vk::ImageLayout::UNDEFINED)
+            .load_op(vk::AttachmentLoadOp::LOAD)
+            .store_op(vk::AttachmentStoreOp::STORE)];
+
+        let depth_attachment = vk::RenderingAttachmentInfo::default()
+            .image_view(self.depth.image_view)
+            .image_layout(self.depth.image_layout)
+            .resolve_mode(vk::ResolveModeFlags::NONE)
+            .resolve_image_view(vk::ImageView::null())
+            .resolve_image_layout(vk::ImageLayout::UNDEFINED)
+            .load_op(vk::AttachmentLoadOp::LOAD)
+            // Read only, made visible by the barrier at the end of DeferredRenderPass
+            .store_op(vk::AttachmentStoreOp::NONE);
+
+        // Left readable by the skybox pass
+        command_buffer_helpers::single_image_pipeline_barrier(
+            &self.device,
+            command_buffer,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            self.color.image_layout,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            self.color.image,
+            vk::ImageAspectFlags::COLOR,
+        );
+
+        let rendering_info = vk::RenderingInfo::default()
+            .render_area(self.render_area)
+            .layer_count(1)
+            .view_mask(0)
+            .color_attachments(&color_attachments)
+            .depth_attachment(&depth_attachment);
+
+        unsafe {
+            self.device
+                .cmd_begin_rendering(command_buffer, &rendering_info);
+        }
+    }
+
+    // Hands the lightning output back to the following passes in the layout it reports
+    fn end_render_pass(&self, command_buffer: vk::CommandBuffer) {
+        unsafe { self.device.cmd_end_rendering(command_buffer) };
+
+        command_buffer_helpers::single_image_pipeline_barrier(
+            &self.device,
+            command_buffer,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            vk::AccessFlags::SHADER_READ,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            self.color.image_layout,
+            self.color.image,
+            vk::ImageAspectFlags::COLOR,
+        );
+    }
+}
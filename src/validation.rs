@@ -0,0 +1,185 @@
+use std::{
+    borrow::Cow,
+    env,
+    ffi::CStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+use ash::{ext::debug_utils, vk, Entry, Instance};
+use log::Level;
+
+use crate::renderer_error::Result;
+
+// "1" or "0", defaults to enabled in debug builds
+pub const VALIDATION_ENV_VAR: &str = "SR_ENGINE_VALIDATION";
+// "1" makes `Renderer::render` panic on validation errors, for tests and CI
+pub const VALIDATION_PANIC_ENV_VAR: &str = "SR_ENGINE_VALIDATION_PANIC";
+
+pub const VALIDATION_LAYER_NAME: &CStr = c"VK_LAYER_KHRONOS_validation";
+
+// Written by the messenger callback, which can't unwind, and checked by the renderer
+static HAS_ERROR: AtomicBool = AtomicBool::new(false);
+// The first error since the last check
+static ERROR_MESSAGE: Mutex<Option<String>> = Mutex::new(None);
+
+#[derive(Clone, Copy, Debug)]
+pub struct ValidationSettings {
+    pub enabled: bool,
+    pub panic_on_error: bool,
+}
+
+impl ValidationSettings {
+    pub fn from_env() -> Self {
+        Self {
+            enabled: Self::get_env_flag(VALIDATION_ENV_VAR).unwrap_or(cfg!(debug_assertions)),
+            panic_on_error: Self::get_env_flag(VALIDATION_PANIC_ENV_VAR).unwrap_or(false),
+        }
+    }
+
+    fn get_env_flag(name: &str) -> Option<bool> {
+        env::var(name)
+            .ok()
+            .map(|value| !matches!(value.as_str(), "" | "0" | "false" | "off"))
+    }
+}
+
+pub fn is_layer_available(entry: &Entry) -> Result<bool> {
+    let layers = unsafe { entry.enumerate_instance_layer_properties()? };
+
+    Ok(layers
+        .iter()
+        .any(|layer| layer.layer_name_as_c_str() == Ok(VALIDATION_LAYER_NAME)))
+}
+
+pub fn is_debug_utils_available(entry: &Entry) -> Result<bool> {
+    let extensions = unsafe { entry.enumerate_instance_extension_properties(None)? };
+
+    Ok(extensions
+        .iter()
+        .any(|extension| extension.extension_name_as_c_str() == Ok(debug_utils::NAME)))
+}
+
+// Validation errors recorded by the callback since the last call
+pub fn take_error() -> Option<String> {
+    if !HAS_ERROR.swap(false, Ordering::Relaxed) {
+        return None;
+    }
+
+    ERROR_MESSAGE
+        .lock()
+        .ok()
+        .and_then(|mut message| message.take())
+}
+
+fn record_error(message: &str) {
+    if let Ok(mut error_message) = ERROR_MESSAGE.lock() {
+        error_message.get_or_insert_with(|| message.to_string());
+    }
+    HAS_ERROR.store(true, Ordering::Relaxed);
+}
+
+// Severities are only requested from the driver when the logger would print them
+pub fn create_debug_info() -> vk::DebugUtilsMessengerCreateInfoEXT<'static> {
+    let mut message_severity = vk::DebugUtilsMessageSeverityFlagsEXT::ERROR;
+    if log::log_enabled!(target: "vulkan", Level::Warn) {
+        message_severity |= vk::DebugUtilsMessageSeverityFlagsEXT::WARNING;
+    }
+    if log::log_enabled!(target: "vulkan", Level::Info) {
+        message_severity |= vk::DebugUtilsMessageSeverityFlagsEXT::INFO;
+    }
+    if log::log_enabled!(target: "vulkan", Level::Trace) {
+        message_severity |= vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE;
+    }
+
+    vk::DebugUtilsMessengerCreateInfoEXT::default()
+        .message_severity(message_severity)
+        .message_type(
+            vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
+                | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE
+                | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION,
+        )
+        .pfn_user_callback(Some(vulkan_debug_callback))
+}
+
+fn get_level(message_severity: vk::DebugUtilsMessageSeverityFlagsEXT) -> Level {
+    if message_severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR) {
+        Level::Error
+    } else if message_severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::WARNING) {
+        Level::Warn
+    } else if message_severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::INFO) {
+        Level::Info
+    } else {
+        Level::Trace
+    }
+}
+
+unsafe extern "system" fn vulkan_debug_callback(
+    message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    message_type: vk::DebugUtilsMessageTypeFlagsEXT,
+    p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
+    _user_data: *mut std::os::raw::c_void,
+) -> vk::Bool32 {
+    let callback_data = *p_callback_data;
+    let message_id_number = callback_data.message_id_number;
+
+    let message_id_name = if callback_data.p_message_id_name.is_null() {
+        Cow::from("")
+    } else {
+        CStr::from_ptr(callback_data.p_message_id_name).to_string_lossy()
+    };
+
+    let message = if callback_data.p_message.is_null() {
+        Cow::from("")
+    } else {
+        CStr::from_ptr(callback_data.p_message).to_string_lossy()
+    };
+
+    let level = get_level(message_severity);
+
+    log::log!(
+        target: "vulkan",
+        level,
+        "[{:?}] [{} ({})] : {}",
+        message_type,
+        message_id_name,
+        message_id_number,
+        message,
+    );
+
+    // Unwinding out of the callback would abort, the renderer checks for it after submitting
+    if level == Level::Error && message_type.contains(vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION)
+    {
+        record_error(&message);
+    }
+
+    vk::FALSE
+}
+
+// Instance level messenger, also covers messages without the validation layer like driver warnings
+pub struct DebugMessenger {
+    debug_utils: debug_utils::Instance,
+    messenger: vk::DebugUtilsMessengerEXT,
+}
+
+impl DebugMessenger {
+    pub fn new(entry: &Entry, instance: &Instance) -> Result<Self> {
+        let debug_utils = debug_utils::Instance::new(entry, instance);
+        let debug_info = create_debug_info();
+        let messenger = unsafe { debug_utils.create_debug_utils_messenger(&debug_info, None)? };
+
+        Ok(Self {
+            debug_utils,
+            messenger,
+        })
+    }
+
+    pub fn destroy(&mut self) {
+        unsafe {
+            self.debug_utils
+                .destroy_debug_utils_messenger(self.messenger, None)
+        };
+    }
+}